@@ -0,0 +1,21 @@
+#[path = "src/chip8emulator/assembler.rs"]
+mod assembler;
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const BOOT_ROM_SOURCE: &str = "src/boot.asm";
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", BOOT_ROM_SOURCE);
+    println!("cargo:rerun-if-changed=src/chip8emulator/assembler.rs");
+
+    let source = fs::read_to_string(BOOT_ROM_SOURCE)
+        .unwrap_or_else(|e| panic!("Can't read {}: {}", BOOT_ROM_SOURCE, e));
+    let rom = assembler::assemble(&source)
+        .unwrap_or_else(|e| panic!("Can't assemble {}: {}", BOOT_ROM_SOURCE, e));
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("boot.ch8"), rom).unwrap();
+}
@@ -0,0 +1,4 @@
+// Entry point of the emulator worker, used with `worker: true` or `?worker=1`.
+import("../pkg/index.js")
+    .then(wasm => wasm.workerMain())
+    .catch(e => console.error("Error starting the emulator worker:", e));
@@ -0,0 +1,685 @@
+//! Debug API exported to JavaScript for tools that inspect the running
+//! emulator.
+//!
+//! Bulk state comes in two flavors:
+//!
+//! * `*View` methods and `*Ptr` methods give direct access to the emulator's
+//!   memory inside the WebAssembly heap without copying. They are cheap enough
+//!   to call every frame, but they are only valid until the next call into the
+//!   module: the emulator keeps running and overwrites the contents, and if the
+//!   heap grows the underlying `ArrayBuffer` is detached and the view becomes
+//!   empty. Read what you need right away and don't keep views around.
+//! * `*Copy` methods return a fresh `Uint8Array` that is safe to keep, for
+//!   snapshots and comparisons, at the cost of a copy on every call.
+//!
+//! The display comes the same two ways, so pages can draw it themselves
+//! without asking for each pixel: `gfxView(plane)` has the pixels of a plane
+//! packed 32 to a word, pixel `n = y * gfxWidth() + x` being bit `n % 32` of
+//! word `n / 32`, and `gfxIndicesView()` has a color index byte per pixel on
+//! indexed (Mega-Chip) displays. `gfxRows()` packs each row of displays up
+//! to 64 pixels wide into a `BigInt`, leftmost pixel first, for text-mode and
+//! other simple renderers.
+//!
+//! Timers are reported with their phase, how far they are from 0 to 1 toward
+//! their next decrement, which matters when debugging races with the timers.
+//! `saveState` snapshots the machine, including timer phases, and `loadState`
+//! restores a snapshot.
+//!
+//! Pages that want to handle sound themselves, e.g. to light an LED or play
+//! their own audio, can register callbacks with `onSoundStart` and
+//! `onSoundStop`, and mute the built-in buzzer.
+//!
+//! `pressedMask()` has the keys the ROM sees pressed, bit `n` for key `n`,
+//! whatever input pressed them, and `onKey` registers callbacks called with
+//! `(key, pressed)` each time one is pressed or released.
+//!
+//! `opcodeTable(profile)` lists the instructions a profile runs, with the
+//! quirks each one is sensitive to, derived from the emulator's decoder.
+//!
+//! `setProfiling(enabled)` starts counting executed instructions by opcode
+//! pattern, and `opcodeStats()` returns the counts as a histogram.
+//!
+//! `capabilities()` reports which optional browser APIs the page found, and
+//! so which features it turned off.
+//!
+//! `memoryUsage()` reports how much memory recordings and macros use, and the
+//! caps they're held to.
+//!
+//! The same handle is set as `window.chip8` for poking the emulator from the
+//! browser's console: `readReg("V3")`, `readMem(addr, n)`,
+//! `writeMem(addr, bytes)`, `pause()`, `resume()`, `step(n)` and
+//! `disasm(addr, n)`, with addresses and registers as plain numbers.
+//!
+//! `state()` says what the emulator is doing, e.g. `running`, `paused` or
+//! `waitingForKey`, so pages can show it and only offer what makes sense.
+//!
+//! `benchmarkMemoryReads` times both flavors in the current browser, so tool
+//! authors can decide which one suits their polling rate.
+
+use super::get_current_time;
+use crate::capabilities::{self, Capability};
+use crate::chip8emulator::{opcode_table, Chip8Emulator, Profile, SaveState, State};
+use crate::memory_usage::{self, BufferCap};
+use js_sys::{Array, Function, Object, Reflect, Uint32Array, Uint8Array};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+
+/// Most instructions `step` runs in one call.
+const MAX_STEP_COUNT: u32 = 100_000;
+
+thread_local! {
+    static EMULATOR: RefCell<Option<Rc<RefCell<Chip8Emulator>>>> = const { RefCell::new(None) };
+
+    static SOUND_CALLBACKS: RefCell<SoundCallbacks> = RefCell::new(SoundCallbacks::default());
+
+    static KEY_CALLBACKS: RefCell<KeyCallbacks> = RefCell::new(KeyCallbacks::default());
+}
+
+#[derive(Default)]
+struct SoundCallbacks {
+    playing: bool,
+    on_start: Vec<Function>,
+    on_stop: Vec<Function>,
+}
+
+#[derive(Default)]
+struct KeyCallbacks {
+    pressed: u16,
+    on_key: Vec<Function>,
+}
+
+/// Makes `chip8` the emulator returned by `debugApi()`.
+pub fn set_emulator(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    EMULATOR.with(|emulator| *emulator.borrow_mut() = Some(Rc::clone(chip8)));
+}
+
+/// Sets `window.chip8` to the debug API's handle, for using it from the
+/// console.
+pub fn expose_on_window() {
+    let window = match web_sys::window() {
+        Some(window) => window,
+        None => return,
+    };
+    if let Some(api) = debug_api() {
+        if let Err(e) = Reflect::set(&window, &"chip8".into(), &api.into()) {
+            web_sys::console::error_2(&"Can't set window.chip8".into(), &e);
+        }
+    }
+}
+
+/// Lists the instructions the profile with the given name runs, e.g.
+/// `opcodeTable("cosmac-vip")`, as
+/// `{ pattern, mnemonic, example, extension, quirks }` objects, where
+/// `extension` is `null` for plain CHIP-8 instructions and `quirks` names the
+/// quirks that change what the instruction does.
+#[wasm_bindgen(js_name = opcodeTable)]
+pub fn opcode_table_js(profile: &str) -> Result<Array, JsValue> {
+    let profile = Profile::parse(profile)
+        .ok_or_else(|| JsValue::from_str(&format!("Unknown profile {}", profile)))?;
+
+    let table = Array::new();
+    for entry in opcode_table(&profile) {
+        let row = Object::new();
+        Reflect::set(&row, &"pattern".into(), &entry.pattern.into())?;
+        Reflect::set(&row, &"mnemonic".into(), &entry.mnemonic.into())?;
+        Reflect::set(&row, &"example".into(), &entry.example.into())?;
+        let extension = entry
+            .extension
+            .map_or(JsValue::NULL, |extension| extension.to_string().into());
+        Reflect::set(&row, &"extension".into(), &extension)?;
+        let quirks = entry
+            .quirks
+            .iter()
+            .map(|&quirk| JsValue::from(quirk))
+            .collect::<Array>();
+        Reflect::set(&row, &"quirks".into(), &quirks)?;
+        table.push(&row);
+    }
+    Ok(table)
+}
+
+/// Reports which optional browser APIs are available, as an object with a
+/// boolean for each, e.g. `{ webAudio: true, audioWorklet: true, ... }`.
+#[wasm_bindgen(js_name = capabilities)]
+pub fn capabilities_js() -> Result<Object, JsValue> {
+    let supported = capabilities::capabilities();
+    let report = Object::new();
+    for &capability in Capability::ALL.iter() {
+        Reflect::set(
+            &report,
+            &capability.key().into(),
+            &supported.supports(capability).into(),
+        )?;
+    }
+    Ok(report)
+}
+
+/// Reports how many bytes the page's buffers use, as `{ wasmHeap,
+/// audioRecording, videoRecording, gifRecording, macros, rewindHistory,
+/// trace, savedState, limits }`, where `limits` has a `{ maxBytes, eviction }`
+/// cap for each recording. Fails before the module has started.
+#[wasm_bindgen(js_name = memoryUsage)]
+pub fn memory_usage_js() -> Result<Object, JsValue> {
+    let chip8 = EMULATOR
+        .with(|emulator| emulator.borrow().clone())
+        .ok_or_else(|| JsValue::from_str("The emulator hasn't started"))?;
+    let usage = memory_usage::memory_usage(&chip8.borrow());
+    let report = Object::new();
+    Reflect::set(&report, &"wasmHeap".into(), &usage.wasm_heap.into())?;
+    Reflect::set(
+        &report,
+        &"audioRecording".into(),
+        &usage.audio_recording.into(),
+    )?;
+    Reflect::set(
+        &report,
+        &"videoRecording".into(),
+        &usage.video_recording.into(),
+    )?;
+    Reflect::set(&report, &"gifRecording".into(), &usage.gif_recording.into())?;
+    Reflect::set(&report, &"macros".into(), &usage.macros.into())?;
+    Reflect::set(
+        &report,
+        &"rewindHistory".into(),
+        &usage.rewind_history.into(),
+    )?;
+    Reflect::set(&report, &"trace".into(), &usage.trace.into())?;
+    Reflect::set(&report, &"savedState".into(), &usage.saved_state.into())?;
+
+    let limits = memory_usage::limits();
+    let caps = Object::new();
+    Reflect::set(
+        &caps,
+        &"audioRecording".into(),
+        &buffer_cap_js(limits.audio_recording)?,
+    )?;
+    Reflect::set(
+        &caps,
+        &"videoRecording".into(),
+        &buffer_cap_js(limits.video_recording)?,
+    )?;
+    Reflect::set(
+        &caps,
+        &"gifRecording".into(),
+        &buffer_cap_js(limits.gif_recording)?,
+    )?;
+    Reflect::set(&report, &"limits".into(), &caps)?;
+    Ok(report)
+}
+
+fn buffer_cap_js(cap: BufferCap) -> Result<JsValue, JsValue> {
+    let object = Object::new();
+    Reflect::set(&object, &"maxBytes".into(), &cap.max_bytes.into())?;
+    Reflect::set(
+        &object,
+        &"eviction".into(),
+        &cap.eviction.to_string().into(),
+    )?;
+    Ok(object.into())
+}
+
+/// Returns a handle to the page's emulator, or `undefined` before the module
+/// has started.
+#[wasm_bindgen(js_name = debugApi)]
+pub fn debug_api() -> Option<DebugApi> {
+    EMULATOR.with(|emulator| {
+        emulator.borrow().as_ref().map(|chip8| DebugApi {
+            chip8: Rc::clone(chip8),
+        })
+    })
+}
+
+/// Calls `callback` whenever the sound timer starts running.
+#[wasm_bindgen(js_name = onSoundStart)]
+pub fn on_sound_start(callback: Function) {
+    SOUND_CALLBACKS.with(|callbacks| callbacks.borrow_mut().on_start.push(callback));
+}
+
+/// Calls `callback` whenever the sound timer runs out.
+#[wasm_bindgen(js_name = onSoundStop)]
+pub fn on_sound_stop(callback: Function) {
+    SOUND_CALLBACKS.with(|callbacks| callbacks.borrow_mut().on_stop.push(callback));
+}
+
+/// Calls the sound callbacks if the sound started or stopped since the last
+/// call.
+pub fn update_sound(playing: bool) {
+    // The callbacks are cloned out first, so that they can register more
+    // callbacks without a borrow conflict.
+    let callbacks = SOUND_CALLBACKS.with(|callbacks| {
+        let mut callbacks = callbacks.borrow_mut();
+        if callbacks.playing == playing {
+            return Vec::new();
+        }
+        callbacks.playing = playing;
+        if playing {
+            callbacks.on_start.clone()
+        } else {
+            callbacks.on_stop.clone()
+        }
+    });
+
+    for callback in callbacks {
+        if let Err(e) = callback.call0(&JsValue::NULL) {
+            web_sys::console::error_2(&"Sound callback failed".into(), &e);
+        }
+    }
+}
+
+/// Calls `callback(key, pressed)` whenever a key is pressed or released.
+/// Keys pressed and released within the same frame aren't reported.
+#[wasm_bindgen(js_name = onKey)]
+pub fn on_key(callback: Function) {
+    KEY_CALLBACKS.with(|callbacks| callbacks.borrow_mut().on_key.push(callback));
+}
+
+/// Calls the key callbacks for each key pressed or released since the last
+/// call, `pressed` having a bit per key.
+pub fn update_keys(pressed: u16) {
+    // Cloned out first for the same reason as in `update_sound`.
+    let (previous, callbacks) = KEY_CALLBACKS.with(|callbacks| {
+        let mut callbacks = callbacks.borrow_mut();
+        let previous = std::mem::replace(&mut callbacks.pressed, pressed);
+        (previous, callbacks.on_key.clone())
+    });
+
+    for key in (0..16).filter(|key| (previous ^ pressed) & 1 << key != 0) {
+        let key_pressed = pressed & 1 << key != 0;
+        for callback in &callbacks {
+            if let Err(e) = callback.call2(&JsValue::NULL, &key.into(), &key_pressed.into()) {
+                web_sys::console::error_2(&"Key callback failed".into(), &e);
+            }
+        }
+    }
+}
+
+/// The module's `WebAssembly.Memory`, for reading the regions returned by the
+/// `*Ptr` methods.
+#[wasm_bindgen(js_name = wasmMemory)]
+pub fn wasm_memory() -> JsValue {
+    wasm_bindgen::memory()
+}
+
+#[wasm_bindgen]
+pub struct DebugApi {
+    chip8: Rc<RefCell<Chip8Emulator>>,
+}
+
+#[wasm_bindgen]
+impl DebugApi {
+    #[wasm_bindgen(js_name = memoryPtr)]
+    pub fn memory_ptr(&self) -> *const u8 {
+        self.chip8.borrow().get_memory().as_ptr()
+    }
+
+    #[wasm_bindgen(js_name = memoryLength)]
+    pub fn memory_length(&self) -> usize {
+        self.chip8.borrow().get_memory().len()
+    }
+
+    /// A view of the 4 KiB of CHIP-8 memory. See the module docs for how long
+    /// it stays valid.
+    #[wasm_bindgen(js_name = memoryView)]
+    pub fn memory_view(&self) -> Uint8Array {
+        // Safety: the memory lives inside the emulator, which is kept alive by
+        // `self`, and the caller is told not to use the view after calling
+        // back into the module.
+        unsafe { Uint8Array::view(self.chip8.borrow().get_memory()) }
+    }
+
+    #[wasm_bindgen(js_name = memoryCopy)]
+    pub fn memory_copy(&self) -> Vec<u8> {
+        self.chip8.borrow().get_memory().to_vec()
+    }
+
+    #[wasm_bindgen(js_name = registersPtr)]
+    pub fn registers_ptr(&self) -> *const u8 {
+        self.chip8.borrow().get_registers().as_ptr()
+    }
+
+    /// A view of registers V0-VF. See the module docs for how long it stays
+    /// valid.
+    #[wasm_bindgen(js_name = registersView)]
+    pub fn registers_view(&self) -> Uint8Array {
+        // Safety: see `memory_view`.
+        unsafe { Uint8Array::view(self.chip8.borrow().get_registers()) }
+    }
+
+    #[wasm_bindgen(js_name = registersCopy)]
+    pub fn registers_copy(&self) -> Vec<u8> {
+        self.chip8.borrow().get_registers().to_vec()
+    }
+
+    #[wasm_bindgen(js_name = gfxWidth)]
+    pub fn gfx_width(&self) -> u32 {
+        self.chip8.borrow().get_gfx_width()
+    }
+
+    #[wasm_bindgen(js_name = gfxHeight)]
+    pub fn gfx_height(&self) -> u32 {
+        self.chip8.borrow().get_gfx_height()
+    }
+
+    /// Row `y` of the display as a `BigInt`, the leftmost pixel in the most
+    /// significant bit, or `undefined` if the display is wider than 64
+    /// pixels.
+    #[wasm_bindgen(js_name = gfxRow)]
+    pub fn gfx_row(&self, y: u32) -> Option<u64> {
+        let chip8 = self.chip8.borrow();
+        (chip8.get_gfx_width() <= u64::BITS && y < chip8.get_gfx_height())
+            .then(|| chip8.get_gfx_row(y))
+    }
+
+    /// Every row as by `gfxRow`, in a `BigUint64Array`.
+    #[wasm_bindgen(js_name = gfxRows)]
+    pub fn gfx_rows(&self) -> Option<Vec<u64>> {
+        let chip8 = self.chip8.borrow();
+        (chip8.get_gfx_width() <= u64::BITS).then(|| chip8.gfx_rows().collect())
+    }
+
+    #[wasm_bindgen(js_name = gfxPlaneCount)]
+    pub fn gfx_plane_count(&self) -> usize {
+        self.chip8.borrow().get_gfx_plane_count()
+    }
+
+    /// Address of a display plane's packed pixels, or 0 if there's no such
+    /// plane.
+    #[wasm_bindgen(js_name = gfxPtr)]
+    pub fn gfx_ptr(&self, plane: usize) -> *const u32 {
+        let chip8 = self.chip8.borrow();
+        chip8
+            .get_gfx_plane_bits(plane)
+            .map_or(std::ptr::null(), <[u32]>::as_ptr)
+    }
+
+    /// Number of 32-bit words of a display plane's packed pixels.
+    #[wasm_bindgen(js_name = gfxLength)]
+    pub fn gfx_length(&self, plane: usize) -> usize {
+        self.chip8
+            .borrow()
+            .get_gfx_plane_bits(plane)
+            .map_or(0, <[u32]>::len)
+    }
+
+    /// A view of a display plane's packed pixels, or `undefined` if there's
+    /// no such plane. See the module docs for how long it stays valid.
+    #[wasm_bindgen(js_name = gfxView)]
+    pub fn gfx_view(&self, plane: usize) -> Option<Uint32Array> {
+        let chip8 = self.chip8.borrow();
+        // Safety: see `memory_view`. The display is also replaced when it
+        // changes size, which the caller can't do without calling back in.
+        chip8
+            .get_gfx_plane_bits(plane)
+            .map(|bits| unsafe { Uint32Array::view(bits) })
+    }
+
+    #[wasm_bindgen(js_name = gfxCopy)]
+    pub fn gfx_copy(&self, plane: usize) -> Option<Vec<u32>> {
+        self.chip8
+            .borrow()
+            .get_gfx_plane_bits(plane)
+            .map(<[u32]>::to_vec)
+    }
+
+    /// A view of the color index of each pixel, or `undefined` unless the
+    /// display is indexed. See the module docs for how long it stays valid.
+    #[wasm_bindgen(js_name = gfxIndicesView)]
+    pub fn gfx_indices_view(&self) -> Option<Uint8Array> {
+        let chip8 = self.chip8.borrow();
+        // Safety: see `gfx_view`.
+        chip8
+            .get_gfx_indices()
+            .map(|indices| unsafe { Uint8Array::view(indices) })
+    }
+
+    #[wasm_bindgen(js_name = gfxIndicesCopy)]
+    pub fn gfx_indices_copy(&self) -> Option<Vec<u8>> {
+        self.chip8.borrow().get_gfx_indices().map(<[u8]>::to_vec)
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.chip8.borrow().get_pc()
+    }
+
+    #[wasm_bindgen(js_name = I)]
+    pub fn index_register(&self) -> u16 {
+        self.chip8.borrow().get_I()
+    }
+
+    #[wasm_bindgen(js_name = delayTimer)]
+    pub fn delay_timer(&self) -> u8 {
+        self.chip8.borrow().get_delay_timer()
+    }
+
+    #[wasm_bindgen(js_name = delayTimerPhase)]
+    pub fn delay_timer_phase(&self) -> f64 {
+        self.chip8.borrow().get_delay_timer_state().phase
+    }
+
+    #[wasm_bindgen(js_name = soundTimer)]
+    pub fn sound_timer(&self) -> u8 {
+        self.chip8.borrow().get_sound_timer()
+    }
+
+    #[wasm_bindgen(js_name = soundTimerPhase)]
+    pub fn sound_timer_phase(&self) -> f64 {
+        self.chip8.borrow().get_sound_timer_state().phase
+    }
+
+    /// The keys the ROM sees pressed, bit `n` for key `n`.
+    #[wasm_bindgen(js_name = pressedMask)]
+    pub fn pressed_mask(&self) -> u16 {
+        self.chip8.borrow().get_pressed_keys()
+    }
+
+    #[wasm_bindgen(js_name = saveState)]
+    pub fn save_state(&self) -> DebugSaveState {
+        DebugSaveState(self.chip8.borrow().save_state())
+    }
+
+    #[wasm_bindgen(js_name = loadState)]
+    pub fn load_state(&self, state: &DebugSaveState) {
+        self.chip8
+            .borrow_mut()
+            .load_state(&state.0, get_current_time());
+    }
+
+    /// Starts counting the instructions executed from zero, by address and
+    /// by opcode pattern, or stops counting.
+    #[wasm_bindgen(js_name = setProfiling)]
+    pub fn set_profiling(&self, enabled: bool) {
+        self.chip8.borrow_mut().set_profiling(enabled);
+    }
+
+    /// Returns how many times each kind of instruction ran while profiling,
+    /// as an array of `{ pattern, count }` like `{ pattern: "DXYN", count:
+    /// 120 }`, the most first.
+    #[wasm_bindgen(js_name = opcodeStats)]
+    pub fn opcode_stats(&self) -> Result<Array, JsValue> {
+        let stats = Array::new();
+        for (pattern, count) in self.chip8.borrow().get_opcode_stats() {
+            let row = Object::new();
+            Reflect::set(&row, &"pattern".into(), &pattern.into())?;
+            Reflect::set(&row, &"count".into(), &(count as f64).into())?;
+            stats.push(&row);
+        }
+        Ok(stats)
+    }
+
+    /// Returns the register named `name`: `V0`-`VF`, `I`, `PC`, `SP`, `DT`
+    /// or `ST`, in any case.
+    #[wasm_bindgen(js_name = readReg)]
+    pub fn read_reg(&self, name: &str) -> Result<u16, JsValue> {
+        register_value(&self.chip8.borrow(), name)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown register {}", name)))
+    }
+
+    /// Returns a copy of the `length` bytes of memory from `address`.
+    #[wasm_bindgen(js_name = readMem)]
+    pub fn read_mem(&self, address: u16, length: usize) -> Result<Vec<u8>, JsValue> {
+        let chip8 = self.chip8.borrow();
+        (address as usize)
+            .checked_add(length)
+            .and_then(|end| chip8.get_memory().get(address as usize..end))
+            .map(<[u8]>::to_vec)
+            .ok_or_else(|| "Range is out of memory".into())
+    }
+
+    /// Writes `data` to memory from `address`, e.g.
+    /// `writeMem(0x300, [1, 2, 3])`.
+    #[wasm_bindgen(js_name = writeMem)]
+    pub fn write_mem(&self, address: u16, data: Vec<u8>) -> Result<(), JsValue> {
+        if self.chip8.borrow_mut().write_memory(address, &data) {
+            Ok(())
+        } else {
+            Err("Range is out of memory".into())
+        }
+    }
+
+    pub fn pause(&self) {
+        self.chip8.borrow_mut().pause();
+    }
+
+    pub fn resume(&self) {
+        self.chip8.borrow_mut().resume();
+    }
+
+    /// What the emulator is doing: `loading`, `running`, `paused`,
+    /// `waitingForKey`, `halted` or `faulted`.
+    pub fn state(&self) -> String {
+        self.chip8.borrow().get_state().name().to_string()
+    }
+
+    /// Pauses, then runs the next `count` instructions, or the next one if
+    /// it's left out. Stops early if the ROM ends or faults.
+    pub fn step(&self, count: Option<u32>) -> Result<(), JsValue> {
+        let count = count.unwrap_or(1);
+        if count > MAX_STEP_COUNT {
+            return Err(format!("Can't step more than {} instructions", MAX_STEP_COUNT).into());
+        }
+        let mut chip8 = self.chip8.borrow_mut();
+        chip8.pause();
+        for _ in 0..count {
+            if chip8.get_state() != State::Paused {
+                break;
+            }
+            chip8.step_instruction();
+        }
+        Ok(())
+    }
+
+    /// Disassembles `count` instructions from `address`, as lines like
+    /// `202  A234  LD I, 0x234`.
+    pub fn disasm(&self, address: u16, count: usize) -> Array {
+        self.chip8
+            .borrow()
+            .disassemble(address, count)
+            .into_iter()
+            .map(|line| {
+                JsValue::from(format!(
+                    "{:03X}  {:04X}  {}",
+                    line.address, line.opcode, line.text
+                ))
+            })
+            .collect()
+    }
+
+    /// Fetches memory `iterations` times as a view and as a copy, the way
+    /// `memoryView` and `memoryCopy` return it to JavaScript, and returns
+    /// `{ viewMs, copyMs }` with the total time each took.
+    #[wasm_bindgen(js_name = benchmarkMemoryReads)]
+    pub fn benchmark_memory_reads(&self, iterations: u32) -> Result<Object, JsValue> {
+        let mut checksum = 0;
+
+        let start = get_current_time();
+        for _ in 0..iterations {
+            checksum ^= self.memory_view().get_index(0);
+        }
+        let view_ms = get_current_time() - start;
+
+        let start = get_current_time();
+        for _ in 0..iterations {
+            checksum ^= Uint8Array::from(self.chip8.borrow().get_memory()).get_index(0);
+        }
+        let copy_ms = get_current_time() - start;
+
+        let result = Object::new();
+        Reflect::set(&result, &"viewMs".into(), &view_ms.into())?;
+        Reflect::set(&result, &"copyMs".into(), &copy_ms.into())?;
+        // Keeps the reads from being optimized away.
+        Reflect::set(&result, &"checksum".into(), &checksum.into())?;
+        Ok(result)
+    }
+}
+
+/// Returns the value of the register `readReg` names `name`.
+fn register_value(chip8: &Chip8Emulator, name: &str) -> Option<u16> {
+    let name = name.trim().to_ascii_uppercase();
+    match name.as_str() {
+        "I" => Some(chip8.get_I()),
+        "PC" => Some(chip8.get_pc()),
+        "SP" => Some(chip8.get_stack_pointer() as u16),
+        "DT" => Some(chip8.get_delay_timer().into()),
+        "ST" => Some(chip8.get_sound_timer().into()),
+        _ => {
+            let x = name.strip_prefix('V')?;
+            if x.len() != 1 {
+                return None;
+            }
+            let x = u8::from_str_radix(x, 16).ok()?;
+            Some(chip8.get_registers()[x as usize].into())
+        }
+    }
+}
+
+/// A machine snapshot returned by `saveState`.
+#[wasm_bindgen]
+pub struct DebugSaveState(SaveState);
+
+#[wasm_bindgen]
+impl DebugSaveState {
+    pub fn pc(&self) -> u16 {
+        self.0.get_pc()
+    }
+
+    #[wasm_bindgen(js_name = delayTimer)]
+    pub fn delay_timer(&self) -> u8 {
+        self.0.get_delay_timer().value
+    }
+
+    #[wasm_bindgen(js_name = delayTimerPhase)]
+    pub fn delay_timer_phase(&self) -> f64 {
+        self.0.get_delay_timer().phase
+    }
+
+    #[wasm_bindgen(js_name = soundTimer)]
+    pub fn sound_timer(&self) -> u8 {
+        self.0.get_sound_timer().value
+    }
+
+    #[wasm_bindgen(js_name = soundTimerPhase)]
+    pub fn sound_timer_phase(&self) -> f64 {
+        self.0.get_sound_timer().phase
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_value() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x6a, 0x2f, 0xa3, 0x45]).unwrap();
+        chip8.step_instruction();
+        chip8.step_instruction();
+        assert_eq!(register_value(&chip8, "VA"), Some(0x2f));
+        assert_eq!(register_value(&chip8, "va"), Some(0x2f));
+        assert_eq!(register_value(&chip8, "I"), Some(0x345));
+        assert_eq!(register_value(&chip8, "pc"), Some(0x204));
+        assert_eq!(register_value(&chip8, "SP"), Some(0));
+        assert_eq!(register_value(&chip8, "V10"), None);
+        assert_eq!(register_value(&chip8, "X"), None);
+    }
+}
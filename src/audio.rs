@@ -0,0 +1,564 @@
+use super::audio_worklet::WorkletBuzzer;
+use super::{get_checkbox, get_element_by_id, storage};
+use crate::chip8emulator::{Chip8Emulator, TimerState};
+use gloo::events::EventListener;
+use js_sys::Math;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{
+    window, AudioBufferSourceNode, AudioContext, AudioNode, GainNode, HtmlElement,
+    HtmlInputElement, HtmlSelectElement, MediaStreamAudioDestinationNode, OscillatorNode,
+    OscillatorType,
+};
+
+const AUDIO_INPUT_IDS: [&str; 4] = [
+    "audio-waveform",
+    "audio-frequency",
+    "audio-volume",
+    "audio-mute",
+];
+
+/// Length of the sound timer's count down steps, in seconds.
+pub const SOUND_TIMER_STEP: f64 = 1.0 / 60.0;
+
+/// How the buzzer's sound is produced.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AudioBackend {
+    /// An oscillator switched on and off from the main thread.
+    Oscillator,
+    /// An AudioWorklet that plays samples generated here for as long as the
+    /// sound timer runs, so short beeps aren't lost when the main thread is
+    /// busy.
+    Worklet,
+}
+
+impl AudioBackend {
+    pub fn parse(value: &str) -> Option<AudioBackend> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "oscillator" => Some(AudioBackend::Oscillator),
+            "worklet" => Some(AudioBackend::Worklet),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    /// White noise. The frequency setting has no effect on it.
+    Noise,
+}
+
+impl Waveform {
+    fn name(self) -> &'static str {
+        match self {
+            Waveform::Square => "square",
+            Waveform::Triangle => "triangle",
+            Waveform::Noise => "noise",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Waveform> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "square" => Some(Waveform::Square),
+            "triangle" => Some(Waveform::Triangle),
+            "noise" => Some(Waveform::Noise),
+            _ => None,
+        }
+    }
+}
+
+/// How the buzzer sounds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioConfig {
+    pub waveform: Waveform,
+    /// Pitch in Hz.
+    pub frequency: f32,
+    /// Gain from 0 (silent) to 1 (full scale).
+    pub volume: f32,
+    pub muted: bool,
+}
+
+/// The waveform and pitch of the buzzer, which a ROM can have its own of.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tone {
+    pub waveform: Waveform,
+    pub frequency: f32,
+}
+
+impl Tone {
+    /// Parses a tone in the form it is stored in, e.g. `triangle 220`.
+    pub fn parse(value: &str) -> Option<Tone> {
+        let (waveform, frequency) = value.trim().split_once(' ')?;
+        Some(Tone {
+            waveform: Waveform::parse(waveform)?,
+            frequency: frequency.trim().parse().ok()?,
+        })
+    }
+}
+
+impl fmt::Display for Tone {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.waveform.name(), self.frequency)
+    }
+}
+
+impl Default for AudioConfig {
+    fn default() -> AudioConfig {
+        AudioConfig {
+            waveform: Waveform::Square,
+            frequency: 440.0,
+            volume: 0.1,
+            muted: false,
+        }
+    }
+}
+
+/// A beep that sounds while the CHIP-8 sound timer is active.
+pub enum Buzzer {
+    Oscillator(OscillatorBuzzer),
+    Worklet(WorkletBuzzer),
+}
+
+impl Buzzer {
+    pub async fn new(backend: AudioBackend, config: AudioConfig) -> Result<Buzzer, JsValue> {
+        Ok(match backend {
+            AudioBackend::Oscillator => Buzzer::Oscillator(OscillatorBuzzer::new(config)?),
+            AudioBackend::Worklet => Buzzer::Worklet(WorkletBuzzer::new(config).await?),
+        })
+    }
+
+    pub fn set_config(&mut self, config: AudioConfig) {
+        match self {
+            Buzzer::Oscillator(buzzer) => buzzer.set_config(config),
+            Buzzer::Worklet(buzzer) => buzzer.set_config(config),
+        }
+    }
+
+    /// Sends the buzzer's sound to a new media stream as well as to the
+    /// speakers, for recording it.
+    pub fn add_stream_destination(&self) -> Result<MediaStreamAudioDestinationNode, JsValue> {
+        let (context, output) = self.context_and_output();
+        let destination = context.create_media_stream_destination()?;
+        output.connect_with_audio_node(&destination)?;
+        Ok(destination)
+    }
+
+    pub fn remove_stream_destination(
+        &self,
+        destination: &MediaStreamAudioDestinationNode,
+    ) -> Result<(), JsValue> {
+        self.context_and_output()
+            .1
+            .disconnect_with_audio_node(destination)
+    }
+
+    fn context_and_output(&self) -> (&AudioContext, &AudioNode) {
+        match self {
+            Buzzer::Oscillator(buzzer) => (&buzzer.context, &buzzer.output_gain),
+            Buzzer::Worklet(buzzer) => (buzzer.context(), buzzer.output()),
+        }
+    }
+
+    /// Updates the buzzer with the current state of the sound timer.
+    pub fn update(&mut self, sound_timer: TimerState) {
+        match self {
+            Buzzer::Oscillator(buzzer) => buzzer.update(sound_timer),
+            Buzzer::Worklet(buzzer) => buzzer.update(sound_timer),
+        }
+    }
+}
+
+/// A change to when the buzzer sounds, in seconds on the audio clock.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SoundChange {
+    /// Sound from now until the given time.
+    PlayUntil(f64),
+    /// Stop now, because the program cut the sound timer short.
+    Stop,
+}
+
+/// Works out when the buzzer should sound from updates of the sound timer.
+///
+/// Beeps are scheduled ahead on the audio clock for as long as the sound timer
+/// will run, instead of being switched on and off as the main thread notices
+/// the timer change, so their length doesn't depend on how regularly the
+/// emulator is ticked.
+#[derive(Default)]
+pub struct SoundSchedule {
+    last_sound_timer: u8,
+    /// When the scheduled sound ends.
+    end: f64,
+}
+
+impl SoundSchedule {
+    /// Returns how the buzzer should change given the state of the sound timer
+    /// at audio clock time `now`, if it should.
+    pub fn update(&mut self, sound_timer: TimerState, now: f64) -> Option<SoundChange> {
+        let last_sound_timer = self.last_sound_timer;
+        self.last_sound_timer = sound_timer.value;
+
+        if sound_timer.value > last_sound_timer {
+            let remaining = (sound_timer.value as f64 - sound_timer.phase) * SOUND_TIMER_STEP;
+            self.end = now + remaining;
+            Some(SoundChange::PlayUntil(self.end))
+        } else if sound_timer.value == 0
+            && last_sound_timer > 0
+            && now < self.end - SOUND_TIMER_STEP
+        {
+            // The timer ran out well before it would have counting down, so
+            // the program must have set it to 0.
+            self.end = now;
+            Some(SoundChange::Stop)
+        } else {
+            None
+        }
+    }
+}
+
+/// Plays the buzzer through oscillator and noise source nodes.
+///
+/// The sources run for the lifetime of the page and are turned on and off by
+/// changing gains, since a source node can't be restarted once stopped.
+pub struct OscillatorBuzzer {
+    context: AudioContext,
+    oscillator: OscillatorNode,
+    /// Passes the oscillator through unless the waveform is noise.
+    oscillator_gain: GainNode,
+    /// Passes the noise source through if the waveform is noise.
+    noise_gain: GainNode,
+    /// Switches the sound on and off at the times scheduled by `schedule`.
+    envelope: GainNode,
+    /// Applies the volume, or silences the buzzer when muted.
+    output_gain: GainNode,
+    schedule: SoundSchedule,
+}
+
+impl OscillatorBuzzer {
+    pub fn new(config: AudioConfig) -> Result<OscillatorBuzzer, JsValue> {
+        let context = AudioContext::new()?;
+
+        let output_gain = context.create_gain()?;
+        output_gain.connect_with_audio_node(&context.destination())?;
+
+        let envelope = context.create_gain()?;
+        envelope.gain().set_value(0.0);
+        envelope.connect_with_audio_node(&output_gain)?;
+
+        let oscillator = context.create_oscillator()?;
+        let oscillator_gain = context.create_gain()?;
+        oscillator.connect_with_audio_node(&oscillator_gain)?;
+        oscillator_gain.connect_with_audio_node(&envelope)?;
+        oscillator.start()?;
+
+        let noise = create_noise_source(&context)?;
+        let noise_gain = context.create_gain()?;
+        noise.connect_with_audio_node(&noise_gain)?;
+        noise_gain.connect_with_audio_node(&envelope)?;
+        noise.start()?;
+
+        register_resume_on_input(&context);
+
+        let mut buzzer = OscillatorBuzzer {
+            context,
+            oscillator,
+            oscillator_gain,
+            noise_gain,
+            envelope,
+            output_gain,
+            schedule: SoundSchedule::default(),
+        };
+        buzzer.set_config(config);
+        Ok(buzzer)
+    }
+
+    pub fn set_config(&mut self, config: AudioConfig) {
+        match config.waveform {
+            Waveform::Square => self.oscillator.set_type(OscillatorType::Square),
+            Waveform::Triangle => self.oscillator.set_type(OscillatorType::Triangle),
+            Waveform::Noise => {}
+        }
+        self.oscillator.frequency().set_value(config.frequency);
+
+        let noise = config.waveform == Waveform::Noise;
+        self.oscillator_gain
+            .gain()
+            .set_value(if noise { 0.0 } else { 1.0 });
+        self.noise_gain
+            .gain()
+            .set_value(if noise { 1.0 } else { 0.0 });
+
+        self.output_gain
+            .gain()
+            .set_value(if config.muted { 0.0 } else { config.volume });
+    }
+
+    pub fn update(&mut self, sound_timer: TimerState) {
+        let now = self.context.current_time();
+        let change = match self.schedule.update(sound_timer, now) {
+            Some(change) => change,
+            None => return,
+        };
+
+        let gain = self.envelope.gain();
+        let scheduled = gain
+            .cancel_scheduled_values(now)
+            .and_then(|gain| match change {
+                SoundChange::PlayUntil(end) => gain
+                    .set_value_at_time(1.0, now)?
+                    .set_value_at_time(0.0, end),
+                SoundChange::Stop => gain.set_value_at_time(0.0, now),
+            });
+        if let Err(e) = scheduled {
+            web_sys::console::error_2(&"Can't schedule the buzzer".into(), &e);
+        }
+    }
+}
+
+/// Creates a looping source of one second of white noise.
+fn create_noise_source(context: &AudioContext) -> Result<AudioBufferSourceNode, JsValue> {
+    let sample_rate = context.sample_rate();
+    let buffer = context.create_buffer(1, sample_rate as u32, sample_rate)?;
+
+    let samples = waveform_samples(Waveform::Noise, 0.0, sample_rate, random_sample);
+    buffer.copy_to_channel(&samples, 0)?;
+
+    let source = context.create_buffer_source()?;
+    source.set_buffer(Some(&buffer));
+    source.set_loop(true);
+    Ok(source)
+}
+
+/// Generates samples of `waveform` that can be played in a loop: one period
+/// at `frequency` Hz, or a second of noise drawn from `random`, at
+/// `sample_rate` samples per second. Samples range from -1 to 1.
+pub fn waveform_samples(
+    waveform: Waveform,
+    frequency: f32,
+    sample_rate: f32,
+    mut random: impl FnMut() -> f32,
+) -> Vec<f32> {
+    if waveform == Waveform::Noise {
+        return (0..sample_rate as usize).map(|_| random()).collect();
+    }
+
+    let period = (sample_rate / frequency).round().max(2.0) as usize;
+    (0..period)
+        .map(|i| {
+            let phase = i as f32 / period as f32;
+            match waveform {
+                Waveform::Square if phase < 0.5 => 1.0,
+                Waveform::Square => -1.0,
+                _ => 1.0 - 4.0 * (phase - 0.5).abs(),
+            }
+        })
+        .collect()
+}
+
+/// A uniformly distributed sample from -1 to 1.
+pub fn random_sample() -> f32 {
+    Math::random() as f32 * 2.0 - 1.0
+}
+
+thread_local! {
+    /// The buzzer the sound panel controls, once the panel is registered.
+    static PANEL_BUZZER: RefCell<Option<Rc<RefCell<Option<Buzzer>>>>> =
+        const { RefCell::new(None) };
+
+    /// The tone of ROMs without one of their own.
+    static DEFAULT_TONE: Cell<Tone> = const {
+        Cell::new(Tone {
+            waveform: Waveform::Square,
+            frequency: 440.0,
+        })
+    };
+}
+
+/// Shows `config` in the sound panel and applies changes made there to
+/// `buzzer`. While the ROM tone checkbox is checked, tone changes are also
+/// stored for the ROM running in `chip8`.
+/// `buzzer` may still be being created, and may fail to be.
+pub fn register_audio_panel(
+    buzzer: &Rc<RefCell<Option<Buzzer>>>,
+    config: AudioConfig,
+    chip8: &Rc<RefCell<Chip8Emulator>>,
+) {
+    let tone = Tone {
+        waveform: config.waveform,
+        frequency: config.frequency,
+    };
+    DEFAULT_TONE.with(|default| default.set(tone));
+    PANEL_BUZZER.with(|panel_buzzer| *panel_buzzer.borrow_mut() = Some(Rc::clone(buzzer)));
+
+    set_tone_panel(tone);
+    get_range_input("audio-volume").set_value(&(config.volume * 100.0).round().to_string());
+    get_checkbox("audio-mute").set_checked(config.muted);
+
+    for id in AUDIO_INPUT_IDS {
+        let chip8 = Rc::clone(chip8);
+        EventListener::new(&get_element_by_id(id), "input", move |e| {
+            let e = e.target().unwrap();
+            e.dyn_ref::<HtmlElement>().unwrap().blur().unwrap();
+            update_panel_buzzer();
+            if get_checkbox("audio-rom-tone").checked() {
+                store_rom_tone(chip8.borrow().get_rom_checksum());
+            }
+        })
+        .forget();
+    }
+
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&get_checkbox("audio-rom-tone"), "input", move |_| {
+        let checkbox = get_checkbox("audio-rom-tone");
+        checkbox.blur().unwrap();
+
+        let checksum = chip8.borrow().get_rom_checksum();
+        if checkbox.checked() {
+            store_rom_tone(checksum);
+        } else if let Err(e) = storage::remove_rom_tone(checksum) {
+            web_sys::console::error_2(&"Can't forget the ROM's tone".into(), &e);
+        }
+    })
+    .forget();
+}
+
+/// Switches the buzzer to the tone stored for the ROM with the given
+/// checksum, or to the default tone if it has none.
+pub fn apply_rom_tone(checksum: u32) {
+    let stored = storage::load_rom_tone(checksum);
+    get_checkbox("audio-rom-tone").set_checked(stored.is_some());
+    set_tone_panel(stored.unwrap_or_else(|| DEFAULT_TONE.with(Cell::get)));
+    update_panel_buzzer();
+}
+
+fn store_rom_tone(checksum: u32) {
+    let config = get_audio_config_from_panel();
+    let tone = Tone {
+        waveform: config.waveform,
+        frequency: config.frequency,
+    };
+    if let Err(e) = storage::store_rom_tone(checksum, tone) {
+        web_sys::console::error_2(&"Can't store the ROM's tone".into(), &e);
+    }
+}
+
+fn set_tone_panel(tone: Tone) {
+    get_waveform_select().set_value(tone.waveform.name());
+    get_range_input("audio-frequency").set_value(&tone.frequency.to_string());
+}
+
+fn update_panel_buzzer() {
+    PANEL_BUZZER.with(|buzzer| {
+        if let Some(buzzer) = buzzer.borrow().as_ref() {
+            if let Some(buzzer) = buzzer.borrow_mut().as_mut() {
+                buzzer.set_config(get_audio_config_from_panel());
+            }
+        }
+    });
+}
+
+pub fn get_audio_config_from_panel() -> AudioConfig {
+    AudioConfig {
+        waveform: Waveform::parse(&get_waveform_select().value()).unwrap_or(Waveform::Square),
+        frequency: get_range_input("audio-frequency").value_as_number() as f32,
+        volume: get_range_input("audio-volume").value_as_number() as f32 / 100.0,
+        muted: get_checkbox("audio-mute").checked(),
+    }
+}
+
+fn get_waveform_select() -> HtmlSelectElement {
+    get_element_by_id("audio-waveform")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #audio-waveform is not a select element")
+}
+
+fn get_range_input(id: &str) -> HtmlInputElement {
+    get_element_by_id(id)
+        .dyn_into::<HtmlInputElement>()
+        .unwrap_or_else(|_| panic!("Element with id #{} is not an input element", id))
+}
+
+/// Browsers keep an audio context suspended until the user interacts with the
+/// page, so resume it on the first key press or click.
+pub fn register_resume_on_input(context: &AudioContext) {
+    for event in ["keydown", "click"] {
+        let context = context.clone();
+        EventListener::once(&window().unwrap(), event, move |_| {
+            let _ = context.resume();
+        })
+        .forget();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_waveform_samples() {
+        let square = waveform_samples(Waveform::Square, 1000.0, 8000.0, || 0.0);
+        assert_eq!(square, [1.0, 1.0, 1.0, 1.0, -1.0, -1.0, -1.0, -1.0]);
+
+        let triangle = waveform_samples(Waveform::Triangle, 1000.0, 8000.0, || 0.0);
+        assert_eq!(triangle, [-1.0, -0.5, 0.0, 0.5, 1.0, 0.5, 0.0, -0.5]);
+
+        let noise = waveform_samples(Waveform::Noise, 1000.0, 8000.0, || 0.25);
+        assert_eq!(noise.len(), 8000);
+        assert!(noise.iter().all(|&sample| sample == 0.25));
+
+        assert_eq!(AudioBackend::parse("Worklet"), Some(AudioBackend::Worklet));
+        assert_eq!(AudioBackend::parse("speaker"), None);
+    }
+
+    #[test]
+    fn test_sound_schedule() {
+        let timer = |value, phase| TimerState { value, phase };
+        let mut schedule = SoundSchedule::default();
+        assert_eq!(schedule.update(timer(0, 0.5), 1.0), None);
+        assert_eq!(
+            schedule.update(timer(6, 0.5), 1.0),
+            Some(SoundChange::PlayUntil(1.0 + 5.5 * SOUND_TIMER_STEP))
+        );
+        assert_eq!(schedule.update(timer(3, 0.0), 1.05), None);
+        assert_eq!(schedule.update(timer(0, 0.0), 1.09), None);
+
+        schedule.update(timer(60, 0.0), 2.0);
+        assert_eq!(schedule.update(timer(0, 0.0), 2.5), Some(SoundChange::Stop));
+    }
+
+    #[test]
+    fn test_parse_waveform() {
+        assert_eq!(Waveform::parse("square"), Some(Waveform::Square));
+        assert_eq!(Waveform::parse(" Triangle"), Some(Waveform::Triangle));
+        assert_eq!(Waveform::parse("NOISE"), Some(Waveform::Noise));
+        assert_eq!(Waveform::parse("sine"), None);
+        assert_eq!(AudioConfig::default().waveform, Waveform::Square);
+
+        for waveform in [Waveform::Square, Waveform::Triangle, Waveform::Noise] {
+            assert_eq!(Waveform::parse(waveform.name()), Some(waveform));
+        }
+    }
+
+    #[test]
+    fn test_parse_tone() {
+        let tone = Tone {
+            waveform: Waveform::Triangle,
+            frequency: 220.5,
+        };
+        assert_eq!(tone.to_string(), "triangle 220.5");
+        assert_eq!(Tone::parse("triangle 220.5"), Some(tone));
+        assert_eq!(
+            Tone::parse(" noise 440 "),
+            Some(Tone {
+                waveform: Waveform::Noise,
+                frequency: 440.0,
+            })
+        );
+        assert_eq!(Tone::parse("triangle"), None);
+        assert_eq!(Tone::parse("sine 440"), None);
+        assert_eq!(Tone::parse("square loud"), None);
+    }
+}
@@ -0,0 +1,247 @@
+use super::audio::{get_audio_config_from_panel, random_sample, waveform_samples, AudioConfig};
+use super::memory_usage::{self, BufferCap, Eviction};
+use super::{download_file, get_element_by_id};
+use crate::chip8emulator::Chip8Emulator;
+use gloo::events::EventListener;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::mem::size_of;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlButtonElement;
+
+const SAMPLE_RATE: u32 = 44100;
+
+/// Renders the buzzer as it sounds during emulation into 16-bit PCM samples.
+pub struct AudioRecorder {
+    /// One loop of the waveform, scaled to the volume.
+    waveform: Vec<f32>,
+    position: usize,
+    last_time: Option<f64>,
+    /// Fraction of a sample left over from the last update.
+    pending: f64,
+    samples: VecDeque<i16>,
+    /// Keeps a forgotten recording from using up memory.
+    cap: BufferCap,
+}
+
+impl AudioRecorder {
+    pub fn new(config: AudioConfig, cap: BufferCap, random: impl FnMut() -> f32) -> AudioRecorder {
+        let waveform = waveform_samples(
+            config.waveform,
+            config.frequency,
+            SAMPLE_RATE as f32,
+            random,
+        )
+        .into_iter()
+        .map(|sample| sample * config.volume)
+        .collect();
+
+        AudioRecorder {
+            waveform,
+            position: 0,
+            last_time: None,
+            pending: 0.0,
+            samples: VecDeque::new(),
+            cap,
+        }
+    }
+
+    /// Adds the audio between the last update and `current_time`, during
+    /// which the buzzer was `playing` or silent.
+    pub fn record(&mut self, current_time: f64, playing: bool) {
+        let last_time = self.last_time.replace(current_time).unwrap_or(current_time);
+        let count =
+            (current_time - last_time).max(0.0) * SAMPLE_RATE as f64 / 1000.0 + self.pending;
+        self.pending = count.fract();
+
+        let max_len = self.cap.max_bytes / size_of::<i16>();
+        let mut count = count as usize;
+        if self.cap.eviction == Eviction::Stop {
+            count = count.min(max_len.saturating_sub(self.samples.len()));
+        }
+        for _ in 0..count {
+            let sample = if playing {
+                self.position = (self.position + 1) % self.waveform.len();
+                self.waveform[self.position]
+            } else {
+                0.0
+            };
+            self.samples.push_back((sample * i16::MAX as f32) as i16);
+        }
+        while self.samples.len() > max_len {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.samples.len() as f64 / SAMPLE_RATE as f64
+    }
+
+    pub fn byte_len(&self) -> usize {
+        self.samples.len() * size_of::<i16>()
+    }
+
+    /// Returns the recording as a WAV file.
+    pub fn finish(mut self) -> Vec<u8> {
+        encode_wav(self.samples.make_contiguous(), SAMPLE_RATE)
+    }
+}
+
+/// Encodes mono 16-bit samples as a WAV file.
+pub fn encode_wav(samples: &[i16], sample_rate: u32) -> Vec<u8> {
+    let data_len = 2 * samples.len() as u32;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    wav.extend_from_slice(&1u16.to_le_bytes()); // Mono
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(2 * sample_rate).to_le_bytes()); // Bytes per second
+    wav.extend_from_slice(&2u16.to_le_bytes()); // Bytes per frame
+    wav.extend_from_slice(&16u16.to_le_bytes()); // Bits per sample
+
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        wav.extend_from_slice(&sample.to_le_bytes());
+    }
+    wav
+}
+
+thread_local! {
+    static RECORDER: RefCell<Option<AudioRecorder>> = const { RefCell::new(None) };
+}
+
+/// Adds the buzzer's state at `current_time` to the recording, if any.
+pub fn record_audio(chip8: &Chip8Emulator, current_time: f64) {
+    RECORDER.with(|recorder| {
+        if let Some(recorder) = recorder.borrow_mut().as_mut() {
+            recorder.record(current_time, chip8.is_sound_playing());
+        }
+    });
+}
+
+/// How many bytes the recording in progress uses, if any.
+pub fn recording_bytes() -> usize {
+    RECORDER.with(|recorder| {
+        recorder
+            .borrow()
+            .as_ref()
+            .map_or(0, AudioRecorder::byte_len)
+    })
+}
+
+/// Wires up the button that starts recording and downloads the recording
+/// when stopped.
+pub fn register_audio_recording() {
+    EventListener::new(&get_record_button(), "click", move |_| {
+        let button = get_record_button();
+        button.blur().unwrap();
+
+        RECORDER.with(|recorder| {
+            let mut recorder = recorder.borrow_mut();
+            match recorder.take() {
+                Some(recording) => {
+                    button.set_text_content(Some("Record WAV"));
+                    button.set_title(&format!("Last recording: {:.1}s", recording.duration()));
+                    download_file("chip8-audio.wav", &recording.finish(), "audio/wav");
+                }
+                None => {
+                    // Recordings use the sound settings in effect when they
+                    // start, but aren't muted.
+                    let config = get_audio_config_from_panel();
+                    let cap = memory_usage::limits().audio_recording;
+                    *recorder = Some(AudioRecorder::new(config, cap, random_sample));
+                    button.set_text_content(Some("Stop recording"));
+                }
+            }
+        });
+    })
+    .forget();
+}
+
+fn get_record_button() -> HtmlButtonElement {
+    get_element_by_id("audio-record")
+        .dyn_into::<HtmlButtonElement>()
+        .expect("Element with id #audio-record is not a button element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::Waveform;
+    use crate::memory_usage::MemoryLimits;
+
+    #[test]
+    fn test_audio_recorder() {
+        let config = AudioConfig {
+            waveform: Waveform::Square,
+            frequency: 441.0,
+            volume: 0.5,
+            muted: true,
+        };
+        let cap = MemoryLimits::default().audio_recording;
+        let mut recorder = AudioRecorder::new(config, cap, || 0.0);
+        recorder.record(0.0, false);
+        recorder.record(10.0, false);
+        recorder.record(20.0, true);
+        assert_eq!(recorder.samples.len(), 882);
+        assert!(recorder.samples.range(..441).all(|&sample| sample == 0));
+        assert!(recorder
+            .samples
+            .range(441..)
+            .all(|&sample| sample.abs() == i16::MAX / 2));
+
+        // Fractions of a sample carry over.
+        recorder.record(20.01, false);
+        recorder.record(20.02, false);
+        recorder.record(20.03, false);
+        assert_eq!(recorder.samples.len(), 883);
+        assert_eq!(recorder.duration(), 883.0 / 44100.0);
+        assert_eq!(recorder.byte_len(), 1766);
+    }
+
+    #[test]
+    fn test_audio_recorder_cap() {
+        let config = AudioConfig {
+            waveform: Waveform::Square,
+            frequency: 441.0,
+            volume: 0.5,
+            muted: false,
+        };
+        let cap = |eviction| BufferCap {
+            max_bytes: 800,
+            eviction,
+        };
+
+        let mut recorder = AudioRecorder::new(config, cap(Eviction::Stop), || 0.0);
+        recorder.record(0.0, false);
+        recorder.record(10.0, false);
+        recorder.record(20.0, true);
+        assert_eq!(recorder.byte_len(), 800);
+        assert!(recorder.samples.iter().all(|&sample| sample == 0));
+
+        let mut recorder = AudioRecorder::new(config, cap(Eviction::DropOldest), || 0.0);
+        recorder.record(0.0, false);
+        recorder.record(10.0, false);
+        recorder.record(20.0, true);
+        assert_eq!(recorder.byte_len(), 800);
+        assert!(recorder.samples.iter().all(|&sample| sample != 0));
+    }
+
+    #[test]
+    fn test_encode_wav() {
+        let wav = encode_wav(&[1, -2], 8000);
+        assert_eq!(wav.len(), 48);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[4..8], &40u32.to_le_bytes());
+        assert_eq!(&wav[24..28], &8000u32.to_le_bytes());
+        assert_eq!(&wav[40..44], &4u32.to_le_bytes());
+        assert_eq!(&wav[44..], &[1, 0, 0xfe, 0xff]);
+    }
+}
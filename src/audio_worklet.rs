@@ -0,0 +1,99 @@
+use super::audio::{
+    random_sample, register_resume_on_input, waveform_samples, AudioConfig, SoundChange,
+    SoundSchedule,
+};
+use crate::chip8emulator::TimerState;
+use js_sys::{Float32Array, Object, Reflect};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{AudioContext, AudioNode, AudioWorkletNode, MessagePort};
+
+/// Script defining the `buzzer` AudioWorklet processor, served from `static`.
+const WORKLET_MODULE: &str = "buzzer-worklet.js";
+const PROCESSOR_NAME: &str = "buzzer";
+
+/// Plays the buzzer in an AudioWorklet.
+///
+/// Instead of being switched on and off, the worklet is told how long the
+/// sound timer will run whenever it is set, and counts the time down itself on
+/// the audio thread. Beeps a frame or two long then play for their full length
+/// even if the main thread only notices the sound timer late.
+pub struct WorkletBuzzer {
+    context: AudioContext,
+    node: AudioWorkletNode,
+    port: MessagePort,
+    schedule: SoundSchedule,
+}
+
+impl WorkletBuzzer {
+    pub async fn new(config: AudioConfig) -> Result<WorkletBuzzer, JsValue> {
+        let context = AudioContext::new()?;
+        JsFuture::from(context.audio_worklet()?.add_module(WORKLET_MODULE)?).await?;
+
+        let node = AudioWorkletNode::new(&context, PROCESSOR_NAME)?;
+        node.connect_with_audio_node(&context.destination())?;
+
+        register_resume_on_input(&context);
+
+        let mut buzzer = WorkletBuzzer {
+            port: node.port()?,
+            context,
+            node,
+            schedule: SoundSchedule::default(),
+        };
+        buzzer.set_config(config);
+        Ok(buzzer)
+    }
+
+    /// Sends the worklet newly generated samples and the volume.
+    pub fn set_config(&mut self, config: AudioConfig) {
+        let samples = waveform_samples(
+            config.waveform,
+            config.frequency,
+            self.context.sample_rate(),
+            random_sample,
+        );
+        let volume = if config.muted { 0.0 } else { config.volume };
+
+        let message = Object::new();
+        Reflect::set(
+            &message,
+            &"samples".into(),
+            &Float32Array::from(&samples[..]),
+        )
+        .unwrap();
+        Reflect::set(&message, &"volume".into(), &volume.into()).unwrap();
+        self.post(&message);
+    }
+
+    /// Starts or extends the beep when the sound timer is set to a higher value
+    /// than it had, and cuts it short if the program clears the timer. The
+    /// worklet stops on its own when the time runs out.
+    pub fn update(&mut self, sound_timer: TimerState) {
+        let now = self.context.current_time();
+        let duration = match self.schedule.update(sound_timer, now) {
+            Some(SoundChange::PlayUntil(end)) => end - now,
+            Some(SoundChange::Stop) => 0.0,
+            None => return,
+        };
+
+        let message = Object::new();
+        Reflect::set(&message, &"duration".into(), &duration.into()).unwrap();
+        self.post(&message);
+    }
+
+    pub fn context(&self) -> &AudioContext {
+        &self.context
+    }
+
+    /// The node the buzzer's sound comes out of.
+    pub fn output(&self) -> &AudioNode {
+        &self.node
+    }
+
+    fn post(&self, message: &Object) {
+        if let Err(e) = self.port.post_message(message) {
+            web_sys::console::error_2(&"Can't send message to the buzzer worklet".into(), &e);
+        }
+    }
+}
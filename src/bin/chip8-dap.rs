@@ -0,0 +1,5 @@
+//! Runs the Debug Adapter Protocol bridge over stdin and stdout.
+
+fn main() -> std::io::Result<()> {
+    chip8_emulator::dap::run()
+}
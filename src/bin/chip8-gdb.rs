@@ -0,0 +1,20 @@
+//! Serves a ROM to GDB: `chip8-gdb <rom> [port]`.
+
+use std::env;
+use std::process;
+
+const DEFAULT_PORT: u16 = 1234;
+
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = env::args().collect();
+    let (path, port) = match args.as_slice() {
+        [_, path] => (path, Some(DEFAULT_PORT)),
+        [_, path, port] => (path, port.parse().ok()),
+        _ => (&args[0], None),
+    };
+    let port = port.unwrap_or_else(|| {
+        eprintln!("Usage: chip8-gdb <rom> [port]");
+        process::exit(2);
+    });
+    chip8_emulator::gdb::run(path, port)
+}
@@ -0,0 +1,39 @@
+//! Plays random keys into every ROM in a directory for a while:
+//! `chip8-soak [roms-dir] [minutes] [seed]`.
+
+use chip8_emulator::soak;
+use std::env;
+use std::process;
+use std::time::Duration;
+
+// The library already installs `wee_alloc` with the `wee_alloc` feature, and
+// there can only be one global allocator, so the heap isn't counted then.
+#[cfg(not(feature = "wee_alloc"))]
+#[global_allocator]
+static ALLOCATOR: soak::CountingAllocator = soak::CountingAllocator;
+
+const DEFAULT_ROMS_DIR: &str = "static/roms";
+const DEFAULT_MINUTES: u64 = 60;
+
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let roms_dir = args.first().map_or(DEFAULT_ROMS_DIR, String::as_str);
+    let minutes = args
+        .get(1)
+        .map_or(Some(DEFAULT_MINUTES), |m| m.parse().ok());
+    let seed = args.get(2).map_or(Some(0), |s| s.parse().ok());
+    let (minutes, seed) = match (minutes, seed) {
+        (Some(minutes), Some(seed)) if args.len() <= 3 => (minutes, seed),
+        _ => {
+            eprintln!("Usage: chip8-soak [roms-dir] [minutes] [seed]");
+            process::exit(2);
+        }
+    };
+
+    let failures = soak::run(roms_dir, Duration::from_secs(minutes * 60), seed)?;
+    if failures > 0 {
+        eprintln!("{} sessions failed", failures);
+        process::exit(1);
+    }
+    Ok(())
+}
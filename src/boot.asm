@@ -0,0 +1,48 @@
+; Boot splash shown while no ROM has been selected yet. Spells out "CHIP-8"
+; one letter at a time, then blinks a cursor after it forever.
+
+start:
+        CLS
+        LD V0, 9            ; x of the next letter
+        LD V1, 13           ; y of the logo
+        LD V2, 6            ; letters left to draw
+        LD V3, 5            ; bytes per letter
+        LD I, logo
+
+next_letter:
+        DRW V0, V1, 5
+        ADD I, V3
+        ADD V0, 8
+        LD V4, 8
+        CALL wait
+        ADD V2, 0xFF        ; one letter fewer left
+        SE V2, 0
+        JP next_letter
+
+        LD I, cursor
+        ADD V1, 4
+blink:
+        DRW V0, V1, 1
+        LD V4, 30
+        CALL wait
+        JP blink
+
+; Waits for V4 frames using the delay timer.
+wait:
+        LD DT, V4
+wait_loop:
+        LD V4, DT
+        SE V4, 0
+        JP wait_loop
+        RET
+
+logo:
+        DB 0xF0, 0x80, 0x80, 0x80, 0xF0     ; C
+        DB 0x90, 0x90, 0xF0, 0x90, 0x90     ; H
+        DB 0xE0, 0x40, 0x40, 0x40, 0xE0     ; I
+        DB 0xF0, 0x90, 0xF0, 0x80, 0x80     ; P
+        DB 0x00, 0x00, 0xF0, 0x00, 0x00     ; -
+        DB 0xF0, 0x90, 0xF0, 0x90, 0xF0     ; 8
+
+cursor:
+        DB 0xF0
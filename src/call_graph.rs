@@ -0,0 +1,136 @@
+//! Call tree in the Debugger panel: the subroutines the ROM has called, each
+//! under the subroutines that called it with how many times, shown on demand
+//! to help make sense of a ROM's structure beyond the 16 return addresses on
+//! the stack. Subroutines are named after their symbols, if any.
+
+use super::get_element_by_id;
+use crate::chip8emulator::{Call, Chip8Emulator};
+use gloo::events::EventListener;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use web_sys::window;
+
+/// Shows the call tree with the button.
+pub fn register_call_graph(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("calls-show"), "click", move |_| {
+        let document = window().unwrap().document().unwrap();
+        let tree = get_element_by_id("call-tree");
+        tree.set_text_content(None);
+        let chip8 = chip8.borrow();
+        for line in tree_lines(&chip8.get_calls(), chip8.get_symbols()) {
+            let item = document.create_element("li").unwrap();
+            item.set_text_content(Some(&line));
+            tree.append_child(&item).unwrap();
+        }
+    })
+    .forget();
+}
+
+/// Returns the call tree as a line for each call, indented under the
+/// subroutine making it, starting from the main program, with the names of
+/// the subroutines in `symbols`.
+fn tree_lines(calls: &[Call], symbols: &BTreeMap<u16, String>) -> Vec<String> {
+    let mut lines = vec!["Main program".to_string()];
+    let mut tree = Tree {
+        calls,
+        symbols,
+        path: Vec::new(),
+        lines: &mut lines,
+    };
+    tree.add_callees(None);
+    lines
+}
+
+struct Tree<'a> {
+    calls: &'a [Call],
+    symbols: &'a BTreeMap<u16, String>,
+    /// The subroutines above the calls being added, so recursion is shown
+    /// only once.
+    path: Vec<u16>,
+    lines: &'a mut Vec<String>,
+}
+
+impl Tree<'_> {
+    /// Adds the lines for the calls from `caller` and, recursively, theirs.
+    fn add_callees(&mut self, caller: Option<u16>) {
+        let calls = self.calls;
+        for call in calls.iter().filter(|call| call.caller == caller) {
+            let indent = "  ".repeat(self.path.len() + 1);
+            let name = match self.symbols.get(&call.address) {
+                Some(symbol) => format!("{:03X} {}", call.address, symbol),
+                None => format!("{:03X}", call.address),
+            };
+            let times = match call.count {
+                1 => "once".to_string(),
+                count => format!("{} times", count),
+            };
+            if self.path.contains(&call.address) {
+                self.lines
+                    .push(format!("{}{}  {}, recursively", indent, name, times));
+                continue;
+            }
+            self.lines.push(format!("{}{}  {}", indent, name, times));
+            self.path.push(call.address);
+            self.add_callees(Some(call.address));
+            self.path.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree_lines() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8
+            .load_rom(&[
+                0x22, 0x08, // CALL 0x208
+                0x22, 0x08, // CALL 0x208
+                0x22, 0x0c, // CALL 0x20C
+                0x12, 0x06, // JP 0x206
+                0x22, 0x0c, // CALL 0x20C
+                0x00, 0xee, // RET
+                0x00, 0xee, // RET
+            ])
+            .unwrap();
+        for _ in 0..12 {
+            chip8.step_instruction();
+        }
+        let mut symbols = BTreeMap::new();
+        symbols.insert(0x20c, "draw".to_string());
+        assert_eq!(
+            tree_lines(&chip8.get_calls(), &symbols),
+            [
+                "Main program",
+                "  208  2 times",
+                "    20C draw  2 times",
+                "  20C draw  once",
+            ]
+        );
+
+        let recursive = [
+            Call {
+                caller: None,
+                address: 0x240,
+                count: 1,
+            },
+            Call {
+                caller: Some(0x240),
+                address: 0x240,
+                count: 3,
+            },
+        ];
+        assert_eq!(
+            tree_lines(&recursive, &BTreeMap::new()),
+            [
+                "Main program",
+                "  240  once",
+                "    240  3 times, recursively"
+            ]
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use super::audio::AudioBackend;
+use super::get_element_by_id;
+use js_sys::{global, Reflect};
+use std::cell::Cell;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{console, window, HtmlButtonElement};
+
+/// A browser API that some of the page's features need, and which older or
+/// locked-down browsers may not have.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Capability {
+    WebAudio,
+    AudioWorklet,
+    MediaRecorder,
+    Gamepad,
+    WebMidi,
+    OffscreenCanvas,
+    SharedArrayBuffer,
+    WebRtc,
+    FileSystemAccess,
+    Clipboard,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 10] = [
+        Capability::WebAudio,
+        Capability::AudioWorklet,
+        Capability::MediaRecorder,
+        Capability::Gamepad,
+        Capability::WebMidi,
+        Capability::OffscreenCanvas,
+        Capability::SharedArrayBuffer,
+        Capability::WebRtc,
+        Capability::FileSystemAccess,
+        Capability::Clipboard,
+    ];
+
+    /// The name the capability is reported under by `capabilities()`.
+    pub fn key(self) -> &'static str {
+        match self {
+            Capability::WebAudio => "webAudio",
+            Capability::AudioWorklet => "audioWorklet",
+            Capability::MediaRecorder => "mediaRecorder",
+            Capability::Gamepad => "gamepad",
+            Capability::WebMidi => "webMidi",
+            Capability::OffscreenCanvas => "offscreenCanvas",
+            Capability::SharedArrayBuffer => "sharedArrayBuffer",
+            Capability::WebRtc => "webRtc",
+            Capability::FileSystemAccess => "fileSystemAccess",
+            Capability::Clipboard => "clipboard",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Capability::WebAudio => "Web Audio",
+            Capability::AudioWorklet => "AudioWorklet",
+            Capability::MediaRecorder => "MediaRecorder",
+            Capability::Gamepad => "Gamepad",
+            Capability::WebMidi => "Web MIDI",
+            Capability::OffscreenCanvas => "OffscreenCanvas",
+            Capability::SharedArrayBuffer => "SharedArrayBuffer",
+            Capability::WebRtc => "WebRTC",
+            Capability::FileSystemAccess => "File System Access",
+            Capability::Clipboard => "Async Clipboard",
+        }
+    }
+
+    /// What stops working without the capability, if anything uses it yet.
+    fn needed_for(self) -> Option<&'static str> {
+        match self {
+            Capability::WebAudio => Some("the buzzer"),
+            Capability::AudioWorklet => Some("the worklet audio backend"),
+            Capability::MediaRecorder => Some("video recording"),
+            Capability::WebMidi => Some("MIDI input"),
+            Capability::OffscreenCanvas => Some("running the emulator in a worker"),
+            Capability::Clipboard => Some("copying screenshots"),
+            _ => None,
+        }
+    }
+
+    /// Checks whether the page's global scope has the capability.
+    fn probe(self) -> bool {
+        let scope: JsValue = global().into();
+        match self {
+            Capability::WebAudio => has(&scope, "AudioContext"),
+            Capability::AudioWorklet => has(&scope, "AudioWorkletNode"),
+            Capability::MediaRecorder => has(&scope, "MediaRecorder"),
+            Capability::Gamepad => {
+                window().is_some_and(|window| has(&window.navigator(), "getGamepads"))
+            }
+            Capability::WebMidi => {
+                window().is_some_and(|window| has(&window.navigator(), "requestMIDIAccess"))
+            }
+            Capability::OffscreenCanvas => has(&scope, "OffscreenCanvas"),
+            // Shared memory is only usable on cross-origin isolated pages.
+            Capability::SharedArrayBuffer => {
+                has(&scope, "SharedArrayBuffer")
+                    && Reflect::get(&scope, &"crossOriginIsolated".into())
+                        .is_ok_and(|isolated| isolated.is_truthy())
+            }
+            Capability::WebRtc => has(&scope, "RTCPeerConnection"),
+            Capability::FileSystemAccess => has(&scope, "showOpenFilePicker"),
+            // Copying images needs `ClipboardItem` on top of the clipboard,
+            // which only secure pages have.
+            Capability::Clipboard => {
+                has(&scope, "ClipboardItem")
+                    && window().is_some_and(|window| has(&window.navigator(), "clipboard"))
+            }
+        }
+    }
+}
+
+fn has(target: &JsValue, name: &str) -> bool {
+    Reflect::has(target, &name.into()).unwrap_or(false)
+}
+
+/// Which capabilities the browser has, one bit per capability.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Capabilities(u16);
+
+impl Capabilities {
+    pub fn detect() -> Capabilities {
+        Capabilities::from_fn(Capability::probe)
+    }
+
+    fn from_fn(mut supported: impl FnMut(Capability) -> bool) -> Capabilities {
+        let mut bits = 0;
+        for (index, &capability) in Capability::ALL.iter().enumerate() {
+            if supported(capability) {
+                bits |= 1 << index;
+            }
+        }
+        Capabilities(bits)
+    }
+
+    pub fn supports(self, capability: Capability) -> bool {
+        let index = Capability::ALL.iter().position(|&c| c == capability);
+        index.is_some_and(|index| self.0 & 1 << index != 0)
+    }
+
+    /// The audio backend to use instead of `requested`, or `None` if the
+    /// buzzer can't play at all. The worklet falls back to the oscillator.
+    pub fn audio_backend(self, requested: AudioBackend) -> Option<AudioBackend> {
+        if !self.supports(Capability::WebAudio) {
+            None
+        } else if requested == AudioBackend::Worklet && !self.supports(Capability::AudioWorklet) {
+            Some(AudioBackend::Oscillator)
+        } else {
+            Some(requested)
+        }
+    }
+}
+
+thread_local! {
+    static CAPABILITIES: Cell<Option<Capabilities>> = const { Cell::new(None) };
+}
+
+/// Returns the browser's capabilities, probing them the first time.
+pub fn capabilities() -> Capabilities {
+    CAPABILITIES.with(|capabilities| {
+        capabilities.get().unwrap_or_else(|| {
+            let detected = Capabilities::detect();
+            capabilities.set(Some(detected));
+            detected
+        })
+    })
+}
+
+/// Disables the controls of features the browser can't run, and fills the
+/// browser support panel.
+pub fn apply_capabilities() {
+    let capabilities = capabilities();
+    if !capabilities.supports(Capability::MediaRecorder) {
+        disable_button("video-record");
+    }
+    if !capabilities.supports(Capability::Clipboard) {
+        disable_button("screenshot-copy");
+    }
+    if !capabilities.supports(Capability::WebMidi) {
+        disable_button("midi-connect");
+    }
+    if let Err(e) = fill_capability_list(capabilities) {
+        console::error_2(&"Can't show browser support".into(), &e);
+    }
+}
+
+fn disable_button(id: &str) {
+    let button = get_element_by_id(id)
+        .dyn_into::<HtmlButtonElement>()
+        .unwrap_or_else(|_| panic!("Element with id #{} is not a button element", id));
+    button.set_disabled(true);
+    button.set_title("Not supported by this browser");
+}
+
+fn fill_capability_list(capabilities: Capabilities) -> Result<(), JsValue> {
+    let document = window().unwrap().document().unwrap();
+    let list = get_element_by_id("capability-list");
+    list.set_text_content(None);
+
+    for &capability in Capability::ALL.iter() {
+        let item = document.create_element("li")?;
+        item.set_text_content(Some(&describe(
+            capability,
+            capabilities.supports(capability),
+        )));
+        list.append_child(&item)?;
+    }
+    Ok(())
+}
+
+fn describe(capability: Capability, supported: bool) -> String {
+    match (supported, capability.needed_for()) {
+        (true, _) => format!("{}: supported", capability.label()),
+        (false, Some(feature)) => format!("{}: missing, so {} is off", capability.label(), feature),
+        (false, None) => format!("{}: missing", capability.label()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_backend() {
+        let all = Capabilities::from_fn(|_| true);
+        assert!(all.supports(Capability::FileSystemAccess));
+        assert_eq!(
+            all.audio_backend(AudioBackend::Worklet),
+            Some(AudioBackend::Worklet)
+        );
+
+        let no_worklet = Capabilities::from_fn(|c| c != Capability::AudioWorklet);
+        assert!(!no_worklet.supports(Capability::AudioWorklet));
+        assert_eq!(
+            no_worklet.audio_backend(AudioBackend::Worklet),
+            Some(AudioBackend::Oscillator)
+        );
+
+        let none = Capabilities::from_fn(|_| false);
+        assert_eq!(none.audio_backend(AudioBackend::Oscillator), None);
+        assert_eq!(
+            describe(Capability::MediaRecorder, false),
+            "MediaRecorder: missing, so video recording is off"
+        );
+    }
+}
@@ -0,0 +1,160 @@
+//! Cheat search in the Cheats panel: finds where a ROM keeps a value like its
+//! lives or score by snapshotting memory, then narrowing down the addresses
+//! by how their bytes changed between snapshots, e.g. decreased after losing
+//! a life or equal to 3 while there are three lives left.
+
+use super::get_element_by_id;
+use crate::chip8emulator::{Chip8Emulator, MemorySearch, SearchFilter};
+use gloo::events::EventListener;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{window, HtmlButtonElement, HtmlInputElement, HtmlSelectElement};
+
+/// How many of the addresses left are listed.
+const RESULT_LIMIT: usize = 50;
+
+thread_local! {
+    static SEARCH: RefCell<Option<MemorySearch>> = const { RefCell::new(None) };
+}
+
+/// Starts a search with the New search button and narrows it down with the
+/// Filter button.
+pub fn register_cheat_search(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let start_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("search-start"), "click", move |_| {
+        let search = MemorySearch::new(start_chip8.borrow().get_memory());
+        set_status(&format!(
+            "Searching {} addresses. Change the value in the game, then filter.",
+            search.candidates().len()
+        ));
+        show_results(&[]);
+        SEARCH.with(|current| *current.borrow_mut() = Some(search));
+        get_filter_button().set_disabled(false);
+    })
+    .forget();
+
+    let filter_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_filter_button(), "click", move |_| {
+        let filter = match read_filter() {
+            Some(filter) => filter,
+            None => {
+                set_status("The value to compare with must be from 0 to 255");
+                return;
+            }
+        };
+        SEARCH.with(|current| {
+            if let Some(search) = current.borrow_mut().as_mut() {
+                search.filter(filter_chip8.borrow().get_memory(), filter);
+                set_status(&describe_count(search, filter));
+                show_results(&describe_results(search));
+            }
+        });
+    })
+    .forget();
+}
+
+/// Returns the filter picked in the panel, or `None` if the value to compare
+/// with isn't a byte.
+fn read_filter() -> Option<SearchFilter> {
+    let filter = get_element_by_id("search-filter")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #search-filter is not a select element")
+        .value();
+    Some(match filter.as_str() {
+        "increased" => SearchFilter::Increased,
+        "decreased" => SearchFilter::Decreased,
+        "changed" => SearchFilter::Changed,
+        "unchanged" => SearchFilter::Unchanged,
+        _ => {
+            let value = get_element_by_id("search-value")
+                .dyn_into::<HtmlInputElement>()
+                .expect("Element with id #search-value is not an input element")
+                .value();
+            SearchFilter::EqualTo(value.trim().parse().ok()?)
+        }
+    })
+}
+
+fn describe_count(search: &MemorySearch, filter: SearchFilter) -> String {
+    match search.candidates().len() {
+        0 => format!("No address {}. Start a new search.", filter),
+        1 => format!("1 address {}", filter),
+        count => format!("{} addresses {}", count, filter),
+    }
+}
+
+/// Returns a line for each of the first addresses left, with its byte in
+/// hex and decimal.
+fn describe_results(search: &MemorySearch) -> Vec<String> {
+    search
+        .candidates()
+        .iter()
+        .take(RESULT_LIMIT)
+        .map(|&address| {
+            let value = search.snapshot_value(address);
+            format!("{:03X}  {:02X}  {:>3}", address, value, value)
+        })
+        .collect()
+}
+
+fn show_results(lines: &[String]) {
+    let document = window().unwrap().document().unwrap();
+    let results = get_element_by_id("search-results");
+    results.set_text_content(None);
+    for line in lines {
+        let item = document.create_element("li").unwrap();
+        item.set_text_content(Some(line));
+        results.append_child(&item).unwrap();
+    }
+}
+
+fn set_status(text: &str) {
+    get_element_by_id("search-status").set_text_content(Some(text));
+}
+
+fn get_filter_button() -> HtmlButtonElement {
+    get_element_by_id("search-filter-apply")
+        .dyn_into::<HtmlButtonElement>()
+        .expect("Element with id #search-filter-apply is not a button element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_results() {
+        // Counts V0 down from 3 into 0xE00.
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8
+            .load_rom(&[
+                0x60, 0x03, // LD V0, 0x03
+                0xae, 0x00, // LD I, 0xE00
+                0xf0, 0x55, // LD [I], V0
+                0x70, 0xff, // ADD V0, 0xFF
+                0x12, 0x04, // JP 0x204
+            ])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.step_instruction();
+        }
+        let mut search = MemorySearch::new(chip8.get_memory());
+        search.filter(chip8.get_memory(), SearchFilter::EqualTo(3));
+        assert!(search.candidates().contains(&0xe00));
+
+        for _ in 0..3 {
+            chip8.step_instruction();
+        }
+        let filter = SearchFilter::Decreased;
+        search.filter(chip8.get_memory(), filter);
+        assert_eq!(describe_count(&search, filter), "1 address decreased");
+        assert_eq!(describe_results(&search), ["E00  02    2"]);
+
+        search.filter(chip8.get_memory(), SearchFilter::Changed);
+        assert_eq!(
+            describe_count(&search, SearchFilter::Changed),
+            "No address changed. Start a new search."
+        );
+    }
+}
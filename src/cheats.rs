@@ -0,0 +1,93 @@
+//! Cheats in the Cheats panel: bytes of memory set to a value, either frozen
+//! there every tick, e.g. to keep a ROM's lives, or written once when added
+//! and whenever the ROM loads, e.g. to patch its code. They're kept in local
+//! storage for the ROM they were added for.
+
+use super::{debug_panel, get_element_by_id, storage};
+use crate::chip8emulator::{Cheat, CheatMode, Chip8Emulator};
+use gloo::events::EventListener;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{console, HtmlInputElement, HtmlSelectElement, KeyboardEvent};
+
+/// Adds cheats typed in the panel, with the Add cheat button or Enter.
+pub fn register_cheats(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let button_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("cheat-add"), "click", move |_| {
+        add_cheat(&button_chip8);
+    })
+    .forget();
+
+    let input_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_input(), "keydown", move |e| {
+        if e.dyn_ref::<KeyboardEvent>().unwrap().key() == "Enter" {
+            add_cheat(&input_chip8);
+        }
+    })
+    .forget();
+}
+
+/// Gives `chip8` the cheats kept for the ROM it has loaded and lists them.
+pub fn apply_rom_cheats(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let checksum = chip8.borrow().get_rom_checksum();
+    chip8
+        .borrow_mut()
+        .set_cheats(storage::load_rom_cheats(checksum));
+    show_cheats(chip8);
+}
+
+fn add_cheat(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let input = get_input();
+    let mode = match get_mode_select().value().as_str() {
+        "once" => CheatMode::Once,
+        _ => CheatMode::Freeze,
+    };
+    match Cheat::parse(&input.value()) {
+        Some(cheat) => {
+            chip8.borrow_mut().add_cheat(Cheat { mode, ..cheat });
+            input.set_value("");
+            set_status("");
+            store_cheats(&chip8.borrow());
+            show_cheats(chip8);
+        }
+        None => set_status(&format!(
+            "Can't add \"{}\", expected an address and a byte in hex like E00=03",
+            input.value()
+        )),
+    }
+}
+
+/// Lists the cheats, each with a button removing it.
+fn show_cheats(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let remove_chip8 = Rc::clone(chip8);
+    let remove = move |cheat| {
+        remove_chip8.borrow_mut().remove_cheat(cheat);
+        store_cheats(&remove_chip8.borrow());
+        show_cheats(&remove_chip8);
+    };
+    let cheats = chip8.borrow().get_cheats().to_vec();
+    debug_panel::show_list("cheat-list", &cheats, Rc::new(remove));
+}
+
+fn store_cheats(chip8: &Chip8Emulator) {
+    if let Err(e) = storage::store_rom_cheats(chip8.get_rom_checksum(), chip8.get_cheats()) {
+        console::error_2(&"Can't store the cheats".into(), &e);
+    }
+}
+
+fn set_status(text: &str) {
+    get_element_by_id("cheat-status").set_text_content(Some(text));
+}
+
+fn get_input() -> HtmlInputElement {
+    get_element_by_id("cheat-input")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #cheat-input is not an input element")
+}
+
+fn get_mode_select() -> HtmlSelectElement {
+    get_element_by_id("cheat-mode")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #cheat-mode is not a select element")
+}
@@ -0,0 +1,169 @@
+use super::extension::Extension;
+use super::instruction::Instruction;
+use super::opcode::Opcode;
+use super::PROGRAM_MEMORY_START;
+use std::ops::Range;
+
+/// What a byte of a ROM is used as, as far as can be told without running
+/// it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ByteUse {
+    /// Part of an instruction reachable from the entry point.
+    Code,
+    /// Read through I, like a sprite, or before the entry point.
+    Data,
+    /// Neither, such as code never reached, or only through `JP V0, NNN`.
+    Unknown,
+}
+
+/// The uses of the bytes of a ROM loaded at 0x200.
+#[derive(Default)]
+pub struct Analysis {
+    uses: Vec<ByteUse>,
+    unreachable: Vec<Range<u16>>,
+}
+
+impl Analysis {
+    /// Returns the use of the byte at `address`, or `None` if it's outside
+    /// the ROM.
+    pub fn byte_use(&self, address: u16) -> Option<ByteUse> {
+        (address as usize)
+            .checked_sub(PROGRAM_MEMORY_START)
+            .and_then(|index| self.uses.get(index))
+            .copied()
+    }
+
+    /// Returns the parts of the ROM that are neither reachable code nor
+    /// data, leaving out zero padding.
+    pub fn unreachable(&self) -> &[Range<u16>] {
+        &self.unreachable
+    }
+}
+
+/// Finds the code in `rom` by following every path from `entry` through
+/// jumps, calls and skips, and the data from where I is pointed. Data runs
+/// from each address loaded into I up to the next code.
+pub fn analyze(rom: &[u8], entry: u16, hires: bool, extension: Option<Extension>) -> Analysis {
+    let mut uses = vec![ByteUse::Unknown; rom.len()];
+    let before_entry = (entry as usize).saturating_sub(PROGRAM_MEMORY_START);
+    for byte_use in uses.iter_mut().take(before_entry) {
+        *byte_use = ByteUse::Data;
+    }
+
+    let mut visited = vec![false; rom.len()];
+    let mut data_starts = Vec::new();
+    let mut pending = vec![entry];
+    while let Some(address) = pending.pop() {
+        let index = match (address as usize).checked_sub(PROGRAM_MEMORY_START) {
+            Some(index) if index + 1 < rom.len() && !visited[index] => index,
+            _ => continue,
+        };
+        visited[index] = true;
+        let opcode = Opcode::new(u16::from_be_bytes([rom[index], rom[index + 1]]));
+        let instruction = match Instruction::decode(&opcode, hires, extension) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        uses[index] = ByteUse::Code;
+        uses[index + 1] = ByteUse::Code;
+
+        let next = address + 2;
+        match instruction {
+            Instruction::ReturnSubroutine | Instruction::JumpToPlusV0(_) => {}
+            Instruction::JumpTo(nnn) => pending.push(nnn),
+            Instruction::ExecuteSubroutine(nnn) | Instruction::MachineSubroutine(nnn) => {
+                pending.extend(&[nnn, next])
+            }
+            Instruction::StoreAddress(nnn) => {
+                data_starts.push(nnn);
+                pending.push(next);
+            }
+            Instruction::SkipIfEq(..)
+            | Instruction::SkipIfNe(..)
+            | Instruction::SkipIfEqReg(..)
+            | Instruction::SkipIfNeReg(..)
+            | Instruction::SkipIfPressed(_)
+            | Instruction::SkipIfNotPressed(_)
+            | Instruction::SkipIfPressed2(_)
+            | Instruction::SkipIfNotPressed2(_) => pending.extend(&[next, next + 2]),
+            _ => pending.push(next),
+        }
+    }
+
+    for start in data_starts {
+        let start = (start as usize).saturating_sub(PROGRAM_MEMORY_START);
+        for byte_use in uses.iter_mut().skip(start) {
+            if *byte_use == ByteUse::Code {
+                break;
+            }
+            *byte_use = ByteUse::Data;
+        }
+    }
+
+    let mut unreachable = Vec::new();
+    let mut index = 0;
+    while index < uses.len() {
+        let length = uses[index..]
+            .iter()
+            .take_while(|&&byte_use| byte_use == ByteUse::Unknown)
+            .count();
+        if rom[index..index + length].iter().any(|&byte| byte != 0) {
+            let address = |index| (PROGRAM_MEMORY_START + index) as u16;
+            unreachable.push(address(index)..address(index + length));
+        }
+        index += length.max(1);
+    }
+
+    Analysis { uses, unreachable }
+}
+
+#[cfg(test)]
+// Unreachable parts are compared against lists of ranges.
+#[allow(clippy::single_range_in_vec_init)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze() {
+        let rom = [
+            0xa2, 0x14, // LD I, 0x214
+            0x22, 0x0c, // CALL 0x20C
+            0x30, 0x01, // SE V0, 0x01
+            0x12, 0x04, // JP 0x204
+            0x12, 0x08, // JP 0x208
+            0x00, 0x00, // padding
+            0x00, 0xee, // RET
+            0x60, 0x01, // LD V0, 0x01
+            0x12, 0x0e, // JP 0x20E
+            0x00, 0x00, //
+            0xff, 0x81, // sprite
+        ];
+        let analysis = analyze(&rom, 0x200, false, None);
+        let uses: Vec<_> = (0x200..0x218)
+            .step_by(2)
+            .map(|address| analysis.byte_use(address))
+            .collect();
+        assert_eq!(
+            uses,
+            [
+                Some(ByteUse::Code),
+                Some(ByteUse::Code),
+                Some(ByteUse::Code),
+                Some(ByteUse::Code),
+                Some(ByteUse::Code),
+                Some(ByteUse::Unknown),
+                Some(ByteUse::Code),
+                Some(ByteUse::Unknown),
+                Some(ByteUse::Unknown),
+                Some(ByteUse::Unknown),
+                Some(ByteUse::Data),
+                None,
+            ]
+        );
+        assert_eq!(analysis.unreachable(), [0x20e..0x214]);
+
+        let analysis = analyze(&rom, 0x20c, false, None);
+        assert_eq!(analysis.byte_use(0x20b), Some(ByteUse::Data));
+        assert_eq!(analysis.byte_use(0x20e), Some(ByteUse::Unknown));
+    }
+}
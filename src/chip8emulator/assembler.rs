@@ -0,0 +1,383 @@
+//! A small two-pass assembler for CHIP-8 programs written with the mnemonics
+//! from Cowgod's technical reference (`LD V0, 0x05`, `DRW V0, V1, 5`, ...).
+//!
+//! Each line holds an optional `label:`, then an instruction or a `DB`
+//! directive with comma-separated byte values. Comments start with `;`.
+//! Numbers can be decimal, hex (`0x1F` or `#1F`) or binary (`0b0110`), and
+//! labels can be used anywhere an address or value is expected.
+//!
+//! The CHIP-8X instructions are written `BGC`, `ADDN VX, VY`, `COL VX, VY, N`,
+//! `SKP2 VX` and `SKNP2 VX`, and the Mega-Chip ones `MEGAOFF`, `MEGAON`,
+//! `LDPAL NN`, `SPRW NN`, `SPRH NN` and `CCOL NN`.
+//!
+//! This file is also compiled into the build script, so it must not depend on
+//! anything else in the crate.
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Address the assembled program is loaded at.
+const ORIGIN: u16 = 0x200;
+
+#[derive(Debug, PartialEq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Operand {
+    Register(u8),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    Bcd,
+    Value(u16),
+}
+
+struct Statement<'a> {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+/// Assembles `source` into a ROM image meant to be loaded at 0x200.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    let mut labels = HashMap::new();
+    let mut statements = Vec::new();
+    let mut address = ORIGIN as usize;
+
+    for (index, line) in source.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message: String| AssembleError {
+            line: line_number,
+            message,
+        };
+
+        let mut code = line.split(';').next().unwrap().trim();
+        while let Some(colon) = code.find(':') {
+            let label = code[..colon].trim();
+            if !is_label(label) {
+                return Err(error(format!("invalid label `{}`", label)));
+            }
+            if labels.insert(label, address as u16).is_some() {
+                return Err(error(format!("duplicate label `{}`", label)));
+            }
+            code = code[colon + 1..].trim();
+        }
+
+        if code.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = match code.find(char::is_whitespace) {
+            Some(index) => (&code[..index], code[index..].trim()),
+            None => (code, ""),
+        };
+        let operands: Vec<&str> = if operands.is_empty() {
+            Vec::new()
+        } else {
+            operands.split(',').map(str::trim).collect()
+        };
+        let mnemonic = mnemonic.to_ascii_uppercase();
+
+        address += if mnemonic == "DB" { operands.len() } else { 2 };
+        if address > 0x1000 {
+            return Err(error("program does not fit in memory".to_string()));
+        }
+
+        statements.push(Statement {
+            line: line_number,
+            mnemonic,
+            operands,
+        });
+    }
+
+    let mut rom = Vec::with_capacity(address - ORIGIN as usize);
+    for statement in statements {
+        let error = |message: String| AssembleError {
+            line: statement.line,
+            message,
+        };
+
+        let operands = statement
+            .operands
+            .iter()
+            .map(|operand| parse_operand(operand, &labels))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(error)?;
+
+        if statement.mnemonic == "DB" {
+            for operand in operands {
+                rom.push(to_byte(operand).map_err(error)?);
+            }
+        } else {
+            let opcode = encode(&statement.mnemonic, &operands).map_err(error)?;
+            rom.push((opcode >> 8) as u8);
+            rom.push(opcode as u8);
+        }
+    }
+
+    Ok(rom)
+}
+
+fn encode(mnemonic: &str, operands: &[Operand]) -> Result<u16, String> {
+    use Operand::*;
+
+    let xy = |x: u8, y: u8| (x as u16) << 8 | (y as u16) << 4;
+    let x = |x: u8| (x as u16) << 8;
+
+    let opcode = match (mnemonic, operands) {
+        ("CLS", []) => 0x00e0,
+        ("RET", []) => 0x00ee,
+        ("SYS", [a]) => to_address(*a)?,
+        ("JP", [a]) => 0x1000 | to_address(*a)?,
+        ("JP", [Register(0), a]) => 0xb000 | to_address(*a)?,
+        ("CALL", [a]) => 0x2000 | to_address(*a)?,
+        ("SE", [Register(vx), Register(vy)]) => 0x5000 | xy(*vx, *vy),
+        ("SE", [Register(vx), b]) => 0x3000 | x(*vx) | to_byte(*b)? as u16,
+        ("SNE", [Register(vx), Register(vy)]) => 0x9000 | xy(*vx, *vy),
+        ("SNE", [Register(vx), b]) => 0x4000 | x(*vx) | to_byte(*b)? as u16,
+        ("LD", [Register(vx), Register(vy)]) => 0x8000 | xy(*vx, *vy),
+        ("LD", [Register(vx), DelayTimer]) => 0xf007 | x(*vx),
+        ("LD", [Register(vx), Key]) => 0xf00a | x(*vx),
+        ("LD", [Register(vx), IndirectI]) => 0xf065 | x(*vx),
+        ("LD", [Register(vx), b]) => 0x6000 | x(*vx) | to_byte(*b)? as u16,
+        ("LD", [I, a]) => 0xa000 | to_address(*a)?,
+        ("LD", [DelayTimer, Register(vx)]) => 0xf015 | x(*vx),
+        ("LD", [SoundTimer, Register(vx)]) => 0xf018 | x(*vx),
+        ("LD", [Font, Register(vx)]) => 0xf029 | x(*vx),
+        ("LD", [Bcd, Register(vx)]) => 0xf033 | x(*vx),
+        ("LD", [IndirectI, Register(vx)]) => 0xf055 | x(*vx),
+        ("ADD", [I, Register(vx)]) => 0xf01e | x(*vx),
+        ("ADD", [Register(vx), Register(vy)]) => 0x8004 | xy(*vx, *vy),
+        ("ADD", [Register(vx), b]) => 0x7000 | x(*vx) | to_byte(*b)? as u16,
+        ("OR", [Register(vx), Register(vy)]) => 0x8001 | xy(*vx, *vy),
+        ("AND", [Register(vx), Register(vy)]) => 0x8002 | xy(*vx, *vy),
+        ("XOR", [Register(vx), Register(vy)]) => 0x8003 | xy(*vx, *vy),
+        ("SUB", [Register(vx), Register(vy)]) => 0x8005 | xy(*vx, *vy),
+        ("SHR", [Register(vx)]) => 0x8006 | xy(*vx, *vx),
+        ("SHR", [Register(vx), Register(vy)]) => 0x8006 | xy(*vx, *vy),
+        ("SUBN", [Register(vx), Register(vy)]) => 0x8007 | xy(*vx, *vy),
+        ("SHL", [Register(vx)]) => 0x800e | xy(*vx, *vx),
+        ("SHL", [Register(vx), Register(vy)]) => 0x800e | xy(*vx, *vy),
+        ("RND", [Register(vx), b]) => 0xc000 | x(*vx) | to_byte(*b)? as u16,
+        ("DRW", [Register(vx), Register(vy), n]) => 0xd000 | xy(*vx, *vy) | to_nibble(*n)?,
+        ("SKP", [Register(vx)]) => 0xe09e | x(*vx),
+        ("SKNP", [Register(vx)]) => 0xe0a1 | x(*vx),
+        ("BGC", []) => 0x02a0,
+        ("ADDN", [Register(vx), Register(vy)]) => 0x5001 | xy(*vx, *vy),
+        ("COL", [Register(vx), Register(vy), n]) => 0xb000 | xy(*vx, *vy) | to_nibble(*n)?,
+        ("SKP2", [Register(vx)]) => 0xe0f2 | x(*vx),
+        ("SKNP2", [Register(vx)]) => 0xe0f5 | x(*vx),
+        ("MEGAOFF", []) => 0x0010,
+        ("MEGAON", []) => 0x0011,
+        ("LDPAL", [b]) => 0x0200 | to_byte(*b)? as u16,
+        ("SPRW", [b]) => 0x0300 | to_byte(*b)? as u16,
+        ("SPRH", [b]) => 0x0400 | to_byte(*b)? as u16,
+        ("CCOL", [b]) => 0x0900 | to_byte(*b)? as u16,
+        _ if is_mnemonic(mnemonic) => {
+            return Err(format!("invalid operands for {}", mnemonic));
+        }
+        _ => return Err(format!("unknown instruction `{}`", mnemonic)),
+    };
+
+    Ok(opcode)
+}
+
+fn parse_operand(text: &str, labels: &HashMap<&str, u16>) -> Result<Operand, String> {
+    let upper = text.to_ascii_uppercase();
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::DelayTimer,
+        "ST" => Operand::SoundTimer,
+        "K" => Operand::Key,
+        "F" => Operand::Font,
+        "B" => Operand::Bcd,
+        _ if is_register(&upper) => Operand::Register(u8::from_str_radix(&upper[1..], 16).unwrap()),
+        _ => match labels.get(text) {
+            Some(address) => Operand::Value(*address),
+            None => Operand::Value(parse_number(text)?),
+        },
+    };
+
+    Ok(operand)
+}
+
+fn parse_number(text: &str) -> Result<u16, String> {
+    let lower = text.to_ascii_lowercase();
+    let parsed = if let Some(hex) = lower.strip_prefix("0x").or_else(|| lower.strip_prefix('#')) {
+        u16::from_str_radix(hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u16::from_str_radix(binary, 2)
+    } else {
+        lower.parse()
+    };
+
+    parsed.map_err(|_| format!("`{}` is not a number or known label", text))
+}
+
+fn to_address(operand: Operand) -> Result<u16, String> {
+    match operand {
+        Operand::Value(value) if value <= 0xfff => Ok(value),
+        Operand::Value(value) => Err(format!("address {:#X} is out of range", value)),
+        _ => Err("expected an address".to_string()),
+    }
+}
+
+fn to_byte(operand: Operand) -> Result<u8, String> {
+    match operand {
+        Operand::Value(value) if value <= 0xff => Ok(value as u8),
+        Operand::Value(value) => Err(format!("value {:#X} does not fit in a byte", value)),
+        _ => Err("expected a byte value".to_string()),
+    }
+}
+
+fn to_nibble(operand: Operand) -> Result<u16, String> {
+    match operand {
+        Operand::Value(value) if value <= 0xf => Ok(value),
+        Operand::Value(value) => Err(format!("value {:#X} does not fit in a nibble", value)),
+        _ => Err("expected a nibble value".to_string()),
+    }
+}
+
+fn is_register(text: &str) -> bool {
+    text.len() == 2 && text.starts_with('V') && text.chars().nth(1).unwrap().is_ascii_hexdigit()
+}
+
+fn is_label(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !is_register(&text.to_ascii_uppercase())
+        && !["I", "DT", "ST", "K", "F", "B"].contains(&text.to_ascii_uppercase().as_str())
+}
+
+fn is_mnemonic(text: &str) -> bool {
+    [
+        "CLS", "RET", "SYS", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB",
+        "SHR", "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP", "BGC", "ADDN", "COL", "SKP2", "SKNP2",
+        "MEGAOFF", "MEGAON", "LDPAL", "SPRW", "SPRH", "CCOL",
+    ]
+    .contains(&text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_assemble_instructions() {
+        let source = "
+            CLS
+            RET
+            JP 0x234
+            JP V0, #234
+            CALL 564
+            SE V3, 0x12
+            SE v3, vA
+            SNE V3, 0b1010
+            SNE V3, VA
+            LD V5, 0xff
+            LD V5, V6
+            LD I, 0x300
+            LD V1, DT
+            LD V1, K
+            LD DT, V1
+            LD ST, V1
+            LD F, V1
+            LD B, V1
+            LD [I], V1
+            LD V1, [I]
+            ADD V2, 1
+            ADD V2, V3
+            ADD I, V2
+            OR V1, V2
+            AND V1, V2
+            XOR V1, V2
+            SUB V1, V2
+            SHR V1
+            SUBN V1, V2
+            SHL V1, V2
+            RND V4, 0x0f
+            DRW V1, V2, 5
+            SKP V7
+            SKNP V7
+        ";
+
+        let expected: [u16; 34] = [
+            0x00e0, 0x00ee, 0x1234, 0xb234, 0x2234, 0x3312, 0x53a0, 0x430a, 0x93a0, 0x65ff, 0x8560,
+            0xa300, 0xf107, 0xf10a, 0xf115, 0xf118, 0xf129, 0xf133, 0xf155, 0xf165, 0x7201, 0x8234,
+            0xf21e, 0x8121, 0x8122, 0x8123, 0x8125, 0x8116, 0x8127, 0x812e, 0xc40f, 0xd125, 0xe79e,
+            0xe7a1,
+        ];
+
+        let rom = assemble(source).unwrap();
+        let opcodes: Vec<u16> = rom
+            .chunks(2)
+            .map(|pair| (pair[0] as u16) << 8 | pair[1] as u16)
+            .collect();
+        assert_eq!(opcodes, expected);
+    }
+
+    #[test]
+    fn test_assemble_labels_and_data() {
+        let source = "
+            start:  LD I, sprite   ; forward reference
+                    JP start
+            sprite: DB 0xF0, 0x90, 0b11110000
+        ";
+
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0xa2, 0x04, 0x12, 0x00, 0xf0, 0x90, 0xf0]
+        );
+    }
+
+    #[test]
+    fn test_assemble_errors() {
+        assert_eq!(
+            assemble("CLS\nFOO V1").unwrap_err(),
+            AssembleError {
+                line: 2,
+                message: "unknown instruction `FOO`".to_string()
+            }
+        );
+        assert_eq!(
+            assemble("LD V1").unwrap_err().message,
+            "invalid operands for LD"
+        );
+        assert_eq!(
+            assemble("ADD V1, 256").unwrap_err().message,
+            "value 0x100 does not fit in a byte"
+        );
+        assert_eq!(
+            assemble("JP nowhere").unwrap_err().message,
+            "`nowhere` is not a number or known label"
+        );
+        assert_eq!(
+            assemble("a: CLS\na: CLS").unwrap_err().message,
+            "duplicate label `a`"
+        );
+        assert_eq!(
+            assemble("VA: CLS").unwrap_err().message,
+            "invalid label `VA`"
+        );
+        assert_eq!(
+            assemble("dt: CLS").unwrap_err().message,
+            "invalid label `dt`"
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+
+/// Calls from one subroutine to another, or from the main program when
+/// `caller` is `None`, and how many times they were made.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Call {
+    pub caller: Option<u16>,
+    pub address: u16,
+    pub count: u64,
+}
+
+/// The subroutine calls a program has made, by the subroutine each was made
+/// from, which together make up its call tree.
+#[derive(Default)]
+pub struct CallGraph {
+    /// The start addresses of the subroutines being executed, innermost last.
+    running: Vec<u16>,
+    counts: BTreeMap<(Option<u16>, u16), u64>,
+}
+
+impl CallGraph {
+    /// Records a call to the subroutine at `address` from the one running.
+    pub fn call(&mut self, address: u16) {
+        let caller = self.running.last().copied();
+        *self.counts.entry((caller, address)).or_insert(0) += 1;
+        self.running.push(address);
+    }
+
+    pub fn ret(&mut self) {
+        self.running.pop();
+    }
+
+    /// Forgets which subroutines are running, e.g. after restoring a state,
+    /// so calls are counted as from the main program until they return.
+    pub fn forget_running(&mut self) {
+        self.running.clear();
+    }
+
+    /// Returns the calls made, ordered by caller, the main program first,
+    /// then by address.
+    pub fn calls(&self) -> Vec<Call> {
+        self.counts
+            .iter()
+            .map(|(&(caller, address), &count)| Call {
+                caller,
+                address,
+                count,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_call_graph() {
+        let mut graph = CallGraph::default();
+        for _ in 0..2 {
+            graph.call(0x240);
+            graph.call(0x300);
+            graph.ret();
+            graph.ret();
+        }
+        graph.call(0x300);
+        graph.ret();
+        assert_eq!(
+            graph.calls(),
+            [
+                Call {
+                    caller: None,
+                    address: 0x240,
+                    count: 2,
+                },
+                Call {
+                    caller: None,
+                    address: 0x300,
+                    count: 1,
+                },
+                Call {
+                    caller: Some(0x240),
+                    address: 0x300,
+                    count: 2,
+                },
+            ]
+        );
+
+        graph.call(0x240);
+        graph.forget_running();
+        graph.call(0x300);
+        assert_eq!(graph.calls()[1].count, 2);
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt;
+
+/// Whether a cheat keeps its byte at its value or writes it once.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CheatMode {
+    /// Written every tick, so the ROM can't change it, e.g. to keep lives.
+    Freeze,
+    /// Written when the cheat is added or the ROM loads, e.g. to patch the
+    /// code that moves on to the next level.
+    Once,
+}
+
+/// A byte of memory a cheat sets.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cheat {
+    pub address: u16,
+    pub value: u8,
+    pub mode: CheatMode,
+}
+
+impl Cheat {
+    /// Parses a cheat written as `E00=03`, in hex, with ` once` after it for
+    /// one written once rather than frozen.
+    pub fn parse(value: &str) -> Option<Cheat> {
+        let value = value.trim();
+        let (cheat, mode) = match value.strip_suffix("once") {
+            Some(cheat) => (cheat, CheatMode::Once),
+            None => (value, CheatMode::Freeze),
+        };
+        let (address, value) = cheat.split_once('=')?;
+        let parse_hex = |text: &str| {
+            let text = text.trim();
+            let digits = text
+                .strip_prefix("0x")
+                .or_else(|| text.strip_prefix("0X"))
+                .unwrap_or(text);
+            u16::from_str_radix(digits, 16).ok()
+        };
+        let address = parse_hex(address).filter(|&address| address < 0x1000)?;
+        let value = parse_hex(value).filter(|&value| value <= 0xff)? as u8;
+        Some(Cheat {
+            address,
+            value,
+            mode,
+        })
+    }
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:03X}={:02X}", self.address, self.value)?;
+        if self.mode == CheatMode::Once {
+            write!(f, " once")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let freeze = Cheat {
+            address: 0xe00,
+            value: 3,
+            mode: CheatMode::Freeze,
+        };
+        assert_eq!(Cheat::parse("E00=03"), Some(freeze));
+        assert_eq!(Cheat::parse(" 0xe00 = 3 "), Some(freeze));
+        assert_eq!(freeze.to_string(), "E00=03");
+
+        let once = Cheat::parse("2A4=12 once").unwrap();
+        assert_eq!(once.mode, CheatMode::Once);
+        assert_eq!(once.to_string(), "2A4=12 once");
+        assert_eq!(Cheat::parse(&once.to_string()), Some(once));
+
+        assert_eq!(Cheat::parse("E00"), None);
+        assert_eq!(Cheat::parse("1000=03"), None);
+        assert_eq!(Cheat::parse("E00=100"), None);
+    }
+}
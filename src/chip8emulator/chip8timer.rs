@@ -14,10 +14,33 @@ impl Chip8Timer {
     }
 
     pub fn step(&mut self, current_time: f64) {
-        let ticks = self.timer.step(current_time).min(std::u8::MAX as u32);
+        let ticks = self.timer.step(current_time).min(u8::MAX as u32);
         self.value = self.value.saturating_sub(ticks as u8);
     }
 
+    /// Lets time pass up to `current_time` without counting down, for while
+    /// the emulator is paused.
+    pub fn skip(&mut self, current_time: f64) {
+        self.timer.step(current_time);
+    }
+
+    /// Counts down `duration` more milliseconds at the next step.
+    pub fn add_time(&mut self, duration: f64) {
+        self.timer.add_time(duration);
+    }
+
+    /// How far the timer is toward its next decrement, from 0 to 1.
+    pub fn phase(&self) -> f64 {
+        self.timer.phase()
+    }
+
+    /// Sets the value and the phase toward the next decrement, starting from
+    /// `current_time`.
+    pub fn restore(&mut self, value: u8, phase: f64, current_time: f64) {
+        self.value = value;
+        self.timer.set_phase(phase, current_time);
+    }
+
     pub fn value(&self) -> u8 {
         self.value
     }
@@ -0,0 +1,133 @@
+use super::opcode::Opcode;
+
+/// The number of CPU clock cycles each kind of instruction takes to execute.
+/// Together with the clock speed this determines how many instructions run
+/// per second.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CostModel {
+    /// Jumps, calls, returns, key waits and anything not listed below.
+    pub default: u32,
+    /// 00E0
+    pub clear_screen: u32,
+    /// 3XNN, 4XNN, 5XY0, 9XY0, EX9E and EXA1.
+    pub skip: u32,
+    /// 6XNN
+    pub load_immediate: u32,
+    /// 7XNN
+    pub add_immediate: u32,
+    /// 8XYN
+    pub register_op: u32,
+    /// ANNN
+    pub set_index: u32,
+    /// CXNN
+    pub random: u32,
+    /// DXYN
+    pub draw_sprite: u32,
+    /// FX07, FX15 and FX18.
+    pub timer: u32,
+    /// FX1E
+    pub add_index: u32,
+    /// FX29
+    pub font: u32,
+    /// FX33
+    pub bcd: u32,
+    /// FX55 and FX65.
+    pub load_store: u32,
+}
+
+impl CostModel {
+    /// Every instruction takes one cycle, so the clock speed is simply the
+    /// number of instructions per second.
+    pub fn uniform() -> CostModel {
+        CostModel {
+            default: 1,
+            clear_screen: 1,
+            skip: 1,
+            load_immediate: 1,
+            add_immediate: 1,
+            register_op: 1,
+            set_index: 1,
+            random: 1,
+            draw_sprite: 1,
+            timer: 1,
+            add_index: 1,
+            font: 1,
+            bcd: 1,
+            load_store: 1,
+        }
+    }
+
+    /// Approximate costs of the original COSMAC VIP interpreter, in cycles of
+    /// its 1.76 MHz clock. Sprite drawing includes the average wait for the
+    /// display interrupt, which is why it dominates everything else.
+    pub fn cosmac_vip() -> CostModel {
+        CostModel {
+            default: 185,
+            clear_screen: 192,
+            skip: 113,
+            load_immediate: 48,
+            add_immediate: 79,
+            register_op: 352,
+            set_index: 97,
+            random: 289,
+            draw_sprite: 40_036,
+            timer: 79,
+            add_index: 151,
+            font: 160,
+            bcd: 1632,
+            load_store: 1065,
+        }
+    }
+
+    /// Returns the cost of `opcode`, which is always at least one cycle.
+    pub fn cycles(&self, opcode: &Opcode) -> u32 {
+        let cycles = match opcode.get_nibble(0) {
+            0 if opcode.get_nibbles_from(1) == 0x0e0 => self.clear_screen,
+            3 | 4 | 5 | 9 | 0xe => self.skip,
+            6 => self.load_immediate,
+            7 => self.add_immediate,
+            8 => self.register_op,
+            0xa => self.set_index,
+            0xc => self.random,
+            0xd => self.draw_sprite,
+            0xf => match opcode.get_nibbles_from(2) {
+                0x07 | 0x15 | 0x18 => self.timer,
+                0x1e => self.add_index,
+                0x29 => self.font,
+                0x33 => self.bcd,
+                0x55 | 0x65 => self.load_store,
+                _ => self.default,
+            },
+            _ => self.default,
+        };
+        cycles.max(1)
+    }
+}
+
+impl Default for CostModel {
+    fn default() -> CostModel {
+        CostModel::uniform()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycles() {
+        let vip = CostModel::cosmac_vip();
+        assert_eq!(vip.cycles(&Opcode::new(0x00e0)), vip.clear_screen);
+        assert_eq!(vip.cycles(&Opcode::new(0x00ee)), vip.default);
+        assert_eq!(vip.cycles(&Opcode::new(0x9120)), vip.skip);
+        assert_eq!(vip.cycles(&Opcode::new(0xd125)), vip.draw_sprite);
+        assert_eq!(vip.cycles(&Opcode::new(0xf315)), vip.timer);
+        assert_eq!(vip.cycles(&Opcode::new(0xf30a)), vip.default);
+
+        let free = CostModel {
+            draw_sprite: 0,
+            ..CostModel::uniform()
+        };
+        assert_eq!(free.cycles(&Opcode::new(0xd125)), 1);
+    }
+}
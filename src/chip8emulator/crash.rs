@@ -0,0 +1,113 @@
+use std::fmt::Write;
+
+/// How many bytes of memory before and after the faulting instruction a
+/// crash report keeps.
+pub const CRASH_MEMORY_RADIUS: u16 = 32;
+
+/// The machine state when an instruction faulted, e.g. an unknown opcode or
+/// a return with nothing on the stack, to download and look into later.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrashReport {
+    /// What went wrong, e.g. `Invalid instruction 0000`.
+    pub reason: String,
+    /// Where the faulting instruction is.
+    pub address: u16,
+    pub opcode: u16,
+    pub registers: [u8; 16],
+    pub i: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// The return addresses on the stack, the innermost last.
+    pub stack: Vec<u16>,
+    pub instruction_count: u64,
+    /// The memory around the faulting instruction, starting at
+    /// `memory_start`.
+    pub memory_start: u16,
+    pub memory: Vec<u8>,
+    /// The instructions executed before it, oldest first, disassembled.
+    pub trace: Vec<String>,
+}
+
+impl CrashReport {
+    /// Writes the report as a JSON object, with camelCase keys and numbers
+    /// for the values.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\n");
+        let _ = writeln!(json, "  \"reason\": {},", quote(&self.reason));
+        let _ = writeln!(json, "  \"address\": {},", self.address);
+        let _ = writeln!(json, "  \"opcode\": {},", self.opcode);
+        let _ = writeln!(json, "  \"registers\": [{}],", numbers(&self.registers));
+        let _ = writeln!(json, "  \"i\": {},", self.i);
+        let _ = writeln!(json, "  \"delayTimer\": {},", self.delay_timer);
+        let _ = writeln!(json, "  \"soundTimer\": {},", self.sound_timer);
+        let _ = writeln!(json, "  \"stack\": [{}],", numbers(&self.stack));
+        let _ = writeln!(json, "  \"instructionCount\": {},", self.instruction_count);
+        let _ = writeln!(json, "  \"memoryStart\": {},", self.memory_start);
+        let _ = writeln!(json, "  \"memory\": [{}],", numbers(&self.memory));
+        let trace: Vec<_> = self.trace.iter().map(|line| quote(line)).collect();
+        let _ = writeln!(json, "  \"trace\": [{}]", trace.join(", "));
+        json.push('}');
+        json
+    }
+}
+
+/// Returns `values` as the items of a JSON array.
+fn numbers<T: ToString>(values: &[T]) -> String {
+    values
+        .iter()
+        .map(|value| value.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Returns `text` as a JSON string.
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        let mut registers = [0; 16];
+        registers[0xf] = 1;
+        let report = CrashReport {
+            reason: "Invalid instruction \"0000\"".into(),
+            address: 0x204,
+            opcode: 0,
+            registers,
+            i: 0x300,
+            delay_timer: 3,
+            sound_timer: 0,
+            stack: vec![0x202],
+            instruction_count: 2,
+            memory_start: 0x202,
+            memory: vec![0x00, 0xe0],
+            trace: vec!["200  2204  CALL 0x204".into()],
+        };
+        assert_eq!(
+            report.to_json(),
+            "{\n  \"reason\": \"Invalid instruction \\\"0000\\\"\",\n  \"address\": 516,\n  \
+             \"opcode\": 0,\n  \"registers\": [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],\n  \
+             \"i\": 768,\n  \"delayTimer\": 3,\n  \"soundTimer\": 0,\n  \"stack\": [514],\n  \
+             \"instructionCount\": 2,\n  \"memoryStart\": 514,\n  \"memory\": [0, 224],\n  \
+             \"trace\": [\"200  2204  CALL 0x204\"]\n}"
+        );
+        assert_eq!(quote("a\tb"), "\"a\\u0009b\"");
+    }
+}
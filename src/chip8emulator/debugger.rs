@@ -0,0 +1,499 @@
+use super::expression::{Expression, Machine};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// Size of the CHIP-8 address space. Watched memory must lie inside it.
+const MEMORY_SIZE: u32 = 0x1000;
+
+/// Memory or a register that pauses execution when an instruction changes it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Watchpoint {
+    /// Addresses `start..=end`.
+    Memory { start: u16, end: u16 },
+    /// Register VX.
+    Register(u8),
+}
+
+impl Watchpoint {
+    /// Parses a register such as `VA`, an address such as `E00` or an
+    /// inclusive range such as `E00-E0F`, in hex.
+    pub fn parse(value: &str) -> Option<Watchpoint> {
+        let value = value.trim();
+        if let Some(register) = value.strip_prefix(['V', 'v']) {
+            return match u8::from_str_radix(register, 16) {
+                Ok(x) if register.len() == 1 => Some(Watchpoint::Register(x)),
+                _ => None,
+            };
+        }
+        let parse_address = |address: &str| {
+            let address = address.trim();
+            let digits = address
+                .strip_prefix("0x")
+                .or_else(|| address.strip_prefix("0X"))
+                .unwrap_or(address);
+            u16::from_str_radix(digits, 16).ok()
+        };
+        let mut bounds = value.splitn(2, '-');
+        let start = parse_address(bounds.next().unwrap())?;
+        let end = match bounds.next() {
+            Some(end) => parse_address(end)?,
+            None => start,
+        };
+        if start <= end && (end as u32) < MEMORY_SIZE {
+            Some(Watchpoint::Memory { start, end })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the watched bytes of `memory` or `registers`.
+    fn values<'a>(&self, memory: &'a [u8], registers: &'a [u8]) -> &'a [u8] {
+        match *self {
+            Watchpoint::Memory { start, end } => &memory[start as usize..=end as usize],
+            Watchpoint::Register(x) => &registers[x as usize..=x as usize],
+        }
+    }
+
+    /// Returns the location of the watched byte at `offset`.
+    fn location(&self, offset: usize) -> WatchLocation {
+        match *self {
+            Watchpoint::Memory { start, .. } => WatchLocation::Memory(start + offset as u16),
+            Watchpoint::Register(x) => WatchLocation::Register(x),
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Watchpoint::Memory { start, end } if start == end => write!(f, "{:03X}", start),
+            Watchpoint::Memory { start, end } => write!(f, "{:03X}-{:03X}", start, end),
+            Watchpoint::Register(x) => write!(f, "V{:X}", x),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchLocation {
+    Memory(u16),
+    Register(u8),
+}
+
+impl fmt::Display for WatchLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            WatchLocation::Memory(address) => write!(f, "{:03X}", address),
+            WatchLocation::Register(x) => write!(f, "V{:X}", x),
+        }
+    }
+}
+
+/// A change to a watched location by the instruction at `pc`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatchHit {
+    pub pc: u16,
+    pub location: WatchLocation,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// An opcode pattern as the opcode reference writes them, such as `DXYN` or
+/// `FX0A`: hex digits must match, and X, Y, N or K match any digit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OpcodePattern {
+    mask: u16,
+    value: u16,
+}
+
+impl OpcodePattern {
+    pub fn parse(value: &str) -> Option<OpcodePattern> {
+        let value = value.trim();
+        if value.chars().count() != 4 {
+            return None;
+        }
+        let mut pattern = OpcodePattern { mask: 0, value: 0 };
+        for (position, digit) in value.chars().enumerate() {
+            let shift = 12 - 4 * position;
+            match digit.to_ascii_uppercase() {
+                'X' | 'Y' | 'N' | 'K' => {}
+                digit => {
+                    pattern.mask |= 0xf << shift;
+                    pattern.value |= (digit.to_digit(16)? as u16) << shift;
+                }
+            }
+        }
+        Some(pattern)
+    }
+
+    pub fn matches(&self, opcode: u16) -> bool {
+        opcode & self.mask == self.value
+    }
+}
+
+/// Writes the pattern with its operands named after their position, as the
+/// opcode reference does.
+impl fmt::Display for OpcodePattern {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for position in 0..4 {
+            let shift = 12 - 4 * position;
+            if self.mask >> shift & 0xf != 0 {
+                write!(f, "{:X}", self.value >> shift & 0xf)?;
+            } else {
+                let operand = match position {
+                    1 => 'X',
+                    2 => 'Y',
+                    _ => 'N',
+                };
+                write!(f, "{}", operand)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Execution control for debugging: whether the emulator is paused, the
+/// addresses, opcodes and conditions it pauses at before executing, and the
+/// locations it pauses after changing.
+#[derive(Default)]
+pub struct Debugger {
+    paused: bool,
+    breakpoints: BTreeSet<u16>,
+    opcode_breaks: Vec<OpcodePattern>,
+    /// Expressions that pause execution when they aren't 0.
+    conditions: Vec<Expression>,
+    /// The condition that paused execution last, until it resumes.
+    condition_hit: Option<Expression>,
+    /// Set when resuming so the breakpoint at the current address, which is
+    /// usually the one that paused execution, doesn't immediately pause again.
+    skip_breakpoint: bool,
+    watchpoints: Vec<Watchpoint>,
+    /// The changes that paused execution last, until it resumes.
+    watch_hits: Vec<WatchHit>,
+    /// The stack depth to pause at once the stack is no deeper, when stepping
+    /// over or out of a subroutine.
+    stop_depth: Option<usize>,
+}
+
+impl Debugger {
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.stop_depth = None;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.skip_breakpoint = true;
+        self.watch_hits.clear();
+        self.condition_hit = None;
+        self.stop_depth = None;
+    }
+
+    /// Resumes until an instruction leaves the stack `depth` deep or less.
+    pub fn run_to_depth(&mut self, depth: usize) {
+        self.resume();
+        self.stop_depth = Some(depth);
+    }
+
+    /// Called after executing an instruction with the stack `depth` deep.
+    /// Pauses if running to that depth or a shallower one.
+    pub fn check_depth(&mut self, depth: usize) {
+        if self
+            .stop_depth
+            .is_some_and(|stop_depth| depth <= stop_depth)
+        {
+            self.pause();
+        }
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Removes the breakpoint at `address` and returns whether there was one.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn add_opcode_break(&mut self, pattern: OpcodePattern) {
+        if !self.opcode_breaks.contains(&pattern) {
+            self.opcode_breaks.push(pattern);
+        }
+    }
+
+    /// Removes `pattern` and returns whether it was there.
+    pub fn remove_opcode_break(&mut self, pattern: OpcodePattern) -> bool {
+        let count = self.opcode_breaks.len();
+        self.opcode_breaks.retain(|&watched| watched != pattern);
+        self.opcode_breaks.len() != count
+    }
+
+    pub fn opcode_breaks(&self) -> &[OpcodePattern] {
+        &self.opcode_breaks
+    }
+
+    pub fn add_condition(&mut self, condition: Expression) {
+        if !self.conditions.contains(&condition) {
+            self.conditions.push(condition);
+        }
+    }
+
+    /// Removes `condition` and returns whether it was there.
+    pub fn remove_condition(&mut self, condition: &Expression) -> bool {
+        let count = self.conditions.len();
+        self.conditions.retain(|watched| watched != condition);
+        self.conditions.len() != count
+    }
+
+    pub fn conditions(&self) -> &[Expression] {
+        &self.conditions
+    }
+
+    pub fn condition_hit(&self) -> Option<&Expression> {
+        self.condition_hit.as_ref()
+    }
+
+    /// Returns the index of the first condition that isn't 0 for `machine`.
+    /// Conditions that can't be evaluated, e.g. dividing by 0, don't count.
+    pub fn met_condition(&self, machine: &Machine) -> Option<usize> {
+        self.conditions
+            .iter()
+            .position(|condition| condition.evaluate(machine).is_ok_and(|value| value != 0))
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    /// Removes `watchpoint` and returns whether it was there.
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let count = self.watchpoints.len();
+        self.watchpoints.retain(|&watched| watched != watchpoint);
+        self.watchpoints.len() != count
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn watch_hits(&self) -> &[WatchHit] {
+        &self.watch_hits
+    }
+
+    /// Returns the watched bytes, to compare with after an instruction, or
+    /// `None` if nothing is watched.
+    pub fn watched_values(&self, memory: &[u8], registers: &[u8]) -> Option<Vec<u8>> {
+        if self.watchpoints.is_empty() {
+            return None;
+        }
+        Some(
+            self.watchpoints
+                .iter()
+                .flat_map(|watchpoint| watchpoint.values(memory, registers))
+                .copied()
+                .collect(),
+        )
+    }
+
+    /// Called after executing the instruction at `pc` with the watched bytes
+    /// from before, `before`. Pauses and returns true if any changed.
+    pub fn check_watchpoints(
+        &mut self,
+        pc: u16,
+        before: &[u8],
+        memory: &[u8],
+        registers: &[u8],
+    ) -> bool {
+        let mut hits = Vec::new();
+        let mut start = 0;
+        for watchpoint in &self.watchpoints {
+            let values = watchpoint.values(memory, registers);
+            let old_values = &before[start..start + values.len()];
+            start += values.len();
+            for (offset, (&old, &new)) in old_values.iter().zip(values).enumerate() {
+                if old != new {
+                    let location = watchpoint.location(offset);
+                    hits.push(WatchHit {
+                        pc,
+                        location,
+                        old,
+                        new,
+                    });
+                }
+            }
+        }
+        if hits.is_empty() {
+            return false;
+        }
+        self.watch_hits = hits;
+        self.paused = true;
+        true
+    }
+
+    /// Called before executing `opcode` at `pc`, with the condition that
+    /// holds from `met_condition`. Pauses and returns true if there is a
+    /// breakpoint there, the opcode matches a pattern to break on, or a
+    /// condition holds.
+    pub fn should_break(&mut self, pc: u16, opcode: u16, met_condition: Option<usize>) -> bool {
+        let skip = std::mem::take(&mut self.skip_breakpoint);
+        let matches = |pattern: &OpcodePattern| pattern.matches(opcode);
+        if skip {
+            return self.paused;
+        }
+        if self.breakpoints.contains(&pc) || self.opcode_breaks.iter().any(matches) {
+            self.paused = true;
+        }
+        if let Some(index) = met_condition {
+            self.condition_hit = Some(self.conditions[index].clone());
+            self.paused = true;
+        }
+        self.paused
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = Debugger::default();
+        debugger.add_breakpoint(0x204);
+        debugger.add_breakpoint(0x200);
+        assert_eq!(debugger.breakpoints().collect::<Vec<_>>(), [0x200, 0x204]);
+
+        assert!(!debugger.should_break(0x202, 0, None));
+        assert!(debugger.should_break(0x204, 0, None));
+        assert!(debugger.is_paused());
+
+        debugger.resume();
+        assert!(!debugger.should_break(0x204, 0, None));
+        assert!(!debugger.should_break(0x206, 0, None));
+        assert!(debugger.should_break(0x204, 0, None));
+
+        assert!(debugger.remove_breakpoint(0x204));
+        assert!(!debugger.remove_breakpoint(0x204));
+        debugger.resume();
+        assert!(!debugger.should_break(0x204, 0, None));
+    }
+
+    #[test]
+    fn test_opcode_breaks() {
+        let draw = OpcodePattern::parse("DXYN").unwrap();
+        assert!(draw.matches(0xd015));
+        assert!(!draw.matches(0xc015));
+        let wait_key = OpcodePattern::parse("fx0a").unwrap();
+        assert_eq!(wait_key.to_string(), "FX0A");
+        assert!(wait_key.matches(0xf30a));
+        assert!(!wait_key.matches(0xf31e));
+        assert_eq!(OpcodePattern::parse("DXY"), None);
+        assert_eq!(OpcodePattern::parse("DXYZ"), None);
+
+        let mut debugger = Debugger::default();
+        debugger.add_opcode_break(draw);
+        assert!(!debugger.should_break(0x200, 0x6005, None));
+        assert!(debugger.should_break(0x202, 0xd015, None));
+        debugger.resume();
+        assert!(!debugger.should_break(0x202, 0xd015, None));
+        assert!(debugger.remove_opcode_break(draw));
+        assert!(!debugger.should_break(0x204, 0xd015, None));
+    }
+
+    #[test]
+    fn test_conditions() {
+        let condition = Expression::parse("V[3] > 4").unwrap();
+        let mut debugger = Debugger::default();
+        debugger.add_condition(condition.clone());
+        debugger.add_condition(Expression::parse("1 / V0").unwrap());
+        debugger.add_condition(condition.clone());
+        assert_eq!(debugger.conditions().len(), 2);
+
+        let memory = [0u8; 0x1000];
+        let registers = [0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        let mut machine = Machine {
+            memory: &memory,
+            registers: &[0; 16],
+            i: 0,
+            pc: 0x200,
+            sp: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+        };
+        assert_eq!(debugger.met_condition(&machine), None);
+        assert!(!debugger.should_break(0x200, 0, None));
+
+        machine.registers = &registers;
+        let met = debugger.met_condition(&machine);
+        assert_eq!(met, Some(0));
+        assert!(debugger.should_break(0x202, 0, met));
+        assert_eq!(debugger.condition_hit(), Some(&condition));
+
+        debugger.resume();
+        assert_eq!(debugger.condition_hit(), None);
+        assert!(!debugger.should_break(0x202, 0, met));
+        assert!(debugger.remove_condition(&condition));
+        assert!(!debugger.remove_condition(&condition));
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        assert_eq!(Watchpoint::parse("va"), Some(Watchpoint::Register(0xa)));
+        let range = Watchpoint::parse("E00-E01").unwrap();
+        assert_eq!(range.to_string(), "E00-E01");
+        assert_eq!(Watchpoint::parse("0x300").unwrap().to_string(), "300");
+        assert_eq!(Watchpoint::parse("V10"), None);
+        assert_eq!(Watchpoint::parse("F00-1000"), None);
+
+        let mut debugger = Debugger::default();
+        let mut memory = [0u8; 0x1000];
+        let mut registers = [0u8; 16];
+        assert_eq!(debugger.watched_values(&memory, &registers), None);
+        debugger.add_watchpoint(range);
+        debugger.add_watchpoint(Watchpoint::Register(3));
+        debugger.add_watchpoint(Watchpoint::Register(3));
+        assert_eq!(debugger.watchpoints().len(), 2);
+
+        // Writing the same value isn't a change.
+        let before = debugger.watched_values(&memory, &registers).unwrap();
+        registers[2] = 7;
+        assert!(!debugger.check_watchpoints(0x200, &before, &memory, &registers));
+        assert!(!debugger.is_paused());
+
+        let before = debugger.watched_values(&memory, &registers).unwrap();
+        memory[0xe01] = 5;
+        registers[3] = 9;
+        assert!(debugger.check_watchpoints(0x202, &before, &memory, &registers));
+        assert!(debugger.is_paused());
+        assert_eq!(
+            debugger.watch_hits(),
+            [
+                WatchHit {
+                    pc: 0x202,
+                    location: WatchLocation::Memory(0xe01),
+                    old: 0,
+                    new: 5,
+                },
+                WatchHit {
+                    pc: 0x202,
+                    location: WatchLocation::Register(3),
+                    old: 0,
+                    new: 9,
+                },
+            ]
+        );
+
+        debugger.resume();
+        assert!(debugger.watch_hits().is_empty());
+        assert!(debugger.remove_watchpoint(range));
+        assert!(!debugger.remove_watchpoint(range));
+    }
+}
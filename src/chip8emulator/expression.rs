@@ -0,0 +1,370 @@
+use std::fmt;
+
+/// The machine state an expression reads.
+pub struct Machine<'a> {
+    pub memory: &'a [u8],
+    pub registers: &'a [u8],
+    pub i: u16,
+    pub pc: u16,
+    pub sp: usize,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Field {
+    I,
+    Pc,
+    Sp,
+    DelayTimer,
+    SoundTimer,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum UnaryOp {
+    Negate,
+    Not,
+    Complement,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Xor,
+    Or,
+    LogicalAnd,
+    LogicalOr,
+}
+
+/// The binary operators with their precedence, higher binding tighter, as
+/// in C. Longer operators come before their prefixes.
+const BINARY_OPERATORS: &[(&str, BinaryOp, u8)] = &[
+    ("||", BinaryOp::LogicalOr, 1),
+    ("&&", BinaryOp::LogicalAnd, 2),
+    ("==", BinaryOp::Eq, 6),
+    ("!=", BinaryOp::Ne, 6),
+    ("<=", BinaryOp::Le, 7),
+    (">=", BinaryOp::Ge, 7),
+    ("<<", BinaryOp::Shl, 8),
+    (">>", BinaryOp::Shr, 8),
+    ("|", BinaryOp::Or, 3),
+    ("^", BinaryOp::Xor, 4),
+    ("&", BinaryOp::And, 5),
+    ("<", BinaryOp::Lt, 7),
+    (">", BinaryOp::Gt, 7),
+    ("+", BinaryOp::Add, 9),
+    ("-", BinaryOp::Sub, 9),
+    ("*", BinaryOp::Mul, 10),
+    ("/", BinaryOp::Div, 10),
+    ("%", BinaryOp::Rem, 10),
+];
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Number(u32),
+    Register(u8),
+    Field(Field),
+    /// `V[index]`.
+    IndexedRegister(Box<Node>),
+    /// `mem[address]`.
+    Memory(Box<Node>),
+    Unary(UnaryOp, Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+}
+
+/// An expression over the machine state, such as `V[3] + I` or
+/// `mem[I+1] & 0x0F`, for watching values and breaking on conditions.
+///
+/// It reads registers `V0`-`VF` or `V[n]`, `I`, `PC`, `SP`, the timers `DT`
+/// and `ST`, and bytes of memory as `mem[address]`, with numbers in decimal,
+/// hex (`0x1F`) or binary (`0b0110`) and C's operators and precedence.
+/// Values are unsigned 32-bit and wrap; comparisons and `!`, `&&` and `||`
+/// give 1 for true and 0 for false.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expression {
+    text: String,
+    node: Node,
+}
+
+impl Expression {
+    pub fn parse(text: &str) -> Result<Expression, String> {
+        let text = text.trim();
+        let mut parser = Parser { rest: text };
+        let node = parser.parse_binary(0)?;
+        if !parser.rest.trim().is_empty() {
+            return Err(format!("unexpected `{}`", parser.rest.trim()));
+        }
+        Ok(Expression {
+            text: text.to_string(),
+            node,
+        })
+    }
+
+    /// Returns the value of the expression for `machine`, or why it has none,
+    /// e.g. reading past the end of memory.
+    pub fn evaluate(&self, machine: &Machine) -> Result<u32, String> {
+        evaluate(&self.node, machine)
+    }
+}
+
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+struct Parser<'a> {
+    rest: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    /// Parses operands joined by operators binding tighter than
+    /// `min_precedence`.
+    fn parse_binary(&mut self, min_precedence: u8) -> Result<Node, String> {
+        let mut left = self.parse_unary()?;
+        loop {
+            self.skip_whitespace();
+            let operator = BINARY_OPERATORS
+                .iter()
+                .find(|&&(symbol, _, _)| self.rest.starts_with(symbol));
+            match operator {
+                Some(&(symbol, op, precedence)) if precedence > min_precedence => {
+                    self.rest = &self.rest[symbol.len()..];
+                    let right = self.parse_binary(precedence)?;
+                    left = Node::Binary(op, Box::new(left), Box::new(right));
+                }
+                _ => return Ok(left),
+            }
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Node, String> {
+        self.skip_whitespace();
+        let op = match self.rest.chars().next() {
+            Some('-') => UnaryOp::Negate,
+            Some('!') => UnaryOp::Not,
+            Some('~') => UnaryOp::Complement,
+            _ => return self.parse_operand(),
+        };
+        self.rest = &self.rest[1..];
+        Ok(Node::Unary(op, Box::new(self.parse_unary()?)))
+    }
+
+    fn parse_operand(&mut self) -> Result<Node, String> {
+        if self.eat('(') {
+            let node = self.parse_binary(0)?;
+            self.expect(')')?;
+            return Ok(node);
+        }
+        let end = self
+            .rest
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(self.rest.len());
+        let (word, rest) = self.rest.split_at(end);
+        if word.is_empty() {
+            return Err(match self.rest.chars().next() {
+                Some(c) => format!("unexpected `{}`", c),
+                None => "expected a value".to_string(),
+            });
+        }
+        self.rest = rest;
+        if word.starts_with(|c: char| c.is_ascii_digit()) {
+            return parse_number(word)
+                .map(Node::Number)
+                .ok_or_else(|| format!("invalid number `{}`", word));
+        }
+        let word_upper = word.to_ascii_uppercase();
+        let field = match word_upper.as_str() {
+            "I" => Field::I,
+            "PC" => Field::Pc,
+            "SP" => Field::Sp,
+            "DT" => Field::DelayTimer,
+            "ST" => Field::SoundTimer,
+            "V" => return Ok(Node::IndexedRegister(Box::new(self.parse_index()?))),
+            "MEM" => return Ok(Node::Memory(Box::new(self.parse_index()?))),
+            name => {
+                return match name.strip_prefix('V').map(|x| u8::from_str_radix(x, 16)) {
+                    Some(Ok(x)) if name.len() == 2 => Ok(Node::Register(x)),
+                    _ => Err(format!("unknown name `{}`", word)),
+                };
+            }
+        };
+        Ok(Node::Field(field))
+    }
+
+    /// Parses `[index]`.
+    fn parse_index(&mut self) -> Result<Node, String> {
+        self.skip_whitespace();
+        self.expect('[')?;
+        let index = self.parse_binary(0)?;
+        self.expect(']')?;
+        Ok(index)
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(format!("expected `{}`", c))
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        match self.rest.strip_prefix(c) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.rest = self.rest.trim_start();
+    }
+}
+
+fn parse_number(word: &str) -> Option<u32> {
+    let lower = word.to_ascii_lowercase();
+    if let Some(digits) = lower.strip_prefix("0x") {
+        u32::from_str_radix(digits, 16).ok()
+    } else if let Some(digits) = lower.strip_prefix("0b") {
+        u32::from_str_radix(digits, 2).ok()
+    } else {
+        lower.parse().ok()
+    }
+}
+
+fn evaluate(node: &Node, machine: &Machine) -> Result<u32, String> {
+    Ok(match node {
+        Node::Number(value) => *value,
+        Node::Register(x) => machine.registers[*x as usize] as u32,
+        Node::Field(field) => match field {
+            Field::I => machine.i as u32,
+            Field::Pc => machine.pc as u32,
+            Field::Sp => machine.sp as u32,
+            Field::DelayTimer => machine.delay_timer as u32,
+            Field::SoundTimer => machine.sound_timer as u32,
+        },
+        Node::IndexedRegister(index) => {
+            let x = evaluate(index, machine)?;
+            match machine.registers.get(x as usize) {
+                Some(&value) => value as u32,
+                None => return Err(format!("there's no register V[{}]", x)),
+            }
+        }
+        Node::Memory(address) => {
+            let address = evaluate(address, machine)?;
+            match machine.memory.get(address as usize) {
+                Some(&value) => value as u32,
+                None => return Err(format!("{:X} is past the end of memory", address)),
+            }
+        }
+        Node::Unary(op, operand) => {
+            let value = evaluate(operand, machine)?;
+            match op {
+                UnaryOp::Negate => value.wrapping_neg(),
+                UnaryOp::Not => (value == 0) as u32,
+                UnaryOp::Complement => !value,
+            }
+        }
+        Node::Binary(BinaryOp::LogicalAnd, left, right) => {
+            (evaluate(left, machine)? != 0 && evaluate(right, machine)? != 0) as u32
+        }
+        Node::Binary(BinaryOp::LogicalOr, left, right) => {
+            (evaluate(left, machine)? != 0 || evaluate(right, machine)? != 0) as u32
+        }
+        Node::Binary(op, left, right) => {
+            let (a, b) = (evaluate(left, machine)?, evaluate(right, machine)?);
+            match op {
+                BinaryOp::Mul => a.wrapping_mul(b),
+                BinaryOp::Div => a.checked_div(b).ok_or("division by zero")?,
+                BinaryOp::Rem => a.checked_rem(b).ok_or("division by zero")?,
+                BinaryOp::Add => a.wrapping_add(b),
+                BinaryOp::Sub => a.wrapping_sub(b),
+                BinaryOp::Shl => a.checked_shl(b).unwrap_or(0),
+                BinaryOp::Shr => a.checked_shr(b).unwrap_or(0),
+                BinaryOp::Lt => (a < b) as u32,
+                BinaryOp::Le => (a <= b) as u32,
+                BinaryOp::Gt => (a > b) as u32,
+                BinaryOp::Ge => (a >= b) as u32,
+                BinaryOp::Eq => (a == b) as u32,
+                BinaryOp::Ne => (a != b) as u32,
+                BinaryOp::And => a & b,
+                BinaryOp::Xor => a ^ b,
+                BinaryOp::Or => a | b,
+                BinaryOp::LogicalAnd | BinaryOp::LogicalOr => unreachable!(),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(text: &str) -> Result<u32, String> {
+        let mut memory = [0u8; 0x1000];
+        memory[0x235] = 0xab;
+        let mut registers = [0u8; 16];
+        registers[3] = 3;
+        registers[0xa] = 0x10;
+        let machine = Machine {
+            memory: &memory,
+            registers: &registers,
+            i: 0x234,
+            pc: 0x202,
+            sp: 1,
+            delay_timer: 60,
+            sound_timer: 0,
+        };
+        Expression::parse(text)?.evaluate(&machine)
+    }
+
+    #[test]
+    fn test_evaluate() {
+        assert_eq!(evaluate("V[3] + I"), Ok(0x237));
+        assert_eq!(evaluate("mem[I+1] & 0x0F"), Ok(0xb));
+        assert_eq!(evaluate("va * 2 + 1"), Ok(0x21));
+        assert_eq!(evaluate("(VA + 0b10) * 2"), Ok(0x24));
+        assert_eq!(evaluate("V[V3 + 7]"), Ok(0x10));
+        assert_eq!(evaluate("PC == 0x202 && DT > 30 || ST"), Ok(1));
+        assert_eq!(evaluate("1 << 4 | SP"), Ok(0x11));
+        assert_eq!(evaluate("!V0 + -1"), Ok(0));
+        assert_eq!(evaluate("~0 >> 28"), Ok(0xf));
+        assert_eq!(evaluate("V3 != 3 && 1 / 0"), Ok(0));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(evaluate("1 / V0"), Err("division by zero".to_string()));
+        assert_eq!(
+            evaluate("mem[0x1000]"),
+            Err("1000 is past the end of memory".to_string())
+        );
+        assert_eq!(
+            evaluate("V[16]"),
+            Err("there's no register V[16]".to_string())
+        );
+        assert_eq!(evaluate("V16"), Err("unknown name `V16`".to_string()));
+        assert_eq!(evaluate("0xZ"), Err("invalid number `0xZ`".to_string()));
+        assert_eq!(evaluate("(V3"), Err("expected `)`".to_string()));
+        assert_eq!(evaluate("V3 V4"), Err("unexpected `V4`".to_string()));
+        assert_eq!(evaluate("V3 +"), Err("expected a value".to_string()));
+        assert_eq!(Expression::parse(" mem[I] ").unwrap().to_string(), "mem[I]");
+    }
+}
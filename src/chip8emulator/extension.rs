@@ -0,0 +1,114 @@
+use super::opcode::Opcode;
+use std::fmt;
+
+/// Instruction set extensions beyond the original CHIP-8.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Extension {
+    SuperChip,
+    XoChip,
+    /// The COSMAC VIP interpreter for the VP-590 color board and VP-580
+    /// second keypad. Unlike the others it changes the meaning of an original
+    /// instruction (BNNN), so it has to be enabled to be decoded.
+    Chip8X,
+    /// Adds a 256x192 display with indexed colors. Its instructions replace
+    /// some 0NNN machine code calls, so it also has to be enabled.
+    MegaChip,
+}
+
+impl Extension {
+    /// Returns the extension that introduced `opcode`, or `None` if it is part
+    /// of the original CHIP-8 instruction set or not a known instruction.
+    pub fn required_by(opcode: &Opcode) -> Option<Extension> {
+        match opcode.get_nibble(0) {
+            0 => match opcode.get_nibbles_from(1) {
+                0x0c0..=0x0cf | 0x0fb..=0x0ff => Some(Extension::SuperChip),
+                0x010 | 0x011 => Some(Extension::MegaChip),
+                0x0d0..=0x0df => Some(Extension::XoChip),
+                0x2a0 => Some(Extension::Chip8X),
+                _ => None,
+            },
+            5 => match opcode.get_nibble(3) {
+                1 => Some(Extension::Chip8X),
+                2 | 3 => Some(Extension::XoChip),
+                _ => None,
+            },
+            0xd if opcode.get_nibble(3) == 0 => Some(Extension::SuperChip),
+            0xe => match opcode.get_nibbles_from(2) {
+                0xf2 | 0xf5 => Some(Extension::Chip8X),
+                _ => None,
+            },
+            0xf => match opcode.get_nibbles_from(2) {
+                0x30 | 0x75 | 0x85 => Some(Extension::SuperChip),
+                0x00 if opcode.get_nibble(1) == 0 => Some(Extension::XoChip),
+                0x01 | 0x02 | 0x3a => Some(Extension::XoChip),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Extension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Extension::SuperChip => write!(f, "SUPER-CHIP"),
+            Extension::XoChip => write!(f, "XO-CHIP"),
+            Extension::Chip8X => write!(f, "CHIP-8X"),
+            Extension::MegaChip => write!(f, "Mega-Chip"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_by() {
+        let required_by = |opcode| Extension::required_by(&Opcode::new(opcode));
+
+        for opcode in [
+            0x00c4, 0x00fb, 0x00fc, 0x00fd, 0x00fe, 0x00ff, 0xd120, 0xf130, 0xf275, 0xf385,
+        ]
+        .iter()
+        {
+            assert_eq!(
+                required_by(*opcode),
+                Some(Extension::SuperChip),
+                "{:04X}",
+                opcode
+            );
+        }
+        for opcode in [0x00d3, 0x5122, 0x5123, 0xf000, 0xf201, 0xf002, 0xf43a].iter() {
+            assert_eq!(
+                required_by(*opcode),
+                Some(Extension::XoChip),
+                "{:04X}",
+                opcode
+            );
+        }
+        for opcode in [0x02a0, 0x5121, 0xe1f2, 0xe2f5].iter() {
+            assert_eq!(
+                required_by(*opcode),
+                Some(Extension::Chip8X),
+                "{:04X}",
+                opcode
+            );
+        }
+        for opcode in [0x0010, 0x0011].iter() {
+            assert_eq!(
+                required_by(*opcode),
+                Some(Extension::MegaChip),
+                "{:04X}",
+                opcode
+            );
+        }
+        for opcode in [
+            0x00e0, 0x00ee, 0x0230, 0x5120, 0xd125, 0xf007, 0xf165, 0x1234, 0xb123,
+        ]
+        .iter()
+        {
+            assert_eq!(required_by(*opcode), None, "{:04X}", opcode);
+        }
+    }
+}
@@ -1,26 +1,96 @@
 use fixedbitset::FixedBitSet;
+use std::mem::size_of_val;
+use std::ops::Range;
 
+/// The most bitplanes a display can have, so a pixel's planes fit in a `u8`
+/// mask with bit `n` standing for plane `n`.
+pub const MAX_PLANES: usize = 8;
+/// Width of the blocks of pixels that share a color attribute. Each block is
+/// one pixel high.
+pub const COLOR_BLOCK_WIDTH: u32 = 8;
+/// The color attribute of every block until changed, red on the CHIP-8X.
+const DEFAULT_COLOR: u8 = 1;
+
+/// A monochrome framebuffer made of one or more bitplanes of the same size.
+/// Each pixel's color is the combination of the planes it is set in.
+///
+/// Displays with color zones, like the CHIP-8X's, also store a foreground
+/// color attribute for each block of pixels and a background color. Their
+/// meaning is up to the renderer.
+///
+/// Indexed displays, like Mega-Chip's, instead store a byte per pixel, which
+/// the renderer looks up in a palette.
+#[derive(Clone, PartialEq)]
 pub struct Graphics {
     width: u32,
     height: u32,
-    display: FixedBitSet,
-    changed: bool,
+    planes: Vec<FixedBitSet>,
+    /// The color index of each pixel, row by row, on indexed displays. The
+    /// planes are unused then.
+    indices: Option<Vec<u8>>,
+    /// Color attribute of each block, row by row.
+    colors: Vec<u8>,
+    background: u8,
+    /// Rows changed since the last `take_dirty_rows`, so that renderers only
+    /// need to repaint those.
+    dirty_rows: FixedBitSet,
 }
 
 impl Graphics {
     pub fn new(width: u32, height: u32) -> Graphics {
-        let display = FixedBitSet::with_capacity((width * height) as usize);
+        Graphics::with_planes(width, height, 1)
+    }
+
+    pub fn with_planes(width: u32, height: u32, plane_count: usize) -> Graphics {
+        assert!(
+            (1..=MAX_PLANES).contains(&plane_count),
+            "Display can't have {} planes",
+            plane_count
+        );
+
         Graphics {
             width,
             height,
-            display,
-            changed: true,
+            planes: vec![FixedBitSet::with_capacity((width * height) as usize); plane_count],
+            indices: None,
+            colors: vec![DEFAULT_COLOR; (width.div_ceil(COLOR_BLOCK_WIDTH) * height) as usize],
+            background: 0,
+            dirty_rows: all_rows(height),
+        }
+    }
+
+    /// Creates a display with a byte per pixel, all 0.
+    pub fn indexed(width: u32, height: u32) -> Graphics {
+        Graphics {
+            indices: Some(vec![0; (width * height) as usize]),
+            ..Graphics::new(width, height)
         }
     }
 
+    /// Changes the display size. The contents of every plane are cleared and
+    /// colors are reset.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        *self = if self.is_indexed() {
+            Graphics::indexed(width, height)
+        } else {
+            Graphics::with_planes(width, height, self.planes.len())
+        };
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.indices.is_some()
+    }
+
     /// Toggles the pixel at column `x` and row `y` (0-indexed) on the display
     /// and returns whether a pixel was toggled from on to off.
     pub fn toggle(&mut self, x: u32, y: u32) -> bool {
+        self.draw(x, y, 1) != 0
+    }
+
+    /// Toggles the pixel at column `x` and row `y` (0-indexed) in each plane
+    /// selected by the `planes` mask. Returns the mask of planes in which the
+    /// pixel was toggled from on to off.
+    pub fn draw(&mut self, x: u32, y: u32, planes: u8) -> u8 {
         assert!(
             x < self.width && y < self.height,
             "Pixel ({}, {}) is out of bounds of display size {}x{}",
@@ -30,11 +100,18 @@ impl Graphics {
             self.height
         );
 
-        let index = y * self.width + x;
-        let res = self.display[index as usize];
-        self.display.toggle(index as usize);
-        self.changed = true;
-        res
+        let index = (y * self.width + x) as usize;
+        let mut collisions = 0;
+        for (plane, display) in self.planes.iter_mut().enumerate() {
+            if planes & (1 << plane) != 0 {
+                if display[index] {
+                    collisions |= 1 << plane;
+                }
+                display.toggle(index);
+                self.dirty_rows.insert(y as usize);
+            }
+        }
+        collisions
     }
 
     pub fn get_width(&self) -> u32 {
@@ -45,43 +122,171 @@ impl Graphics {
         self.height
     }
 
+    /// Returns whether the pixel is set in any plane.
     pub fn get_pixel(&self, x: u32, y: u32) -> bool {
-        self.display[(y * self.width + x) as usize]
+        self.get_pixel_planes(x, y) != 0
+    }
+
+    /// Returns the mask of planes the pixel is set in. On indexed displays a
+    /// pixel with a nonzero index counts as set in the first plane.
+    pub fn get_pixel_planes(&self, x: u32, y: u32) -> u8 {
+        let index = (y * self.width + x) as usize;
+        if let Some(indices) = &self.indices {
+            return (indices[index] != 0) as u8;
+        }
+        self.planes
+            .iter()
+            .enumerate()
+            .filter(|(_, display)| display[index])
+            .fold(0, |mask, (plane, _)| mask | 1 << plane)
+    }
+
+    /// Returns a row of the display packed into bits, the leftmost pixel in
+    /// bit `width - 1` and the rightmost in bit 0, like sprite rows. A pixel
+    /// is set if it's set in any plane. Only displays up to 64 pixels wide
+    /// fit.
+    pub fn get_row(&self, y: u32) -> u64 {
+        assert!(
+            self.width <= u64::BITS,
+            "Display is {} pixels wide, too wide to pack a row in 64 bits",
+            self.width
+        );
+        (0..self.width).fold(0, |row, x| row << 1 | self.get_pixel(x, y) as u64)
     }
 
-    pub fn needs_rerender(&mut self) -> bool {
-        let res = self.changed;
-        self.changed = false;
-        res
+    pub fn plane_count(&self) -> usize {
+        self.planes.len()
+    }
+
+    /// Returns the pixels of a plane packed into words, row by row. Pixel `n`
+    /// is bit `n % 32` of word `n / 32`, and bits past the last pixel are 0.
+    pub fn get_plane_bits(&self, plane: usize) -> Option<&[u32]> {
+        self.planes.get(plane).map(FixedBitSet::as_slice)
+    }
+
+    /// Returns the color index of every pixel, row by row, on an indexed
+    /// display.
+    pub fn get_indices(&self) -> Option<&[u8]> {
+        self.indices.as_deref()
+    }
+
+    /// Returns the color index of the pixel on an indexed display.
+    pub fn get_index(&self, x: u32, y: u32) -> u8 {
+        let indices = self.indices.as_ref().expect("Display is not indexed");
+        indices[(y * self.width + x) as usize]
+    }
+
+    /// Sets the color index of the pixel on an indexed display and returns
+    /// the index it had.
+    pub fn set_index(&mut self, x: u32, y: u32, index: u8) -> u8 {
+        let width = self.width;
+        let indices = self.indices.as_mut().expect("Display is not indexed");
+        self.dirty_rows.insert(y as usize);
+        std::mem::replace(&mut indices[(y * width + x) as usize], index)
+    }
+
+    /// Sets the color attribute of the block in column `column` (counted in
+    /// blocks) and row `y`. Blocks outside the display are ignored.
+    pub fn set_color(&mut self, column: u32, y: u32, color: u8) {
+        let columns = self.width.div_ceil(COLOR_BLOCK_WIDTH);
+        if column < columns && y < self.height {
+            self.colors[(y * columns + column) as usize] = color;
+            self.dirty_rows.insert(y as usize);
+        }
+    }
+
+    /// Returns the color attribute of the block containing the pixel.
+    pub fn get_color(&self, x: u32, y: u32) -> u8 {
+        let columns = self.width.div_ceil(COLOR_BLOCK_WIDTH);
+        self.colors[(y * columns + x / COLOR_BLOCK_WIDTH) as usize]
+    }
+
+    pub fn set_background(&mut self, background: u8) {
+        self.background = background;
+        self.mark_changed();
+    }
+
+    pub fn get_background(&self) -> u8 {
+        self.background
+    }
+
+    /// Returns the runs of consecutive rows changed since the last call, in
+    /// order, and starts tracking changes afresh.
+    pub fn take_dirty_rows(&mut self) -> Vec<Range<u32>> {
+        let mut runs: Vec<Range<u32>> = Vec::new();
+        for row in self.dirty_rows.ones() {
+            let row = row as u32;
+            match runs.last_mut() {
+                Some(run) if run.end == row => run.end += 1,
+                _ => runs.push(row..row + 1),
+            }
+        }
+        self.dirty_rows.clear();
+        runs
+    }
+
+    /// Marks every row as changed, for when the contents were replaced
+    /// wholesale.
+    pub fn mark_changed(&mut self) {
+        self.dirty_rows = all_rows(self.height);
     }
 
     pub fn clear(&mut self) {
-        self.display.clear();
-        self.changed = true;
+        if let Some(indices) = &mut self.indices {
+            indices.fill(0);
+        }
+        self.clear_planes(u8::MAX);
+    }
+
+    /// Clears the planes selected by the `planes` mask.
+    pub fn clear_planes(&mut self, planes: u8) {
+        for (plane, display) in self.planes.iter_mut().enumerate() {
+            if planes & (1 << plane) != 0 {
+                display.clear();
+            }
+        }
+        self.mark_changed();
+    }
+
+    /// How many bytes the pixels, colors and dirty rows take up on the heap.
+    pub fn heap_bytes(&self) -> usize {
+        let bits = |set: &FixedBitSet| size_of_val(set.as_slice());
+        self.planes.iter().map(bits).sum::<usize>()
+            + self.indices.as_ref().map_or(0, Vec::len)
+            + self.colors.len()
+            + bits(&self.dirty_rows)
     }
 }
 
+fn all_rows(height: u32) -> FixedBitSet {
+    let mut rows = FixedBitSet::with_capacity(height as usize);
+    rows.insert_range(..);
+    rows
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
+    // Dirty rows are compared against lists of row ranges.
+    #[allow(clippy::bool_assert_comparison, clippy::single_range_in_vec_init)]
     fn test_graphics_toggle() {
         let mut gfx = Graphics::new(2, 2);
 
         assert_eq!(gfx.get_width(), 2);
         assert_eq!(gfx.get_height(), 2);
 
-        gfx.display.insert(2);
+        gfx.planes[0].insert(2);
         assert_eq!(gfx.toggle(0, 1), true);
-        assert!(gfx.needs_rerender());
-        assert!(!gfx.needs_rerender());
+        assert_eq!(gfx.take_dirty_rows(), [0..2]);
+        assert!(gfx.take_dirty_rows().is_empty());
         assert_eq!(gfx.toggle(0, 1), false);
-        assert!(gfx.display[2]);
-        assert!(gfx.needs_rerender());
+        assert!(gfx.planes[0][2]);
+        assert_eq!(gfx.take_dirty_rows(), [1..2]);
         assert_eq!(gfx.toggle(0, 1), true);
         assert_eq!(gfx.toggle(1, 1), false);
-        assert!(gfx.display[3]);
+        assert!(gfx.planes[0][3]);
         assert_eq!(gfx.toggle(0, 0), false);
         assert_eq!(gfx.toggle(1, 1), true);
         assert!(
@@ -99,4 +304,89 @@ mod tests {
                 && !gfx.get_pixel(1, 1)
         );
     }
+
+    #[test]
+    // Dirty rows are compared against lists of row ranges.
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_graphics_planes() {
+        let mut gfx = Graphics::with_planes(2, 2, 2);
+        assert_eq!(gfx.planes.len(), 2);
+
+        assert_eq!(gfx.draw(0, 0, 0b01), 0);
+        assert_eq!(gfx.draw(0, 0, 0b11), 0b01);
+        assert_eq!(gfx.get_pixel_planes(0, 0), 0b10);
+        assert_eq!(gfx.draw(1, 0, 0b11), 0);
+        assert_eq!(gfx.get_pixel_planes(1, 0), 0b11);
+
+        assert_eq!((gfx.get_row(0), gfx.get_row(1)), (0b11, 0));
+        assert_eq!(gfx.plane_count(), 2);
+        assert_eq!(gfx.get_plane_bits(0), Some(&[0b10][..]));
+        assert_eq!(gfx.get_plane_bits(1), Some(&[0b11][..]));
+        assert_eq!(gfx.get_plane_bits(2), None);
+
+        gfx.clear_planes(0b10);
+        assert_eq!(gfx.get_pixel_planes(0, 0), 0);
+        assert_eq!(gfx.get_pixel_planes(1, 0), 0b01);
+
+        gfx.resize(4, 3);
+        assert_eq!((gfx.get_width(), gfx.get_height()), (4, 3));
+        assert_eq!(gfx.planes.len(), 2);
+        assert_eq!(gfx.take_dirty_rows(), [0..3]);
+        assert!(!gfx.get_pixel(1, 0));
+        assert_eq!(gfx.draw(3, 2, 0b10), 0);
+        assert!(gfx.get_pixel(3, 2));
+    }
+
+    #[test]
+    // Dirty rows are compared against lists of row ranges.
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_graphics_colors() {
+        let mut gfx = Graphics::new(20, 2);
+        assert_eq!(gfx.get_color(19, 1), DEFAULT_COLOR);
+        assert_eq!(gfx.take_dirty_rows(), [0..2]);
+
+        gfx.set_color(1, 1, 5);
+        assert_eq!(gfx.take_dirty_rows(), [1..2]);
+        assert_eq!(gfx.get_color(8, 1), 5);
+        assert_eq!(gfx.get_color(15, 1), 5);
+        assert_eq!(gfx.get_color(16, 1), DEFAULT_COLOR);
+        assert_eq!(gfx.get_color(8, 0), DEFAULT_COLOR);
+
+        gfx.set_color(3, 0, 6);
+        gfx.set_color(0, 2, 6);
+        assert!(gfx.take_dirty_rows().is_empty());
+
+        gfx.set_background(2);
+        assert_eq!(gfx.get_background(), 2);
+        gfx.clear();
+        assert_eq!((gfx.get_color(8, 1), gfx.get_background()), (5, 2));
+    }
+
+    #[test]
+    // Dirty rows are compared against lists of row ranges.
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_graphics_indexed() {
+        let mut gfx = Graphics::indexed(3, 2);
+        assert!(gfx.is_indexed());
+        assert!(!Graphics::new(3, 2).is_indexed());
+
+        assert_eq!(gfx.set_index(2, 1, 7), 0);
+        assert_eq!(gfx.set_index(2, 1, 9), 7);
+        assert_eq!(gfx.get_index(2, 1), 9);
+        assert_eq!(gfx.get_indices(), Some(&[0, 0, 0, 0, 0, 9][..]));
+        assert_eq!(Graphics::new(3, 2).get_indices(), None);
+        assert!(gfx.get_pixel(2, 1));
+        assert!(!gfx.get_pixel(1, 1));
+
+        gfx.take_dirty_rows();
+        gfx.set_index(0, 0, 1);
+        gfx.set_index(1, 1, 1);
+        assert_eq!(gfx.take_dirty_rows(), [0..2]);
+
+        gfx.clear();
+        assert_eq!(gfx.get_index(2, 1), 0);
+        gfx.resize(4, 4);
+        assert!(gfx.is_indexed());
+        assert_eq!(gfx.get_index(3, 3), 0);
+    }
 }
@@ -0,0 +1,471 @@
+use super::extension::Extension;
+use super::opcode::Opcode;
+use arrayvec::ArrayVec;
+use std::fmt;
+
+/// A decoded CHIP-8 instruction. Register operands are register indices,
+/// other operands are immediate values or addresses.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    /// 00E0, or 0230 in hi-res mode
+    ClearScreen,
+    /// 00EE
+    ReturnSubroutine,
+    /// 0NNN, a machine code routine on the original hardware. It is executed
+    /// as a regular subroutine call.
+    MachineSubroutine(u16),
+    /// 1NNN
+    JumpTo(u16),
+    /// 2NNN
+    ExecuteSubroutine(u16),
+    /// 3XNN
+    SkipIfEq(u8, u8),
+    /// 4XNN
+    SkipIfNe(u8, u8),
+    /// 5XY0
+    SkipIfEqReg(u8, u8),
+    /// 6XNN
+    Store(u8, u8),
+    /// 7XNN
+    Add(u8, u8),
+    /// 8XY0
+    StoreReg(u8, u8),
+    /// 8XY1
+    StoreRegOr(u8, u8),
+    /// 8XY2
+    StoreRegAnd(u8, u8),
+    /// 8XY3
+    StoreRegXor(u8, u8),
+    /// 8XY4
+    AddReg(u8, u8),
+    /// 8XY5
+    SubReg(u8, u8),
+    /// 8XY6
+    StoreRegShr1(u8, u8),
+    /// 8XY7
+    StoreRegSub(u8, u8),
+    /// 8XYE
+    StoreRegShl1(u8, u8),
+    /// 9XY0
+    SkipIfNeReg(u8, u8),
+    /// ANNN
+    StoreAddress(u16),
+    /// BNNN
+    JumpToPlusV0(u16),
+    /// CXNN
+    StoreRandom(u8, u8),
+    /// DXYN
+    DrawSprite(u8, u8, u8),
+    /// EX9E
+    SkipIfPressed(u8),
+    /// EXA1
+    SkipIfNotPressed(u8),
+    /// FX07
+    StoreDelay(u8),
+    /// FX0A
+    WaitForKeypress(u8),
+    /// FX15
+    SetDelay(u8),
+    /// FX18
+    SetSound(u8),
+    /// FX1E
+    AddToI(u8),
+    /// FX29
+    StoreDigitAddress(u8),
+    /// FX33
+    StoreBcd(u8),
+    /// FX55
+    StoreRegsInMemory(u8),
+    /// FX65
+    StoreMemoryInRegs(u8),
+    /// 02A0 (CHIP-8X)
+    StepBackground,
+    /// 5XY1 (CHIP-8X)
+    AddNibbles(u8, u8),
+    /// BXYN (CHIP-8X), in place of BNNN
+    SetZoneColor(u8, u8, u8),
+    /// EXF2 (CHIP-8X)
+    SkipIfPressed2(u8),
+    /// EXF5 (CHIP-8X)
+    SkipIfNotPressed2(u8),
+    /// 0010 (Mega-Chip)
+    DisableMegaChip,
+    /// 0011 (Mega-Chip)
+    EnableMegaChip,
+    /// 02NN (Mega-Chip)
+    LoadPalette(u8),
+    /// 03NN (Mega-Chip)
+    SetSpriteWidth(u8),
+    /// 04NN (Mega-Chip)
+    SetSpriteHeight(u8),
+    /// 09NN (Mega-Chip)
+    SetCollisionIndex(u8),
+}
+
+impl Instruction {
+    /// Decodes `opcode`, or returns `None` if it is not a valid instruction.
+    /// `hires` selects the meaning of opcodes that differ in hi-res mode, and
+    /// `extension` the extension whose instructions are recognized, if any.
+    pub fn decode(
+        opcode: &Opcode,
+        hires: bool,
+        extension: Option<Extension>,
+    ) -> Option<Instruction> {
+        use Instruction::*;
+
+        let chip8x = extension == Some(Extension::Chip8X);
+        let megachip = extension == Some(Extension::MegaChip);
+        let x = opcode.get_nibble(1);
+        let y = opcode.get_nibble(2);
+        let n = opcode.get_nibble(3);
+        let nn = opcode.get_nibbles_from(2) as u8;
+        let nnn = opcode.get_nibbles_from(1);
+
+        let instruction = match opcode.get_nibble(0) {
+            0 => match nnn {
+                0x0e0 => ClearScreen,
+                0x230 if hires => ClearScreen,
+                0x2a0 if chip8x => StepBackground,
+                0x010 if megachip => DisableMegaChip,
+                0x011 if megachip => EnableMegaChip,
+                0x200..=0x2ff if megachip => LoadPalette(nn),
+                0x300..=0x3ff if megachip => SetSpriteWidth(nn),
+                0x400..=0x4ff if megachip => SetSpriteHeight(nn),
+                0x900..=0x9ff if megachip => SetCollisionIndex(nn),
+                0x0ee => ReturnSubroutine,
+                address => MachineSubroutine(address),
+            },
+            1 => JumpTo(nnn),
+            2 => ExecuteSubroutine(nnn),
+            3 => SkipIfEq(x, nn),
+            4 => SkipIfNe(x, nn),
+            5 if n == 0 => SkipIfEqReg(x, y),
+            5 if n == 1 && chip8x => AddNibbles(x, y),
+            6 => Store(x, nn),
+            7 => Add(x, nn),
+            8 => match n {
+                0 => StoreReg(x, y),
+                1 => StoreRegOr(x, y),
+                2 => StoreRegAnd(x, y),
+                3 => StoreRegXor(x, y),
+                4 => AddReg(x, y),
+                5 => SubReg(x, y),
+                6 => StoreRegShr1(x, y),
+                7 => StoreRegSub(x, y),
+                0xe => StoreRegShl1(x, y),
+                _ => return None,
+            },
+            9 if n == 0 => SkipIfNeReg(x, y),
+            0xa => StoreAddress(nnn),
+            0xb if chip8x => SetZoneColor(x, y, n),
+            0xb => JumpToPlusV0(nnn),
+            0xc => StoreRandom(x, nn),
+            0xd => DrawSprite(x, y, n),
+            0xe => match nn {
+                0x9e => SkipIfPressed(x),
+                0xa1 => SkipIfNotPressed(x),
+                0xf2 if chip8x => SkipIfPressed2(x),
+                0xf5 if chip8x => SkipIfNotPressed2(x),
+                _ => return None,
+            },
+            0xf => match nn {
+                0x07 => StoreDelay(x),
+                0x0a => WaitForKeypress(x),
+                0x15 => SetDelay(x),
+                0x18 => SetSound(x),
+                0x1e => AddToI(x),
+                0x29 => StoreDigitAddress(x),
+                0x33 => StoreBcd(x),
+                0x55 => StoreRegsInMemory(x),
+                0x65 => StoreMemoryInRegs(x),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        Some(instruction)
+    }
+
+    /// Whether the instruction reads memory at I, so its behavior depends on
+    /// the current value of I.
+    pub fn reads_i(&self) -> bool {
+        matches!(
+            self,
+            Instruction::DrawSprite(..)
+                | Instruction::AddToI(_)
+                | Instruction::StoreBcd(_)
+                | Instruction::StoreRegsInMemory(_)
+                | Instruction::StoreMemoryInRegs(_)
+        )
+    }
+
+    /// The instruction's operands, in the order they appear in the opcode.
+    pub fn operands(&self) -> ArrayVec<[u16; 3]> {
+        use Instruction::*;
+
+        let operands: &[u16] = match *self {
+            ClearScreen | ReturnSubroutine | StepBackground | DisableMegaChip | EnableMegaChip => {
+                &[]
+            }
+            MachineSubroutine(nnn)
+            | JumpTo(nnn)
+            | ExecuteSubroutine(nnn)
+            | StoreAddress(nnn)
+            | JumpToPlusV0(nnn) => &[nnn],
+            SkipIfEq(x, nn) | SkipIfNe(x, nn) | Store(x, nn) | Add(x, nn) | StoreRandom(x, nn) => {
+                &[x as u16, nn as u16]
+            }
+            SkipIfEqReg(x, y)
+            | StoreReg(x, y)
+            | StoreRegOr(x, y)
+            | StoreRegAnd(x, y)
+            | StoreRegXor(x, y)
+            | AddReg(x, y)
+            | SubReg(x, y)
+            | StoreRegShr1(x, y)
+            | StoreRegSub(x, y)
+            | StoreRegShl1(x, y)
+            | SkipIfNeReg(x, y)
+            | AddNibbles(x, y) => &[x as u16, y as u16],
+            DrawSprite(x, y, n) | SetZoneColor(x, y, n) => &[x as u16, y as u16, n as u16],
+            SkipIfPressed(x) | SkipIfNotPressed(x) | StoreDelay(x) | WaitForKeypress(x)
+            | SetDelay(x) | SetSound(x) | AddToI(x) | StoreDigitAddress(x) | StoreBcd(x)
+            | StoreRegsInMemory(x) | StoreMemoryInRegs(x) | SkipIfPressed2(x)
+            | SkipIfNotPressed2(x) => &[x as u16],
+            LoadPalette(nn) | SetSpriteWidth(nn) | SetSpriteHeight(nn) | SetCollisionIndex(nn) => {
+                &[nn as u16]
+            }
+        };
+        operands.iter().copied().collect()
+    }
+
+    /// The opcode of the instruction with its operand nibbles as letters, as
+    /// in the opcode reference, e.g. `DXYN`. Clearing the screen is `00E0`
+    /// in hi-res mode too.
+    pub fn pattern(&self) -> &'static str {
+        use Instruction::*;
+
+        match self {
+            ClearScreen => "00E0",
+            ReturnSubroutine => "00EE",
+            MachineSubroutine(_) => "0NNN",
+            JumpTo(_) => "1NNN",
+            ExecuteSubroutine(_) => "2NNN",
+            SkipIfEq(..) => "3XNN",
+            SkipIfNe(..) => "4XNN",
+            SkipIfEqReg(..) => "5XY0",
+            Store(..) => "6XNN",
+            Add(..) => "7XNN",
+            StoreReg(..) => "8XY0",
+            StoreRegOr(..) => "8XY1",
+            StoreRegAnd(..) => "8XY2",
+            StoreRegXor(..) => "8XY3",
+            AddReg(..) => "8XY4",
+            SubReg(..) => "8XY5",
+            StoreRegShr1(..) => "8XY6",
+            StoreRegSub(..) => "8XY7",
+            StoreRegShl1(..) => "8XYE",
+            SkipIfNeReg(..) => "9XY0",
+            StoreAddress(_) => "ANNN",
+            JumpToPlusV0(_) => "BNNN",
+            StoreRandom(..) => "CXNN",
+            DrawSprite(..) => "DXYN",
+            SkipIfPressed(_) => "EX9E",
+            SkipIfNotPressed(_) => "EXA1",
+            StoreDelay(_) => "FX07",
+            WaitForKeypress(_) => "FX0A",
+            SetDelay(_) => "FX15",
+            SetSound(_) => "FX18",
+            AddToI(_) => "FX1E",
+            StoreDigitAddress(_) => "FX29",
+            StoreBcd(_) => "FX33",
+            StoreRegsInMemory(_) => "FX55",
+            StoreMemoryInRegs(_) => "FX65",
+            StepBackground => "02A0",
+            AddNibbles(..) => "5XY1",
+            SetZoneColor(..) => "BXYN",
+            SkipIfPressed2(_) => "EXF2",
+            SkipIfNotPressed2(_) => "EXF5",
+            DisableMegaChip => "0010",
+            EnableMegaChip => "0011",
+            LoadPalette(_) => "02NN",
+            SetSpriteWidth(_) => "03NN",
+            SetSpriteHeight(_) => "04NN",
+            SetCollisionIndex(_) => "09NN",
+        }
+    }
+
+    /// The address the instruction jumps to, if it's a fixed one, and whether
+    /// it's a subroutine call.
+    pub fn target(&self) -> Option<(u16, bool)> {
+        match *self {
+            Instruction::JumpTo(nnn) => Some((nnn, false)),
+            Instruction::ExecuteSubroutine(nnn) | Instruction::MachineSubroutine(nnn) => {
+                Some((nnn, true))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the instruction may transfer control somewhere other than the
+    /// next instruction or the one after it.
+    pub fn is_jump(&self) -> bool {
+        matches!(
+            self,
+            Instruction::ReturnSubroutine
+                | Instruction::MachineSubroutine(_)
+                | Instruction::JumpTo(_)
+                | Instruction::ExecuteSubroutine(_)
+                | Instruction::JumpToPlusV0(_)
+        )
+    }
+}
+
+/// Formats the instruction with the mnemonics accepted by the assembler.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Instruction::*;
+
+        match *self {
+            ClearScreen => write!(f, "CLS"),
+            ReturnSubroutine => write!(f, "RET"),
+            MachineSubroutine(nnn) => write!(f, "SYS {:#05X}", nnn),
+            JumpTo(nnn) => write!(f, "JP {:#05X}", nnn),
+            ExecuteSubroutine(nnn) => write!(f, "CALL {:#05X}", nnn),
+            SkipIfEq(x, nn) => write!(f, "SE V{:X}, {:#04X}", x, nn),
+            SkipIfNe(x, nn) => write!(f, "SNE V{:X}, {:#04X}", x, nn),
+            SkipIfEqReg(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            Store(x, nn) => write!(f, "LD V{:X}, {:#04X}", x, nn),
+            Add(x, nn) => write!(f, "ADD V{:X}, {:#04X}", x, nn),
+            StoreReg(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            StoreRegOr(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            StoreRegAnd(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            StoreRegXor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddReg(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            SubReg(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            StoreRegShr1(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            StoreRegSub(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            StoreRegShl1(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SkipIfNeReg(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            StoreAddress(nnn) => write!(f, "LD I, {:#05X}", nnn),
+            JumpToPlusV0(nnn) => write!(f, "JP V0, {:#05X}", nnn),
+            StoreRandom(x, nn) => write!(f, "RND V{:X}, {:#04X}", x, nn),
+            DrawSprite(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            SkipIfPressed(x) => write!(f, "SKP V{:X}", x),
+            SkipIfNotPressed(x) => write!(f, "SKNP V{:X}", x),
+            StoreDelay(x) => write!(f, "LD V{:X}, DT", x),
+            WaitForKeypress(x) => write!(f, "LD V{:X}, K", x),
+            SetDelay(x) => write!(f, "LD DT, V{:X}", x),
+            SetSound(x) => write!(f, "LD ST, V{:X}", x),
+            AddToI(x) => write!(f, "ADD I, V{:X}", x),
+            StoreDigitAddress(x) => write!(f, "LD F, V{:X}", x),
+            StoreBcd(x) => write!(f, "LD B, V{:X}", x),
+            StoreRegsInMemory(x) => write!(f, "LD [I], V{:X}", x),
+            StoreMemoryInRegs(x) => write!(f, "LD V{:X}, [I]", x),
+            StepBackground => write!(f, "BGC"),
+            AddNibbles(x, y) => write!(f, "ADDN V{:X}, V{:X}", x, y),
+            SetZoneColor(x, y, n) => write!(f, "COL V{:X}, V{:X}, {}", x, y, n),
+            SkipIfPressed2(x) => write!(f, "SKP2 V{:X}", x),
+            SkipIfNotPressed2(x) => write!(f, "SKNP2 V{:X}", x),
+            DisableMegaChip => write!(f, "MEGAOFF"),
+            EnableMegaChip => write!(f, "MEGAON"),
+            LoadPalette(nn) => write!(f, "LDPAL {}", nn),
+            SetSpriteWidth(nn) => write!(f, "SPRW {}", nn),
+            SetSpriteHeight(nn) => write!(f, "SPRH {}", nn),
+            SetCollisionIndex(nn) => write!(f, "CCOL {:#04X}", nn),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::assembler::assemble;
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let decode = |opcode| Instruction::decode(&Opcode::new(opcode), false, None);
+
+        assert_eq!(decode(0x00e0), Some(Instruction::ClearScreen));
+        assert_eq!(decode(0x0230), Some(Instruction::MachineSubroutine(0x230)));
+        assert_eq!(
+            Instruction::decode(&Opcode::new(0x0230), true, None),
+            Some(Instruction::ClearScreen)
+        );
+        assert_eq!(decode(0x3a7b), Some(Instruction::SkipIfEq(0xa, 0x7b)));
+        assert_eq!(decode(0x8ab6), Some(Instruction::StoreRegShr1(0xa, 0xb)));
+        assert_eq!(decode(0xd12f), Some(Instruction::DrawSprite(1, 2, 0xf)));
+        assert_eq!(decode(0x5121), None);
+        assert_eq!(decode(0x8128), None);
+        assert_eq!(decode(0xe1a2), None);
+        assert_eq!(decode(0xf175), None);
+        assert_eq!(decode(0xb123), Some(Instruction::JumpToPlusV0(0x123)));
+        assert_eq!(decode(0xe1f2), None);
+
+        let decode_chip8x =
+            |opcode| Instruction::decode(&Opcode::new(opcode), false, Some(Extension::Chip8X));
+        assert_eq!(decode_chip8x(0x02a0), Some(Instruction::StepBackground));
+        assert_eq!(decode_chip8x(0x5121), Some(Instruction::AddNibbles(1, 2)));
+        assert_eq!(
+            decode_chip8x(0xb123),
+            Some(Instruction::SetZoneColor(1, 2, 3))
+        );
+        assert_eq!(decode_chip8x(0xe1f2), Some(Instruction::SkipIfPressed2(1)));
+        assert_eq!(
+            decode_chip8x(0xe1f5),
+            Some(Instruction::SkipIfNotPressed2(1))
+        );
+        assert_eq!(decode_chip8x(0x3a7b), decode(0x3a7b));
+
+        let decode_megachip =
+            |opcode| Instruction::decode(&Opcode::new(opcode), false, Some(Extension::MegaChip));
+        assert_eq!(decode(0x0011), Some(Instruction::MachineSubroutine(0x011)));
+        assert_eq!(decode_megachip(0x0011), Some(Instruction::EnableMegaChip));
+        assert_eq!(decode_megachip(0x0203), Some(Instruction::LoadPalette(3)));
+        assert_eq!(
+            decode_megachip(0x0400),
+            Some(Instruction::SetSpriteHeight(0))
+        );
+        assert_eq!(
+            decode_megachip(0x0123),
+            Some(Instruction::MachineSubroutine(0x123))
+        );
+    }
+
+    #[test]
+    fn test_display_round_trips_through_assembler() {
+        let opcodes = [
+            0x00e0, 0x00ee, 0x0123, 0x1234, 0x2345, 0x3a7b, 0x4a7b, 0x5ab0, 0x6a7b, 0x7a7b, 0x8ab0,
+            0x8ab1, 0x8ab2, 0x8ab3, 0x8ab4, 0x8ab5, 0x8ab6, 0x8ab7, 0x8abe, 0x9ab0, 0xa123, 0xb123,
+            0xca7b, 0xdab5, 0xea9e, 0xeaa1, 0xfa07, 0xfa0a, 0xfa15, 0xfa18, 0xfa1e, 0xfa29, 0xfa33,
+            0xfa55, 0xfa65,
+        ];
+
+        let chip8x_opcodes = [0x02a0, 0x5ab1, 0xbab0, 0xbab5, 0xeaf2, 0xeaf5];
+        let megachip_opcodes = [0x0010, 0x0011, 0x0203, 0x0310, 0x0400, 0x09ff];
+
+        let decoded = opcodes
+            .iter()
+            .map(|&opcode| (opcode, None))
+            .chain(
+                chip8x_opcodes
+                    .iter()
+                    .map(|&opcode| (opcode, Some(Extension::Chip8X))),
+            )
+            .chain(
+                megachip_opcodes
+                    .iter()
+                    .map(|&opcode| (opcode, Some(Extension::MegaChip))),
+            );
+        for (opcode, extension) in decoded {
+            let instruction = Instruction::decode(&Opcode::new(opcode), false, extension).unwrap();
+            let rom = assemble(&instruction.to_string()).unwrap();
+            assert_eq!(
+                (rom[0] as u16) << 8 | rom[1] as u16,
+                opcode,
+                "{}",
+                instruction
+            );
+        }
+    }
+}
@@ -1,15 +1,66 @@
+use std::collections::VecDeque;
+
+/// Every key on the keypad, one bit per key.
+pub const ALL_KEYS: u16 = 0xffff;
+
 pub struct KeyPad {
     state: [bool; 16],
+    /// Keys the emulated machine has, one bit per key. Others never register
+    /// as pressed.
+    available: u16,
+    /// Presses and releases waiting to be applied, oldest first, as the key
+    /// and whether it's pressed.
+    queue: VecDeque<(u8, bool)>,
 }
 
 impl KeyPad {
     pub fn new() -> KeyPad {
-        KeyPad { state: [false; 16] }
+        KeyPad {
+            state: [false; 16],
+            available: ALL_KEYS,
+            queue: VecDeque::new(),
+        }
+    }
+
+    /// Queues a press or release of `key` to be applied by `apply_queued`.
+    /// Key repeats, which don't change the key's state, aren't queued.
+    pub fn queue_key(&mut self, key: u8, pressed: bool) {
+        KeyPad::check_key_in_range(key);
+        let last = self
+            .queue
+            .iter()
+            .rev()
+            .find(|&&(queued, _)| queued == key)
+            .map_or(self.state[key as usize], |&(_, pressed)| pressed);
+        if pressed != last {
+            self.queue.push_back((key, pressed));
+        }
+    }
+
+    /// Applies queued presses and releases in order, but at most one for each
+    /// key, so a key pressed and released between two calls is seen pressed
+    /// until the next one. The rest stay queued for later calls.
+    pub fn apply_queued(&mut self) {
+        let mut changed = 0u16;
+        while let Some(&(key, pressed)) = self.queue.front() {
+            if changed & 1 << key != 0 {
+                break;
+            }
+            changed |= 1 << key;
+            self.queue.pop_front();
+            if pressed {
+                self.keydown(key);
+            } else {
+                self.keyup(key);
+            }
+        }
     }
 
     pub fn keydown(&mut self, key: u8) {
         KeyPad::check_key_in_range(key);
-        self.state[key as usize] = true;
+        if self.available & 1 << key != 0 {
+            self.state[key as usize] = true;
+        }
     }
 
     pub fn keyup(&mut self, key: u8) {
@@ -22,11 +73,40 @@ impl KeyPad {
         self.state[key as usize]
     }
 
+    /// Returns the keys that are down, one bit per key.
+    pub fn pressed_mask(&self) -> u16 {
+        self.state
+            .iter()
+            .enumerate()
+            .filter(|&(_, &down)| down)
+            .fold(0, |mask, (key, _)| mask | 1 << key)
+    }
+
+    /// Limits the keypad to the keys in `mask`, for machines with fewer than
+    /// 16 keys. Keys outside of it are released.
+    pub fn set_available_keys(&mut self, mask: u16) {
+        self.available = mask;
+        for key in 0..16 {
+            if mask & 1 << key == 0 {
+                self.state[key] = false;
+            }
+        }
+    }
+
     fn check_key_in_range(key: u8) {
         assert!(key <= 0xf, "{:X} is not a key on the keypad", key);
     }
 }
 
+/// Parses a list of keys as hex digits, e.g. `"0123456789"`, into a mask with
+/// one bit per key. Spaces and commas between keys are ignored.
+pub fn parse_key_mask(value: &str) -> Option<u16> {
+    value
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != ',')
+        .try_fold(0, |mask, c| Some(mask | 1 << c.to_digit(16)?))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -41,5 +121,47 @@ mod tests {
 
         keypad.keyup(0xa);
         assert!(!keypad.is_key_down(0xa));
+
+        keypad.keydown(0x0);
+        keypad.keydown(0xf);
+        assert_eq!(keypad.pressed_mask(), 0x8001);
+    }
+
+    #[test]
+    fn test_key_queue() {
+        let mut keypad = KeyPad::new();
+        // A tap between two ticks, with a key repeat.
+        keypad.queue_key(0x5, true);
+        keypad.queue_key(0x5, true);
+        keypad.queue_key(0x6, true);
+        keypad.queue_key(0x5, false);
+        assert!(!keypad.is_key_down(0x5));
+
+        keypad.apply_queued();
+        assert!(keypad.is_key_down(0x5));
+        assert!(keypad.is_key_down(0x6));
+
+        keypad.apply_queued();
+        assert!(!keypad.is_key_down(0x5));
+        assert!(keypad.is_key_down(0x6));
+        assert!(keypad.queue.is_empty());
+    }
+
+    #[test]
+    fn test_available_keys() {
+        let mut keypad = KeyPad::new();
+        keypad.keydown(0x1);
+        keypad.keydown(0xf);
+
+        keypad.set_available_keys(0x03ff);
+        assert!(keypad.is_key_down(0x1));
+        assert!(!keypad.is_key_down(0xf));
+        keypad.keydown(0xf);
+        assert!(!keypad.is_key_down(0xf));
+
+        assert_eq!(parse_key_mask("0123456789"), Some(0x03ff));
+        assert_eq!(parse_key_mask("a, B f"), Some(0x8c00));
+        assert_eq!(parse_key_mask("0-9"), None);
+        assert_eq!(parse_key_mask(""), Some(0));
     }
 }
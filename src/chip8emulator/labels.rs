@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+
+/// How a labeled address is reached, from the least to the most telling.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    /// The target of a jump forward.
+    Label,
+    /// The target of a jump back.
+    Loop,
+    Subroutine,
+}
+
+/// Names addresses after how the program reaches them: `sub_0240` for
+/// subroutines, `loop_0212` for the targets of jumps back and `label_0230`
+/// for the targets of jumps forward. `jumps` has the address of each jump or
+/// call, its target and whether it's a call, and `subroutines` has
+/// subroutines known some other way, e.g. from having been called.
+pub fn synthesize_labels(
+    jumps: impl IntoIterator<Item = (u16, u16, bool)>,
+    subroutines: impl IntoIterator<Item = u16>,
+) -> BTreeMap<u16, String> {
+    let mut kinds = BTreeMap::new();
+    for (address, target, is_call) in jumps {
+        let kind = if is_call {
+            LabelKind::Subroutine
+        } else if target <= address {
+            LabelKind::Loop
+        } else {
+            LabelKind::Label
+        };
+        let current = kinds.entry(target).or_insert(kind);
+        *current = kind.max(*current);
+    }
+    for address in subroutines {
+        kinds.insert(address, LabelKind::Subroutine);
+    }
+    kinds
+        .into_iter()
+        .map(|(address, kind)| {
+            let prefix = match kind {
+                LabelKind::Label => "label",
+                LabelKind::Loop => "loop",
+                LabelKind::Subroutine => "sub",
+            };
+            (address, format!("{}_{:04X}", prefix, address))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_synthesize_labels() {
+        let labels = synthesize_labels(
+            vec![
+                (0x200, 0x240, true),
+                (0x214, 0x212, false),
+                (0x216, 0x230, false),
+                (0x250, 0x230, false),
+                (0x260, 0x240, false),
+            ],
+            vec![0x2a0],
+        );
+        let labels: Vec<_> = labels.values().map(String::as_str).collect();
+        assert_eq!(labels, ["loop_0212", "loop_0230", "sub_0240", "sub_02A0"]);
+    }
+}
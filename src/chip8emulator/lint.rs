@@ -0,0 +1,151 @@
+use super::instruction::Instruction;
+use super::opcode::Opcode;
+use super::quirks::Quirk;
+use super::PROGRAM_MEMORY_START;
+use std::fmt;
+
+/// How many instructions after FX55/FX65 are searched for a use of I.
+const LOAD_STORE_LOOKAHEAD: usize = 8;
+
+/// An instruction whose behavior depends on which interpreter runs the ROM.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LintWarning {
+    pub address: u16,
+    pub quirk: Quirk,
+    pub message: String,
+}
+
+impl fmt::Display for LintWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#05X}: {}", self.address, self.message)
+    }
+}
+
+/// Statically scans `rom` for instruction sequences that behave differently
+/// depending on the interpreter quirks in effect.
+///
+/// The ROM is read as a flat sequence of instructions, so sprite data that
+/// happens to decode as one of the flagged instructions can produce false
+/// positives.
+pub fn lint(rom: &[u8]) -> Vec<LintWarning> {
+    let instructions: Vec<Option<Instruction>> = rom
+        .chunks_exact(2)
+        .map(|bytes| {
+            Instruction::decode(
+                &Opcode::new(u16::from_be_bytes([bytes[0], bytes[1]])),
+                false,
+                None,
+            )
+        })
+        .collect();
+
+    let mut warnings = Vec::new();
+
+    for (index, instruction) in instructions.iter().enumerate() {
+        let address = PROGRAM_MEMORY_START as u16 + 2 * index as u16;
+        let mut warn = |quirk, message| {
+            warnings.push(LintWarning {
+                address,
+                quirk,
+                message,
+            })
+        };
+
+        match *instruction {
+            Some(i @ Instruction::StoreRegShr1(x, y))
+            | Some(i @ Instruction::StoreRegShl1(x, y))
+                if x != y =>
+            {
+                warn(
+                    Quirk::ShiftUsesVy,
+                    format!(
+                        "{} shifts V{:X} or V{:X} depending on the interpreter",
+                        i, y, x
+                    ),
+                );
+            }
+            Some(i @ Instruction::StoreRegsInMemory(_))
+            | Some(i @ Instruction::StoreMemoryInRegs(_)) => {
+                if let Some(next) = next_use_of_i(&instructions[index + 1..]) {
+                    warn(
+                        Quirk::LoadStoreIncrementsI,
+                        format!("{} is followed by {}, which uses I", i, next),
+                    );
+                }
+            }
+            Some(i @ Instruction::JumpToPlusV0(address)) if address & 0xf00 != 0 => {
+                warn(
+                    Quirk::JumpUsesVx,
+                    format!(
+                        "{} adds V0 or V{:X} depending on the interpreter",
+                        i,
+                        address >> 8
+                    ),
+                );
+            }
+            _ => {}
+        }
+    }
+
+    warnings
+}
+
+/// Finds the first instruction in `following` that reads I before I is set
+/// again or control flow leaves the straight-line sequence.
+fn next_use_of_i(following: &[Option<Instruction>]) -> Option<Instruction> {
+    for instruction in following.iter().take(LOAD_STORE_LOOKAHEAD) {
+        match *instruction {
+            Some(Instruction::StoreAddress(_)) | Some(Instruction::StoreDigitAddress(_)) => {
+                return None
+            }
+            Some(i) if i.reads_i() => return Some(i),
+            Some(i) if i.is_jump() => return None,
+            Some(_) => {}
+            None => return None,
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quirks_flagged(rom: &[u8]) -> Vec<(u16, Quirk)> {
+        lint(rom)
+            .into_iter()
+            .map(|warning| (warning.address, warning.quirk))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        assert_eq!(
+            quirks_flagged(&[
+                0x81, 0x26, // SHR V1, V2
+                0x81, 0x16, // SHR V1, V1
+                0xf3, 0x55, // LD [I], V3
+                0x60, 0x01, // LD V0, 0x01
+                0xf3, 0x65, // LD V3, [I]
+                0xb0, 0x20, // JP V0, 0x020
+                0xb3, 0x00, // JP V0, 0x300
+            ]),
+            vec![
+                (0x200, Quirk::ShiftUsesVy),
+                (0x204, Quirk::LoadStoreIncrementsI),
+                (0x20c, Quirk::JumpUsesVx),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_lint_load_store_stops_at_reassigned_i() {
+        assert!(lint(&[0xf3, 0x55, 0xa3, 0x00, 0xf3, 0x65]).is_empty());
+        assert!(lint(&[0xf3, 0x55, 0x12, 0x00, 0xf3, 0x65]).is_empty());
+        assert_eq!(
+            quirks_flagged(&[0xf3, 0x65, 0x60, 0x00, 0xd0, 0x05]),
+            vec![(0x200, Quirk::LoadStoreIncrementsI)]
+        );
+    }
+}
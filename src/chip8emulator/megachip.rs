@@ -0,0 +1,72 @@
+/// State used by Mega-Chip mode, where the display is 256x192 pixels of 8-bit
+/// color indices and sprites are drawn a byte per pixel.
+#[derive(Clone)]
+pub struct MegaChip {
+    /// Size of the sprites DXYN draws, set with 03NN and 04NN.
+    pub sprite_width: u32,
+    pub sprite_height: u32,
+    /// Drawing over a pixel with this index sets VF, set with 09NN.
+    pub collision_index: u8,
+    /// The RGB color of each index. Index 0 is transparent when drawing.
+    palette: [[u8; 3]; 256],
+}
+
+impl MegaChip {
+    pub fn new() -> MegaChip {
+        let mut palette = [[0; 3]; 256];
+        // Until a ROM loads its own colors, indices are shades of gray.
+        for (index, color) in palette.iter_mut().enumerate() {
+            *color = [index as u8; 3];
+        }
+
+        MegaChip {
+            sprite_width: 8,
+            sprite_height: 8,
+            collision_index: 0,
+            palette,
+        }
+    }
+
+    /// Loads colors from `data` into the palette starting at index 1, as 02NN
+    /// does. Each color is 4 bytes of alpha, red, green and blue, and alpha is
+    /// ignored.
+    pub fn load_palette(&mut self, data: &[u8]) {
+        for (color, argb) in self.palette[1..].iter_mut().zip(data.chunks_exact(4)) {
+            *color = [argb[1], argb[2], argb[3]];
+        }
+    }
+
+    pub fn color(&self, index: u8) -> [u8; 3] {
+        self.palette[index as usize]
+    }
+}
+
+/// Converts the operand of 03NN or 04NN to a sprite size, where 0 stands for
+/// 256.
+pub fn sprite_size(nn: u8) -> u32 {
+    if nn == 0 {
+        256
+    } else {
+        nn as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_palette() {
+        let mut megachip = MegaChip::new();
+        assert_eq!(megachip.color(0x80), [0x80; 3]);
+
+        megachip.load_palette(&[0xff, 1, 2, 3, 0x00, 4, 5, 6, 0xff]);
+        assert_eq!(megachip.color(0), [0; 3]);
+        assert_eq!(megachip.color(1), [1, 2, 3]);
+        assert_eq!(megachip.color(2), [4, 5, 6]);
+        assert_eq!(megachip.color(3), [3; 3]);
+
+        assert_eq!(sprite_size(0), 256);
+        assert_eq!(sprite_size(16), 16);
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt;
+
+/// How a byte must compare with its value in the last snapshot to stay a
+/// candidate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SearchFilter {
+    Increased,
+    Decreased,
+    Changed,
+    Unchanged,
+    EqualTo(u8),
+}
+
+impl SearchFilter {
+    fn matches(self, old: u8, new: u8) -> bool {
+        match self {
+            SearchFilter::Increased => new > old,
+            SearchFilter::Decreased => new < old,
+            SearchFilter::Changed => new != old,
+            SearchFilter::Unchanged => new == old,
+            SearchFilter::EqualTo(value) => new == value,
+        }
+    }
+}
+
+impl fmt::Display for SearchFilter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SearchFilter::Increased => write!(f, "increased"),
+            SearchFilter::Decreased => write!(f, "decreased"),
+            SearchFilter::Changed => write!(f, "changed"),
+            SearchFilter::Unchanged => write!(f, "unchanged"),
+            SearchFilter::EqualTo(value) => write!(f, "equal to {}", value),
+        }
+    }
+}
+
+/// A search for the address of a value a ROM keeps in memory, like its
+/// lives or score: starting from every address, each filter keeps the ones
+/// whose byte compares with the last snapshot as it says, e.g. decreased
+/// after losing a life, then takes a new snapshot.
+pub struct MemorySearch {
+    snapshot: Vec<u8>,
+    candidates: Vec<u16>,
+}
+
+impl MemorySearch {
+    /// Starts a search with every address of `memory` a candidate.
+    pub fn new(memory: &[u8]) -> MemorySearch {
+        MemorySearch {
+            snapshot: memory.to_vec(),
+            candidates: (0..memory.len() as u16).collect(),
+        }
+    }
+
+    /// Keeps the candidates whose byte in `memory` matches `filter`, and
+    /// snapshots `memory` for the next filter.
+    pub fn filter(&mut self, memory: &[u8], filter: SearchFilter) {
+        let snapshot = &self.snapshot;
+        self.candidates.retain(|&address| {
+            let address = address as usize;
+            filter.matches(snapshot[address], memory[address])
+        });
+        self.snapshot = memory.to_vec();
+    }
+
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    /// Returns the byte at `address` in the last snapshot.
+    pub fn snapshot_value(&self, address: u16) -> u8 {
+        self.snapshot[address as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let mut memory = [0u8; 16];
+        memory[3] = 3;
+        memory[7] = 3;
+        memory[9] = 5;
+        let mut search = MemorySearch::new(&memory);
+        assert_eq!(search.candidates().len(), 16);
+
+        search.filter(&memory, SearchFilter::EqualTo(3));
+        assert_eq!(search.candidates(), [3, 7]);
+
+        memory[3] = 2;
+        memory[7] = 4;
+        search.filter(&memory, SearchFilter::Decreased);
+        assert_eq!(search.candidates(), [3]);
+        assert_eq!(search.snapshot_value(3), 2);
+
+        search.filter(&memory, SearchFilter::Unchanged);
+        assert_eq!(search.candidates(), [3]);
+        memory[3] = 1;
+        search.filter(&memory, SearchFilter::Increased);
+        assert!(search.candidates().is_empty());
+        assert_eq!(SearchFilter::EqualTo(3).to_string(), "equal to 3");
+    }
+}
@@ -1,20 +1,98 @@
+mod analysis;
+mod assembler;
+mod call_graph;
+mod cheat;
 mod chip8timer;
+mod cost;
+mod crash;
+mod debugger;
+mod expression;
+mod extension;
 mod graphics;
+mod instruction;
 mod keypad;
+mod labels;
+mod lint;
+mod megachip;
+mod memory_search;
 mod opcode;
+mod opcode_table;
+mod palette;
+mod profile;
+mod profiler;
+mod quirks;
+mod save_ram;
+mod save_state;
+mod sprite;
+mod state;
+mod symbols;
+mod thumbnail;
 mod timer;
+mod trace;
 
+use analysis::{analyze, Analysis, ByteUse};
 use arrayvec::ArrayVec;
+pub use assembler::assemble;
+pub use call_graph::Call;
+use call_graph::CallGraph;
+pub use cheat::{Cheat, CheatMode};
 use chip8timer::Chip8Timer;
+pub use cost::CostModel;
+pub use crash::CrashReport;
+use crash::CRASH_MEMORY_RADIUS;
+use debugger::Debugger;
+pub use debugger::{OpcodePattern, WatchHit, Watchpoint};
+pub use expression::Expression;
+use expression::Machine;
+use extension::Extension;
 use graphics::Graphics;
+use instruction::Instruction;
+pub use keypad::parse_key_mask;
 use keypad::KeyPad;
+use labels::synthesize_labels;
+pub use lint::lint;
+use megachip::{sprite_size, MegaChip};
+pub use memory_search::{MemorySearch, SearchFilter};
 use opcode::Opcode;
-use rand;
+pub use opcode_table::opcode_table;
+pub use palette::{hex_color, parse_hex_color, Palette};
+use palette::{CHIP8X_BACKGROUND_COLORS, CHIP8X_FOREGROUND_COLORS};
+pub use profile::Profile;
+use profiler::Profiler;
+pub use quirks::Quirks;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+pub use save_ram::{checksum, SaveRegion};
+pub use save_state::{SaveState, TimerState};
+pub use sprite::{sprite_pixel, sprite_rgba, toggle_sprite_pixel, SPRITE_WIDTH};
+pub use state::State;
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Range;
+pub use symbols::parse_symbols;
 use timer::Timer;
+use trace::Trace;
+pub use trace::TraceEntry;
+
+pub use thumbnail::thumbnail;
 
 const WIDTH: u8 = 64;
 const HEIGHT: u8 = 32;
+const HIRES_HEIGHT: u8 = 64;
+const MEGACHIP_WIDTH: u32 = 256;
+const MEGACHIP_HEIGHT: u32 = 192;
 const PROGRAM_MEMORY_START: usize = 0x200;
+const DEFAULT_CLOCK_SPEED: f64 = 800.0;
+/// How long a frame of the 60 Hz delay and sound timers lasts, in
+/// milliseconds.
+const FRAME_DURATION: f64 = 1000.0 / 60.0;
+/// Hi-res CHIP-8 ROMs start with a jump over the patched-in interpreter code
+/// at 0x260, and their actual program starts at 0x2C0.
+const HIRES_ENTRY_OPCODE: u16 = 0x1260;
+const HIRES_PROGRAM_START: u16 = 0x2c0;
+/// Height of the CHIP-8X color zones BXY0 colors. Zones are 8 pixels wide.
+const COLOR_ZONE_HEIGHT: u32 = 4;
+/// How many of the last instructions executed a crash report lists.
+const RECENT_INSTRUCTIONS: usize = 32;
 const FONT_MEMORY_START: usize = 0x050;
 const FONT_MEMORY: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -35,6 +113,14 @@ const FONT_MEMORY: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+pub struct DisassembledInstruction {
+    pub address: u16,
+    pub opcode: u16,
+    pub text: String,
+    /// The address the instruction jumps to or calls, if it's a fixed one.
+    pub target: Option<u16>,
+}
+
 #[allow(non_snake_case)]
 pub struct Chip8Emulator {
     memory: [u8; 4096],
@@ -46,7 +132,52 @@ pub struct Chip8Emulator {
     sound_timer: Chip8Timer,
     stack: ArrayVec<[u16; 16]>,
     keypad: KeyPad,
+    /// The CHIP-8X's second keypad.
+    second_keypad: KeyPad,
     timer: Timer,
+    cost_model: CostModel,
+    pending_cycles: u64,
+    /// Instructions executed since the emulator was created or reset.
+    instruction_count: u64,
+    rom_loaded: bool,
+    /// How long the ROM loaded last is, in bytes.
+    rom_length: usize,
+    /// Which bytes of the ROM are code and which data.
+    analysis: Analysis,
+    /// Names for addresses in the ROM, e.g. from a symbol file.
+    symbols: BTreeMap<u16, String>,
+    hires: bool,
+    /// Run the next ROM in hi-res mode even if it isn't detected as hi-res.
+    force_hires: bool,
+    megachip: MegaChip,
+    quirks: Quirks,
+    strict_mode: bool,
+    extension: Option<Extension>,
+    rng: StdRng,
+    rom_checksum: u32,
+    save_regions: Vec<SaveRegion>,
+    save_ram_changed: bool,
+    debugger: Debugger,
+    /// The machine state from before each of the last instructions executed,
+    /// most recent last, for stepping back.
+    history: VecDeque<SaveState>,
+    history_limit: usize,
+    trace: Trace,
+    profiler: Profiler,
+    call_graph: CallGraph,
+    cheats: Vec<Cheat>,
+    /// The addresses of the last instructions executed, most recent last.
+    recent_addresses: VecDeque<u16>,
+    /// The state when the first instruction since the reset faulted.
+    crash_report: Option<CrashReport>,
+    /// Whether the last instruction executed was FX0A finding no key down.
+    waiting_for_key: bool,
+    /// Whether the ROM ended by jumping to itself, and so runs no more
+    /// instructions.
+    halted: bool,
+    /// Whether an instruction faulted in a way the ROM can't carry on from,
+    /// which stops it until the reset.
+    faulted: bool,
 }
 
 impl Chip8Emulator {
@@ -65,22 +196,749 @@ impl Chip8Emulator {
             sound_timer: Chip8Timer::new(current_time),
             stack: ArrayVec::new(),
             keypad: KeyPad::new(),
-            timer: Timer::new(current_time, 1000.0 / 800.0),
+            second_keypad: KeyPad::new(),
+            timer: Timer::new(current_time, 1000.0 / DEFAULT_CLOCK_SPEED),
+            cost_model: CostModel::default(),
+            pending_cycles: 0,
+            instruction_count: 0,
+            rom_loaded: false,
+            rom_length: 0,
+            analysis: Analysis::default(),
+            symbols: BTreeMap::new(),
+            hires: false,
+            force_hires: false,
+            megachip: MegaChip::new(),
+            quirks: Quirks::default(),
+            strict_mode: false,
+            extension: None,
+            rng: StdRng::from_entropy(),
+            rom_checksum: 0,
+            save_regions: Vec::new(),
+            save_ram_changed: false,
+            debugger: Debugger::default(),
+            history: VecDeque::new(),
+            history_limit: 0,
+            trace: Trace::default(),
+            profiler: Profiler::default(),
+            call_graph: CallGraph::default(),
+            cheats: Vec::new(),
+            recent_addresses: VecDeque::with_capacity(RECENT_INSTRUCTIONS),
+            crash_report: None,
+            waiting_for_key: false,
+            halted: false,
+            faulted: false,
         }
     }
 
+    /// Runs the instructions and timer updates due by `current_time`. Does
+    /// nothing until a ROM has been loaded, and lets time pass without
+    /// running anything while paused.
     pub fn tick(&mut self, current_time: f64) {
-        for _ in 0..self.timer.step(current_time) as u32 {
-            self.execute_next_instruction();
+        // Every instruction of a tick sees the keypad in the same state.
+        self.keypad.apply_queued();
+        self.second_keypad.apply_queued();
+        if !self.rom_loaded {
+            return;
+        }
+
+        if self.debugger.is_paused() || self.faulted {
+            self.skip_time(current_time);
+            return;
+        }
+
+        self.pending_cycles += self.timer.step(current_time) as u64;
+        while !self.halted && !self.faulted {
+            let opcode = self.get_opcode_at(self.pc);
+            let met_condition = self.debugger.met_condition(&self.machine());
+            if self
+                .debugger
+                .should_break(self.pc, opcode.value(), met_condition)
+            {
+                self.pending_cycles = 0;
+                break;
+            }
+            let cost = self.cost_model.cycles(&opcode) as u64;
+            if cost > self.pending_cycles {
+                break;
+            }
+            self.pending_cycles -= cost;
+            self.execute_watched();
+            self.debugger.check_depth(self.stack.len());
         }
+        if self.halted {
+            // Nothing is left to run the time on.
+            self.pending_cycles = 0;
+        }
+        self.apply_frozen_cheats();
 
         self.delay_timer.step(current_time);
         self.sound_timer.step(current_time);
     }
 
-    pub fn load_rom(&mut self, rom_data: &[u8]) {
+    /// Lets time pass up to `current_time` without running anything, e.g.
+    /// after the page was in the background for too long to catch up.
+    pub fn skip_time(&mut self, current_time: f64) {
+        self.timer.step(current_time);
+        self.delay_timer.skip(current_time);
+        self.sound_timer.skip(current_time);
+    }
+
+    /// Runs `duration` milliseconds of emulated time on top of the real time
+    /// the next tick catches up on, for fast-forwarding. The CPU and the
+    /// timers both speed up, so games run faster as a whole.
+    pub fn fast_forward(&mut self, duration: f64) {
+        self.timer.add_time(duration);
+        self.delay_timer.add_time(duration);
+        self.sound_timer.add_time(duration);
+    }
+
+    /// Returns how many instructions have been executed since the emulator
+    /// was created or reset, for measuring how fast it runs.
+    pub fn get_instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    /// Loads `rom_data` into memory at 0x200. Fails without changing
+    /// anything if it doesn't fit.
+    pub fn load_rom(&mut self, rom_data: &[u8]) -> Result<(), String> {
+        let max_length = self.memory.len() - PROGRAM_MEMORY_START;
+        if rom_data.len() > max_length {
+            // Mega-Chip ROMs can run past 4 KB with 24-bit addressing, which
+            // isn't supported, so they only get the usual memory.
+            let kind = if self.extension == Some(Extension::MegaChip) {
+                "Mega-Chip ROM"
+            } else {
+                "ROM"
+            };
+            return Err(format!(
+                "The {} is {} bytes, but only {} fit in memory",
+                kind,
+                rom_data.len(),
+                max_length
+            ));
+        }
+
         let end_index = PROGRAM_MEMORY_START + rom_data.len();
         self.memory[PROGRAM_MEMORY_START..end_index].clone_from_slice(rom_data);
+        self.rom_loaded = true;
+        self.rom_length = rom_data.len();
+        self.rom_checksum = checksum(rom_data);
+
+        let has_hires_entry =
+            self.get_opcode_at(PROGRAM_MEMORY_START as u16).value() == HIRES_ENTRY_OPCODE;
+        if self.force_hires || (!self.strict_mode && has_hires_entry) {
+            self.enter_hires_mode(has_hires_entry);
+        }
+        self.analysis = analyze(rom_data, self.pc, self.hires, self.extension);
+        Ok(())
+    }
+
+    /// Switches to the two-page 64x64 display used by hi-res CHIP-8 ROMs. If
+    /// the ROM starts like one, also skips the interpreter extension those
+    /// ROMs expect at 0x200-0x2BF.
+    fn enter_hires_mode(&mut self, has_hires_entry: bool) {
+        self.hires = true;
+        self.gfx.resize(WIDTH as u32, HIRES_HEIGHT as u32);
+        if has_hires_entry {
+            self.jump_to(HIRES_PROGRAM_START);
+        }
+    }
+
+    pub fn get_memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Returns `length` bytes of memory from `address`, or fewer if memory
+    /// ends first, for looking at a part of memory at a time.
+    pub fn memory_slice(&self, address: u16, length: usize) -> &[u8] {
+        let start = (address as usize).min(self.memory.len());
+        let end = start.saturating_add(length).min(self.memory.len());
+        &self.memory[start..end]
+    }
+
+    /// Overwrites memory starting at `address` with `data`, as debuggers do
+    /// when patching a program. Returns false without writing anything if
+    /// `data` doesn't fit in memory.
+    pub fn write_memory(&mut self, address: u16, data: &[u8]) -> bool {
+        let start = address as usize;
+        if start + data.len() > self.memory.len() {
+            return false;
+        }
+        self.memory[start..start + data.len()].copy_from_slice(data);
+        self.memory_written(start, data.len());
+        true
+    }
+
+    pub fn get_registers(&self) -> &[u8] {
+        &self.V
+    }
+
+    pub fn get_pc(&self) -> u16 {
+        self.pc
+    }
+
+    #[allow(non_snake_case)]
+    pub fn get_I(&self) -> u16 {
+        self.I
+    }
+
+    pub fn get_delay_timer(&self) -> u8 {
+        self.delay_timer.value()
+    }
+
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer.value()
+    }
+
+    /// Returns the keys of the keypad the running ROM sees pressed, one bit
+    /// per key.
+    pub fn get_pressed_keys(&self) -> u16 {
+        self.keypad.pressed_mask()
+    }
+
+    pub fn get_stack(&self) -> &[u16] {
+        &self.stack
+    }
+
+    /// Returns the state when the first instruction since the reset faulted,
+    /// e.g. an unknown opcode or a return with nothing on the stack, if any
+    /// has.
+    pub fn get_crash_report(&self) -> Option<&CrashReport> {
+        self.crash_report.as_ref()
+    }
+
+    /// Returns how many return addresses are on the stack, which is where
+    /// the next one goes.
+    pub fn get_stack_pointer(&self) -> usize {
+        self.stack.len()
+    }
+
+    pub fn get_delay_timer_state(&self) -> TimerState {
+        TimerState {
+            value: self.delay_timer.value(),
+            phase: self.delay_timer.phase(),
+        }
+    }
+
+    pub fn get_sound_timer_state(&self) -> TimerState {
+        TimerState {
+            value: self.sound_timer.value(),
+            phase: self.sound_timer.phase(),
+        }
+    }
+
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            memory: self.memory,
+            V: self.V,
+            I: self.I,
+            pc: self.pc,
+            stack: self.stack.clone(),
+            gfx: self.gfx.clone(),
+            hires: self.hires,
+            megachip: self.megachip.clone(),
+            rom_loaded: self.rom_loaded,
+            delay_timer: self.get_delay_timer_state(),
+            sound_timer: self.get_sound_timer_state(),
+            rng: self.rng.clone(),
+            halted: self.halted,
+            faulted: self.faulted,
+        }
+    }
+
+    /// Restores a snapshot taken by `save_state`, with the timers picking up
+    /// at their saved phase from `current_time`. The instructions before it
+    /// can't be stepped back through.
+    pub fn load_state(&mut self, state: &SaveState, current_time: f64) {
+        self.history.clear();
+        self.restore_state(state, current_time);
+    }
+
+    fn restore_state(&mut self, state: &SaveState, current_time: f64) {
+        self.call_graph.forget_running();
+        self.memory = state.memory;
+        self.V = state.V;
+        self.I = state.I;
+        self.pc = state.pc;
+        self.stack = state.stack.clone();
+        self.gfx = state.gfx.clone();
+        self.gfx.mark_changed();
+        self.hires = state.hires;
+        self.megachip = state.megachip.clone();
+        self.rom_loaded = state.rom_loaded;
+        self.rng = state.rng.clone();
+        self.waiting_for_key = false;
+        self.halted = state.halted;
+        self.faulted = state.faulted;
+
+        let TimerState { value, phase } = state.delay_timer;
+        self.delay_timer.restore(value, phase, current_time);
+        let TimerState { value, phase } = state.sound_timer;
+        self.sound_timer.restore(value, phase, current_time);
+
+        // Cycles owed from before the restore don't carry over.
+        self.timer.step(current_time);
+        self.pending_cycles = 0;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.debugger.is_paused()
+    }
+
+    /// Returns what the emulator is doing. A fault or the ROM ending
+    /// outranks a pause, and a pause outranks waiting for a key.
+    pub fn get_state(&self) -> State {
+        if !self.rom_loaded {
+            State::Loading
+        } else if self.faulted {
+            State::Faulted
+        } else if self.halted {
+            State::Halted
+        } else if self.debugger.is_paused() {
+            State::Paused
+        } else if self.waiting_for_key {
+            State::WaitingForKey
+        } else {
+            State::Running
+        }
+    }
+
+    pub fn pause(&mut self) {
+        self.debugger.pause();
+    }
+
+    /// Continues running after a pause, starting with the instruction at the
+    /// current address even if it has a breakpoint.
+    pub fn resume(&mut self) {
+        self.debugger.resume();
+    }
+
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.debugger.add_breakpoint(address);
+    }
+
+    /// Removes the breakpoint at `address` and returns whether there was one.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.debugger.remove_breakpoint(address)
+    }
+
+    pub fn get_breakpoints(&self) -> Vec<u16> {
+        self.debugger.breakpoints().collect()
+    }
+
+    /// Pauses before executing any instruction whose opcode matches
+    /// `pattern`.
+    pub fn add_opcode_break(&mut self, pattern: OpcodePattern) {
+        self.debugger.add_opcode_break(pattern);
+    }
+
+    /// Removes the break on `pattern` and returns whether there was one.
+    pub fn remove_opcode_break(&mut self, pattern: OpcodePattern) -> bool {
+        self.debugger.remove_opcode_break(pattern)
+    }
+
+    pub fn get_opcode_breaks(&self) -> &[OpcodePattern] {
+        self.debugger.opcode_breaks()
+    }
+
+    /// Pauses before executing any instruction while `condition` isn't 0.
+    pub fn add_break_condition(&mut self, condition: Expression) {
+        self.debugger.add_condition(condition);
+    }
+
+    /// Removes the break on `condition` and returns whether there was one.
+    pub fn remove_break_condition(&mut self, condition: &Expression) -> bool {
+        self.debugger.remove_condition(condition)
+    }
+
+    pub fn get_break_conditions(&self) -> &[Expression] {
+        self.debugger.conditions()
+    }
+
+    /// Returns the break condition that paused execution, until it resumes.
+    pub fn get_condition_hit(&self) -> Option<&Expression> {
+        self.debugger.condition_hit()
+    }
+
+    /// Returns the value of `expression` for the current machine state.
+    pub fn evaluate(&self, expression: &Expression) -> Result<u32, String> {
+        expression.evaluate(&self.machine())
+    }
+
+    fn machine(&self) -> Machine<'_> {
+        Machine {
+            memory: &self.memory,
+            registers: &self.V,
+            i: self.I,
+            pc: self.pc,
+            sp: self.stack.len(),
+            delay_timer: self.delay_timer.value(),
+            sound_timer: self.sound_timer.value(),
+        }
+    }
+
+    /// Pauses after any instruction that changes what `watchpoint` watches.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.debugger.add_watchpoint(watchpoint);
+    }
+
+    /// Removes `watchpoint` and returns whether there was one.
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        self.debugger.remove_watchpoint(watchpoint)
+    }
+
+    pub fn get_watchpoints(&self) -> &[Watchpoint] {
+        self.debugger.watchpoints()
+    }
+
+    /// Returns the changes to watched locations that paused execution, with
+    /// their old and new values, until it resumes.
+    pub fn get_watch_hits(&self) -> &[WatchHit] {
+        self.debugger.watch_hits()
+    }
+
+    /// Executes the next instruction, whether or not the emulator is paused.
+    /// Does nothing until a ROM has been loaded, or after a fault.
+    pub fn step_instruction(&mut self) {
+        self.keypad.apply_queued();
+        self.second_keypad.apply_queued();
+        if self.rom_loaded && !self.faulted {
+            self.execute_watched();
+        }
+    }
+
+    /// Executes the next instruction like `step_instruction`, except that a
+    /// subroutine call runs until it returns: the emulator resumes and pauses
+    /// again once the stack is back to its current depth.
+    pub fn step_over(&mut self) {
+        if self.rom_loaded && self.get_opcode_at(self.pc).value() & 0xf000 == 0x2000 {
+            self.debugger.run_to_depth(self.stack.len());
+        } else {
+            self.step_instruction();
+        }
+    }
+
+    /// Resumes until the current subroutine returns, pausing at the
+    /// instruction after its call. Does nothing outside a subroutine.
+    pub fn step_out(&mut self) {
+        if self.rom_loaded && !self.stack.is_empty() {
+            self.debugger.run_to_depth(self.stack.len() - 1);
+        }
+    }
+
+    /// Keeps the machine state from before each of the last `limit`
+    /// instructions executed, so that `step_back` can return to it. Off by
+    /// default, as it copies the state for every instruction.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit;
+        let excess = self.history.len().saturating_sub(limit);
+        self.history.drain(..excess);
+    }
+
+    /// Returns how many instructions `step_back` can undo.
+    pub fn get_history_length(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Returns how many bytes the states kept for `step_back` use.
+    pub fn get_history_bytes(&self) -> usize {
+        self.history.iter().map(SaveState::byte_len).sum()
+    }
+
+    /// Pauses and undoes the last instruction executed, restoring the state
+    /// from before it with the timers picking up from `current_time`. Returns
+    /// false if there's no state kept from before it.
+    pub fn step_back(&mut self, current_time: f64) -> bool {
+        match self.history.pop_back() {
+            Some(state) => {
+                self.pause();
+                self.restore_state(&state, current_time);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Runs a frame's worth of instructions at the current clock speed and
+    /// counts the delay and sound timers down once, whether or not the
+    /// emulator is paused. Breakpoints don't stop it, but a watchpoint does,
+    /// leaving the timers as they were. Does nothing until a ROM has been
+    /// loaded, or after a fault.
+    pub fn step_frame(&mut self) {
+        self.keypad.apply_queued();
+        self.second_keypad.apply_queued();
+        if !self.rom_loaded || self.faulted {
+            return;
+        }
+
+        let mut cycles = (FRAME_DURATION / self.timer.interval()) as u64;
+        loop {
+            let cost = self.cost_model.cycles(&self.get_opcode_at(self.pc)) as u64;
+            if cost > cycles {
+                break;
+            }
+            cycles -= cost;
+            if self.execute_watched() || self.faulted {
+                return;
+            }
+        }
+
+        for timer in [&mut self.delay_timer, &mut self.sound_timer] {
+            timer.set_value(timer.value().saturating_sub(1));
+        }
+    }
+
+    /// Disassembles `count` instructions starting at `address`. Words that
+    /// aren't valid instructions are shown as data.
+    pub fn disassemble(&self, address: u16, count: usize) -> Vec<DisassembledInstruction> {
+        (address as usize..self.memory.len() - 1)
+            .step_by(2)
+            .take(count)
+            .map(|address| {
+                let opcode = self.get_opcode_at(address as u16);
+                if self.is_data(address as u16) {
+                    return DisassembledInstruction {
+                        address: address as u16,
+                        opcode: opcode.value(),
+                        text: data_directive(&opcode),
+                        target: None,
+                    };
+                }
+                let target = Instruction::decode(&opcode, self.hires, self.extension)
+                    .and_then(|instruction| instruction.target())
+                    .map(|(target, _)| target);
+                DisassembledInstruction {
+                    address: address as u16,
+                    opcode: opcode.value(),
+                    text: self.describe(&opcode),
+                    target,
+                }
+            })
+            .collect()
+    }
+
+    /// Names the addresses in the program the ROM jumps to and calls, such as
+    /// `sub_0240` or `loop_0212`, from the jumps and calls in the ROM and the
+    /// subroutines it has called so far, along with the symbols set, which
+    /// take the place of the names made up.
+    pub fn get_labels(&self) -> BTreeMap<u16, String> {
+        let end = PROGRAM_MEMORY_START + self.rom_length;
+        let jumps = (PROGRAM_MEMORY_START..end.min(self.memory.len() - 1))
+            .step_by(2)
+            .filter(|&address| !self.is_data(address as u16))
+            .filter_map(|address| {
+                let opcode = self.get_opcode_at(address as u16);
+                let instruction = Instruction::decode(&opcode, self.hires, self.extension)?;
+                let (target, is_call) = instruction.target()?;
+                // Zeroed data reads as calls to 0.
+                (target as usize >= PROGRAM_MEMORY_START).then_some((
+                    address as u16,
+                    target,
+                    is_call,
+                ))
+            });
+        let called = self.call_graph.calls().into_iter().map(|call| call.address);
+        let mut labels = synthesize_labels(jumps, called);
+        labels.extend(self.symbols.clone());
+        labels
+    }
+
+    /// Names addresses in the ROM for debugging, like the labels in the
+    /// source it was assembled from.
+    pub fn set_symbols(&mut self, symbols: BTreeMap<u16, String>) {
+        self.symbols = symbols;
+    }
+
+    pub fn get_symbols(&self) -> &BTreeMap<u16, String> {
+        &self.symbols
+    }
+
+    /// Returns the assembly for `opcode` in the current mode, or a DB
+    /// directive if it isn't a valid instruction.
+    fn describe(&self, opcode: &Opcode) -> String {
+        match Instruction::decode(opcode, self.hires, self.extension) {
+            Some(instruction) => instruction.to_string(),
+            None => data_directive(opcode),
+        }
+    }
+
+    /// Whether the instruction at `address` is in the ROM's data rather than
+    /// its code, so is disassembled as DB even if it decodes.
+    fn is_data(&self, address: u16) -> bool {
+        self.analysis.byte_use(address) == Some(ByteUse::Data)
+    }
+
+    /// Returns the addresses the ROM loaded last takes up.
+    pub fn get_rom_range(&self) -> Range<u16> {
+        let start = PROGRAM_MEMORY_START as u16;
+        start..start + self.rom_length as u16
+    }
+
+    /// Returns the addresses in `range` of the `height`-row sprites laid end
+    /// to end from its start that could be graphics: ones with a pixel on
+    /// and, if `data_only`, a byte the ROM reads as data.
+    pub fn find_sprites(&self, range: Range<u16>, height: u16, data_only: bool) -> Vec<u16> {
+        let end = range.end.min(self.memory.len() as u16);
+        let height = height.max(1);
+        (range.start..end)
+            .step_by(height as usize)
+            .filter(|&address| {
+                let rows = address..address.saturating_add(height).min(end);
+                let lit = self.memory[rows.start as usize..rows.end as usize]
+                    .iter()
+                    .any(|&row| row != 0);
+                lit && (!data_only || rows.clone().any(|row| self.is_data(row)))
+            })
+            .collect()
+    }
+
+    /// Returns the parts of the ROM loaded last that can't be reached from
+    /// its start and aren't read as data either, for ROM authors to find
+    /// dead code. Code only jumped to with `JP V0, NNN` is counted too.
+    pub fn get_unreachable_code(&self) -> &[Range<u16>] {
+        self.analysis.unreachable()
+    }
+
+    /// Keeps a trace of the last `limit` instructions executed, with the
+    /// registers each changed, or stops tracing if it's 0. Off by default.
+    pub fn set_trace_limit(&mut self, limit: usize) {
+        self.trace.set_limit(limit);
+    }
+
+    /// Counts how many times the instruction at each address, and each kind
+    /// of instruction, is executed, starting from zero, or stops counting.
+    /// Off by default.
+    pub fn set_profiling(&mut self, enabled: bool) {
+        self.profiler.set_enabled(enabled, self.memory.len());
+    }
+
+    pub fn reset_profile(&mut self) {
+        self.profiler.reset();
+    }
+
+    /// Returns the `count` addresses executed the most while profiling,
+    /// with how many times, the most first.
+    pub fn get_hot_spots(&self, count: usize) -> Vec<(u16, u64)> {
+        self.profiler.hot_spots(count)
+    }
+
+    /// Returns each kind of instruction executed while profiling, by its
+    /// opcode pattern like `DXYN`, with how many times, the most first.
+    pub fn get_opcode_stats(&self) -> Vec<(&'static str, u64)> {
+        self.profiler.pattern_counts()
+    }
+
+    /// Returns the subroutine calls made since the emulator was created or
+    /// reset, by the subroutine they were made from, with how many times.
+    /// Calls made before a state was loaded are counted as from the main
+    /// program until they return.
+    pub fn get_calls(&self) -> Vec<Call> {
+        self.call_graph.calls()
+    }
+
+    /// Returns how many instructions were executed while profiling.
+    pub fn get_profiled_count(&self) -> u64 {
+        self.profiler.total()
+    }
+
+    /// Returns the traced instructions, oldest first.
+    pub fn get_trace(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.trace.entries().iter()
+    }
+
+    /// Returns how many bytes the traced instructions use.
+    pub fn get_trace_bytes(&self) -> usize {
+        self.trace.byte_len()
+    }
+
+    /// Returns whether the buzzer should be sounding, which is whenever the
+    /// sound timer is nonzero.
+    pub fn is_sound_playing(&self) -> bool {
+        self.sound_timer.value() > 0
+    }
+
+    pub fn get_rom_checksum(&self) -> u32 {
+        self.rom_checksum
+    }
+
+    /// Sets the memory ranges that are persisted between sessions. Writes to
+    /// them are reported by `save_ram_needs_persisting`.
+    pub fn set_save_regions(&mut self, regions: Vec<SaveRegion>) {
+        self.save_regions = regions;
+    }
+
+    /// Returns the contents of all save regions, concatenated in order.
+    pub fn get_save_ram(&self) -> Vec<u8> {
+        self.save_regions
+            .iter()
+            .flat_map(|region| &self.memory[region.start()..region.start() + region.len()])
+            .copied()
+            .collect()
+    }
+
+    /// Restores save regions from data returned by `get_save_ram`. Data that
+    /// doesn't fit the current regions is ignored.
+    pub fn load_save_ram(&mut self, data: &[u8]) {
+        let mut data = data;
+        for region in &self.save_regions {
+            let len = region.len().min(data.len());
+            self.memory[region.start()..region.start() + len].copy_from_slice(&data[..len]);
+            data = &data[len..];
+        }
+    }
+
+    /// Returns whether the ROM has written to a save region since the last
+    /// call.
+    pub fn save_ram_needs_persisting(&mut self) -> bool {
+        let res = self.save_ram_changed;
+        self.save_ram_changed = false;
+        res
+    }
+
+    /// Replaces the cheats with `cheats`, writing each of their values.
+    pub fn set_cheats(&mut self, cheats: Vec<Cheat>) {
+        self.cheats.clear();
+        for cheat in cheats {
+            self.add_cheat(cheat);
+        }
+    }
+
+    /// Writes the value of `cheat`, and keeps writing it every tick if it's
+    /// frozen. Replaces any cheat on the same address.
+    pub fn add_cheat(&mut self, cheat: Cheat) {
+        self.cheats.retain(|other| other.address != cheat.address);
+        self.write_memory(cheat.address, &[cheat.value]);
+        self.cheats.push(cheat);
+    }
+
+    /// Removes `cheat` and returns whether it was there. The byte it set
+    /// keeps its value until the ROM changes it.
+    pub fn remove_cheat(&mut self, cheat: Cheat) -> bool {
+        let count = self.cheats.len();
+        self.cheats.retain(|&other| other != cheat);
+        self.cheats.len() != count
+    }
+
+    pub fn get_cheats(&self) -> &[Cheat] {
+        &self.cheats
+    }
+
+    fn apply_frozen_cheats(&mut self) {
+        for cheat in &self.cheats {
+            if cheat.mode == CheatMode::Freeze {
+                self.memory[cheat.address as usize] = cheat.value;
+            }
+        }
+    }
+
+    fn memory_written(&mut self, start: usize, len: usize) {
+        if self
+            .save_regions
+            .iter()
+            .any(|region| region.overlaps(start, len))
+        {
+            self.save_ram_changed = true;
+        }
     }
 
     pub fn reset(&mut self, current_time: f64) {
@@ -99,93 +957,288 @@ impl Chip8Emulator {
         self.gfx.get_pixel(x, y)
     }
 
-    pub fn gfx_needs_rerender(&mut self) -> bool {
-        self.gfx.needs_rerender()
+    /// Returns row `y` of the display packed into bits, the leftmost pixel in
+    /// the most significant bit used, so a 64-pixel row reads left to right
+    /// from bit 63. Panics on displays wider than 64 pixels, i.e. Mega-Chip's.
+    pub fn get_gfx_row(&self, y: u32) -> u64 {
+        self.gfx.get_row(y)
+    }
+
+    /// Returns the display's rows packed as by `get_gfx_row`, top to bottom.
+    pub fn gfx_rows(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.gfx.get_height()).map(move |y| self.gfx.get_row(y))
+    }
+
+    pub fn get_gfx_plane_count(&self) -> usize {
+        self.gfx.plane_count()
+    }
+
+    /// Returns the pixels of a display plane packed 32 to a word, row by row,
+    /// for renderers that read the whole display at once.
+    pub fn get_gfx_plane_bits(&self, plane: usize) -> Option<&[u32]> {
+        self.gfx.get_plane_bits(plane)
+    }
+
+    /// Returns the color index of every pixel, row by row, on an indexed
+    /// display.
+    pub fn get_gfx_indices(&self) -> Option<&[u8]> {
+        self.gfx.get_indices()
+    }
+
+    /// Returns whether the machine has a second keypad, as the CHIP-8X does.
+    pub fn has_second_keypad(&self) -> bool {
+        self.extension == Some(Extension::Chip8X)
+    }
+
+    /// Returns whether the display is colored by zones, as on the CHIP-8X,
+    /// instead of drawn in the palette's two colors.
+    pub fn has_color_zones(&self) -> bool {
+        self.extension == Some(Extension::Chip8X)
     }
 
-    pub fn keydown(&mut self, key: u8) {
-        self.keypad.keydown(key);
+    /// Returns the color of pixels that are off on displays with color zones.
+    pub fn get_gfx_background(&self) -> [u8; 3] {
+        CHIP8X_BACKGROUND_COLORS[self.gfx.get_background() as usize]
     }
 
-    pub fn keyup(&mut self, key: u8) {
-        self.keypad.keyup(key);
+    /// Returns the color the pixel has when on, on displays with color zones.
+    pub fn get_gfx_foreground(&self, x: u32, y: u32) -> [u8; 3] {
+        CHIP8X_FOREGROUND_COLORS[self.gfx.get_color(x, y) as usize]
     }
 
-    pub fn set_ticks_per_second(&mut self, ticks_per_second: f64) {
-        self.timer.set_interval(1000.0 / ticks_per_second);
+    /// Returns whether the display holds a color index per pixel, as in
+    /// Mega-Chip mode.
+    pub fn is_gfx_indexed(&self) -> bool {
+        self.gfx.is_indexed()
+    }
+
+    /// Returns the palette color of the pixel on an indexed display.
+    pub fn get_gfx_indexed_color(&self, x: u32, y: u32) -> [u8; 3] {
+        self.megachip.color(self.gfx.get_index(x, y))
+    }
+
+    /// Returns the runs of display rows changed since the last call, which
+    /// need to be rendered again.
+    pub fn take_gfx_dirty_rows(&mut self) -> Vec<Range<u32>> {
+        self.gfx.take_dirty_rows()
+    }
+
+    /// Marks the whole display as changed, so it's rendered again even though
+    /// no pixel changed, e.g. after switching palettes.
+    pub fn mark_gfx_changed(&mut self) {
+        self.gfx.mark_changed();
+    }
+
+    /// Presses or releases `key` at the start of the next tick or step. Input
+    /// arriving between ticks is queued so the instructions of a tick all see
+    /// the same keys pressed, and a key pressed and released before the next
+    /// tick is still seen pressed for one.
+    pub fn queue_key(&mut self, key: u8, pressed: bool) {
+        self.keypad.queue_key(key, pressed);
+    }
+
+    /// Queues a press or release of a key on the CHIP-8X's second keypad, as
+    /// `queue_key` does.
+    pub fn queue_second_key(&mut self, key: u8, pressed: bool) {
+        self.second_keypad.queue_key(key, pressed);
+    }
+
+    /// Replaces the built-in font that FX29 points to with `font`, five bytes
+    /// per digit from 0 to F.
+    pub fn set_font(&mut self, font: &[u8; 80]) {
+        self.memory[FONT_MEMORY_START..FONT_MEMORY_START + font.len()].copy_from_slice(font);
+    }
+
+    /// Limits the keypad to the keys in `mask`, one bit per key, as on
+    /// machines with fewer than 16 keys. Other keys never count as pressed,
+    /// so EX9E skips for them and FX0A ignores them.
+    pub fn set_available_keys(&mut self, mask: u16) {
+        self.keypad.set_available_keys(mask);
+    }
+
+    /// Sets the speed of the simulated CPU clock in Hz. How many instructions
+    /// that amounts to depends on the cost model.
+    pub fn set_clock_speed(&mut self, hz: f64) {
+        self.timer.set_interval(1000.0 / hz);
+    }
+
+    pub fn set_cost_model(&mut self, cost_model: CostModel) {
+        self.cost_model = cost_model;
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    /// In strict mode only the original CHIP-8 instruction set is accepted:
+    /// SUPER-CHIP and XO-CHIP instructions fault, and hi-res ROMs are not
+    /// detected. Takes effect for hi-res detection on the next `load_rom`.
+    pub fn set_strict_mode(&mut self, strict_mode: bool) {
+        self.strict_mode = strict_mode;
+    }
+
+    /// Enables the instructions of `extension` that would otherwise be decoded
+    /// differently or not at all. Only CHIP-8X and Mega-Chip need enabling so
+    /// far.
+    pub fn set_extension(&mut self, extension: Option<Extension>) {
+        self.extension = extension;
+        self.gfx.mark_changed();
+    }
+
+    /// Makes `load_rom` run ROMs in hi-res mode even if they don't start with
+    /// the jump hi-res CHIP-8 ROMs are detected by, and even in strict mode.
+    pub fn set_force_hires(&mut self, force_hires: bool) {
+        self.force_hires = force_hires;
+    }
+
+    /// Makes CXNN produce the same sequence of values on every run.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Executes the next instruction, and pauses if it changes a watched
+    /// location. Returns whether it did. Keeps the state from before it if
+    /// there's a history limit.
+    fn execute_watched(&mut self) -> bool {
+        if self.history_limit > 0 {
+            if self.history.len() >= self.history_limit {
+                self.history.pop_front();
+            }
+            self.history.push_back(self.save_state());
+        }
+        let before = self.debugger.watched_values(&self.memory, &self.V);
+        let pc = self.pc;
+        self.execute_next_instruction();
+        match before {
+            Some(before) => self
+                .debugger
+                .check_watchpoints(pc, &before, &self.memory, &self.V),
+            None => false,
+        }
     }
 
     fn execute_next_instruction(&mut self) {
+        if !self.trace.is_enabled() {
+            self.execute_untraced();
+            return;
+        }
+        let address = self.pc;
+        let opcode = self.get_opcode_at(address);
+        let text = self.describe(&opcode);
+        let (v, i) = (self.V, self.I);
+        self.execute_untraced();
+        let entry = TraceEntry::new(address, opcode.value(), text, (&v, i), (&self.V, self.I));
+        self.trace.push(entry);
+    }
+
+    fn execute_untraced(&mut self) {
+        self.instruction_count += 1;
+        self.profiler.record(self.pc);
+        if self.recent_addresses.len() == RECENT_INSTRUCTIONS {
+            self.recent_addresses.pop_front();
+        }
+        self.recent_addresses.push_back(self.pc);
+        self.waiting_for_key = false;
+        if self.pc as usize + 1 >= self.memory.len() {
+            self.fault_at(
+                self.pc,
+                "Program counter past the end of memory".to_string(),
+            );
+            return;
+        }
         let opcode = self.get_next_opcode();
-        // web_sys::console::log_1(&format!("{:04X}", opcode.value()).into());
-        // web_sys::console::log_1(&format!("{:?}", self.V).into());
-
-        match opcode.get_nibble(0) {
-            0 => match opcode.get_nibbles_from(1) {
-                0x0e0 => self.clear_screen(),
-                0x0ee => self.return_subroutine(),
-                address => self.execute_subroutine(address),
-            },
-            1 => self.jump_to(opcode.get_nibbles_from(1)),
-            2 => self.execute_subroutine(opcode.get_nibbles_from(1)),
-            3 => self.skip_if_eq(opcode.get_nibble(1), opcode.get_nibbles_from(2) as u8),
-            4 => self.skip_if_ne(opcode.get_nibble(1), opcode.get_nibbles_from(2) as u8),
-            5 => match opcode.get_nibble(3) {
-                0 => self.skip_if_eq_reg(opcode.get_nibble(1), opcode.get_nibble(2)),
-                _ => Chip8Emulator::invalid_instruction(opcode),
-            },
-            6 => self.store(opcode.get_nibble(1), opcode.get_nibbles_from(2) as u8),
-            7 => self.add(opcode.get_nibble(1), opcode.get_nibbles_from(2) as u8),
-            8 => match opcode.get_nibble(3) {
-                0 => self.store_reg(opcode.get_nibble(1), opcode.get_nibble(2)),
-                1 => self.store_reg_or(opcode.get_nibble(1), opcode.get_nibble(2)),
-                2 => self.store_reg_and(opcode.get_nibble(1), opcode.get_nibble(2)),
-                3 => self.store_reg_xor(opcode.get_nibble(1), opcode.get_nibble(2)),
-                4 => self.add_reg(opcode.get_nibble(1), opcode.get_nibble(2)),
-                5 => self.sub_reg(opcode.get_nibble(1), opcode.get_nibble(2)),
-                6 => self.store_reg_shr1(opcode.get_nibble(1), opcode.get_nibble(2)),
-                7 => self.store_reg_sub(opcode.get_nibble(1), opcode.get_nibble(2)),
-                0xe => self.store_reg_shl1(opcode.get_nibble(1), opcode.get_nibble(2)),
-                _ => Chip8Emulator::invalid_instruction(opcode),
-            },
-            9 => match opcode.get_nibble(3) {
-                0 => self.skip_if_ne_reg(opcode.get_nibble(1), opcode.get_nibble(2)),
-                _ => Chip8Emulator::invalid_instruction(opcode),
-            },
-            0xa => self.store_address(opcode.get_nibbles_from(1)),
-            0xb => self.jump_to_plus_v0(opcode.get_nibbles_from(1)),
-            0xc => self.store_random(opcode.get_nibble(1), opcode.get_nibbles_from(2) as u8),
-            0xd => self.draw_sprite(
-                opcode.get_nibble(1),
-                opcode.get_nibble(2),
-                opcode.get_nibble(3),
-            ),
-            0xe => match opcode.get_nibbles_from(2) {
-                0x9e => self.skip_if_pressed(opcode.get_nibble(1)),
-                0xa1 => self.skip_if_not_pressed(opcode.get_nibble(1)),
-                _ => Chip8Emulator::invalid_instruction(opcode),
-            },
-            0xf => match opcode.get_nibbles_from(2) {
-                0x07 => self.store_delay(opcode.get_nibble(1)),
-                0x0a => self.wait_for_keypress(opcode.get_nibble(1)),
-                0x15 => self.set_delay(opcode.get_nibble(1)),
-                0x18 => self.set_sound(opcode.get_nibble(1)),
-                0x1e => self.add_to_I(opcode.get_nibble(1)),
-                0x29 => self.store_digit_address(opcode.get_nibble(1)),
-                0x33 => self.store_bcd(opcode.get_nibble(1)),
-                0x55 => self.store_regs_in_memory(opcode.get_nibble(1)),
-                0x65 => self.store_memory_in_regs(opcode.get_nibble(1)),
-                _ => Chip8Emulator::invalid_instruction(opcode),
-            },
-            _ => Chip8Emulator::invalid_instruction(opcode),
+
+        if self.strict_mode {
+            if let Some(extension) = Extension::required_by(&opcode) {
+                self.fault(format!(
+                    "{} instruction {:04X} is not allowed in strict CHIP-8 mode",
+                    extension,
+                    opcode.value()
+                ));
+                return;
+            }
+        }
+
+        match Instruction::decode(&opcode, self.hires, self.extension) {
+            Some(instruction) => {
+                self.profiler.record_pattern(instruction.pattern());
+                self.execute(instruction);
+            }
+            None => self.fault(format!("Invalid instruction {:04X}", opcode.value())),
+        }
+    }
+
+    fn execute(&mut self, instruction: Instruction) {
+        use Instruction::*;
+
+        match instruction {
+            ClearScreen => self.clear_screen(),
+            ReturnSubroutine => self.return_subroutine(),
+            MachineSubroutine(address) => self.execute_subroutine(address),
+            JumpTo(address) => {
+                // A jump to itself is how most ROMs end, as CHIP-8 has no
+                // instruction for it.
+                if address == self.pc - 2 {
+                    self.halted = true;
+                }
+                self.jump_to(address)
+            }
+            ExecuteSubroutine(address) => self.execute_subroutine(address),
+            SkipIfEq(x, value) => self.skip_if_eq(x, value),
+            SkipIfNe(x, value) => self.skip_if_ne(x, value),
+            SkipIfEqReg(x, y) => self.skip_if_eq_reg(x, y),
+            Store(x, value) => self.store(x, value),
+            Add(x, value) => self.add(x, value),
+            StoreReg(x, y) => self.store_reg(x, y),
+            StoreRegOr(x, y) => self.store_reg_or(x, y),
+            StoreRegAnd(x, y) => self.store_reg_and(x, y),
+            StoreRegXor(x, y) => self.store_reg_xor(x, y),
+            AddReg(x, y) => self.add_reg(x, y),
+            SubReg(x, y) => self.sub_reg(x, y),
+            StoreRegShr1(x, y) => self.store_reg_shr1(x, y),
+            StoreRegSub(x, y) => self.store_reg_sub(x, y),
+            StoreRegShl1(x, y) => self.store_reg_shl1(x, y),
+            SkipIfNeReg(x, y) => self.skip_if_ne_reg(x, y),
+            StoreAddress(address) => self.store_address(address),
+            JumpToPlusV0(address) => self.jump_to_plus_v0(address),
+            StoreRandom(x, mask) => self.store_random(x, mask),
+            DrawSprite(x, y, n) => self.draw_sprite(x, y, n),
+            SkipIfPressed(x) => self.skip_if_pressed(x),
+            SkipIfNotPressed(x) => self.skip_if_not_pressed(x),
+            StoreDelay(x) => self.store_delay(x),
+            WaitForKeypress(x) => self.wait_for_keypress(x),
+            SetDelay(x) => self.set_delay(x),
+            SetSound(x) => self.set_sound(x),
+            AddToI(x) => self.add_to_I(x),
+            StoreDigitAddress(x) => self.store_digit_address(x),
+            StoreBcd(x) => self.store_bcd(x),
+            StoreRegsInMemory(x) => self.store_regs_in_memory(x),
+            StoreMemoryInRegs(x) => self.store_memory_in_regs(x),
+            StepBackground => self.step_background(),
+            AddNibbles(x, y) => self.add_nibbles(x, y),
+            SetZoneColor(x, y, n) => self.set_zone_color(x, y, n),
+            SkipIfPressed2(x) => self.skip_if_pressed2(x),
+            SkipIfNotPressed2(x) => self.skip_if_not_pressed2(x),
+            DisableMegaChip => self.set_megachip_mode(false),
+            EnableMegaChip => self.set_megachip_mode(true),
+            LoadPalette(n) => self.load_palette(n),
+            SetSpriteWidth(nn) => self.megachip.sprite_width = sprite_size(nn),
+            SetSpriteHeight(nn) => self.megachip.sprite_height = sprite_size(nn),
+            SetCollisionIndex(nn) => self.megachip.collision_index = nn,
         }
     }
 
     fn get_next_opcode(&mut self) -> Opcode {
-        let opcode = ((self.memory[self.pc as usize] as u16) << 8)
-            + self.memory[self.pc as usize + 1] as u16;
+        let opcode = self.get_opcode_at(self.pc);
         self.pc += 2;
-        Opcode::new(opcode)
+        opcode
+    }
+
+    /// Returns the opcode at `address`, with 0 for the bytes past the end of
+    /// memory.
+    fn get_opcode_at(&self, address: u16) -> Opcode {
+        let byte = |address: usize| self.memory.get(address).copied().unwrap_or(0) as u16;
+        Opcode::new((byte(address as usize) << 8) + byte(address as usize + 1))
     }
 
     fn clear_screen(&mut self) {
@@ -193,11 +1246,22 @@ impl Chip8Emulator {
     }
 
     fn return_subroutine(&mut self) {
-        self.pc = self.stack.pop().expect("Cannot pop empty stack");
+        match self.stack.pop() {
+            Some(address) => self.pc = address,
+            None => {
+                self.fault("Return with an empty stack".to_string());
+                return;
+            }
+        }
+        self.call_graph.ret();
     }
 
     fn execute_subroutine(&mut self, address: u16) {
-        self.stack.push(self.pc);
+        if self.stack.try_push(self.pc).is_err() {
+            self.fault(format!("Stack overflow calling {:03X}", address));
+            return;
+        }
+        self.call_graph.call(address);
         self.jump_to(address);
     }
 
@@ -236,15 +1300,24 @@ impl Chip8Emulator {
     }
 
     fn store_reg_or(&mut self, x: u8, y: u8) {
-        self.V[x as usize] = self.V[x as usize] | self.V[y as usize]
+        self.V[x as usize] |= self.V[y as usize];
+        self.reset_vf_after_logic();
     }
 
     fn store_reg_and(&mut self, x: u8, y: u8) {
-        self.V[x as usize] = self.V[x as usize] & self.V[y as usize]
+        self.V[x as usize] &= self.V[y as usize];
+        self.reset_vf_after_logic();
     }
 
     fn store_reg_xor(&mut self, x: u8, y: u8) {
-        self.V[x as usize] = self.V[x as usize] ^ self.V[y as usize]
+        self.V[x as usize] ^= self.V[y as usize];
+        self.reset_vf_after_logic();
+    }
+
+    fn reset_vf_after_logic(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.V[0xf] = 0;
+        }
     }
 
     fn add_reg(&mut self, x: u8, y: u8) {
@@ -259,7 +1332,10 @@ impl Chip8Emulator {
         self.V[0xf] = (!borrow) as u8;
     }
 
-    fn store_reg_shr1(&mut self, x: u8, _: u8) {
+    fn store_reg_shr1(&mut self, x: u8, y: u8) {
+        if self.quirks.shift_uses_vy {
+            self.store_reg(x, y);
+        }
         self.V[0xf] = self.V[x as usize] & 0x1;
         self.V[x as usize] >>= 1;
     }
@@ -270,7 +1346,10 @@ impl Chip8Emulator {
         self.V[0xf] = (!borrow) as u8;
     }
 
-    fn store_reg_shl1(&mut self, x: u8, _: u8) {
+    fn store_reg_shl1(&mut self, x: u8, y: u8) {
+        if self.quirks.shift_uses_vy {
+            self.store_reg(x, y);
+        }
         self.V[0xf] = self.V[x as usize] & 0x80;
         self.V[x as usize] <<= 1;
     }
@@ -286,29 +1365,57 @@ impl Chip8Emulator {
     }
 
     fn jump_to_plus_v0(&mut self, address: u16) {
-        self.jump_to(address + self.V[0] as u16)
+        let offset_register = if self.quirks.jump_uses_vx {
+            address >> 8
+        } else {
+            0
+        };
+        let target = address + self.V[offset_register as usize] as u16;
+        if target as usize >= self.memory.len() {
+            self.fault(format!("Jump to {:03X} past the end of memory", target));
+            return;
+        }
+        self.jump_to(target)
     }
 
     fn store_random(&mut self, x: u8, mask: u8) {
-        self.V[x as usize] = rand::random::<u8>() & mask;
+        self.V[x as usize] = self.rng.gen::<u8>() & mask;
     }
 
     fn draw_sprite(&mut self, x: u8, y: u8, n: u8) {
-        let x = self.V[x as usize] % WIDTH;
-        let y = self.V[y as usize] % HEIGHT;
+        if self.gfx.is_indexed() {
+            self.draw_indexed_sprite(x, y);
+            return;
+        }
+
+        let start = self.I as usize;
+        if self.memory.get(start..start + n as usize).is_none() {
+            self.fault_past_memory("Reading", n as usize);
+            return;
+        }
+
+        let width = self.gfx.get_width();
+        let height = self.gfx.get_height();
+        let x = self.V[x as usize] as u32 % width;
+        let y = self.V[y as usize] as u32 % height;
 
         self.V[0xf] = 0;
 
-        for dy in 0..n {
-            let row = self.memory[self.I as usize + dy as usize];
-            for dx in 0..8 {
-                if (row >> (7 - dx) & 1) == 1 {
-                    if self
-                        .gfx
-                        .toggle(((x + dx) % WIDTH) as u32, ((y + dy) % HEIGHT) as u32)
-                    {
-                        self.V[0xf] = 1;
-                    }
+        for dy in 0..n {
+            let row = self.memory[self.I as usize + dy as usize];
+            let pixel_y = y + dy as u32;
+            if self.quirks.clip_sprites && pixel_y >= height {
+                break;
+            }
+
+            for dx in 0..SPRITE_WIDTH {
+                let pixel_x = x + dx;
+                if self.quirks.clip_sprites && pixel_x >= width {
+                    break;
+                }
+
+                if sprite_pixel(row, dx) && self.gfx.toggle(pixel_x % width, pixel_y % height) {
+                    self.V[0xf] = 1;
                 }
             }
         }
@@ -339,6 +1446,7 @@ impl Chip8Emulator {
                 return;
             }
         }
+        self.waiting_for_key = true;
     }
 
     fn set_delay(&mut self, x: u8) {
@@ -362,37 +1470,228 @@ impl Chip8Emulator {
 
     fn store_bcd(&mut self, x: u8) {
         let value = self.V[x as usize];
-        self.memory[self.I as usize] = value / 100;
-        self.memory[self.I as usize + 1] = (value / 10) % 10;
-        self.memory[self.I as usize + 2] = value % 10;
+        let start = self.I as usize;
+        match self.memory.get_mut(start..start + 3) {
+            Some(digits) => digits.copy_from_slice(&[value / 100, (value / 10) % 10, value % 10]),
+            None => {
+                self.fault_past_memory("Writing", 3);
+                return;
+            }
+        }
+        self.memory_written(start, 3);
     }
 
     fn store_regs_in_memory(&mut self, x: u8) {
-        self.memory[self.I as usize..=self.I as usize + x as usize]
-            .copy_from_slice(&self.V[..=x as usize]);
-        // self.I += x as u16 + 1;
+        let start = self.I as usize;
+        match self.memory.get_mut(start..=start + x as usize) {
+            Some(data) => data.copy_from_slice(&self.V[..=x as usize]),
+            None => {
+                self.fault_past_memory("Writing", x as usize + 1);
+                return;
+            }
+        }
+        self.memory_written(start, x as usize + 1);
+        if self.quirks.load_store_increments_i {
+            self.I += x as u16 + 1;
+        }
     }
 
     fn store_memory_in_regs(&mut self, x: u8) {
-        self.V[..=x as usize]
-            .copy_from_slice(&self.memory[self.I as usize..=self.I as usize + x as usize]);
-        // self.I += x as u16 + 1;
+        let start = self.I as usize;
+        match self.memory.get(start..=start + x as usize) {
+            Some(data) => self.V[..=x as usize].copy_from_slice(data),
+            None => {
+                self.fault_past_memory("Reading", x as usize + 1);
+                return;
+            }
+        }
+        if self.quirks.load_store_increments_i {
+            self.I += x as u16 + 1;
+        }
+    }
+
+    fn step_background(&mut self) {
+        let background = (self.gfx.get_background() + 1) % CHIP8X_BACKGROUND_COLORS.len() as u8;
+        self.gfx.set_background(background);
+    }
+
+    /// Adds VY to VX one nibble at a time, without carrying from the low
+    /// nibble into the high one.
+    fn add_nibbles(&mut self, x: u8, y: u8) {
+        let (vx, vy) = (self.V[x as usize], self.V[y as usize]);
+        self.V[x as usize] = (vx & 0xf0).wrapping_add(vy & 0xf0) | (vx.wrapping_add(vy) & 0xf);
+    }
+
+    /// Colors a rectangle of color zones with the color in VY. The low nibble
+    /// of VX is the first 8-pixel column and its high nibble how many more
+    /// columns follow. With N = 0, V(X+1) selects zone rows 4 pixels high the
+    /// same way; otherwise it is the first pixel row and N rows are colored.
+    fn set_zone_color(&mut self, x: u8, y: u8, n: u8) {
+        let horizontal = self.V[x as usize];
+        let vertical = self.V[(x as usize + 1) & 0xf];
+        let color = self.V[y as usize] & 0x7;
+
+        let first_column = (horizontal & 0xf) as u32;
+        let columns = first_column..=first_column + (horizontal >> 4) as u32;
+        let rows = if n == 0 {
+            let first_row = (vertical & 0xf) as u32 * COLOR_ZONE_HEIGHT;
+            first_row..first_row + ((vertical >> 4) as u32 + 1) * COLOR_ZONE_HEIGHT
+        } else {
+            vertical as u32..vertical as u32 + n as u32
+        };
+
+        for y in rows {
+            for column in columns.clone() {
+                self.gfx.set_color(column, y, color);
+            }
+        }
+    }
+
+    /// Switches between the 256x192 indexed display of Mega-Chip mode and the
+    /// regular display. Either way the display starts out clear.
+    fn set_megachip_mode(&mut self, enabled: bool) {
+        self.gfx = if enabled {
+            Graphics::indexed(MEGACHIP_WIDTH, MEGACHIP_HEIGHT)
+        } else if self.hires {
+            Graphics::new(WIDTH as u32, HIRES_HEIGHT as u32)
+        } else {
+            Graphics::new(WIDTH as u32, HEIGHT as u32)
+        };
+    }
+
+    fn load_palette(&mut self, n: u8) {
+        let start = (self.I as usize).min(self.memory.len());
+        let end = (start + 4 * n as usize).min(self.memory.len());
+        self.megachip.load_palette(&self.memory[start..end]);
+    }
+
+    /// Draws a Mega-Chip sprite of the current sprite size from I, one color
+    /// index per byte. Index 0 is transparent, other pixels replace what was
+    /// there, and VF is set if one of those had the collision index. Sprites
+    /// are clipped at the edges of the display.
+    fn draw_indexed_sprite(&mut self, x: u8, y: u8) {
+        let x = self.V[x as usize] as u32;
+        let y = self.V[y as usize] as u32;
+        let MegaChip {
+            sprite_width,
+            sprite_height,
+            collision_index,
+            ..
+        } = self.megachip;
+
+        self.V[0xf] = 0;
+
+        for dy in 0..sprite_height.min(self.gfx.get_height().saturating_sub(y)) {
+            for dx in 0..sprite_width.min(self.gfx.get_width().saturating_sub(x)) {
+                let address = self.I as usize + (dy * sprite_width + dx) as usize;
+                let index = match self.memory.get(address) {
+                    Some(&index) => index,
+                    None => return,
+                };
+                if index != 0 && self.gfx.set_index(x + dx, y + dy, index) == collision_index {
+                    self.V[0xf] = 1;
+                }
+            }
+        }
+    }
+
+    fn skip_if_pressed2(&mut self, x: u8) {
+        if self.second_keypad.is_key_down(self.V[x as usize]) {
+            self.pc += 2;
+        }
+    }
+
+    fn skip_if_not_pressed2(&mut self, x: u8) {
+        if !self.second_keypad.is_key_down(self.V[x as usize]) {
+            self.pc += 2;
+        }
+    }
+
+    /// Faults the instruction just fetched for accessing `length` bytes at I
+    /// when they don't all fit in memory.
+    fn fault_past_memory(&mut self, access: &str, length: usize) {
+        self.fault(format!(
+            "{} {} bytes at {:03X} past the end of memory",
+            access, length, self.I
+        ));
     }
 
-    fn invalid_instruction(opcode: Opcode) {
-        web_sys::console::error_1(&format!("Invalid instruction {:X}", opcode.value()).into())
+    /// Stops the ROM for the instruction just fetched faulting. See
+    /// `fault_at`.
+    fn fault(&mut self, reason: String) {
+        self.fault_at(self.pc.wrapping_sub(2), reason);
+    }
+
+    /// Stops the ROM for the instruction at `address` faulting, logs
+    /// `reason`, and keeps a crash report if it's the first fault since the
+    /// reset.
+    fn fault_at(&mut self, address: u16, reason: String) {
+        self.faulted = true;
+        log_error(&format!("{} at {:03X}", reason, address));
+        if self.crash_report.is_some() {
+            return;
+        }
+
+        let memory_end = (address as usize + CRASH_MEMORY_RADIUS as usize).min(self.memory.len());
+        let memory_start = (address.saturating_sub(CRASH_MEMORY_RADIUS) as usize).min(memory_end);
+        let trace = self
+            .recent_addresses
+            .iter()
+            .map(|&address| {
+                let opcode = self.get_opcode_at(address);
+                format!(
+                    "{:03X}  {:04X}  {}",
+                    address,
+                    opcode.value(),
+                    self.describe(&opcode)
+                )
+            })
+            .collect();
+        self.crash_report = Some(CrashReport {
+            reason,
+            address,
+            opcode: self.get_opcode_at(address).value(),
+            registers: self.V,
+            i: self.I,
+            delay_timer: self.delay_timer.value(),
+            sound_timer: self.sound_timer.value(),
+            stack: self.stack.to_vec(),
+            instruction_count: self.instruction_count,
+            memory_start: memory_start as u16,
+            memory: self.memory[memory_start..memory_end].to_vec(),
+            trace,
+        });
     }
 }
 
+/// Returns a DB directive for the two bytes of `opcode`.
+fn data_directive(opcode: &Opcode) -> String {
+    format!(
+        "DB {:#04X}, {:#04X}",
+        opcode.value() >> 8,
+        opcode.value() & 0xff
+    )
+}
+
+/// Logs to the browser console, or to stderr when running headlessly outside
+/// the browser.
+fn log_error(message: &str) {
+    #[cfg(target_arch = "wasm32")]
+    web_sys::console::error_1(&message.into());
+    #[cfg(not(target_arch = "wasm32"))]
+    eprintln!("{}", message);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::needless_range_loop)]
     fn test_load_rom() {
         let mut chip8 = Chip8Emulator::new(0.0);
         let data = [1u8, 5, 3, 5, 1, 255, 9];
-        chip8.load_rom(&data);
+        chip8.load_rom(&data).unwrap();
 
         for i in 0..data.len() {
             assert_eq!(chip8.memory[PROGRAM_MEMORY_START + i], data[i]);
@@ -404,11 +1703,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_memory_slice() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[1, 5, 3, 5, 1, 255, 9]).unwrap();
+        assert_eq!(chip8.memory_slice(0x201, 3), [5, 3, 5]);
+        assert_eq!(chip8.memory_slice(0xffe, 4).len(), 2);
+        assert!(chip8.memory_slice(0xffff, 4).is_empty());
+    }
+
+    #[test]
+    fn test_load_oversized_rom() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        assert!(chip8.load_rom(&[0x12; 4000]).is_err());
+        assert_eq!(chip8.get_state(), State::Loading);
+        assert!(chip8.load_rom(&[0x12; 0xe00]).is_ok());
+    }
+
     #[test]
     fn test_reset() {
         let mut chip8 = Chip8Emulator::new(0.0);
         let data = [1u8, 5, 3, 5, 1, 255, 9];
-        chip8.load_rom(&data);
+        chip8.load_rom(&data).unwrap();
         chip8.reset(1.0);
         for i in 0..data.len() {
             assert_eq!(chip8.memory[PROGRAM_MEMORY_START + i], 0);
@@ -416,19 +1732,788 @@ mod tests {
     }
 
     #[test]
+    fn test_reset_unloads_rom() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x12, 0x00]).unwrap();
+        assert!(chip8.rom_loaded);
+        chip8.reset(1.0);
+        assert!(!chip8.rom_loaded);
+    }
+
+    #[test]
+    fn test_tick_without_rom() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.tick(1000.0);
+        assert_eq!(chip8.pc, PROGRAM_MEMORY_START as u16);
+        assert!(chip8.stack.is_empty());
+
+        chip8.load_rom(&[0x12, 0x00]).unwrap();
+        assert!(chip8.rom_loaded);
+        chip8.tick(1000.0);
+        assert_eq!(chip8.pc, PROGRAM_MEMORY_START as u16);
+    }
+
+    #[test]
+    fn test_skip_time() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x70, 0x01, 0x12, 0x02]).unwrap();
+        chip8.delay_timer.set_value(10);
+        chip8.skip_time(10_000.0);
+        assert_eq!(chip8.V[0], 0);
+        assert_eq!(chip8.get_delay_timer(), 10);
+
+        chip8.tick(10_020.0);
+        assert_eq!(chip8.V[0], 1);
+    }
+
+    #[test]
+    // Dirty rows are compared against lists of row ranges.
+    #[allow(clippy::single_range_in_vec_init)]
     fn test_gfx() {
         let mut chip8 = Chip8Emulator::new(0.0);
 
         assert_eq!(chip8.get_gfx_width(), WIDTH as u32);
         assert_eq!(chip8.get_gfx_height(), HEIGHT as u32);
-        assert!(chip8.gfx_needs_rerender());
-        assert!(!chip8.gfx_needs_rerender());
+        assert_eq!(chip8.take_gfx_dirty_rows(), [0..HEIGHT as u32]);
+        assert!(chip8.take_gfx_dirty_rows().is_empty());
         assert!(!chip8.get_gfx_pixel(5, 5));
         chip8.gfx.toggle(5, 5);
         assert!(chip8.get_gfx_pixel(5, 5));
         assert!(chip8.gfx.toggle(5, 5));
     }
 
+    #[test]
+    fn test_gfx_rows() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.gfx.toggle(5, 5);
+        chip8.gfx.toggle(63, 5);
+        assert_eq!(chip8.get_gfx_row(5), 1 << 58 | 1);
+        assert_eq!(chip8.gfx_rows().filter(|&row| row != 0).count(), 1);
+    }
+
+    #[test]
+    fn test_hires_mode() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x12, 0x60]).unwrap();
+        assert!(chip8.hires);
+        assert_eq!(chip8.get_gfx_width(), WIDTH as u32);
+        assert_eq!(chip8.get_gfx_height(), HIRES_HEIGHT as u32);
+        assert_eq!(chip8.pc, HIRES_PROGRAM_START);
+
+        chip8.store(0, 40);
+        chip8.store_address(FONT_MEMORY_START as u16);
+        chip8.draw_sprite(0, 0, 1);
+        assert!(chip8.get_gfx_pixel(40, 40));
+
+        chip8.memory[HIRES_PROGRAM_START as usize] = 0x02;
+        chip8.memory[HIRES_PROGRAM_START as usize + 1] = 0x30;
+        chip8.execute_next_instruction();
+        assert!(!chip8.get_gfx_pixel(40, 40));
+        assert!(chip8.stack.is_empty());
+
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x12, 0x02]).unwrap();
+        assert!(!chip8.hires);
+        assert_eq!(chip8.get_gfx_height(), HEIGHT as u32);
+        assert_eq!(chip8.pc, PROGRAM_MEMORY_START as u16);
+
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.set_strict_mode(true);
+        chip8.set_force_hires(true);
+        chip8.load_rom(&[0x12, 0x02]).unwrap();
+        assert!(chip8.hires);
+        assert_eq!(chip8.get_gfx_height(), HIRES_HEIGHT as u32);
+        assert_eq!(chip8.pc, PROGRAM_MEMORY_START as u16);
+    }
+
+    #[test]
+    fn test_crash_report() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8
+            .load_rom(&[
+                0x60, 0x07, // LD V0, 0x07
+                0xe0, 0x00, // invalid
+                0x00, 0xee, // RET
+                0x00, 0xe0, // CLS
+            ])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.execute_next_instruction();
+        }
+        assert_eq!(chip8.get_state(), State::Faulted);
+
+        // The first fault is the one kept.
+        let report = chip8.get_crash_report().unwrap();
+        assert_eq!(report.reason, "Invalid instruction E000");
+        assert_eq!((report.address, report.opcode), (0x202, 0xe000));
+        assert_eq!(report.registers[0], 7);
+        assert_eq!(report.memory_start, 0x202 - CRASH_MEMORY_RADIUS);
+        assert_eq!(report.memory[CRASH_MEMORY_RADIUS as usize], 0xe0);
+        assert_eq!(
+            report.trace,
+            ["200  6007  LD V0, 0x07", "202  E000  DB 0xE0, 0x00"]
+        );
+
+        chip8.reset(0.0);
+        assert_eq!(chip8.get_crash_report(), None);
+        chip8.load_rom(&[0x00, 0xee]).unwrap();
+        chip8.execute_next_instruction();
+        assert_eq!(
+            chip8.get_crash_report().unwrap().reason,
+            "Return with an empty stack"
+        );
+    }
+
+    /// Runs `rom` until it faults, and returns why.
+    fn fault_reason(rom: &[u8]) -> String {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(rom).unwrap();
+        for _ in 0..rom.len() / 2 {
+            chip8.execute_next_instruction();
+        }
+        assert_eq!(chip8.get_state(), State::Faulted);
+        chip8.get_crash_report().unwrap().reason.clone()
+    }
+
+    #[test]
+    fn test_fault_fetching_past_memory() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x1f, 0xff]).unwrap();
+        chip8.execute_next_instruction();
+        chip8.execute_next_instruction();
+        assert_eq!(chip8.get_state(), State::Faulted);
+        let report = chip8.get_crash_report().unwrap();
+        assert_eq!(report.reason, "Program counter past the end of memory");
+        assert_eq!((report.address, report.opcode), (0xfff, 0x0000));
+        assert_eq!(report.memory.len(), CRASH_MEMORY_RADIUS as usize + 1);
+    }
+
+    #[test]
+    fn test_fault_jumping_past_memory() {
+        assert_eq!(
+            fault_reason(&[0x60, 0x10, 0xbf, 0xf0]),
+            "Jump to 1000 past the end of memory"
+        );
+    }
+
+    #[test]
+    fn test_fault_drawing_past_memory() {
+        assert_eq!(
+            fault_reason(&[0xaf, 0xfe, 0xd0, 0x03]),
+            "Reading 3 bytes at FFE past the end of memory"
+        );
+    }
+
+    #[test]
+    fn test_fault_storing_bcd_past_memory() {
+        assert_eq!(
+            fault_reason(&[0xaf, 0xff, 0xf0, 0x33]),
+            "Writing 3 bytes at FFF past the end of memory"
+        );
+    }
+
+    #[test]
+    fn test_fault_storing_registers_past_memory() {
+        assert_eq!(
+            fault_reason(&[0xaf, 0xfe, 0xf2, 0x55]),
+            "Writing 3 bytes at FFE past the end of memory"
+        );
+    }
+
+    #[test]
+    fn test_fault_loading_registers_past_memory() {
+        assert_eq!(
+            fault_reason(&[0xaf, 0xff, 0xf1, 0x65]),
+            "Reading 2 bytes at FFF past the end of memory"
+        );
+    }
+
+    #[test]
+    fn test_state() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        assert_eq!(chip8.get_state(), State::Loading);
+        chip8
+            .load_rom(&[
+                0xf3, 0x0a, // LD V3, K
+                0x00, 0xee, // RET
+            ])
+            .unwrap();
+        assert_eq!(chip8.get_state(), State::Running);
+
+        chip8.step_instruction();
+        assert_eq!(chip8.get_state(), State::WaitingForKey);
+        chip8.pause();
+        assert_eq!(chip8.get_state(), State::Paused);
+        chip8.resume();
+        chip8.queue_key(5, true);
+        chip8.step_instruction();
+        assert_eq!((chip8.get_state(), chip8.V[3]), (State::Running, 5));
+
+        chip8.step_instruction();
+        assert_eq!(chip8.get_state(), State::Faulted);
+        chip8.step_instruction();
+        assert_eq!(chip8.pc, 0x204);
+    }
+
+    #[test]
+    fn test_halt() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8
+            .load_rom(&[
+                0x60, 0x01, // LD V0, 0x01
+                0xf0, 0x18, // LD ST, V0
+                0x12, 0x04, // JP 0x204
+            ])
+            .unwrap();
+        let start = chip8.save_state();
+        chip8.tick(100.0);
+        assert_eq!(chip8.get_state(), State::Halted);
+        assert_eq!((chip8.pc, chip8.get_instruction_count()), (0x204, 3));
+
+        // The timers still run down.
+        chip8.tick(200.0);
+        assert_eq!(chip8.get_instruction_count(), 3);
+        assert_eq!(chip8.get_sound_timer(), 0);
+
+        chip8.load_state(&start, 200.0);
+        assert_eq!(chip8.get_state(), State::Running);
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.set_strict_mode(true);
+        chip8.load_rom(&[0x12, 0x60]).unwrap();
+        assert!(!chip8.hires);
+        assert_eq!(chip8.pc, PROGRAM_MEMORY_START as u16);
+
+        chip8.load_rom(&[0x00, 0xff, 0x00, 0xe0]).unwrap();
+        chip8.execute_next_instruction();
+        assert_eq!(chip8.pc, PROGRAM_MEMORY_START as u16 + 2);
+        assert!(chip8.stack.is_empty());
+
+        chip8.set_strict_mode(false);
+        chip8.jump_to(PROGRAM_MEMORY_START as u16);
+        chip8.execute_next_instruction();
+        assert_eq!(chip8.pc, 0x0ff);
+    }
+
+    #[test]
+    fn test_chip8x() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.set_extension(Some(Extension::Chip8X));
+        assert!(chip8.has_color_zones());
+        chip8
+            .load_rom(&[
+                0x02, 0xa0, // BGC
+                0x51, 0x21, // ADDN V1, V2
+                0xb3, 0x40, // COL V3, V4, 0
+                0xb3, 0x52, // COL V3, V5, 2
+                0xe6, 0xf2, // SKP2 V6
+            ])
+            .unwrap();
+        chip8.store(1, 0x3c);
+        chip8.store(2, 0x15);
+        chip8.store(3, 0x11);
+        chip8.store(4, 0x10);
+        chip8.store(5, 0x06);
+        chip8.store(6, 0xa);
+        chip8.second_keypad.keydown(0xa);
+
+        chip8.execute_next_instruction();
+        assert_eq!(chip8.get_gfx_background(), CHIP8X_BACKGROUND_COLORS[1]);
+        chip8.execute_next_instruction();
+        assert_eq!(chip8.V[1], 0x41);
+
+        // V3 = 0x11 covers zone columns 1-2, V4 = 0x10 zone rows 0-1.
+        chip8.execute_next_instruction();
+        assert_eq!(chip8.get_gfx_foreground(8, 0), CHIP8X_FOREGROUND_COLORS[0]);
+        assert_eq!(chip8.get_gfx_foreground(23, 7), CHIP8X_FOREGROUND_COLORS[0]);
+        assert_eq!(chip8.get_gfx_foreground(24, 7), CHIP8X_FOREGROUND_COLORS[1]);
+        assert_eq!(chip8.get_gfx_foreground(8, 8), CHIP8X_FOREGROUND_COLORS[1]);
+
+        // With N = 2, V4 = 0x10 is the first of two pixel rows.
+        chip8.execute_next_instruction();
+        assert_eq!(chip8.get_gfx_foreground(8, 16), CHIP8X_FOREGROUND_COLORS[6]);
+        assert_eq!(chip8.get_gfx_foreground(8, 17), CHIP8X_FOREGROUND_COLORS[6]);
+        assert_eq!(chip8.get_gfx_foreground(8, 18), CHIP8X_FOREGROUND_COLORS[1]);
+
+        chip8.execute_next_instruction();
+        assert_eq!(chip8.pc, PROGRAM_MEMORY_START as u16 + 12);
+    }
+
+    #[test]
+    fn test_load_oversized_megachip_rom() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.set_extension(Some(Extension::MegaChip));
+        assert_eq!(
+            chip8.load_rom(&[0x00; 0x2000]),
+            Err("The Mega-Chip ROM is 8192 bytes, but only 3584 fit in memory".to_string())
+        );
+        assert_eq!(chip8.get_state(), State::Loading);
+        assert_eq!(chip8.rom_length, 0);
+    }
+
+    #[test]
+    fn test_megachip() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.set_extension(Some(Extension::MegaChip));
+        chip8
+            .load_rom(&[
+                0x00, 0x11, // MEGAON
+                0x02, 0x01, // LDPAL 1
+                0x03, 0x02, // SPRW 2
+                0x04, 0x01, // SPRH 1
+                0x09, 0x01, // CCOL 0x01
+                0xd0, 0x10, // DRW V0, V1, 0
+                0x00, 0x10, // MEGAOFF
+                0xff, 0x10, 0x20, 0x30, // Palette
+                0x01, 0x00, // Sprite
+            ])
+            .unwrap();
+        chip8.store(0, 255);
+        chip8.store(1, 191);
+
+        chip8.execute_next_instruction();
+        assert!(chip8.is_gfx_indexed());
+        assert_eq!(chip8.get_gfx_width(), MEGACHIP_WIDTH);
+        assert_eq!(chip8.get_gfx_height(), MEGACHIP_HEIGHT);
+
+        chip8.store_address(PROGRAM_MEMORY_START as u16 + 14);
+        for _ in 0..4 {
+            chip8.execute_next_instruction();
+        }
+        assert_eq!(chip8.get_gfx_indexed_color(0, 0), [0, 0, 0]);
+        assert_eq!(chip8.megachip.color(1), [0x10, 0x20, 0x30]);
+
+        // The sprite's second pixel is clipped at the right edge.
+        chip8.store_address(PROGRAM_MEMORY_START as u16 + 18);
+        chip8.execute_next_instruction();
+        assert_eq!(chip8.get_gfx_indexed_color(255, 191), [0x10, 0x20, 0x30]);
+        assert_eq!(chip8.V[0xf], 0);
+        chip8.pc -= 2;
+        chip8.execute_next_instruction();
+        assert_eq!(chip8.V[0xf], 1);
+
+        chip8.execute_next_instruction();
+        assert!(!chip8.is_gfx_indexed());
+        assert_eq!(chip8.get_gfx_width(), WIDTH as u32);
+    }
+
+    #[test]
+    fn test_sound() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x12, 0x00]).unwrap();
+        assert!(!chip8.is_sound_playing());
+
+        chip8.store(0, 2);
+        chip8.set_sound(0);
+        assert!(chip8.is_sound_playing());
+        chip8.tick(1000.0 / 60.0 + 1.0);
+        assert!(chip8.is_sound_playing());
+        chip8.tick(2000.0 / 60.0 + 1.0);
+        assert!(!chip8.is_sound_playing());
+    }
+
+    #[test]
+    // Dirty rows are compared against lists of row ranges.
+    #[allow(clippy::single_range_in_vec_init)]
+    fn test_save_state() {
+        // Increments V0 forever.
+        let interval = 1000.0 / 60.0;
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        chip8.delay_timer.set_value(5);
+        chip8.tick(1.25 * interval);
+
+        let state = chip8.save_state();
+        let delay_timer = state.get_delay_timer();
+        assert_eq!(delay_timer.value, 4);
+        assert!((delay_timer.phase - 0.25).abs() < 1e-9);
+        assert_eq!(state.get_sound_timer().value, 0);
+        let v0 = chip8.V[0];
+
+        chip8.tick(10.0 * interval);
+        assert_eq!(chip8.get_delay_timer(), 0);
+
+        chip8.load_state(&state, 1000.0);
+        assert_eq!((chip8.V[0], chip8.pc), (v0, state.get_pc()));
+        assert_eq!(chip8.take_gfx_dirty_rows(), [0..HEIGHT as u32]);
+        let restored = chip8.get_delay_timer_state();
+        assert_eq!(restored.value, 4);
+        assert!((restored.phase - delay_timer.phase).abs() < 1e-9);
+        chip8.tick(1000.0 + 0.7 * interval);
+        assert_eq!(chip8.get_delay_timer(), 4);
+        chip8.tick(1000.0 + 0.8 * interval);
+        assert_eq!(chip8.get_delay_timer(), 3);
+    }
+
+    #[test]
+    fn test_debugger() {
+        // Increments V0 forever.
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        chip8.add_breakpoint(0x202);
+
+        chip8.tick(100.0);
+        assert!(chip8.is_paused());
+        assert_eq!((chip8.pc, chip8.V[0]), (0x202, 1));
+        chip8.tick(200.0);
+        assert_eq!((chip8.pc, chip8.V[0]), (0x202, 1));
+
+        chip8.step_instruction();
+        assert_eq!(chip8.pc, 0x200);
+        chip8.resume();
+        chip8.tick(200.0 + 1000.0 / DEFAULT_CLOCK_SPEED);
+        assert_eq!((chip8.pc, chip8.V[0]), (0x202, 2));
+        assert!(chip8.is_paused());
+
+        assert_eq!(chip8.get_breakpoints(), [0x202]);
+        assert!(chip8.remove_breakpoint(0x202));
+        chip8.resume();
+        chip8.tick(300.0);
+        assert!(!chip8.is_paused());
+        assert!(chip8.V[0] > 2);
+    }
+
+    #[test]
+    fn test_step_over_and_out() {
+        let rom = [
+            0x22, 0x06, // CALL 0x206
+            0x12, 0x02, // JP 0x202
+            0x00, 0x00, //
+            0x70, 0x01, // ADD V0, 0x01
+            0x22, 0x0c, // CALL 0x20C
+            0x00, 0xee, // RET
+            0x71, 0x01, // ADD V1, 0x01
+            0x00, 0xee, // RET
+        ];
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&rom).unwrap();
+        chip8.pause();
+        chip8.step_over();
+        assert!(!chip8.is_paused());
+        chip8.tick(100.0);
+        assert!(chip8.is_paused());
+        assert_eq!((chip8.pc, chip8.V[0], chip8.V[1]), (0x202, 1, 1));
+
+        // Other instructions are stepped as usual.
+        chip8.step_over();
+        assert_eq!(chip8.pc, 0x202);
+
+        // Stepping out of the inner subroutine stops in the outer one.
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&rom).unwrap();
+        chip8.pause();
+        for _ in 0..3 {
+            chip8.step_instruction();
+        }
+        assert_eq!((chip8.pc, chip8.get_stack_pointer()), (0x20c, 2));
+        chip8.step_out();
+        chip8.tick(100.0);
+        assert!(chip8.is_paused());
+        assert_eq!((chip8.pc, chip8.get_stack_pointer()), (0x20a, 1));
+        chip8.step_out();
+        chip8.tick(200.0);
+        assert_eq!((chip8.pc, chip8.get_stack_pointer()), (0x202, 0));
+
+        // Outside a subroutine there's nothing to step out of.
+        chip8.step_out();
+        assert!(chip8.is_paused());
+    }
+
+    #[test]
+    fn test_step_back() {
+        // Increments V0 forever.
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        chip8.tick(100.0);
+        assert!(!chip8.step_back(100.0));
+
+        chip8.set_history_limit(3);
+        chip8.tick(200.0);
+        assert_eq!(chip8.get_history_length(), 3);
+        let v0 = chip8.V[0];
+        assert!(chip8.step_back(200.0));
+        assert!(chip8.is_paused());
+        assert_eq!((chip8.pc, chip8.V[0]), (0x202, v0));
+        assert!(chip8.step_back(200.0));
+        assert_eq!((chip8.pc, chip8.V[0]), (0x200, v0 - 1));
+        chip8.step_instruction();
+        assert_eq!((chip8.pc, chip8.V[0]), (0x202, v0));
+
+        // Loading a state starts the history over.
+        chip8.load_state(&chip8.save_state(), 200.0);
+        assert!(!chip8.step_back(200.0));
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8
+            .load_rom(&[0x60, 0x05, 0xa2, 0x34, 0x12, 0x04])
+            .unwrap();
+        chip8.set_trace_limit(2);
+        for _ in 0..3 {
+            chip8.step_instruction();
+        }
+        let trace: Vec<_> = chip8.get_trace().map(ToString::to_string).collect();
+        assert_eq!(
+            trace,
+            [
+                "202  A234  LD I, 0x234           I 0000->0234",
+                "204  1204  JP 0x204",
+            ]
+        );
+        // Turning it off drops the trace.
+        chip8.set_trace_limit(0);
+        chip8.step_instruction();
+        assert_eq!(chip8.get_trace().count(), 0);
+    }
+
+    #[test]
+    fn test_history_and_trace_bytes() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        assert_eq!((chip8.get_history_bytes(), chip8.get_trace_bytes()), (0, 0));
+
+        chip8.set_history_limit(4);
+        chip8.set_trace_limit(4);
+        chip8.step_instruction();
+        let history = chip8.get_history_bytes();
+        assert!(history > 4096);
+        assert!(chip8.get_trace_bytes() > 0);
+
+        // Both stop growing at their limits.
+        for _ in 0..9 {
+            chip8.step_instruction();
+        }
+        let trace = chip8.get_trace_bytes();
+        assert_eq!(chip8.get_history_bytes(), history * 4);
+        for _ in 0..10 {
+            chip8.step_instruction();
+        }
+        assert_eq!(chip8.get_history_bytes(), history * 4);
+        assert_eq!(chip8.get_trace_bytes(), trace);
+    }
+
+    #[test]
+    fn test_profiling() {
+        // Loops over two instructions after the first.
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8
+            .load_rom(&[0x60, 0x05, 0x70, 0x01, 0x12, 0x02])
+            .unwrap();
+        chip8.set_profiling(true);
+        for _ in 0..7 {
+            chip8.step_instruction();
+        }
+        assert_eq!(chip8.get_profiled_count(), 7);
+        assert_eq!(chip8.get_hot_spots(2), [(0x202, 3), (0x204, 3)]);
+        assert_eq!(
+            chip8.get_opcode_stats(),
+            [("1NNN", 3), ("7XNN", 3), ("6XNN", 1)]
+        );
+
+        chip8.set_profiling(false);
+        chip8.step_instruction();
+        assert_eq!(chip8.get_profiled_count(), 0);
+    }
+
+    #[test]
+    fn test_labels() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8
+            .load_rom(&[
+                0x22, 0x06, // CALL 0x206
+                0x12, 0x02, // JP 0x202
+                0x00, 0x00, //
+                0x00, 0xee, // RET
+            ])
+            .unwrap();
+        let labels: Vec<_> = chip8.get_labels().into_iter().collect();
+        assert_eq!(
+            labels,
+            [
+                (0x202, "loop_0202".to_string()),
+                (0x206, "sub_0206".to_string()),
+            ]
+        );
+        assert_eq!(chip8.disassemble(0x200, 1)[0].target, Some(0x206));
+
+        chip8.set_symbols(parse_symbols("0x0206 draw\n0x0200 main"));
+        let labels: Vec<_> = chip8.get_labels().into_values().collect();
+        assert_eq!(labels, ["main", "loop_0202", "draw"]);
+    }
+
+    #[test]
+    fn test_stepping() {
+        // Sets the delay timer, then increments V0 forever.
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8
+            .load_rom(&[0x60, 0x05, 0xf0, 0x15, 0x70, 0x01, 0x12, 0x04])
+            .unwrap();
+        chip8.pause();
+        chip8.step_instruction();
+        chip8.step_instruction();
+        assert_eq!((chip8.pc, chip8.get_delay_timer()), (0x204, 5));
+
+        // 800 Hz makes 13 instructions a frame, breakpoints or not.
+        chip8.add_breakpoint(0x204);
+        chip8.step_frame();
+        assert_eq!((chip8.V[0], chip8.get_delay_timer()), (5 + 7, 4));
+        assert!(chip8.is_paused());
+
+        // A watchpoint stops the frame short, with the timers untouched.
+        chip8.add_watchpoint(Watchpoint::Register(0));
+        chip8.step_frame();
+        assert_eq!((chip8.V[0], chip8.get_delay_timer()), (13, 4));
+    }
+
+    #[test]
+    fn test_watchpoints() {
+        // Counts up in V0 and stores it at 0x300 every time.
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8
+            .load_rom(&[0xa3, 0x00, 0x70, 0x01, 0xf0, 0x55, 0x12, 0x02])
+            .unwrap();
+        chip8.add_watchpoint(Watchpoint::parse("300").unwrap());
+
+        chip8.tick(100.0);
+        assert!(chip8.is_paused());
+        assert_eq!(chip8.pc, 0x206);
+        let hit = chip8.get_watch_hits()[0];
+        assert_eq!((hit.pc, hit.old, hit.new), (0x204, 0, 1));
+
+        chip8.resume();
+        chip8.tick(100.0 + 4000.0 / DEFAULT_CLOCK_SPEED);
+        assert_eq!(chip8.get_watch_hits()[0].new, 2);
+    }
+
+    #[test]
+    fn test_cheats() {
+        // Counts the byte at 0x300 down.
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8
+            .load_rom(&[0xa3, 0x00, 0xf0, 0x65, 0x70, 0xff, 0xf0, 0x55, 0x12, 0x02])
+            .unwrap();
+        chip8.set_cheats(vec![
+            Cheat::parse("300=05").unwrap(),
+            Cheat::parse("301=07 once").unwrap(),
+        ]);
+        assert_eq!(chip8.memory[0x300..0x302], [5, 7]);
+
+        chip8.tick(100.0);
+        assert_eq!(chip8.memory[0x300], 5);
+        chip8.add_cheat(Cheat::parse("300=09 once").unwrap());
+        assert_eq!(chip8.get_cheats().len(), 2);
+        chip8.tick(200.0);
+        assert_ne!(chip8.memory[0x300], 9);
+
+        assert!(chip8.remove_cheat(Cheat::parse("301=07 once").unwrap()));
+        assert!(!chip8.remove_cheat(Cheat::parse("301=07").unwrap()));
+        assert_eq!(chip8.memory[0x301], 7);
+    }
+
+    #[test]
+    fn test_find_sprites() {
+        // Draws the 2-row sprite after the loop, with a blank one after it.
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8
+            .load_rom(&[
+                0xa2, 0x08, 0xd0, 0x02, 0x12, 0x04, 0x00, 0x00, 0xf0, 0x90, 0x00, 0x00,
+            ])
+            .unwrap();
+        assert_eq!(chip8.get_rom_range(), 0x200..0x20c);
+
+        let range = chip8.get_rom_range();
+        assert_eq!(chip8.find_sprites(range.clone(), 2, true), [0x208]);
+        assert_eq!(
+            chip8.find_sprites(range.clone(), 2, false),
+            [0x200, 0x202, 0x204, 0x208]
+        );
+        assert_eq!(chip8.find_sprites(0x208..0x209, 5, false), [0x208]);
+        assert_eq!(chip8.find_sprites(0x208..0xffff, u16::MAX, false), [0x208]);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x70, 0x01, 0xff, 0xff]).unwrap();
+
+        let lines: Vec<_> = chip8
+            .disassemble(0x200, 2)
+            .into_iter()
+            .map(|line| (line.address, line.opcode, line.text))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (0x200, 0x7001, "ADD V0, 0x01".to_string()),
+                (0x202, 0xffff, "DB 0xFF, 0xFF".to_string()),
+            ]
+        );
+        assert_eq!(chip8.disassemble(0xffc, 5).len(), 2);
+
+        // A sprite that reads as a jump is data, and so is the jump it
+        // doesn't make.
+        chip8
+            .load_rom(&[
+                0xa2, 0x06, // LD I, 0x206
+                0xd0, 0x01, // DRW V0, V0, 1
+                0x12, 0x02, // JP 0x202
+                0x14, 0x00, // sprite
+            ])
+            .unwrap();
+        let text = chip8.disassemble(0x206, 1).pop().unwrap().text;
+        assert_eq!(text, "DB 0x14, 0x00");
+        assert!(!chip8.get_labels().contains_key(&0x400));
+        assert!(chip8.get_unreachable_code().is_empty());
+    }
+
+    #[test]
+    fn test_clock_speed() {
+        // Increments V0 forever.
+        let rom = [0x70, 0x01, 0x12, 0x00];
+
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&rom).unwrap();
+        chip8.tick(100.0);
+        assert_eq!(chip8.V[0], 40);
+        assert_eq!(chip8.get_instruction_count(), 80);
+
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.set_clock_speed(1000.0);
+        chip8.set_cost_model(CostModel {
+            add_immediate: 3,
+            default: 2,
+            ..CostModel::uniform()
+        });
+        chip8.load_rom(&rom).unwrap();
+        chip8.tick(100.0);
+        assert_eq!(chip8.V[0], 20);
+        chip8.tick(102.0);
+        assert_eq!(chip8.V[0], 20);
+        assert_eq!(chip8.pending_cycles, 2);
+        chip8.tick(103.0);
+        assert_eq!(chip8.V[0], 21);
+    }
+
+    #[test]
+    fn test_fast_forward() {
+        // Increments V0 forever.
+        let rom = [0x70, 0x01, 0x12, 0x00];
+
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&rom).unwrap();
+        chip8.delay_timer.set_value(30);
+        chip8.fast_forward(100.0);
+        chip8.tick(100.0);
+        assert_eq!(chip8.V[0], 80);
+        // 200 ms of the 60 Hz timer.
+        assert_eq!(chip8.get_delay_timer(), 18);
+    }
+
     #[test]
     fn test_get_next_opcode() {
         let mut chip8 = Chip8Emulator::new(0.0);
@@ -441,7 +2526,7 @@ mod tests {
             0xf17du16, 0x0500, 0x135c, 0x1ac4, 0x58df, 0x0001, 0x0000, 0x1a43,
         ];
 
-        chip8.load_rom(&data);
+        chip8.load_rom(&data).unwrap();
         for opcode in opcodes.iter() {
             assert_eq!(chip8.get_next_opcode().value(), *opcode)
         }
@@ -458,6 +2543,16 @@ mod tests {
         assert!(chip8.stack.is_empty())
     }
 
+    #[test]
+    fn test_stack_pointer() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        assert_eq!(chip8.get_stack_pointer(), 0);
+        chip8.execute_subroutine(0x300);
+        assert_eq!(chip8.get_stack_pointer(), 1);
+        chip8.return_subroutine();
+        assert_eq!(chip8.get_stack_pointer(), 0);
+    }
+
     #[test]
     fn test_skip() {
         let mut chip8 = Chip8Emulator::new(0.0);
@@ -491,7 +2586,7 @@ mod tests {
         chip8.store(0xa, 0xa);
         chip8.store(0xb, 0xb);
 
-        chip8.keydown(0xa);
+        chip8.keypad.keydown(0xa);
         chip8.skip_if_pressed(0xb);
         assert_eq!(chip8.pc, 15);
         chip8.skip_if_not_pressed(0xa);
@@ -500,7 +2595,7 @@ mod tests {
         assert_eq!(chip8.pc, 17);
         chip8.skip_if_not_pressed(0xb);
         assert_eq!(chip8.pc, 19);
-        chip8.keyup(0xa);
+        chip8.keypad.keyup(0xa);
         chip8.skip_if_pressed(0xa);
         assert_eq!(chip8.pc, 19);
     }
@@ -569,6 +2664,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::absurd_extreme_comparisons, clippy::legacy_numeric_constants)]
     fn test_rand() {
         let mut chip8 = Chip8Emulator::new(0.0);
         for _ in 1..10 {
@@ -579,15 +2675,17 @@ mod tests {
     }
 
     #[test]
+    // Dirty rows are compared against lists of row ranges.
+    #[allow(clippy::bool_assert_comparison, clippy::single_range_in_vec_init)]
     fn test_draw_sprite() {
         let mut chip8 = Chip8Emulator::new(0.0);
-        assert!(chip8.gfx_needs_rerender());
+        assert!(!chip8.take_gfx_dirty_rows().is_empty());
 
         chip8.store(0, 10);
         chip8.store(1, 10);
 
         chip8.draw_sprite(0, 1, 3);
-        assert!(!chip8.gfx_needs_rerender());
+        assert!(chip8.take_gfx_dirty_rows().is_empty());
         assert_eq!(chip8.V[0xf], 0);
 
         chip8.memory[5] = 0b11110000;
@@ -600,7 +2698,7 @@ mod tests {
         // Now, I == 5
 
         chip8.draw_sprite(0, 1, 3);
-        assert!(chip8.gfx_needs_rerender());
+        assert_eq!(chip8.take_gfx_dirty_rows(), [10..13]);
         assert_eq!(chip8.V[0xf], 0);
         assert_eq!(chip8.get_gfx_pixel(10, 10), true);
         assert_eq!(chip8.get_gfx_pixel(11, 10), true);
@@ -611,7 +2709,7 @@ mod tests {
         assert_eq!(chip8.get_gfx_pixel(11, 12), false);
 
         chip8.draw_sprite(0, 1, 3);
-        assert!(chip8.gfx_needs_rerender());
+        assert_eq!(chip8.take_gfx_dirty_rows(), [10..13]);
         assert_eq!(chip8.V[0xf], 1);
         assert_eq!(chip8.get_gfx_pixel(10, 10), false);
         assert_eq!(chip8.get_gfx_pixel(11, 10), false);
@@ -636,6 +2734,15 @@ mod tests {
         assert_eq!(chip8.I, FONT_MEMORY_START as u16 + 75);
     }
 
+    #[test]
+    fn test_dream6800_digit_address() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.set_font(&Profile::dream_6800().font);
+        chip8.store(0, 0xf);
+        chip8.store_digit_address(0);
+        assert_eq!(chip8.memory[chip8.I as usize + 2], 0xc0);
+    }
+
     #[test]
     fn test_store_bcd() {
         let mut chip8 = Chip8Emulator::new(0.0);
@@ -663,4 +2770,99 @@ mod tests {
         assert_eq!(chip8.memory[0xf], 0xF0);
         assert_eq!(chip8.memory[0xf + 1], 0);
     }
+
+    #[test]
+    fn test_quirks() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.set_quirks(Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: true,
+            jump_uses_vx: true,
+            logic_resets_vf: true,
+            clip_sprites: true,
+        });
+
+        chip8.store(0, 0xff);
+        chip8.store(1, 0b110);
+        chip8.store_reg_shr1(0, 1);
+        assert_eq!(chip8.V[0], 0b11);
+        assert_eq!(chip8.V[0xf], 0);
+        chip8.store_reg_shl1(0, 1);
+        assert_eq!(chip8.V[0], 0b1100);
+
+        chip8.store(0xf, 1);
+        chip8.store_reg_or(0, 1);
+        assert_eq!(chip8.V[0xf], 0);
+
+        chip8.store(2, 0x10);
+        chip8.jump_to_plus_v0(0x234);
+        assert_eq!(chip8.pc, 0x244);
+
+        chip8.store_address(0x300);
+        chip8.store_regs_in_memory(2);
+        assert_eq!(chip8.I, 0x303);
+        chip8.store_memory_in_regs(0);
+        assert_eq!(chip8.I, 0x304);
+
+        chip8.store(0, 60);
+        chip8.store(1, 30);
+        chip8.store_address(FONT_MEMORY_START as u16);
+        chip8.draw_sprite(0, 1, 5);
+        assert!(chip8.get_gfx_pixel(60, 30) && chip8.get_gfx_pixel(63, 31));
+        assert!(!chip8.get_gfx_pixel(0, 30) && !chip8.get_gfx_pixel(60, 0));
+    }
+
+    #[test]
+    fn test_save_state_keeps_stop() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x00, 0xee]).unwrap();
+        chip8.execute_next_instruction();
+        let faulted = chip8.save_state();
+
+        chip8.reset(0.0);
+        chip8.load_rom(&[0x12, 0x00]).unwrap();
+        chip8.execute_next_instruction();
+        let halted = chip8.save_state();
+
+        chip8.load_state(&faulted, 0.0);
+        assert_eq!(chip8.get_state(), State::Faulted);
+        chip8.tick(100.0);
+        assert_eq!(chip8.get_pc(), 0x202);
+        chip8.load_state(&halted, 100.0);
+        assert_eq!(chip8.get_state(), State::Halted);
+    }
+
+    #[test]
+    fn test_save_ram() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x12, 0x00]).unwrap();
+        assert_eq!(chip8.get_rom_checksum(), checksum(&[0x12, 0x00]));
+
+        chip8.set_save_regions(vec![
+            SaveRegion::new(0xe00, 0xe01).unwrap(),
+            SaveRegion::new(0xf00, 0xf00).unwrap(),
+        ]);
+        chip8.load_save_ram(&[1, 2, 3, 4]);
+        assert_eq!(chip8.get_save_ram(), vec![1, 2, 3]);
+        assert!(!chip8.save_ram_needs_persisting());
+
+        chip8.store_address(0xdff);
+        chip8.store_regs_in_memory(0);
+        assert!(!chip8.save_ram_needs_persisting());
+        chip8.store(0, 9);
+        chip8.store_regs_in_memory(1);
+        assert_eq!(chip8.get_save_ram(), vec![0, 2, 3]);
+        assert!(chip8.save_ram_needs_persisting());
+        assert!(!chip8.save_ram_needs_persisting());
+
+        chip8.store_address(0xeff);
+        chip8.store_bcd(0);
+        assert_eq!(chip8.get_save_ram(), vec![0, 2, 0]);
+        assert!(chip8.save_ram_needs_persisting());
+
+        assert!(chip8.write_memory(0xf00, &[7]));
+        assert_eq!(chip8.get_save_ram(), vec![0, 2, 7]);
+        assert!(chip8.save_ram_needs_persisting());
+        assert!(!chip8.write_memory(0xfff, &[1, 2]));
+    }
 }
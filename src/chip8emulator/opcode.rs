@@ -19,9 +19,9 @@ impl Opcode {
 
         let mut mask = 0;
         for i in start_index as u16..end_index as u16 {
-            mask += 0xf << (3 - i) * 4;
+            mask += 0xf << ((3 - i) * 4);
         }
-        (self.0 & mask) >> (4 - end_index as u16) * 4
+        (self.0 & mask) >> ((4 - end_index as u16) * 4)
     }
 
     pub fn get_nibbles_from(&self, start_index: u8) -> u16 {
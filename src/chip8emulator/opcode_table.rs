@@ -0,0 +1,247 @@
+use super::extension::Extension;
+use super::instruction::Instruction;
+use super::opcode::Opcode;
+use super::profile::Profile;
+use super::quirks::Quirks;
+use super::Chip8Emulator;
+use std::mem::discriminant;
+
+/// Gives access to one of the flags in `Quirks`.
+type QuirkFlag = fn(&mut Quirks) -> &mut bool;
+
+/// The quirks an instruction can be sensitive to, by the names the page uses
+/// for them.
+const QUIRKS: [(&str, QuirkFlag); 5] = [
+    ("shift-uses-vy", |quirks| &mut quirks.shift_uses_vy),
+    ("load-store-increments-i", |quirks| {
+        &mut quirks.load_store_increments_i
+    }),
+    ("jump-uses-vx", |quirks| &mut quirks.jump_uses_vx),
+    ("logic-resets-vf", |quirks| &mut quirks.logic_resets_vf),
+    ("clip-sprites", |quirks| &mut quirks.clip_sprites),
+];
+
+/// A row of the opcode reference: one form of an instruction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OpcodeEntry {
+    /// The opcode with its operand nibbles as letters, e.g. `8XY6` or `3XNN`.
+    pub pattern: String,
+    /// The assembler mnemonic, e.g. `SHR`.
+    pub mnemonic: String,
+    /// The instruction in assembler syntax with example operands.
+    pub example: String,
+    /// The extension that adds the instruction or changes its meaning, if it
+    /// isn't part of plain CHIP-8.
+    pub extension: Option<Extension>,
+    /// The quirks that change what the instruction does.
+    pub quirks: Vec<&'static str>,
+}
+
+/// Lists the instructions `profile` runs, in opcode order.
+///
+/// Nothing in the table is written by hand: every opcode is decoded as the
+/// emulator would decode it, operand nibbles are found by changing them and
+/// seeing the operands change, and quirk sensitivities by running each
+/// instruction with the quirk flipped and seeing the result change.
+pub fn opcode_table(profile: &Profile) -> Vec<OpcodeEntry> {
+    let mut rows: Vec<(u16, u16, Instruction)> = Vec::new();
+    for value in 0..=u16::MAX {
+        let instruction = match decode(value, profile) {
+            Some(instruction) => instruction,
+            None => continue,
+        };
+        let covered = rows
+            .iter()
+            .any(|&(row_value, fixed_mask, row_instruction)| {
+                value & fixed_mask == row_value & fixed_mask
+                    && discriminant(&instruction) == discriminant(&row_instruction)
+            });
+        if !covered {
+            rows.push((value, fixed_mask(value, profile), instruction));
+        }
+    }
+
+    rows.iter()
+        .map(|&(value, _, _)| entry(value, profile))
+        .collect()
+}
+
+fn decode(value: u16, profile: &Profile) -> Option<Instruction> {
+    Instruction::decode(&Opcode::new(value), profile.hires, profile.extension)
+}
+
+/// Which operand of the instruction at `value`, if any, each nibble of the
+/// opcode belongs to.
+fn operand_nibbles(value: u16, profile: &Profile) -> [Option<usize>; 4] {
+    let instruction = decode(value, profile).unwrap();
+    let operands = instruction.operands();
+
+    let mut nibbles = [None; 4];
+    for (position, nibble) in nibbles.iter_mut().enumerate() {
+        let shift = 12 - 4 * position;
+        *nibble = (1..16).find_map(|change| {
+            let changed = decode(value ^ change << shift, profile)?;
+            if discriminant(&changed) != discriminant(&instruction) {
+                return None;
+            }
+            let changed_operands = changed.operands();
+            (0..operands.len()).find(|&i| changed_operands[i] != operands[i])
+        });
+    }
+    nibbles
+}
+
+/// The bits of `value` that opcodes of the same form share.
+fn fixed_mask(value: u16, profile: &Profile) -> u16 {
+    operand_nibbles(value, profile)
+        .iter()
+        .enumerate()
+        .filter(|(_, operand)| operand.is_none())
+        .fold(0, |mask, (position, _)| mask | 0xf000 >> (4 * position))
+}
+
+fn entry(value: u16, profile: &Profile) -> OpcodeEntry {
+    let nibbles = operand_nibbles(value, profile);
+
+    let mut pattern = String::new();
+    let mut example_value = value;
+    for (position, operand) in nibbles.iter().enumerate() {
+        let shift = 12 - 4 * position;
+        match operand {
+            None => pattern.push_str(&format!("{:X}", value >> shift & 0xf)),
+            Some(operand) => {
+                // Operands spanning several nibbles are numbers, single nibble
+                // ones are named after their position.
+                let width = nibbles.iter().filter(|&o| o == &Some(*operand)).count();
+                pattern.push(match (width, position) {
+                    (1, 1) => 'X',
+                    (1, 2) => 'Y',
+                    _ => 'N',
+                });
+                example_value = example_value & !(0xf << shift) | (position as u16) << shift;
+            }
+        }
+    }
+
+    let instruction = decode(value, profile).unwrap();
+    // Distinct example operands, so that e.g. VX and VY are different
+    // registers when checking quirks.
+    let example = decode(example_value, profile)
+        .filter(|example| discriminant(example) == discriminant(&instruction))
+        .unwrap_or(instruction);
+
+    let extension = profile.extension.filter(|_| {
+        Instruction::decode(&Opcode::new(value), profile.hires, None) != Some(instruction)
+    });
+
+    let result = run(profile, profile.quirks, example);
+    let quirks = QUIRKS
+        .iter()
+        .filter(|(_, quirk)| {
+            let mut flipped = profile.quirks;
+            *quirk(&mut flipped) ^= true;
+            !same_state(&run(profile, flipped, example), &result)
+        })
+        .map(|&(name, _)| name)
+        .collect();
+
+    OpcodeEntry {
+        pattern,
+        mnemonic: example.to_string().split(' ').next().unwrap().to_string(),
+        example: example.to_string(),
+        extension,
+        quirks,
+    }
+}
+
+/// Runs `instruction` on a machine set up like `profile` with `quirks`, in a
+/// state where the quirks make a difference: registers all hold different
+/// values, I points at a solid sprite and there is a subroutine to return
+/// from. Registers hold their own number, so that they are valid keys, except
+/// for V2, the VY of the examples, which puts sprites at the bottom edge.
+fn run(profile: &Profile, quirks: Quirks, instruction: Instruction) -> Chip8Emulator {
+    let mut chip8 = Chip8Emulator::new(0.0);
+    chip8.set_font(&profile.font);
+    chip8.set_extension(profile.extension);
+    chip8.set_quirks(quirks);
+    chip8.seed_rng(0);
+    if profile.hires {
+        chip8.enter_hires_mode(false);
+    }
+
+    for (register, value) in chip8.V.iter_mut().enumerate() {
+        *value = register as u8;
+    }
+    chip8.V[2] = 0x3e;
+    chip8.I = 0x300;
+    for byte in &mut chip8.memory[0x300..0x310] {
+        *byte = 0xff;
+    }
+    chip8.pc = 0x202;
+    chip8.stack.push(0x400);
+
+    chip8.execute(instruction);
+    chip8
+}
+
+fn same_state(a: &Chip8Emulator, b: &Chip8Emulator) -> bool {
+    a.V == b.V
+        && a.I == b.I
+        && a.pc == b.pc
+        && a.stack == b.stack
+        && a.memory[..] == b.memory[..]
+        && a.gfx == b.gfx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'a>(table: &'a [OpcodeEntry], pattern: &str) -> Option<&'a OpcodeEntry> {
+        table.iter().find(|entry| entry.pattern == pattern)
+    }
+
+    #[test]
+    fn test_opcode_table() {
+        let table = opcode_table(&Profile::chip8());
+        assert_eq!(table.len(), 35);
+        assert_eq!(table[0].pattern, "0NNN");
+
+        let shift = find(&table, "8XY6").unwrap();
+        assert_eq!(shift.mnemonic, "SHR");
+        assert_eq!(shift.example, "SHR V1, V2");
+        assert_eq!(shift.quirks, ["shift-uses-vy"]);
+        assert_eq!(find(&table, "DXYN").unwrap().quirks, ["clip-sprites"]);
+        assert_eq!(find(&table, "BNNN").unwrap().quirks, ["jump-uses-vx"]);
+        assert_eq!(
+            find(&table, "FX65").unwrap().quirks,
+            ["load-store-increments-i"]
+        );
+        assert_eq!(find(&table, "8XY1").unwrap().quirks, ["logic-resets-vf"]);
+        assert!(find(&table, "3XNN").unwrap().quirks.is_empty());
+        assert!(table.iter().all(|entry| entry.extension.is_none()));
+
+        let table = opcode_table(&Profile::chip_8x());
+        let color = find(&table, "BXYN").unwrap();
+        assert_eq!(color.extension, Some(Extension::Chip8X));
+        assert!(find(&table, "BNNN").is_none());
+
+        let table = opcode_table(&Profile::hires_chip8());
+        assert_eq!(find(&table, "0230").unwrap().mnemonic, "CLS");
+        assert_eq!(find(&table, "00E0").unwrap().mnemonic, "CLS");
+
+        let table = opcode_table(&Profile::mega_chip());
+        assert_eq!(find(&table, "02NN").unwrap().mnemonic, "LDPAL");
+    }
+
+    #[test]
+    fn test_instruction_patterns() {
+        for profile in [Profile::chip8(), Profile::chip_8x(), Profile::mega_chip()] {
+            for entry in opcode_table(&profile) {
+                let digits = entry.pattern.replace(|c| "XYN".contains(c), "0");
+                let value = u16::from_str_radix(&digits, 16).unwrap();
+                assert_eq!(decode(value, &profile).unwrap().pattern(), entry.pattern);
+            }
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::fmt;
+
+/// Foreground colors of the CHIP-8X's VP-590 color board, indexed by the
+/// color values set with BXYN.
+pub const CHIP8X_FOREGROUND_COLORS: [[u8; 3]; 8] = [
+    [0x00, 0x00, 0x00], // Black
+    [0xff, 0x00, 0x00], // Red
+    [0x00, 0x00, 0xff], // Blue
+    [0xff, 0x00, 0xff], // Violet
+    [0x00, 0xff, 0x00], // Green
+    [0xff, 0xff, 0x00], // Yellow
+    [0x00, 0xff, 0xff], // Aqua
+    [0xff, 0xff, 0xff], // White
+];
+
+/// Background colors of the VP-590, in the order 02A0 steps through them.
+pub const CHIP8X_BACKGROUND_COLORS: [[u8; 3]; 4] = [
+    [0x00, 0x00, 0x80], // Blue
+    [0x00, 0x00, 0x00], // Black
+    [0x00, 0x80, 0x00], // Green
+    [0x80, 0x00, 0x00], // Red
+];
+
+/// Colors used when converting the display into an image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Palette {
+    pub pixel_off: [u8; 3],
+    pub pixel_on: [u8; 3],
+}
+
+impl Palette {
+    /// Palettes to choose from, by name.
+    pub const PRESETS: [(&'static str, Palette); 3] = [
+        (
+            "classic",
+            Palette {
+                pixel_off: [0x00, 0x00, 0x00],
+                pixel_on: [0x00, 0xa8, 0x6b],
+            },
+        ),
+        (
+            "amber",
+            Palette {
+                pixel_off: [0x1a, 0x0f, 0x00],
+                pixel_on: [0xff, 0xb0, 0x00],
+            },
+        ),
+        (
+            "white",
+            Palette {
+                pixel_off: [0x00, 0x00, 0x00],
+                pixel_on: [0xff, 0xff, 0xff],
+            },
+        ),
+    ];
+
+    pub fn preset(name: &str) -> Option<Palette> {
+        Palette::PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .map(|&(_, palette)| palette)
+    }
+
+    /// Parses a palette written as the on and off colors in hex, e.g.
+    /// `#00a86b,#000000`.
+    pub fn parse(value: &str) -> Option<Palette> {
+        let (pixel_on, pixel_off) = value.split_once(',')?;
+        Some(Palette {
+            pixel_on: parse_hex_color(pixel_on)?,
+            pixel_off: parse_hex_color(pixel_off)?,
+        })
+    }
+
+    /// Returns the opaque RGBA color for a pixel in the given state.
+    pub fn rgba(&self, pixel_on: bool) -> [u8; 4] {
+        let [r, g, b] = if pixel_on {
+            self.pixel_on
+        } else {
+            self.pixel_off
+        };
+        [r, g, b, 0xff]
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{},{}",
+            hex_color(self.pixel_on),
+            hex_color(self.pixel_off)
+        )
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::PRESETS[0].1
+    }
+}
+
+/// Parses a color written like `#00a86b`, as color inputs report them.
+pub fn parse_hex_color(value: &str) -> Option<[u8; 3]> {
+    let digits = value.trim().strip_prefix('#')?;
+    if digits.len() != 6 || !digits.is_ascii() {
+        return None;
+    }
+
+    let mut color = [0; 3];
+    for (i, channel) in color.iter_mut().enumerate() {
+        *channel = u8::from_str_radix(&digits[2 * i..2 * i + 2], 16).ok()?;
+    }
+    Some(color)
+}
+
+pub fn hex_color([r, g, b]: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_palette() {
+        assert_eq!(parse_hex_color("#00A86b"), Some([0x00, 0xa8, 0x6b]));
+        assert_eq!(parse_hex_color("00a86b"), None);
+        assert_eq!(parse_hex_color("#00a86"), None);
+        assert_eq!(hex_color([0xff, 0xb0, 0x00]), "#ffb000");
+
+        let amber = Palette::preset("amber").unwrap();
+        assert_eq!(amber.to_string(), "#ffb000,#1a0f00");
+        assert_eq!(Palette::parse(&amber.to_string()), Some(amber));
+        assert_eq!(Palette::parse("#ffb000"), None);
+        assert_eq!(Palette::preset("classic"), Some(Palette::default()));
+    }
+}
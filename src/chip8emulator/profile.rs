@@ -0,0 +1,171 @@
+use super::extension::Extension;
+use super::keypad::ALL_KEYS;
+use super::quirks::Quirks;
+use super::FONT_MEMORY;
+
+/// The keys of the COSMAC VIP's 4x4 keypad, row by row.
+const COSMAC_VIP_KEYPAD: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, //
+    0x4, 0x5, 0x6, 0xD, //
+    0x7, 0x8, 0x9, 0xE, //
+    0xA, 0x0, 0xB, 0xF, //
+];
+
+/// The DREAM 6800's keypad has its keys in order.
+const DREAM_6800_KEYPAD: [u8; 16] = [
+    0x0, 0x1, 0x2, 0x3, //
+    0x4, 0x5, 0x6, 0x7, //
+    0x8, 0x9, 0xA, 0xB, //
+    0xC, 0xD, 0xE, 0xF, //
+];
+
+/// CHIPOS's font has narrower digits than the COSMAC VIP's.
+const DREAM_6800_FONT: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+/// A machine the emulator can imitate: how its interpreter behaves, what its
+/// font looks like and how its keypad is laid out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Profile {
+    pub quirks: Quirks,
+    pub font: [u8; 80],
+    /// Keys the machine has, one bit per key.
+    pub available_keys: u16,
+    /// The key at each position of the 4x4 keypad, row by row, so that keys
+    /// can be mapped to the same positions on a computer keyboard.
+    pub keypad_layout: [u8; 16],
+    /// The instruction set extension the machine's interpreter adds.
+    pub extension: Option<Extension>,
+    /// Whether ROMs always run on the 64x64 display of hi-res CHIP-8, instead
+    /// of only when detected as hi-res.
+    pub hires: bool,
+}
+
+impl Profile {
+    /// Returns the profile with the given name, as used in the page config:
+    /// `chip8`, `cosmac-vip`, `hires-chip8`, `chip-8x`, `dream-6800` or
+    /// `mega-chip`.
+    pub fn parse(name: &str) -> Option<Profile> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "chip8" => Some(Profile::chip8()),
+            "cosmac-vip" => Some(Profile::cosmac_vip()),
+            "hires-chip8" => Some(Profile::hires_chip8()),
+            "chip-8x" => Some(Profile::chip_8x()),
+            "dream-6800" => Some(Profile::dream_6800()),
+            "mega-chip" => Some(Profile::mega_chip()),
+            _ => None,
+        }
+    }
+
+    /// The behavior most modern CHIP-8 programs expect, with every quirk off.
+    pub fn chip8() -> Profile {
+        Profile {
+            quirks: Quirks::default(),
+            font: FONT_MEMORY,
+            available_keys: ALL_KEYS,
+            keypad_layout: COSMAC_VIP_KEYPAD,
+            extension: None,
+            hires: false,
+        }
+    }
+
+    pub fn cosmac_vip() -> Profile {
+        Profile {
+            quirks: Quirks {
+                shift_uses_vy: true,
+                load_store_increments_i: true,
+                jump_uses_vx: false,
+                logic_resets_vf: true,
+                clip_sprites: true,
+            },
+            ..Profile::chip8()
+        }
+    }
+
+    /// A COSMAC VIP running the hi-res CHIP-8 interpreter, which has a 64x64
+    /// display. Hi-res ROMs bring the interpreter's extra code along at
+    /// 0x200-0x2BF and start with a jump over it.
+    pub fn hires_chip8() -> Profile {
+        Profile {
+            hires: true,
+            ..Profile::cosmac_vip()
+        }
+    }
+
+    /// A COSMAC VIP with the VP-590 color board and a second keypad, running
+    /// the CHIP-8X interpreter.
+    pub fn chip_8x() -> Profile {
+        Profile {
+            extension: Some(Extension::Chip8X),
+            ..Profile::cosmac_vip()
+        }
+    }
+
+    /// The DREAM 6800 running CHIPOS, which was modeled on the COSMAC VIP
+    /// interpreter but has its own font and keypad.
+    pub fn dream_6800() -> Profile {
+        Profile {
+            font: DREAM_6800_FONT,
+            keypad_layout: DREAM_6800_KEYPAD,
+            ..Profile::cosmac_vip()
+        }
+    }
+
+    /// Mega-Chip, an extension of SUPER-CHIP. Only its display and sprite
+    /// instructions are supported, not the SUPER-CHIP ones it builds on.
+    pub fn mega_chip() -> Profile {
+        Profile {
+            quirks: Quirks {
+                jump_uses_vx: true,
+                clip_sprites: true,
+                ..Quirks::default()
+            },
+            extension: Some(Extension::MegaChip),
+            ..Profile::chip8()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        assert_eq!(Profile::parse("chip8"), Some(Profile::chip8()));
+        assert_eq!(Profile::parse("COSMAC-VIP"), Some(Profile::cosmac_vip()));
+        assert_eq!(Profile::parse(" dream-6800"), Some(Profile::dream_6800()));
+        assert_eq!(Profile::parse("chip-8x"), Some(Profile::chip_8x()));
+        assert_eq!(Profile::parse("eti-660"), None);
+        assert_eq!(Profile::chip_8x().extension, Some(Extension::Chip8X));
+        assert_eq!(Profile::cosmac_vip().extension, None);
+        assert_eq!(Profile::parse("mega-chip"), Some(Profile::mega_chip()));
+        assert_eq!(Profile::parse("hires-chip8"), Some(Profile::hires_chip8()));
+        assert!(Profile::hires_chip8().hires && !Profile::cosmac_vip().hires);
+
+        let dream = Profile::dream_6800();
+        assert_eq!(dream.quirks, Profile::cosmac_vip().quirks);
+        assert_ne!(dream.font, FONT_MEMORY);
+        for profile in [Profile::chip8(), dream] {
+            let mut keys = profile.keypad_layout;
+            keys.sort_unstable();
+            assert_eq!(keys, DREAM_6800_KEYPAD);
+        }
+    }
+}
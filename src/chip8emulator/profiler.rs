@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+/// How many times the instruction at each address, and each kind of
+/// instruction, has been executed, for finding where a program spends its
+/// time.
+#[derive(Default)]
+pub struct Profiler {
+    /// A count for every address, or nothing while not profiling.
+    hits: Vec<u64>,
+    /// A count for every opcode pattern executed, like `DXYN`.
+    patterns: HashMap<&'static str, u64>,
+}
+
+impl Profiler {
+    pub fn is_enabled(&self) -> bool {
+        !self.hits.is_empty()
+    }
+
+    /// Starts counting from zero, or stops and drops the counts.
+    pub fn set_enabled(&mut self, enabled: bool, memory_size: usize) {
+        if enabled != self.is_enabled() {
+            self.hits = if enabled {
+                vec![0; memory_size]
+            } else {
+                Vec::new()
+            };
+            self.patterns.clear();
+        }
+    }
+
+    pub fn record(&mut self, address: u16) {
+        if let Some(hits) = self.hits.get_mut(address as usize) {
+            *hits += 1;
+        }
+    }
+
+    /// Counts an instruction with the opcode pattern `pattern`.
+    pub fn record_pattern(&mut self, pattern: &'static str) {
+        if self.is_enabled() {
+            *self.patterns.entry(pattern).or_insert(0) += 1;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.hits.iter_mut().for_each(|hits| *hits = 0);
+        self.patterns.clear();
+    }
+
+    pub fn total(&self) -> u64 {
+        self.hits.iter().sum()
+    }
+
+    /// Returns the `count` addresses executed the most with how many times,
+    /// the most first and ties by address.
+    pub fn hot_spots(&self, count: usize) -> Vec<(u16, u64)> {
+        let mut hot_spots: Vec<_> = (0..)
+            .zip(self.hits.iter().copied())
+            .filter(|&(_, hits)| hits > 0)
+            .collect();
+        hot_spots.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hot_spots.truncate(count);
+        hot_spots
+    }
+
+    /// Returns each opcode pattern executed with how many times, the most
+    /// first and ties in opcode order.
+    pub fn pattern_counts(&self) -> Vec<(&'static str, u64)> {
+        let mut counts: Vec<_> = self.patterns.iter().map(|(&p, &n)| (p, n)).collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hot_spots() {
+        let mut profiler = Profiler::default();
+        profiler.record(0x200);
+        assert!(profiler.hot_spots(10).is_empty());
+
+        profiler.set_enabled(true, 4096);
+        for &address in &[0x200, 0x204, 0x202, 0x204, 0x202, 0x204, 0xfff] {
+            profiler.record(address);
+        }
+        assert_eq!(profiler.total(), 7);
+        assert_eq!(profiler.hot_spots(3), [(0x204, 3), (0x202, 2), (0x200, 1)]);
+
+        for &pattern in &["DXYN", "7XNN", "DXYN"] {
+            profiler.record_pattern(pattern);
+        }
+        assert_eq!(profiler.pattern_counts(), [("DXYN", 2), ("7XNN", 1)]);
+
+        profiler.reset();
+        assert!(profiler.hot_spots(10).is_empty());
+        assert!(profiler.pattern_counts().is_empty());
+        assert!(profiler.is_enabled());
+    }
+}
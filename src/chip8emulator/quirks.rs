@@ -0,0 +1,43 @@
+/// Behaviors that differ between CHIP-8 interpreters. ROMs written for one
+/// interpreter often rely on its particular combination of these. The default
+/// leaves every quirk off.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VY and store the result in VX, instead of shifting VX
+    /// in place.
+    pub shift_uses_vy: bool,
+    /// FX55/FX65 leave I pointing just past the last register stored or
+    /// loaded, instead of leaving it unchanged.
+    pub load_store_increments_i: bool,
+    /// BNNN is treated as BXNN and jumps to XNN + VX, instead of NNN + V0.
+    pub jump_uses_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub logic_resets_vf: bool,
+    /// Sprites are clipped at the edges of the display, instead of wrapping
+    /// around to the opposite edge.
+    pub clip_sprites: bool,
+}
+
+/// A quirk that `lint` can detect a ROM depending on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quirk {
+    ShiftUsesVy,
+    LoadStoreIncrementsI,
+    JumpUsesVx,
+}
+
+impl Quirk {
+    /// How interpreters differ on this quirk, in a form suitable for showing
+    /// to the user.
+    pub fn description(self) -> &'static str {
+        match self {
+            Quirk::ShiftUsesVy => {
+                "8XY6/8XYE shift VY on the COSMAC VIP but VX on CHIP-48 and SUPER-CHIP"
+            }
+            Quirk::LoadStoreIncrementsI => {
+                "FX55/FX65 advance I on the COSMAC VIP but leave it unchanged on CHIP-48 and SUPER-CHIP"
+            }
+            Quirk::JumpUsesVx => "BNNN adds V0 on the COSMAC VIP but VX on CHIP-48 and SUPER-CHIP",
+        }
+    }
+}
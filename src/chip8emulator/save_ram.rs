@@ -0,0 +1,126 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Size of the CHIP-8 address space. Save regions must lie inside it.
+const MEMORY_SIZE: u32 = 0x1000;
+
+/// A range of memory that is kept between sessions, like battery-backed SRAM
+/// on a cartridge. Homebrew ROMs can use it to keep settings or high scores.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SaveRegion {
+    start: u16,
+    end: u16,
+}
+
+impl SaveRegion {
+    /// Creates the region `start..=end`, or returns `None` if it is empty or
+    /// extends past the end of memory.
+    pub fn new(start: u16, end: u16) -> Option<SaveRegion> {
+        if start <= end && (end as u32) < MEMORY_SIZE {
+            Some(SaveRegion { start, end })
+        } else {
+            None
+        }
+    }
+
+    pub fn start(&self) -> usize {
+        self.start as usize
+    }
+
+    pub fn len(&self) -> usize {
+        (self.end - self.start) as usize + 1
+    }
+
+    /// Whether any address in `start..start + len` is inside the region.
+    pub fn overlaps(&self, start: usize, len: usize) -> bool {
+        start <= self.end as usize && start + len > self.start as usize
+    }
+
+    /// Parses a comma-separated list of regions such as `E00-EFF,F00-F0F`.
+    pub fn parse_list(value: &str) -> Result<Vec<SaveRegion>, ParseSaveRegionError> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|region| !region.is_empty())
+            .map(str::parse)
+            .collect()
+    }
+}
+
+/// Parses an inclusive hexadecimal address range such as `E00-EFF`, with an
+/// optional `0x` prefix on each address.
+impl FromStr for SaveRegion {
+    type Err = ParseSaveRegionError;
+
+    fn from_str(value: &str) -> Result<SaveRegion, ParseSaveRegionError> {
+        let error = || ParseSaveRegionError(value.to_string());
+        let parse_address = |address: &str| {
+            let address = address.trim();
+            let digits = address
+                .strip_prefix("0x")
+                .or_else(|| address.strip_prefix("0X"))
+                .unwrap_or(address);
+            u16::from_str_radix(digits, 16).map_err(|_| error())
+        };
+
+        let mut bounds = value.splitn(2, '-');
+        let start = parse_address(bounds.next().unwrap())?;
+        let end = parse_address(bounds.next().ok_or_else(error)?)?;
+
+        SaveRegion::new(start, end).ok_or_else(error)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ParseSaveRegionError(String);
+
+impl fmt::Display for ParseSaveRegionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid save RAM region \"{}\", expected a range like E00-EFF",
+            self.0
+        )
+    }
+}
+
+/// FNV-1a hash of `rom`, used to tell ROMs apart when persisting per-ROM data.
+pub fn checksum(rom: &[u8]) -> u32 {
+    rom.iter().fold(0x811c_9dc5, |hash, &byte| {
+        (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_save_regions() {
+        assert_eq!(
+            SaveRegion::parse_list("E00-EFF, 0xF00-0xF0F"),
+            Ok(vec![
+                SaveRegion::new(0xe00, 0xeff).unwrap(),
+                SaveRegion::new(0xf00, 0xf0f).unwrap(),
+            ])
+        );
+        assert_eq!(SaveRegion::parse_list(""), Ok(vec![]));
+        assert!(SaveRegion::parse_list("E00").is_err());
+        assert!(SaveRegion::parse_list("F00-E00").is_err());
+        assert!(SaveRegion::parse_list("F00-1000").is_err());
+
+        let region = SaveRegion::new(0xe00, 0xe0f).unwrap();
+        assert_eq!(region.len(), 0x10);
+        assert!(region.overlaps(0xdff, 2));
+        assert!(region.overlaps(0xe0f, 1));
+        assert!(!region.overlaps(0xdfd, 3));
+        assert!(!region.overlaps(0xe10, 1));
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(checksum(&[]), 0x811c_9dc5);
+        assert_eq!(checksum(b"a"), 0xe40c_292c);
+        assert_ne!(checksum(&[0x12, 0x00]), checksum(&[0x12, 0x01]));
+    }
+}
@@ -0,0 +1,56 @@
+use super::graphics::Graphics;
+use super::megachip::MegaChip;
+use arrayvec::ArrayVec;
+use rand::rngs::StdRng;
+use std::mem::size_of;
+
+/// The value of a CHIP-8 timer and how far it is toward its next decrement,
+/// from 0 up to but not including 1. Timer-sensitive programs need both to
+/// resume exactly where they left off.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimerState {
+    pub value: u8,
+    pub phase: f64,
+}
+
+/// A snapshot of the emulated machine that can be restored later. Settings
+/// like the clock speed and quirks aren't part of it.
+#[allow(non_snake_case)]
+#[derive(Clone)]
+pub struct SaveState {
+    pub(super) memory: [u8; 4096],
+    pub(super) V: [u8; 16],
+    pub(super) I: u16,
+    pub(super) pc: u16,
+    pub(super) stack: ArrayVec<[u16; 16]>,
+    pub(super) gfx: Graphics,
+    pub(super) hires: bool,
+    pub(super) megachip: MegaChip,
+    pub(super) rom_loaded: bool,
+    pub(super) delay_timer: TimerState,
+    pub(super) sound_timer: TimerState,
+    /// Saved so that CXNN continues the same sequence after a restore.
+    pub(super) rng: StdRng,
+    /// Saved so that a ROM that ended or faulted stays stopped instead of
+    /// running past where it stopped.
+    pub(super) halted: bool,
+    pub(super) faulted: bool,
+}
+
+impl SaveState {
+    pub fn get_pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn get_delay_timer(&self) -> TimerState {
+        self.delay_timer
+    }
+
+    pub fn get_sound_timer(&self) -> TimerState {
+        self.sound_timer
+    }
+
+    pub fn byte_len(&self) -> usize {
+        size_of::<SaveState>() + self.gfx.heap_bytes()
+    }
+}
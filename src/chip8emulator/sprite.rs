@@ -0,0 +1,56 @@
+use super::Palette;
+
+/// How many pixels wide a sprite row is: one per bit of its byte.
+pub const SPRITE_WIDTH: u32 = 8;
+
+/// Returns whether the pixel `dx` from the left of a sprite row is on. Each
+/// byte of a sprite is a row, its most significant bit the leftmost pixel,
+/// as DXYN draws them.
+pub fn sprite_pixel(row: u8, dx: u32) -> bool {
+    row >> (SPRITE_WIDTH - 1 - dx) & 1 == 1
+}
+
+/// Returns `row` with the pixel `dx` from the left switched on or off.
+pub fn toggle_sprite_pixel(row: u8, dx: u32) -> u8 {
+    row ^ 1 << (SPRITE_WIDTH - 1 - dx)
+}
+
+/// Renders the sprite with the rows `rows` as an RGBA image, 8 pixels wide
+/// and a pixel high per row, in the colors of `palette`.
+pub fn sprite_rgba(rows: &[u8], palette: Palette) -> Vec<u8> {
+    rows.iter()
+        .flat_map(|&row| (0..SPRITE_WIDTH).map(move |dx| sprite_pixel(row, dx)))
+        .flat_map(|on| {
+            let [r, g, b] = if on {
+                palette.pixel_on
+            } else {
+                palette.pixel_off
+            };
+            [r, g, b, 0xff]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sprite_rgba() {
+        assert!(sprite_pixel(0x80, 0));
+        assert!(!sprite_pixel(0x80, 1));
+        assert!(sprite_pixel(0x01, 7));
+        assert_eq!(toggle_sprite_pixel(0x80, 0), 0x00);
+        assert_eq!(toggle_sprite_pixel(0x80, 7), 0x81);
+
+        let palette = Palette {
+            pixel_off: [0, 0, 0],
+            pixel_on: [0xff, 0xff, 0xff],
+        };
+        let rgba = sprite_rgba(&[0x81, 0x00], palette);
+        assert_eq!(rgba.len(), 2 * 8 * 4);
+        assert_eq!(rgba[..8], [0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0xff]);
+        assert_eq!(rgba[28..32], [0xff, 0xff, 0xff, 0xff]);
+        assert!(rgba[32..].chunks(4).all(|pixel| pixel == [0, 0, 0, 0xff]));
+    }
+}
@@ -0,0 +1,46 @@
+use std::fmt;
+
+/// What the emulator is doing, so frontends can show it and tell which
+/// actions make sense, e.g. stepping only while paused.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    /// No ROM has been loaded yet.
+    Loading,
+    Running,
+    /// Stopped by the user, a breakpoint or a watchpoint, and can resume.
+    Paused,
+    /// Running FX0A, which waits for a key to be pressed.
+    WaitingForKey,
+    /// The ROM ended, so nothing more can happen until it's reset.
+    Halted,
+    /// An instruction faulted in a way the ROM can't carry on from, e.g. a
+    /// return with nothing on the stack.
+    Faulted,
+}
+
+impl State {
+    /// Returns the state's name in the debug API, like `waitingForKey`.
+    pub fn name(self) -> &'static str {
+        match self {
+            State::Loading => "loading",
+            State::Running => "running",
+            State::Paused => "paused",
+            State::WaitingForKey => "waitingForKey",
+            State::Halted => "halted",
+            State::Faulted => "faulted",
+        }
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            State::Loading => "Loading",
+            State::Running => "Running",
+            State::Paused => "Paused",
+            State::WaitingForKey => "Waiting for a key",
+            State::Halted => "Halted",
+            State::Faulted => "Faulted",
+        })
+    }
+}
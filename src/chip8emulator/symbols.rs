@@ -0,0 +1,63 @@
+use super::PROGRAM_MEMORY_START;
+use std::collections::BTreeMap;
+
+/// Reads the labels in a symbol file written by Octo, by their addresses.
+/// Each line has a name and an address, in either order and separated by
+/// spaces, `=` or `:`, the address in hex with `0x` or in decimal. Lines
+/// that don't name an address in program memory, like most constants, are
+/// skipped.
+pub fn parse_symbols(text: &str) -> BTreeMap<u16, String> {
+    text.lines().filter_map(parse_symbol).collect()
+}
+
+fn parse_symbol(line: &str) -> Option<(u16, String)> {
+    let mut tokens = line
+        .split(|c: char| c.is_whitespace() || c == '=' || c == ':')
+        .filter(|token| !token.is_empty());
+    let (first, second) = (tokens.next()?, tokens.next()?);
+    if tokens.next().is_some() {
+        return None;
+    }
+    let (address, name) = match parse_address(first) {
+        Some(address) => (address, second),
+        None => (parse_address(second)?, first),
+    };
+    let is_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+    (is_name && address as usize >= PROGRAM_MEMORY_START).then(|| (address, name.to_string()))
+}
+
+fn parse_address(token: &str) -> Option<u16> {
+    match token
+        .strip_prefix("0x")
+        .or_else(|| token.strip_prefix("0X"))
+    {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => token.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_symbols() {
+        let symbols = parse_symbols(
+            "0x0200 main\n\
+             draw-player = 0x0240\n\
+             loop: 530\n\
+             \n\
+             speed 3\n\
+             0x0300\n\
+             0x0400 not a label\n",
+        );
+        let symbols: Vec<_> = symbols
+            .iter()
+            .map(|(&address, name)| (address, name.as_str()))
+            .collect();
+        assert_eq!(
+            symbols,
+            [(0x200, "main"), (0x212, "loop"), (0x240, "draw-player")]
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use super::{Chip8Emulator, Palette};
+
+const FRAME_INTERVAL: f64 = 1000.0 / 60.0;
+
+/// Seed for the random number generator during thumbnail runs, so the same
+/// ROM always produces the same thumbnail.
+const THUMBNAIL_SEED: u64 = 0xc8;
+
+/// An RGBA image of the display, 4 bytes per pixel in row-major order.
+pub struct Thumbnail {
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+}
+
+impl Thumbnail {
+    pub fn get_width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn get_height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+}
+
+/// Runs `rom_data` headlessly and deterministically for `frames` 60Hz frames
+/// of simulated time, with no keys pressed, and renders a representative frame
+/// with `palette`.
+///
+/// Many games erase and redraw their sprites every frame, so the last frame
+/// alone is often half-drawn. The frame with the most lit pixels is used
+/// instead, with ties going to the latest one. Fails if the ROM doesn't fit
+/// in memory.
+pub fn thumbnail(rom_data: &[u8], frames: u32, palette: Palette) -> Result<Thumbnail, String> {
+    let mut chip8 = Chip8Emulator::new(0.0);
+    chip8.seed_rng(THUMBNAIL_SEED);
+    chip8.load_rom(rom_data)?;
+
+    let mut best = capture(&chip8);
+    let mut best_lit = count_lit(&best);
+    for frame in 1..=frames {
+        chip8.tick(frame as f64 * FRAME_INTERVAL);
+
+        let pixels = capture(&chip8);
+        let lit = count_lit(&pixels);
+        if lit >= best_lit {
+            best = pixels;
+            best_lit = lit;
+        }
+    }
+
+    Ok(Thumbnail {
+        width: chip8.get_gfx_width(),
+        height: chip8.get_gfx_height(),
+        rgba: best.into_iter().flat_map(|on| palette.rgba(on)).collect(),
+    })
+}
+
+fn capture(chip8: &Chip8Emulator) -> Vec<bool> {
+    let width = chip8.get_gfx_width();
+    let height = chip8.get_gfx_height();
+    let mut pixels = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            pixels.push(chip8.get_gfx_pixel(x, y));
+        }
+    }
+    pixels
+}
+
+fn count_lit(pixels: &[bool]) -> usize {
+    pixels.iter().filter(|&&on| on).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{HEIGHT, WIDTH};
+    use super::*;
+
+    fn pixel(thumbnail: &Thumbnail, x: u32, y: u32) -> &[u8] {
+        let index = ((y * thumbnail.get_width() + x) * 4) as usize;
+        &thumbnail.rgba()[index..index + 4]
+    }
+
+    #[test]
+    fn test_thumbnail() {
+        // Draws the font sprite for 0 at (0, 0), then loops forever.
+        let rom = [
+            0x60, 0x00, // V0 = 0
+            0xf0, 0x29, // I = sprite address of digit V0
+            0xd0, 0x05, // Draw 5 rows at (V0, V0)
+            0x12, 0x06, // Jump to self
+        ];
+        let palette = Palette {
+            pixel_off: [1, 2, 3],
+            pixel_on: [4, 5, 6],
+        };
+
+        let thumbnail = thumbnail(&rom, 1, palette).unwrap();
+        assert_eq!(thumbnail.get_width(), WIDTH as u32);
+        assert_eq!(thumbnail.get_height(), HEIGHT as u32);
+        assert_eq!(
+            thumbnail.rgba().len(),
+            (WIDTH as usize) * (HEIGHT as usize) * 4
+        );
+        assert_eq!(pixel(&thumbnail, 0, 0), [4, 5, 6, 0xff]);
+        assert_eq!(pixel(&thumbnail, 3, 0), [4, 5, 6, 0xff]);
+        assert_eq!(pixel(&thumbnail, 1, 1), [1, 2, 3, 0xff]);
+        assert_eq!(pixel(&thumbnail, 4, 0), [1, 2, 3, 0xff]);
+    }
+
+    #[test]
+    fn test_thumbnail_is_deterministic() {
+        // Draws a random byte as a sprite at a random position, forever.
+        let rom = [
+            0xc0, 0xff, // V0 = random
+            0xc1, 0x1f, // V1 = random & 0x1f
+            0xa3, 0x00, // I = 0x300
+            0xf0, 0x55, // Store V0 at I
+            0xd1, 0x11, // Draw 1 row at (V1, V1)
+            0x12, 0x00, // Jump to start
+        ];
+
+        let first = thumbnail(&rom, 30, Palette::default()).unwrap();
+        let second = thumbnail(&rom, 30, Palette::default()).unwrap();
+        assert_eq!(first.rgba(), second.rgba());
+    }
+
+    #[test]
+    fn test_thumbnail_picks_busiest_frame() {
+        // Draws the font sprite for 8, waits a frame, then clears the screen.
+        let rom = [
+            0x60, 0x08, // V0 = 8
+            0xf0, 0x29, // I = sprite address of digit V0
+            0xd0, 0x05, // Draw 5 rows at (V0, V0)
+            0x61, 0x01, // V1 = 1
+            0xf1, 0x15, // Delay timer = V1
+            0xf1, 0x07, // V1 = delay timer
+            0x31, 0x00, // Skip if V1 == 0
+            0x12, 0x0a, // Jump back to reading the delay timer
+            0x00, 0xe0, // Clear the screen
+            0x12, 0x12, // Jump to self
+        ];
+
+        let thumbnail = thumbnail(&rom, 10, Palette::default()).unwrap();
+        assert_eq!(pixel(&thumbnail, 8, 8), Palette::default().rgba(true));
+    }
+}
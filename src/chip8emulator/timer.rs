@@ -1,5 +1,8 @@
 pub struct Timer {
+    /// Time of the last tick.
     prev_time: f64,
+    /// Time of the last step.
+    last_time: f64,
     interval: f64,
 }
 
@@ -7,6 +10,7 @@ impl Timer {
     pub fn new(current_time: f64, interval: f64) -> Timer {
         Timer {
             prev_time: current_time,
+            last_time: current_time,
             interval,
         }
     }
@@ -15,9 +19,33 @@ impl Timer {
         let ticks = (current_time - self.prev_time) / self.interval;
         assert!(ticks >= 0.0, "Current time less than previous time");
         self.prev_time += ticks.floor() * self.interval;
+        self.last_time = current_time;
         ticks as u32
     }
 
+    /// How far the timer was toward its next tick at the last step, from 0 up
+    /// to but not including 1.
+    pub fn phase(&self) -> f64 {
+        ((self.last_time - self.prev_time) / self.interval).clamp(0.0, 1.0 - f64::EPSILON)
+    }
+
+    /// Restarts the timer at `current_time` with its next tick `phase` of an
+    /// interval away.
+    pub fn set_phase(&mut self, phase: f64, current_time: f64) {
+        self.prev_time = current_time - phase.clamp(0.0, 1.0 - f64::EPSILON) * self.interval;
+        self.last_time = current_time;
+    }
+
+    /// Counts `duration` more milliseconds at the next step, as if the timer
+    /// had started that much earlier.
+    pub fn add_time(&mut self, duration: f64) {
+        self.prev_time -= duration;
+    }
+
+    pub fn interval(&self) -> f64 {
+        self.interval
+    }
+
     pub fn set_interval(&mut self, interval: f64) {
         self.interval = interval;
     }
@@ -46,5 +74,11 @@ mod tests {
         timer.set_interval(interval);
         assert_eq!(timer.step(t + 0.99 * interval), 0);
         assert_eq!(timer.step(t + 2.99 * interval), 2);
+
+        timer.set_phase(0.25, 100.0);
+        assert_eq!(timer.phase(), 0.25);
+        assert_eq!(timer.step(100.0 + 0.74 * interval), 0);
+        assert_eq!(timer.step(100.0 + 1.5 * interval), 1);
+        assert!((timer.phase() - 0.75).abs() < 1e-9);
     }
 }
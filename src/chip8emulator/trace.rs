@@ -0,0 +1,160 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::mem::size_of;
+
+/// An executed instruction and the registers it changed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub address: u16,
+    pub opcode: u16,
+    pub text: String,
+    /// The V registers the instruction changed, with their old and new
+    /// values.
+    pub changes: Vec<(u8, u8, u8)>,
+    /// I before and after, if the instruction changed it.
+    pub i_change: Option<(u16, u16)>,
+    /// The V registers after executing it.
+    pub registers: [u8; 16],
+    /// I after executing it.
+    pub i: u16,
+}
+
+impl TraceEntry {
+    /// Describes the instruction at `address`, given the V registers and I
+    /// from before and after executing it.
+    pub fn new(
+        address: u16,
+        opcode: u16,
+        text: String,
+        before: (&[u8; 16], u16),
+        after: (&[u8; 16], u16),
+    ) -> TraceEntry {
+        let changes = before
+            .0
+            .iter()
+            .zip(after.0)
+            .enumerate()
+            .filter(|(_, (old, new))| old != new)
+            .map(|(x, (&old, &new))| (x as u8, old, new))
+            .collect();
+        TraceEntry {
+            address,
+            opcode,
+            text,
+            changes,
+            i_change: (before.1 != after.1).then_some((before.1, after.1)),
+            registers: *after.0,
+            i: after.1,
+        }
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    /// Writes the entry as a line like `206  A234  LD I, 0x234  I 0000->0234`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:03X}  {:04X}  ", self.address, self.opcode)?;
+        if self.changes.is_empty() && self.i_change.is_none() {
+            return write!(f, "{}", self.text);
+        }
+        write!(f, "{:<20}", self.text)?;
+        for &(x, old, new) in &self.changes {
+            write!(f, "  V{:X} {:02X}->{:02X}", x, old, new)?;
+        }
+        if let Some((old, new)) = self.i_change {
+            write!(f, "  I {:04X}->{:04X}", old, new)?;
+        }
+        Ok(())
+    }
+}
+
+/// The last instructions executed, up to a limit, oldest first.
+#[derive(Default)]
+pub struct Trace {
+    entries: VecDeque<TraceEntry>,
+    limit: usize,
+}
+
+impl Trace {
+    pub fn is_enabled(&self) -> bool {
+        self.limit > 0
+    }
+
+    /// Keeps the last `limit` entries from now on, or none if it's 0.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+        let excess = self.entries.len().saturating_sub(limit);
+        self.entries.drain(..excess);
+    }
+
+    pub fn push(&mut self, entry: TraceEntry) {
+        if self.entries.len() >= self.limit {
+            self.entries.pop_front();
+        }
+        if self.limit > 0 {
+            self.entries.push_back(entry);
+        }
+    }
+
+    pub fn entries(&self) -> &VecDeque<TraceEntry> {
+        &self.entries
+    }
+
+    pub fn byte_len(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|entry| {
+                size_of::<TraceEntry>()
+                    + entry.text.len()
+                    + entry.changes.len() * size_of::<(u8, u8, u8)>()
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace() {
+        let mut registers = [0; 16];
+        registers[0] = 5;
+        let entry = |address| {
+            TraceEntry::new(
+                address,
+                0x6005,
+                "LD V0, 0x05".into(),
+                (&[0; 16], 0),
+                (&registers, 0),
+            )
+        };
+        let mut trace = Trace::default();
+        trace.push(entry(0x200));
+        assert!(trace.entries().is_empty());
+
+        trace.set_limit(2);
+        for address in [0x200, 0x202, 0x204] {
+            trace.push(entry(address));
+        }
+        let addresses: Vec<_> = trace.entries().iter().map(|entry| entry.address).collect();
+        assert_eq!(addresses, [0x202, 0x204]);
+        trace.set_limit(1);
+        assert_eq!(trace.entries().len(), 1);
+
+        assert_eq!(
+            trace.entries()[0].to_string(),
+            "204  6005  LD V0, 0x05           V0 00->05"
+        );
+        let load_i = TraceEntry::new(
+            0x206,
+            0xa234,
+            "LD I, 0x234".into(),
+            (&[0; 16], 0),
+            (&[0; 16], 0x234),
+        );
+        assert_eq!(
+            load_i.to_string(),
+            "206  A234  LD I, 0x234           I 0000->0234"
+        );
+    }
+}
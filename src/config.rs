@@ -0,0 +1,503 @@
+use crate::audio::{AudioBackend, AudioConfig, Waveform};
+use crate::chip8emulator::{parse_key_mask, Palette, Profile, SaveRegion};
+use crate::memory_usage::{BufferCap, Eviction, MemoryLimits};
+use crate::palette_picker::SchemePalettes;
+use js_sys::{Array, Reflect};
+use wasm_bindgen::JsValue;
+use web_sys::{console, window, UrlSearchParams};
+
+const DEFAULT_ROM: &str = "INVADERS";
+const DEFAULT_THEMES: &str = "themes/manifest.json";
+
+/// Name of the global object an embedding page can define before the wasm
+/// module starts, e.g. `window.chip8Config = { initialRom: "PONG" }`.
+const INIT_CONFIG_GLOBAL: &str = "chip8Config";
+
+#[derive(Debug, PartialEq)]
+pub enum InitialRom {
+    /// Fetch and run the ROM with this name from the ROMs directory.
+    Named(String),
+    /// Start idle and prompt the user to pick a ROM.
+    None,
+}
+
+impl InitialRom {
+    /// Parses an initial ROM choice. An empty string or `none` (in any case)
+    /// means that no ROM should be loaded on startup.
+    pub fn parse(value: &str) -> InitialRom {
+        let value = value.trim();
+        if value.is_empty() || value.eq_ignore_ascii_case("none") {
+            InitialRom::None
+        } else {
+            InitialRom::Named(value.to_string())
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Mode {
+    /// The regular single-ROM emulator view.
+    Emulator,
+    /// A grid of ROM cards with thumbnails that load the ROM on click.
+    Gallery,
+}
+
+impl Mode {
+    pub fn parse(value: &str) -> Option<Mode> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "emulator" => Some(Mode::Emulator),
+            "gallery" => Some(Mode::Gallery),
+            _ => None,
+        }
+    }
+}
+
+pub struct Config {
+    pub initial_rom: InitialRom,
+    pub mode: Mode,
+    /// Start without showing the boot splash screen.
+    pub skip_boot: bool,
+    /// Memory ranges persisted per ROM. Empty unless the page opts in.
+    pub save_regions: Vec<SaveRegion>,
+    /// Name of the machine profile to start with, e.g. `dream-6800`.
+    pub profile: Option<String>,
+    /// Keys the emulated machine has, one bit per key. ROMs for machines with
+    /// fewer keys may expect the others to never be pressed.
+    pub available_keys: u16,
+    pub audio: AudioConfig,
+    pub audio_backend: AudioBackend,
+    /// Vibrate the device while the sound timer is active.
+    pub vibrate: bool,
+    /// Answer debug protocol requests posted from other windows.
+    pub debug_protocol: bool,
+    /// Origins besides the page's own that may post debug protocol
+    /// requests, e.g. `https://example.com`. Only the init config can add
+    /// them, so a link can't let another site control the emulator.
+    pub debug_origins: Vec<String>,
+    /// URL of a WebSocket to take debug protocol requests from.
+    pub debug_socket: Option<String>,
+    /// Caps for buffers that grow while the page is used, like recordings.
+    pub memory_limits: MemoryLimits,
+    /// Run the emulator in a Web Worker drawing to an OffscreenCanvas.
+    pub worker: bool,
+    /// URL of the manifest listing theme files to fetch, if any.
+    pub themes: Option<String>,
+    /// Palettes used for dark and light color schemes when the display
+    /// panel follows the system's.
+    pub scheme_palettes: SchemePalettes,
+}
+
+impl Config {
+    /// Builds the startup configuration from the page. Values from the init
+    /// config object take precedence over the defaults, and query parameters
+    /// (`?rom=PONG`, `?rom=none`, `?mode=gallery`, `?saveram=E00-EFF`,
+    /// `?profile=dream-6800`, `?keys=0123456789`, `?mute=1`, `?audio=worklet`,
+    /// `?vibrate=1`, `?debug=1`, `?debugws=ws://localhost:9000`, `?worker=1`,
+    /// `?themes=none`) take precedence over both. Worker mode is turned off
+    /// when another setting needs the emulator on the page.
+    pub fn from_page() -> Config {
+        let mut config = Config::default();
+
+        if let Some(init) = get_init_config() {
+            if let Some(rom) = get_init_config_field(&init, "initialRom") {
+                config.initial_rom = rom
+                    .as_string()
+                    .map_or(InitialRom::None, |rom| InitialRom::parse(&rom));
+            }
+            if let Some(mode) = get_init_config_field(&init, "mode")
+                .and_then(|mode| mode.as_string())
+                .and_then(|mode| Mode::parse(&mode))
+            {
+                config.mode = mode;
+            }
+            if let Some(skip_boot) =
+                get_init_config_field(&init, "skipBoot").and_then(|skip| skip.as_bool())
+            {
+                config.skip_boot = skip_boot;
+            }
+            if let Some(regions) = get_init_config_field(&init, "saveRam")
+                .and_then(|regions| regions.as_string())
+                .and_then(|regions| parse_save_regions(&regions))
+            {
+                config.save_regions = regions;
+            }
+            if let Some(profile) = get_init_config_field(&init, "profile")
+                .and_then(|profile| profile.as_string())
+                .and_then(|profile| parse_profile(&profile))
+            {
+                config.profile = Some(profile);
+            }
+            if let Some(keys) = get_init_config_field(&init, "keys")
+                .and_then(|keys| keys.as_string())
+                .and_then(|keys| parse_available_keys(&keys))
+            {
+                config.available_keys = keys;
+            }
+            if let Some(audio) = get_init_config_field(&init, "audio") {
+                config.audio = parse_audio_config(&audio, config.audio);
+                if let Some(backend) = get_init_config_field(&audio, "backend")
+                    .and_then(|backend| backend.as_string())
+                    .and_then(|backend| AudioBackend::parse(&backend))
+                {
+                    config.audio_backend = backend;
+                }
+            }
+            if let Some(vibrate) =
+                get_init_config_field(&init, "vibrate").and_then(|vibrate| vibrate.as_bool())
+            {
+                config.vibrate = vibrate;
+            }
+            if let Some(debug) =
+                get_init_config_field(&init, "debugProtocol").and_then(|debug| debug.as_bool())
+            {
+                config.debug_protocol = debug;
+            }
+            if let Some(origins) =
+                get_init_config_field(&init, "debugOrigins").filter(Array::is_array)
+            {
+                config.debug_origins = Array::from(&origins)
+                    .iter()
+                    .filter_map(|origin| origin.as_string())
+                    .collect();
+            }
+            if let Some(url) =
+                get_init_config_field(&init, "debugSocket").and_then(|url| url.as_string())
+            {
+                config.debug_socket = Some(url);
+            }
+            if let Some(memory) = get_init_config_field(&init, "memory") {
+                config.memory_limits = parse_memory_limits(&memory, config.memory_limits);
+            }
+            if let Some(worker) =
+                get_init_config_field(&init, "worker").and_then(|worker| worker.as_bool())
+            {
+                config.worker = worker;
+            }
+            if let Some(themes) = get_init_config_field(&init, "themes") {
+                config.themes = themes.as_string().and_then(|themes| parse_themes(&themes));
+            }
+            if let Some(palettes) = get_init_config_field(&init, "colorSchemePalettes") {
+                config.scheme_palettes = parse_scheme_palettes(&palettes, config.scheme_palettes);
+            }
+        }
+
+        if let Some(params) = get_query_params() {
+            if let Some(rom) = params.get("rom") {
+                config.initial_rom = InitialRom::parse(&rom);
+            }
+            if let Some(mode) = params.get("mode").and_then(|mode| Mode::parse(&mode)) {
+                config.mode = mode;
+            }
+            if let Some(boot) = params.get("boot").and_then(|boot| parse_flag(&boot)) {
+                config.skip_boot = !boot;
+            }
+            if let Some(regions) = params
+                .get("saveram")
+                .and_then(|regions| parse_save_regions(&regions))
+            {
+                config.save_regions = regions;
+            }
+            if let Some(profile) = params
+                .get("profile")
+                .and_then(|profile| parse_profile(&profile))
+            {
+                config.profile = Some(profile);
+            }
+            if let Some(keys) = params
+                .get("keys")
+                .and_then(|keys| parse_available_keys(&keys))
+            {
+                config.available_keys = keys;
+            }
+            if let Some(mute) = params.get("mute").and_then(|mute| parse_flag(&mute)) {
+                config.audio.muted = mute;
+            }
+            if let Some(backend) = params
+                .get("audio")
+                .and_then(|backend| AudioBackend::parse(&backend))
+            {
+                config.audio_backend = backend;
+            }
+            if let Some(vibrate) = params
+                .get("vibrate")
+                .and_then(|vibrate| parse_flag(&vibrate))
+            {
+                config.vibrate = vibrate;
+            }
+            if let Some(debug) = params.get("debug").and_then(|debug| parse_flag(&debug)) {
+                config.debug_protocol = debug;
+            }
+            if let Some(url) = params.get("debugws") {
+                config.debug_socket = Some(url);
+            }
+            if let Some(worker) = params.get("worker").and_then(|worker| parse_flag(&worker)) {
+                config.worker = worker;
+            }
+            if let Some(themes) = params.get("themes") {
+                config.themes = parse_themes(&themes);
+            }
+        }
+
+        if config.worker {
+            if let Some(feature) = config.needs_page_emulator() {
+                let message = format!(
+                    "{} needs the emulator on the page, so it doesn't run in a worker",
+                    feature
+                );
+                console::warn_1(&message.into());
+                config.worker = false;
+            }
+        }
+        config
+    }
+
+    /// Names a feature the page asked for that only works with the emulator
+    /// running on the page, if any. The worker's emulator doesn't persist
+    /// save RAM or answer debug protocol requests.
+    pub fn needs_page_emulator(&self) -> Option<&'static str> {
+        if !self.save_regions.is_empty() {
+            Some("Save RAM")
+        } else if self.debug_protocol || self.debug_socket.is_some() {
+            Some("The debug protocol")
+        } else {
+            None
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            initial_rom: InitialRom::Named(DEFAULT_ROM.to_string()),
+            mode: Mode::Emulator,
+            skip_boot: false,
+            save_regions: Vec::new(),
+            profile: None,
+            available_keys: u16::MAX,
+            audio: AudioConfig::default(),
+            audio_backend: AudioBackend::Oscillator,
+            vibrate: false,
+            debug_protocol: false,
+            debug_origins: Vec::new(),
+            debug_socket: None,
+            memory_limits: MemoryLimits::default(),
+            worker: false,
+            themes: Some(DEFAULT_THEMES.to_string()),
+            scheme_palettes: SchemePalettes::default(),
+        }
+    }
+}
+
+/// Parses an on/off query parameter value.
+fn parse_flag(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parses a theme manifest URL. Like for the initial ROM, an empty string or
+/// `none` means that no themes should be loaded.
+fn parse_themes(value: &str) -> Option<String> {
+    match InitialRom::parse(value) {
+        InitialRom::Named(url) => Some(url),
+        InitialRom::None => None,
+    }
+}
+
+/// Parses a list of save RAM regions, logging and discarding invalid lists.
+fn parse_save_regions(value: &str) -> Option<Vec<SaveRegion>> {
+    match SaveRegion::parse_list(value) {
+        Ok(regions) => Some(regions),
+        Err(e) => {
+            console::error_1(&e.to_string().into());
+            None
+        }
+    }
+}
+
+/// Checks that a profile name is valid and normalizes it, logging and
+/// discarding invalid names.
+fn parse_profile(value: &str) -> Option<String> {
+    if Profile::parse(value).is_some() {
+        Some(value.trim().to_ascii_lowercase())
+    } else {
+        console::error_1(&format!("Unknown profile {}", value).into());
+        None
+    }
+}
+
+/// Parses a list of available keys, logging and discarding invalid lists.
+fn parse_available_keys(value: &str) -> Option<u16> {
+    let keys = parse_key_mask(value);
+    if keys.is_none() {
+        console::error_1(&format!("Invalid key list {}", value).into());
+    }
+    keys
+}
+
+/// Reads the `audio` init config object, e.g.
+/// `{ waveform: "triangle", frequency: 220, volume: 0.2, muted: false }`.
+/// Missing or invalid fields keep their value from `audio`.
+fn parse_audio_config(init: &JsValue, mut audio: AudioConfig) -> AudioConfig {
+    if let Some(waveform) = get_init_config_field(init, "waveform")
+        .and_then(|waveform| waveform.as_string())
+        .and_then(|waveform| Waveform::parse(&waveform))
+    {
+        audio.waveform = waveform;
+    }
+    if let Some(frequency) = get_init_config_field(init, "frequency").and_then(|f| f.as_f64()) {
+        audio.frequency = frequency as f32;
+    }
+    if let Some(volume) = get_init_config_field(init, "volume").and_then(|v| v.as_f64()) {
+        audio.volume = volume.clamp(0.0, 1.0) as f32;
+    }
+    if let Some(muted) = get_init_config_field(init, "muted").and_then(|m| m.as_bool()) {
+        audio.muted = muted;
+    }
+    audio
+}
+
+/// Reads the `colorSchemePalettes` init config object, e.g.
+/// `{ dark: "#00a86b,#000000", light: "#0b5d3b,#f4f1e8" }`. Missing or invalid
+/// palettes keep their value from `palettes`.
+fn parse_scheme_palettes(init: &JsValue, mut palettes: SchemePalettes) -> SchemePalettes {
+    let palette = |name: &str| {
+        let value = get_init_config_field(init, name)?.as_string()?;
+        let palette = Palette::parse(&value);
+        if palette.is_none() {
+            console::error_1(&format!("Invalid {} palette {}", name, value).into());
+        }
+        palette
+    };
+    if let Some(dark) = palette("dark") {
+        palettes.dark = dark;
+    }
+    if let Some(light) = palette("light") {
+        palettes.light = light;
+    }
+    palettes
+}
+
+/// Reads the `memory` init config object, e.g.
+/// `{ audioRecording: { maxBytes: 1000000, eviction: "drop-oldest" } }`.
+/// Missing or invalid fields keep their value from `limits`.
+fn parse_memory_limits(init: &JsValue, mut limits: MemoryLimits) -> MemoryLimits {
+    if let Some(cap) = get_init_config_field(init, "audioRecording") {
+        limits.audio_recording = parse_buffer_cap(&cap, limits.audio_recording);
+    }
+    if let Some(cap) = get_init_config_field(init, "videoRecording") {
+        limits.video_recording = parse_buffer_cap(&cap, limits.video_recording);
+    }
+    if let Some(cap) = get_init_config_field(init, "gifRecording") {
+        limits.gif_recording = parse_buffer_cap(&cap, limits.gif_recording);
+    }
+    limits
+}
+
+fn parse_buffer_cap(init: &JsValue, mut cap: BufferCap) -> BufferCap {
+    if let Some(max_bytes) = get_init_config_field(init, "maxBytes").and_then(|m| m.as_f64()) {
+        cap.max_bytes = max_bytes.max(0.0) as usize;
+    }
+    if let Some(eviction) = get_init_config_field(init, "eviction")
+        .and_then(|eviction| eviction.as_string())
+        .and_then(|eviction| Eviction::parse(&eviction))
+    {
+        cap.eviction = eviction;
+    }
+    cap
+}
+
+fn get_init_config() -> Option<JsValue> {
+    let init = Reflect::get(&window()?.into(), &INIT_CONFIG_GLOBAL.into()).ok()?;
+    if init.is_object() {
+        Some(init)
+    } else {
+        None
+    }
+}
+
+/// Returns the field with the given name from the init config, or `None` if
+/// the field is absent. A field explicitly set to `null` is returned as-is.
+fn get_init_config_field(init: &JsValue, name: &str) -> Option<JsValue> {
+    let value = Reflect::get(init, &name.into()).ok()?;
+    if value.is_undefined() {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+fn get_query_params() -> Option<UrlSearchParams> {
+    let search = window()?.location().search().ok()?;
+    UrlSearchParams::new_with_str(&search).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_initial_rom() {
+        assert_eq!(
+            InitialRom::parse("PONG"),
+            InitialRom::Named("PONG".to_string())
+        );
+        assert_eq!(
+            InitialRom::parse(" TETRIS "),
+            InitialRom::Named("TETRIS".to_string())
+        );
+        assert_eq!(InitialRom::parse(""), InitialRom::None);
+        assert_eq!(InitialRom::parse("none"), InitialRom::None);
+        assert_eq!(InitialRom::parse("None"), InitialRom::None);
+        assert_eq!(
+            Config::default().initial_rom,
+            InitialRom::Named(DEFAULT_ROM.to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert_eq!(Mode::parse("gallery"), Some(Mode::Gallery));
+        assert_eq!(Mode::parse("Emulator"), Some(Mode::Emulator));
+        assert_eq!(Mode::parse("arcade"), None);
+        assert_eq!(Config::default().mode, Mode::Emulator);
+    }
+
+    #[test]
+    fn test_parse_flag() {
+        assert_eq!(parse_flag("1"), Some(true));
+        assert_eq!(parse_flag("Off"), Some(false));
+        assert_eq!(parse_flag("maybe"), None);
+        assert!(!Config::default().skip_boot);
+    }
+
+    #[test]
+    fn test_parse_themes() {
+        assert_eq!(
+            parse_themes(" my/themes.json"),
+            Some("my/themes.json".to_string())
+        );
+        assert_eq!(parse_themes("NONE"), None);
+        assert_eq!(
+            Config::default().themes.as_deref(),
+            Some("themes/manifest.json")
+        );
+    }
+
+    #[test]
+    fn test_needs_page_emulator() {
+        assert_eq!(Config::default().needs_page_emulator(), None);
+        let config = Config {
+            save_regions: parse_save_regions("E00-EFF").unwrap(),
+            ..Config::default()
+        };
+        assert_eq!(config.needs_page_emulator(), Some("Save RAM"));
+        let config = Config {
+            debug_socket: Some("ws://localhost:9000".to_string()),
+            ..Config::default()
+        };
+        assert_eq!(config.needs_page_emulator(), Some("The debug protocol"));
+    }
+}
@@ -0,0 +1,75 @@
+//! Crash reports: when an instruction faults, e.g. an unknown opcode or a
+//! return with nothing on the stack, a notice under the display says so, with
+//! a button downloading the registers, stack, memory around it and the
+//! instructions before it as JSON.
+
+use super::{download_file, get_element_by_id, osd};
+use crate::chip8emulator::{Chip8Emulator, CrashReport};
+use gloo::events::EventListener;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::HtmlElement;
+
+thread_local! {
+    /// The instruction count and address of the report shown, if any.
+    static SHOWN: Cell<Option<(u64, u16)>> = const { Cell::new(None) };
+}
+
+/// Downloads the crash report with the notice's Download button.
+pub fn register_crash_report(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("crash-download"), "click", move |_| {
+        if let Some(report) = chip8.borrow().get_crash_report() {
+            download_file(
+                &file_name(report),
+                report.to_json().as_bytes(),
+                "application/json",
+            );
+        }
+    })
+    .forget();
+}
+
+/// Shows the notice when an instruction faulted, and hides it again once
+/// the emulator is reset. Called every frame.
+pub fn update(chip8: &Chip8Emulator) {
+    let report = chip8.get_crash_report();
+    let key = report.map(|report| (report.instruction_count, report.address));
+    if SHOWN.with(|shown| shown.replace(key)) == key {
+        return;
+    }
+
+    let notice = get_element_by_id("crash")
+        .dyn_into::<HtmlElement>()
+        .expect("Element with id #crash is not an HTML element");
+    notice.set_hidden(report.is_none());
+    if let Some(report) = report {
+        let text = describe(report);
+        get_element_by_id("crash-reason").set_text_content(Some(&text));
+        osd::show_error(&text);
+    }
+}
+
+fn describe(report: &CrashReport) -> String {
+    format!("{} at {:03X}", report.reason, report.address)
+}
+
+fn file_name(report: &CrashReport) -> String {
+    format!("chip8-crash-{:03x}.json", report.address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x00, 0xee]).unwrap();
+        chip8.tick(100.0);
+        let report = chip8.get_crash_report().unwrap();
+        assert_eq!(describe(report), "Return with an empty stack at 200");
+        assert_eq!(file_name(report), "chip8-crash-200.json");
+    }
+}
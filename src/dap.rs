@@ -0,0 +1,657 @@
+//! Debug Adapter Protocol bridge, so editors like VS Code can debug CHIP-8
+//! programs running in the core.
+//!
+//! The adapter speaks DAP over stdin and stdout and runs the ROM headlessly in
+//! real time. Debugging is address-level: breakpoints are instruction
+//! breakpoints on addresses, and code is shown as disassembly.
+
+use crate::chip8emulator::{assemble, Chip8Emulator, State};
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// DAP requires a thread, so the emulator is presented as a single one.
+const THREAD_ID: u64 = 1;
+const REGISTERS_REFERENCE: u64 = 1;
+/// The most instructions or bytes a `disassemble` or `readMemory` request can
+/// ask for, enough for all of memory.
+const MAX_REQUEST_COUNT: u64 = 0x1000;
+/// How long the adapter sleeps between emulator ticks.
+const TICK_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Runs the adapter until the client disconnects or closes stdin.
+pub fn run() -> io::Result<()> {
+    let requests = spawn_reader(io::stdin());
+    let mut stdout = io::stdout();
+    let start = Instant::now();
+    let mut session = Session::new(0.0);
+
+    loop {
+        loop {
+            match requests.try_recv() {
+                Ok(request) => {
+                    let now = start.elapsed().as_secs_f64() * 1000.0;
+                    for message in session.handle(&request, now) {
+                        write_message(&mut stdout, &message)?;
+                    }
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+
+        if session.is_terminated() {
+            return Ok(());
+        }
+
+        let now = start.elapsed().as_secs_f64() * 1000.0;
+        for message in session.tick(now) {
+            write_message(&mut stdout, &message)?;
+        }
+        thread::sleep(TICK_INTERVAL);
+    }
+}
+
+/// Reads messages on a separate thread so the emulator keeps running while
+/// the client is quiet.
+fn spawn_reader<R: Read + Send + 'static>(input: R) -> Receiver<Value> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = BufReader::new(input);
+        while let Ok(Some(message)) = read_message(&mut input) {
+            if sender.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Reads one `Content-Length` framed message, or returns `None` at the end of
+/// the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(length) = line.strip_prefix("Content-Length:") {
+            content_length = length.trim().parse().ok();
+        }
+    }
+
+    let content_length = content_length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length"))?;
+    let mut body = vec![0; content_length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// The state of one debugging session, independent of how messages are
+/// transported.
+struct Session {
+    chip8: Chip8Emulator,
+    seq: u64,
+    /// Whether the emulator was stopped after the last tick, to notice when a
+    /// breakpoint pauses it or an instruction faults.
+    was_stopped: bool,
+    /// Whether a step over or out is running, so the pause ending it is
+    /// reported as a step rather than a breakpoint.
+    stepping: bool,
+    terminated: bool,
+}
+
+impl Session {
+    fn new(current_time: f64) -> Session {
+        Session {
+            chip8: Chip8Emulator::new(current_time),
+            seq: 0,
+            was_stopped: false,
+            stepping: false,
+            terminated: false,
+        }
+    }
+
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    /// Runs the emulator up to `current_time` and returns the events that
+    /// resulted.
+    fn tick(&mut self, current_time: f64) -> Vec<Value> {
+        self.chip8.tick(current_time);
+
+        let stopped = self.is_stopped();
+        let mut events = Vec::new();
+        if stopped && !self.was_stopped {
+            let reason = if self.stepping { "step" } else { "breakpoint" };
+            events.push(self.stopped_event(reason));
+            self.stepping = false;
+        }
+        self.was_stopped = stopped;
+        events
+    }
+
+    /// Returns whether the emulator is paused or can't run any further.
+    fn is_stopped(&self) -> bool {
+        matches!(
+            self.chip8.get_state(),
+            State::Paused | State::Halted | State::Faulted
+        )
+    }
+
+    /// Handles a request and returns the response followed by any events.
+    fn handle(&mut self, request: &Value, current_time: f64) -> Vec<Value> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let arguments = &request["arguments"];
+        let mut events = Vec::new();
+
+        let result = match command {
+            "initialize" => {
+                events.push(self.event("initialized", json!({})));
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsInstructionBreakpoints": true,
+                    "supportsDisassembleRequest": true,
+                    "supportsReadMemoryRequest": true,
+                    "supportsSteppingGranularity": true,
+                }))
+            }
+            "launch" => self.launch(arguments, current_time),
+            "configurationDone" => Ok(json!({})),
+            "setBreakpoints" => {
+                // Source-level breakpoints need a line map from the assembler.
+                let breakpoints: Vec<Value> = arguments["breakpoints"]
+                    .as_array()
+                    .map_or(&[][..], Vec::as_slice)
+                    .iter()
+                    .map(|_| {
+                        json!({
+                            "verified": false,
+                            "message": "Only instruction breakpoints are supported",
+                        })
+                    })
+                    .collect();
+                Ok(json!({ "breakpoints": breakpoints }))
+            }
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(arguments),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => Ok(self.stack_trace()),
+            "scopes" => Ok(json!({
+                "scopes": [{
+                    "name": "Registers",
+                    "variablesReference": REGISTERS_REFERENCE,
+                    "expensive": false,
+                }]
+            })),
+            "variables" => Ok(self.variables()),
+            "continue" => {
+                self.chip8.resume();
+                self.was_stopped = false;
+                self.stepping = false;
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" => Ok(self.step(Chip8Emulator::step_over, &mut events)),
+            "stepIn" => Ok(self.step(Chip8Emulator::step_instruction, &mut events)),
+            "stepOut" => Ok(self.step(Chip8Emulator::step_out, &mut events)),
+            "pause" => {
+                self.chip8.pause();
+                self.was_stopped = true;
+                events.push(self.stopped_event("pause"));
+                Ok(json!({}))
+            }
+            "disassemble" => self.disassemble(arguments),
+            "readMemory" => self.read_memory(arguments),
+            "disconnect" | "terminate" => {
+                self.terminated = true;
+                Ok(json!({}))
+            }
+            _ => Err(format!("Unsupported request {}", command)),
+        };
+
+        let mut response = json!({
+            "seq": self.next_seq(),
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": command,
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = message.into(),
+        }
+
+        let mut messages = vec![response];
+        messages.append(&mut events);
+        messages
+    }
+
+    fn launch(&mut self, arguments: &Value, current_time: f64) -> Result<Value, String> {
+        let program = arguments["program"]
+            .as_str()
+            .ok_or("Missing program to launch")?;
+        let rom = fs::read(program).map_err(|e| format!("Can't read {}: {}", program, e))?;
+        let rom = if program.ends_with(".asm") {
+            let source = String::from_utf8_lossy(&rom);
+            assemble(&source).map_err(|e| format!("Can't assemble {}: {}", program, e))?
+        } else {
+            rom
+        };
+
+        self.chip8 = Chip8Emulator::new(current_time);
+        self.chip8
+            .load_rom(&rom)
+            .map_err(|e| format!("Can't load {}: {}", program, e))?;
+        if arguments["stopOnEntry"].as_bool().unwrap_or(false) {
+            self.chip8.pause();
+        }
+        Ok(json!({}))
+    }
+
+    fn set_instruction_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        for address in self.chip8.get_breakpoints() {
+            self.chip8.remove_breakpoint(address);
+        }
+
+        let breakpoints = arguments["breakpoints"]
+            .as_array()
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .map(|breakpoint| {
+                let address = breakpoint["instructionReference"]
+                    .as_str()
+                    .and_then(parse_address)
+                    .map(|address| address as i64 + breakpoint["offset"].as_i64().unwrap_or(0))
+                    .filter(|address| (0..0x1000).contains(address));
+                match address {
+                    Some(address) => {
+                        self.chip8.add_breakpoint(address as u16);
+                        json!({
+                            "verified": true,
+                            "instructionReference": format_address(address as u16),
+                        })
+                    }
+                    None => json!({ "verified": false, "message": "Invalid address" }),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /// Shows the current address, then the return address of each call on the
+    /// stack.
+    fn stack_trace(&self) -> Value {
+        let pc = self.chip8.get_pc();
+        let frames: Vec<Value> = std::iter::once(pc)
+            .chain(self.chip8.get_stack().iter().rev().copied())
+            .enumerate()
+            .map(|(i, address)| {
+                json!({
+                    "id": i,
+                    "name": format_address(address),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format_address(address),
+                })
+            })
+            .collect();
+        json!({ "stackFrames": frames, "totalFrames": frames.len() })
+    }
+
+    fn variables(&self) -> Value {
+        let byte = |name: String, value: u8| json!({ "name": name, "value": format!("{:#04X}", value), "variablesReference": 0 });
+        let word = |name: &str, value: u16| json!({ "name": name, "value": format_address(value), "variablesReference": 0 });
+
+        let mut variables: Vec<Value> = self
+            .chip8
+            .get_registers()
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| byte(format!("V{:X}", i), value))
+            .collect();
+        variables.push(word("I", self.chip8.get_I()));
+        variables.push(word("PC", self.chip8.get_pc()));
+        variables.push(byte("DT".to_string(), self.chip8.get_delay_timer()));
+        variables.push(byte("ST".to_string(), self.chip8.get_sound_timer()));
+        for (name, timer) in [
+            ("DT phase", self.chip8.get_delay_timer_state()),
+            ("ST phase", self.chip8.get_sound_timer_state()),
+        ] {
+            variables.push(json!({
+                "name": name,
+                "value": format!("{:.3}", timer.phase),
+                "variablesReference": 0,
+            }));
+        }
+
+        json!({ "variables": variables })
+    }
+
+    fn disassemble(&self, arguments: &Value) -> Result<Value, String> {
+        let start = arguments["instructionOffset"]
+            .as_i64()
+            .unwrap_or(0)
+            .checked_mul(2)
+            .and_then(|offset| offset.checked_add(start_address(arguments).ok()?))
+            .ok_or("Invalid memoryReference or offset")?;
+        let count = request_count(&arguments["instructionCount"])?;
+
+        // DAP expects exactly `count` instructions, so addresses outside of
+        // memory are padded with invalid entries.
+        let instructions = (0..count)
+            .map(|i| start.saturating_add(2 * i as i64))
+            .map(|address| {
+                match (0..0xfff)
+                    .contains(&address)
+                    .then(|| self.chip8.disassemble(address as u16, 1).pop())
+                    .flatten()
+                {
+                    Some(line) => json!({
+                        "address": format_address(line.address),
+                        "instructionBytes": format!("{:04X}", line.opcode),
+                        "instruction": line.text,
+                    }),
+                    None => json!({
+                        "address": format!("{:#X}", address.max(0)),
+                        "instruction": "??",
+                        "presentationHint": "invalid",
+                    }),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Ok(json!({ "instructions": instructions }))
+    }
+
+    fn read_memory(&self, arguments: &Value) -> Result<Value, String> {
+        let memory = self.chip8.get_memory();
+        let start = start_address(arguments)?;
+        let count = request_count(&arguments["count"])?;
+
+        let start = start.clamp(0, memory.len() as i64) as usize;
+        let end = (start + count).min(memory.len());
+        Ok(json!({
+            "address": format_address(start as u16),
+            "data": encode_base64(&memory[start..end]),
+            "unreadableBytes": count - (end - start),
+        }))
+    }
+
+    /// Pauses and steps with `step`. Steps that let the emulator run, over or
+    /// out of a subroutine, are reported by the tick that pauses it again.
+    fn step(&mut self, step: fn(&mut Chip8Emulator), events: &mut Vec<Value>) -> Value {
+        self.chip8.pause();
+        step(&mut self.chip8);
+        self.was_stopped = self.is_stopped();
+        self.stepping = !self.was_stopped;
+        if self.was_stopped {
+            events.push(self.stopped_event("step"));
+        }
+        json!({})
+    }
+
+    /// Reports a stop for `reason`, or as an exception if an instruction
+    /// faulted, or as a pause saying so if the ROM ended.
+    fn stopped_event(&mut self, reason: &str) -> Value {
+        let mut body =
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true });
+        match self.chip8.get_state() {
+            State::Faulted => {
+                body["reason"] = json!("exception");
+                if let Some(report) = self.chip8.get_crash_report() {
+                    body["text"] = json!(report.reason);
+                }
+            }
+            State::Halted => {
+                body["reason"] = json!("pause");
+                body["description"] =
+                    json!(format!("Program ended at {:03X}", self.chip8.get_pc()));
+            }
+            _ => {}
+        }
+        self.event("stopped", body)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Value {
+        json!({ "seq": self.next_seq(), "type": "event", "event": event, "body": body })
+    }
+
+    fn next_seq(&mut self) -> u64 {
+        self.seq += 1;
+        self.seq
+    }
+}
+
+fn format_address(address: u16) -> String {
+    format!("{:#05X}", address)
+}
+
+/// Returns the address a request's `memoryReference` and `offset` point at,
+/// which may be outside of memory.
+fn start_address(arguments: &Value) -> Result<i64, String> {
+    let address = arguments["memoryReference"]
+        .as_str()
+        .and_then(parse_address)
+        .ok_or("Invalid memoryReference")?;
+    (address as i64)
+        .checked_add(arguments["offset"].as_i64().unwrap_or(0))
+        .ok_or_else(|| "Invalid offset".to_string())
+}
+
+/// Returns the instruction or byte count of a request, which defaults to 0
+/// and can't be more than `MAX_REQUEST_COUNT`.
+fn request_count(count: &Value) -> Result<usize, String> {
+    if count.is_null() {
+        return Ok(0);
+    }
+    count
+        .as_u64()
+        .filter(|&count| count <= MAX_REQUEST_COUNT)
+        .map(|count| count as usize)
+        .ok_or_else(|| format!("Count must be from 0 to {}", MAX_REQUEST_COUNT))
+}
+
+fn parse_address(reference: &str) -> Option<u16> {
+    let reference = reference.trim();
+    match reference
+        .strip_prefix("0x")
+        .or_else(|| reference.strip_prefix("0X"))
+    {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => reference.parse().ok(),
+    }
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(session: &mut Session, command: &str, arguments: Value) -> Vec<Value> {
+        session.handle(
+            &json!({ "seq": 1, "type": "request", "command": command, "arguments": arguments }),
+            0.0,
+        )
+    }
+
+    #[test]
+    fn test_session() {
+        // Increments V0 forever.
+        let path = std::env::temp_dir().join("chip8-dap-test.ch8");
+        fs::write(&path, [0x70, 0x01, 0x12, 0x00]).unwrap();
+
+        let mut session = Session::new(0.0);
+        let messages = request(&mut session, "initialize", json!({}));
+        assert_eq!(messages[0]["body"]["supportsInstructionBreakpoints"], true);
+        assert_eq!(messages[1]["event"], "initialized");
+
+        let messages = request(
+            &mut session,
+            "launch",
+            json!({ "program": path.to_str().unwrap() }),
+        );
+        assert_eq!(messages[0]["success"], true);
+
+        let messages = request(
+            &mut session,
+            "setInstructionBreakpoints",
+            json!({ "breakpoints": [{ "instructionReference": "0x202" }] }),
+        );
+        assert_eq!(messages[0]["body"]["breakpoints"][0]["verified"], true);
+
+        let events = session.tick(100.0);
+        assert_eq!(events[0]["event"], "stopped");
+        assert_eq!(events[0]["body"]["reason"], "breakpoint");
+        assert!(session.tick(200.0).is_empty());
+
+        let messages = request(&mut session, "variables", json!({}));
+        let variables = &messages[0]["body"]["variables"];
+        assert_eq!(variables[0]["value"], "0x01");
+        assert_eq!(variables[17]["value"], "0x202");
+
+        let messages = request(&mut session, "next", json!({ "threadId": 1 }));
+        assert_eq!(messages[1]["body"]["reason"], "step");
+        let messages = request(&mut session, "stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(
+            messages[0]["body"]["stackFrames"][0]["instructionPointerReference"],
+            "0x200"
+        );
+
+        let messages = request(
+            &mut session,
+            "disassemble",
+            json!({ "memoryReference": "0x200", "instructionCount": 2 }),
+        );
+        let instructions = &messages[0]["body"]["instructions"];
+        assert_eq!(instructions[0]["instruction"], "ADD V0, 0x01");
+        assert_eq!(instructions[1]["instruction"], "JP 0x200");
+
+        let messages = request(
+            &mut session,
+            "readMemory",
+            json!({ "memoryReference": "0x200", "count": 4 }),
+        );
+        assert_eq!(messages[0]["body"]["data"], "cAESAA==");
+        let messages = request(
+            &mut session,
+            "readMemory",
+            json!({ "memoryReference": "0xffe", "count": 4 }),
+        );
+        assert_eq!(messages[0]["body"]["unreadableBytes"], 2);
+        for arguments in [
+            json!({ "memoryReference": "0x200", "count": u64::MAX }),
+            json!({ "memoryReference": "0x200", "count": -1 }),
+            json!({ "memoryReference": "0x200", "offset": i64::MAX, "count": 4 }),
+        ] {
+            let messages = request(&mut session, "readMemory", arguments);
+            assert_eq!(messages[0]["success"], false);
+        }
+        for arguments in [
+            json!({ "memoryReference": "0x200", "instructionCount": u64::MAX }),
+            json!({ "memoryReference": "0x200", "instructionOffset": i64::MAX }),
+        ] {
+            let messages = request(&mut session, "disassemble", arguments);
+            assert_eq!(messages[0]["success"], false);
+        }
+
+        let messages = request(&mut session, "frobnicate", json!({}));
+        assert_eq!(messages[0]["success"], false);
+
+        request(&mut session, "disconnect", json!({}));
+        assert!(session.is_terminated());
+    }
+
+    #[test]
+    fn test_session_fault() {
+        let path = std::env::temp_dir().join("chip8-dap-fault-test.ch8");
+        fs::write(&path, [0x70, 0x01, 0x00, 0xee]).unwrap();
+
+        let mut session = Session::new(0.0);
+        request(
+            &mut session,
+            "launch",
+            json!({ "program": path.to_str().unwrap() }),
+        );
+        let events = session.tick(100.0);
+        assert_eq!(events[0]["event"], "stopped");
+        assert_eq!(events[0]["body"]["reason"], "exception");
+        assert_eq!(events[0]["body"]["text"], "Return with an empty stack");
+        assert!(session.tick(200.0).is_empty());
+
+        // Continuing can't get past the fault, so it stops again right away.
+        request(&mut session, "continue", json!({ "threadId": 1 }));
+        assert_eq!(session.tick(300.0)[0]["body"]["reason"], "exception");
+    }
+
+    #[test]
+    fn test_session_halt() {
+        // Ends with a jump to itself.
+        let path = std::env::temp_dir().join("chip8-dap-halt-test.ch8");
+        fs::write(&path, [0x70, 0x01, 0x12, 0x02]).unwrap();
+
+        let mut session = Session::new(0.0);
+        request(
+            &mut session,
+            "launch",
+            json!({ "program": path.to_str().unwrap() }),
+        );
+        let events = session.tick(100.0);
+        assert_eq!(events[0]["event"], "stopped");
+        assert_eq!(events[0]["body"]["reason"], "pause");
+        assert_eq!(events[0]["body"]["description"], "Program ended at 202");
+        assert!(session.tick(200.0).is_empty());
+    }
+
+    #[test]
+    fn test_message_framing() {
+        let message = json!({ "seq": 1, "command": "threads" });
+        let mut framed = Vec::new();
+        write_message(&mut framed, &message).unwrap();
+        assert!(framed.starts_with(b"Content-Length: 29\r\n\r\n"));
+
+        let mut input = &framed[..];
+        assert_eq!(read_message(&mut input).unwrap(), Some(message));
+        assert_eq!(read_message(&mut input).unwrap(), None);
+
+        assert_eq!(encode_base64(b"chip8"), "Y2hpcDg=");
+        assert_eq!(encode_base64(b"ch"), "Y2g=");
+    }
+}
@@ -0,0 +1,237 @@
+//! Debugger panel showing the machine's registers, stack pointer, timers and
+//! stack, its watchpoints, expressions, opcode breaks and instruction trace,
+//! the disassembly around PC and a hex dump of memory, updated every frame
+//! while the panel is open. Registers and addresses are in hex, counts and timers in
+//! decimal. While paused, the emulator can be stepped an instruction or a frame
+//! at a time, over and out of subroutines, and back through the instructions it
+//! ran while the panel was open.
+
+use super::{
+    disassembly_view, expressions, get_current_time, get_element_by_id, hex_view, opcode_breaks,
+    watchpoints,
+};
+use crate::chip8emulator::{Chip8Emulator, State};
+use gloo::events::EventListener;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{window, HtmlButtonElement};
+
+/// How many instructions can be stepped back through. The state before each
+/// is kept while the panel is open.
+const HISTORY_LIMIT: usize = 256;
+
+/// Names of the fields after V0-VF.
+const FIELD_NAMES: [&str; 5] = ["I", "PC", "SP", "DT", "ST"];
+
+type Step = fn(&mut Chip8Emulator);
+
+/// The buttons stepping the paused emulator and how.
+const STEP_BUTTONS: &[(&str, Step)] = &[
+    ("debug-step", Chip8Emulator::step_instruction),
+    ("debug-step-over", Chip8Emulator::step_over),
+    ("debug-step-out", Chip8Emulator::step_out),
+    ("debug-step-frame", Chip8Emulator::step_frame),
+];
+
+thread_local! {
+    /// The fields' values as last shown, with the stack last, so only the
+    /// ones that changed are written.
+    static SHOWN: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+
+    /// The emulator's state the buttons were last shown for, and whether it
+    /// could step back.
+    static SHOWN_STATE: Cell<Option<(State, bool)>> = const { Cell::new(None) };
+}
+
+/// Fills the panel with a field for each register, and makes its buttons
+/// pause, resume and step `chip8`.
+pub fn register_debug_panel(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let document = window().unwrap().document().unwrap();
+    let fields = get_element_by_id("debug-registers");
+    let names = (0..16)
+        .map(|x| format!("V{:X}", x))
+        .chain(FIELD_NAMES.iter().map(|name| name.to_string()));
+    for name in names {
+        let field = document.create_element("div").unwrap();
+        field.set_class_name("debug-field");
+        for (class, text) in [("debug-name", name.as_str()), ("debug-value", "")] {
+            let span = document.create_element("span").unwrap();
+            span.set_class_name(class);
+            span.set_text_content(Some(text));
+            field.append_child(&span).unwrap();
+        }
+        fields.append_child(&field).unwrap();
+    }
+
+    let pause_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_button("debug-pause"), "click", move |_| {
+        get_button("debug-pause").blur().unwrap();
+        let mut chip8 = pause_chip8.borrow_mut();
+        if chip8.is_paused() {
+            chip8.resume();
+        } else {
+            chip8.pause();
+        }
+    })
+    .forget();
+
+    for &(id, step) in STEP_BUTTONS {
+        let chip8 = Rc::clone(chip8);
+        EventListener::new(&get_button(id), "click", move |_| {
+            step(&mut chip8.borrow_mut());
+        })
+        .forget();
+    }
+
+    let back_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_button("debug-step-back"), "click", move |_| {
+        back_chip8.borrow_mut().step_back(get_current_time());
+    })
+    .forget();
+
+    let toggle_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("debugger"), "toggle", move |_| {
+        apply_history_limit(&mut toggle_chip8.borrow_mut());
+    })
+    .forget();
+}
+
+/// Has `chip8` keep the states to step back to while the panel is open.
+pub fn apply_history_limit(chip8: &mut Chip8Emulator) {
+    let open = get_element_by_id("debugger").has_attribute("open");
+    chip8.set_history_limit(if open { HISTORY_LIMIT } else { 0 });
+}
+
+/// Fills the list with the id `id` with `items`, each with a button calling
+/// `remove` with it.
+pub fn show_list<T>(id: &str, items: &[T], remove: Rc<dyn Fn(T)>)
+where
+    T: Clone + fmt::Display + 'static,
+{
+    let document = window().unwrap().document().unwrap();
+    let list = get_element_by_id(id);
+    list.set_text_content(None);
+    for item in items {
+        let element = document.create_element("li").unwrap();
+        element.set_text_content(Some(&item.to_string()));
+        let button = document.create_element("button").unwrap();
+        button.set_class_name("debug-remove");
+        button.set_text_content(Some("Remove"));
+        let remove = Rc::clone(&remove);
+        let item = item.clone();
+        EventListener::new(&button, "click", move |_| remove(item.clone())).forget();
+        element.append_child(&button).unwrap();
+        list.append_child(&element).unwrap();
+    }
+}
+
+/// Returns the text of each field, and the stack's, for `chip8`.
+fn field_values(chip8: &Chip8Emulator) -> Vec<String> {
+    let registers = chip8.get_registers().iter().map(|v| format!("{:02X}", v));
+    let stack = chip8
+        .get_stack()
+        .iter()
+        .rev()
+        .map(|address| format!("{:04X}", address))
+        .collect::<Vec<_>>();
+    registers
+        .chain([
+            format!("{:04X}", chip8.get_I()),
+            format!("{:04X}", chip8.get_pc()),
+            chip8.get_stack_pointer().to_string(),
+            chip8.get_delay_timer().to_string(),
+            chip8.get_sound_timer().to_string(),
+            if stack.is_empty() {
+                "Empty".to_string()
+            } else {
+                stack.join(" ")
+            },
+        ])
+        .collect()
+}
+
+/// Shows the state of `chip8`, if the panel is open.
+pub fn update(chip8: &Chip8Emulator) {
+    if !get_element_by_id("debugger").has_attribute("open") {
+        return;
+    }
+    let values = field_values(chip8);
+    SHOWN.with(|shown| {
+        let mut shown = shown.borrow_mut();
+        let fields = get_element_by_id("debug-registers").children();
+        for (index, value) in values.iter().enumerate() {
+            if shown.get(index) == Some(value) {
+                continue;
+            }
+            // The stack comes after the fields.
+            let element = match fields.item(index as u32) {
+                Some(field) => field.last_element_child(),
+                None => Some(get_element_by_id("debug-stack")),
+            };
+            if let Some(element) = element {
+                element.set_text_content(Some(value));
+            }
+        }
+        *shown = values;
+    });
+    let state = chip8.get_state();
+    let paused = state == State::Paused;
+    // Stepping back from where the ROM ended or faulted picks it up again.
+    let can_step_back = matches!(state, State::Paused | State::Halted | State::Faulted)
+        && chip8.get_history_length() > 0;
+    let shown_state = Some((state, can_step_back));
+    if SHOWN_STATE.with(|shown| shown.replace(shown_state)) != shown_state {
+        get_element_by_id("debug-state").set_text_content(Some(&state.to_string()));
+        let pause = get_button("debug-pause");
+        pause.set_text_content(Some(if paused { "Resume" } else { "Pause" }));
+        pause.set_disabled(matches!(
+            state,
+            State::Loading | State::Halted | State::Faulted
+        ));
+        for &(id, _) in STEP_BUTTONS {
+            get_button(id).set_disabled(!paused);
+        }
+        get_button("debug-step-back").set_disabled(!can_step_back);
+        get_button("patch-apply").set_disabled(!paused);
+    }
+    watchpoints::update(chip8);
+    expressions::update(chip8);
+    opcode_breaks::update(chip8);
+    disassembly_view::update(chip8);
+    hex_view::update(chip8);
+}
+
+fn get_button(id: &str) -> HtmlButtonElement {
+    get_element_by_id(id)
+        .dyn_into::<HtmlButtonElement>()
+        .unwrap_or_else(|_| panic!("Element with id #{} is not a button element", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_field_values() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8
+            .load_rom(&[
+                0x6a, 0x12, // LD VA, 0x12
+                0xa2, 0x34, // LD I, 0x234
+                0x22, 0x08, // CALL 0x208
+                0x00, 0x00, //
+                0x00, 0xee, // RET
+            ])
+            .unwrap();
+        for _ in 0..3 {
+            chip8.step_instruction();
+        }
+
+        let values = field_values(&chip8);
+        assert_eq!(values.len(), 16 + FIELD_NAMES.len() + 1);
+        assert_eq!(values[0xa], "12");
+        assert_eq!(values[16..], ["0234", "0208", "1", "0", "0", "0206"]);
+    }
+}
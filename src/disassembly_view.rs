@@ -0,0 +1,93 @@
+//! Disassembly around PC in the Debugger panel, scrolling along as the
+//! program runs. Jump and call targets get labels like `sub_0240:` and
+//! `loop_0212:`, named from the jumps and calls in the ROM and the
+//! subroutines it has called, and are referred to by them.
+
+use super::get_element_by_id;
+use crate::chip8emulator::Chip8Emulator;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+/// How many instructions are shown before and after the one at PC.
+const BEFORE: u16 = 6;
+const AFTER: usize = 13;
+
+thread_local! {
+    static SHOWN: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Shows the instructions around PC. Called while the Debugger panel is
+/// open.
+pub fn update(chip8: &Chip8Emulator) {
+    let text = view_lines(chip8, &chip8.get_labels()).join("\n");
+    let changed = SHOWN.with(|shown| {
+        let mut shown = shown.borrow_mut();
+        let changed = *shown != text;
+        if changed {
+            *shown = text.clone();
+        }
+        changed
+    });
+    if changed {
+        get_element_by_id("disassembly-view").set_text_content(Some(&text));
+    }
+}
+
+/// Returns a line for each instruction around PC, marking the one at PC,
+/// with a line for the label of any that has one.
+fn view_lines(chip8: &Chip8Emulator, labels: &BTreeMap<u16, String>) -> Vec<String> {
+    let pc = chip8.get_pc();
+    let start = pc.saturating_sub(BEFORE * 2);
+    let count = ((pc - start) / 2) as usize + 1 + AFTER;
+    let mut lines = Vec::new();
+    for instruction in chip8.disassemble(start, count) {
+        if let Some(label) = labels.get(&instruction.address) {
+            lines.push(format!("{}:", label));
+        }
+        let target_label = instruction.target.and_then(|target| labels.get(&target));
+        let text = match (target_label, instruction.text.split_once(' ')) {
+            (Some(label), Some((mnemonic, _))) => format!("{} {}", mnemonic, label),
+            _ => instruction.text,
+        };
+        let marker = if instruction.address == pc { '>' } else { ' ' };
+        lines.push(format!(
+            "{} {:03X}  {:04X}  {}",
+            marker, instruction.address, instruction.opcode, text
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view_lines() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8
+            .load_rom(&[
+                0x22, 0x06, // CALL 0x206
+                0x12, 0x02, // JP 0x202
+                0x00, 0x00, //
+                0x00, 0xee, // RET
+            ])
+            .unwrap();
+        chip8.step_instruction();
+        let lines = view_lines(&chip8, &chip8.get_labels());
+        assert_eq!(
+            lines[3..11],
+            [
+                "  200  2206  CALL sub_0206",
+                "loop_0202:",
+                "  202  1202  JP loop_0202",
+                "  204  0000  SYS 0x000",
+                "sub_0206:",
+                "> 206  00EE  RET",
+                "  208  0000  SYS 0x000",
+                "  20A  0000  SYS 0x000",
+            ]
+        );
+        assert_eq!(lines.len(), BEFORE as usize + 1 + AFTER + 2);
+    }
+}
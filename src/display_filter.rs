@@ -0,0 +1,333 @@
+//! Optional filters that draw the display scaled up, so each of its pixels
+//! covers a block of canvas pixels: a CRT look, with dark scanlines between
+//! rows, bulging slightly towards the viewer and darker towards the corners,
+//! and pixels shaped like dots or LEDs.
+
+use super::{get_element_by_id, storage};
+use crate::chip8emulator::Chip8Emulator;
+use gloo::events::EventListener;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{console, HtmlInputElement, HtmlSelectElement};
+
+/// Width the filtered image is scaled up to, so that scanlines and pixel
+/// shapes stay a few canvas pixels large on every display size.
+const OUTPUT_WIDTH: u32 = 512;
+/// Fewest canvas pixels a display pixel is scaled up to, across and down.
+const MIN_SCALE: u32 = 3;
+/// Part of each display row, from its bottom, taken up by its scanline.
+const SCANLINE_HEIGHT: f32 = 0.3;
+const SCANLINE_BRIGHTNESS: f32 = 0.55;
+/// How far the image bulges out; 0 keeps it flat.
+const CURVATURE: f32 = 0.08;
+/// How much darker the corners are than the center.
+const VIGNETTE: f32 = 0.3;
+/// Radius of a round pixel, and half the side of an LED, as part of the
+/// pixel's size. The rest is a dark gap between pixels.
+const DOT_RADIUS: f32 = 0.45;
+const LED_SIZE: f32 = 0.4;
+
+/// The shape each pixel of the display is drawn in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelShape {
+    Square,
+    Circle,
+    /// Square LEDs with gaps between them, like an LED matrix.
+    Led,
+}
+
+impl PixelShape {
+    pub fn parse(value: &str) -> Option<PixelShape> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "square" => Some(PixelShape::Square),
+            "circle" => Some(PixelShape::Circle),
+            "led" => Some(PixelShape::Led),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the shape covers the point (`x`, `y`) of a pixel, both
+    /// going from 0 to 1 across it.
+    fn covers(self, x: f32, y: f32) -> bool {
+        let (dx, dy) = (x - 0.5, y - 0.5);
+        match self {
+            PixelShape::Square => true,
+            PixelShape::Circle => dx * dx + dy * dy <= DOT_RADIUS * DOT_RADIUS,
+            PixelShape::Led => dx.abs() <= LED_SIZE && dy.abs() <= LED_SIZE,
+        }
+    }
+}
+
+impl fmt::Display for PixelShape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            PixelShape::Square => "square",
+            PixelShape::Circle => "circle",
+            PixelShape::Led => "led",
+        })
+    }
+}
+
+/// Which filters the display is drawn with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DisplayFilters {
+    pub crt: bool,
+    pub pixel_shape: PixelShape,
+}
+
+impl DisplayFilters {
+    /// Returns whether the filters draw the display scaled up at all.
+    pub fn is_enabled(self) -> bool {
+        self.crt || self.pixel_shape != PixelShape::Square
+    }
+}
+
+impl Default for DisplayFilters {
+    fn default() -> DisplayFilters {
+        DisplayFilters {
+            crt: false,
+            pixel_shape: PixelShape::Square,
+        }
+    }
+}
+
+/// Maps each pixel of the filtered image to the display pixel it shows and
+/// how much that is dimmed, which only depends on the display size and the
+/// filters.
+struct FilterMap {
+    width: u32,
+    height: u32,
+    filters: DisplayFilters,
+    /// Byte offset of the display pixel and its brightness, or `None` where
+    /// nothing is shown.
+    taps: Vec<Option<(usize, f32)>>,
+    output: Vec<u8>,
+}
+
+impl FilterMap {
+    fn new(width: u32, height: u32, filters: DisplayFilters) -> FilterMap {
+        let (output_width, output_height) = filtered_size(width, height);
+        let mut taps = Vec::with_capacity((output_width * output_height) as usize);
+        for y in 0..output_height {
+            for x in 0..output_width {
+                let u = (x as f32 + 0.5) / output_width as f32 * 2.0 - 1.0;
+                let v = (y as f32 + 0.5) / output_height as f32 * 2.0 - 1.0;
+                taps.push(tap(width, height, filters, u, v));
+            }
+        }
+
+        FilterMap {
+            width,
+            height,
+            filters,
+            taps,
+            output: vec![0; (output_width * output_height * 4) as usize],
+        }
+    }
+
+    /// Filters an RGBA image of the display.
+    fn apply(&mut self, frame: &[u8]) -> &[u8] {
+        for (pixel, tap) in self.output.chunks_exact_mut(4).zip(&self.taps) {
+            let [r, g, b] = match *tap {
+                Some((offset, brightness)) => {
+                    let dim = |channel: u8| (channel as f32 * brightness) as u8;
+                    [
+                        dim(frame[offset]),
+                        dim(frame[offset + 1]),
+                        dim(frame[offset + 2]),
+                    ]
+                }
+                None => [0, 0, 0],
+            };
+            pixel.copy_from_slice(&[r, g, b, 0xff]);
+        }
+        &self.output
+    }
+}
+
+/// Finds what the filtered image shows at (`u`, `v`), both going from -1 to 1
+/// across it.
+fn tap(width: u32, height: u32, filters: DisplayFilters, u: f32, v: f32) -> Option<(usize, f32)> {
+    let (u, v) = if filters.crt {
+        let bulge = 1.0 + CURVATURE * (u * u + v * v);
+        (u * bulge, v * bulge)
+    } else {
+        (u, v)
+    };
+    if u.abs() > 1.0 || v.abs() > 1.0 {
+        return None;
+    }
+
+    let column = (u + 1.0) / 2.0 * width as f32;
+    let row = (v + 1.0) / 2.0 * height as f32;
+    if !filters.pixel_shape.covers(column.fract(), row.fract()) {
+        return None;
+    }
+    let x = (column as u32).min(width - 1);
+    let y = (row as u32).min(height - 1);
+
+    let brightness = if filters.crt {
+        let scanline = if row.fract() > 1.0 - SCANLINE_HEIGHT {
+            SCANLINE_BRIGHTNESS
+        } else {
+            1.0
+        };
+        scanline * (1.0 - VIGNETTE * (u * u + v * v) / 2.0)
+    } else {
+        1.0
+    };
+    Some((((y * width + x) * 4) as usize, brightness))
+}
+
+fn filtered_size(width: u32, height: u32) -> (u32, u32) {
+    let scale = (OUTPUT_WIDTH / width).max(MIN_SCALE);
+    (width * scale, height * scale)
+}
+
+thread_local! {
+    static FILTERS: Cell<DisplayFilters> = Cell::new(DisplayFilters::default());
+
+    static FILTER_MAP: RefCell<Option<FilterMap>> = const { RefCell::new(None) };
+}
+
+pub fn filters() -> DisplayFilters {
+    FILTERS.with(Cell::get)
+}
+
+pub fn set_filters(filters: DisplayFilters) {
+    FILTERS.with(|current| current.set(filters));
+}
+
+pub fn is_enabled() -> bool {
+    filters().is_enabled()
+}
+
+/// Returns the size of the canvas a display of the given size is drawn on.
+pub fn canvas_size(width: u32, height: u32) -> (u32, u32) {
+    if is_enabled() {
+        filtered_size(width, height)
+    } else {
+        (width, height)
+    }
+}
+
+/// Filters an RGBA image of the whole display and passes the result to
+/// `put_image` with its width.
+pub fn filter_frame(frame: &[u8], width: u32, height: u32, put_image: impl FnOnce(&[u8], u32)) {
+    let filters = filters();
+    FILTER_MAP.with(|map| {
+        let mut map = map.borrow_mut();
+        let map = match map.as_mut() {
+            Some(map) if (map.width, map.height, map.filters) == (width, height, filters) => map,
+            _ => map.insert(FilterMap::new(width, height, filters)),
+        };
+        let (output_width, _) = filtered_size(width, height);
+        put_image(map.apply(frame), output_width);
+    });
+}
+
+/// Shows the stored filters in the display panel, and applies changes made
+/// there.
+pub fn register_display_filter_panel(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    set_filters(DisplayFilters {
+        crt: storage::load_crt_filter().unwrap_or(false),
+        pixel_shape: storage::load_pixel_shape().unwrap_or(PixelShape::Square),
+    });
+    get_crt_checkbox().set_checked(filters().crt);
+    get_pixel_shape_select().set_value(&filters().pixel_shape.to_string());
+
+    for id in ["crt-filter", "pixel-shape"] {
+        let chip8 = Rc::clone(chip8);
+        EventListener::new(&get_element_by_id(id), "input", move |_| {
+            let filters = DisplayFilters {
+                crt: get_crt_checkbox().checked(),
+                pixel_shape: PixelShape::parse(&get_pixel_shape_select().value())
+                    .unwrap_or(PixelShape::Square),
+            };
+            get_crt_checkbox().blur().unwrap();
+            get_pixel_shape_select().blur().unwrap();
+            apply_filters(&chip8, filters);
+        })
+        .forget();
+    }
+}
+
+/// Switches to `filters` as if they were picked in the display panel.
+pub fn choose_filters(chip8: &Rc<RefCell<Chip8Emulator>>, filters: DisplayFilters) {
+    get_crt_checkbox().set_checked(filters.crt);
+    get_pixel_shape_select().set_value(&filters.pixel_shape.to_string());
+    apply_filters(chip8, filters);
+}
+
+fn apply_filters(chip8: &Rc<RefCell<Chip8Emulator>>, filters: DisplayFilters) {
+    set_filters(filters);
+    chip8.borrow_mut().mark_gfx_changed();
+
+    let stored = storage::store_crt_filter(filters.crt)
+        .and_then(|()| storage::store_pixel_shape(filters.pixel_shape));
+    if let Err(e) = stored {
+        console::error_2(&"Can't store the display filters".into(), &e);
+    }
+}
+
+fn get_crt_checkbox() -> HtmlInputElement {
+    get_element_by_id("crt-filter")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #crt-filter is not an input element")
+}
+
+fn get_pixel_shape_select() -> HtmlSelectElement {
+    get_element_by_id("pixel-shape")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #pixel-shape is not a select element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crt_filter() {
+        let frame = [0xff; 64 * 32 * 4];
+        let filters = DisplayFilters {
+            crt: true,
+            pixel_shape: PixelShape::Square,
+        };
+        let mut map = FilterMap::new(64, 32, filters);
+        assert_eq!(filtered_size(64, 32), (512, 256));
+        assert_eq!(filtered_size(256, 192), (768, 576));
+
+        let output = map.apply(&frame);
+        assert_eq!(output.len(), 512 * 256 * 4);
+        let pixel = |x: usize, y: usize| output[(y * 512 + x) * 4];
+
+        // Corners fall outside of the curved screen.
+        assert_eq!(pixel(0, 0), 0);
+        // The center is lit, and darker on a scanline and towards the edges.
+        assert!(pixel(256, 128) > 0xf0);
+        assert!(pixel(256, 127) < 0xa0);
+        assert!(pixel(40, 128) < pixel(256, 128));
+    }
+
+    #[test]
+    fn test_pixel_shapes() {
+        let frame = [0xff; 64 * 32 * 4];
+        let shaped = |pixel_shape| {
+            let filters = DisplayFilters {
+                crt: false,
+                pixel_shape,
+            };
+            // The top row of canvas pixels of the first display pixel.
+            let output = FilterMap::new(64, 32, filters).apply(&frame).to_vec();
+            (0..8).map(|x| output[x * 4]).collect::<Vec<_>>()
+        };
+
+        assert_eq!(shaped(PixelShape::Square), [0xff; 8]);
+        assert_eq!(shaped(PixelShape::Led), [0; 8]);
+        assert_eq!(shaped(PixelShape::Circle), [0, 0, 0, 0xff, 0xff, 0, 0, 0]);
+        assert_eq!(PixelShape::parse("LED"), Some(PixelShape::Led));
+        assert_eq!(PixelShape::Circle.to_string(), "circle");
+    }
+}
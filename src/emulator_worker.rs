@@ -0,0 +1,397 @@
+//! Runs the emulator in a Web Worker that draws to an OffscreenCanvas, so
+//! that a busy page doesn't make the game stutter.
+//!
+//! The page keeps its controls and the buzzer. It sends the worker messages
+//! with a `type` and some fields:
+//!
+//! * `start` hands over the display as `canvas`.
+//! * `rom` loads the ROM in `data` with the `settings` from the panels.
+//! * `settings` applies panel changes: `clockSpeed`, `cpuModel`, `quirks` (in
+//!   the order of the quirks panel), `strictMode`, `profile`, `availableKeys`,
+//!   `palette`, `crtFilter`, `pixelShape` and `afterglow`.
+//! * `key` presses or releases `key`, on the second keypad if `second`.
+//!
+//! The worker sends back a `sound` message with the sound timer's `value` and
+//! `phase` whenever the value changes, which drives the buzzer, and a `keys`
+//! message with the keys `pressed` whenever they change, with the `time` of
+//! the tick that saw them since the epoch, for measuring input latency.
+
+use super::audio::Buzzer;
+use super::display_filter::{self, DisplayFilters, PixelShape};
+use super::haptics::Haptics;
+use super::palette_picker::{current_palette, set_palette};
+use super::phosphor;
+use super::{
+    api, apply_profile, draw_display, get_canvas, get_checkbox, get_clock_speed_input,
+    get_cpu_model, get_cpu_model_select, get_current_time, get_profile_from_panel,
+    get_profile_select, get_quirks_from_panel, get_time_origin, latency, render_sound_indicator,
+    request_animation_frames, skip_frame_gap, AVAILABLE_KEYS,
+};
+use crate::chip8emulator::{Chip8Emulator, Palette, Profile, Quirks, TimerState};
+use gloo::events::EventListener;
+use js_sys::{global, Array, Object, Reflect, Uint8Array};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{
+    console, window, DedicatedWorkerGlobalScope, MessageEvent, OffscreenCanvas,
+    OffscreenCanvasRenderingContext2d, Worker,
+};
+
+/// Script that starts the module in the worker and calls `workerMain`.
+const WORKER_SCRIPT: &str = "worker.js";
+
+thread_local! {
+    static WORKER: RefCell<Option<Worker>> = const { RefCell::new(None) };
+}
+
+/// Starts the emulator worker and moves the display to it. The page's own
+/// emulator keeps getting ROMs and keys but is no longer run.
+pub fn start_worker(
+    buzzer: Rc<RefCell<Option<Buzzer>>>,
+    haptics: Rc<RefCell<Haptics>>,
+) -> Result<(), JsValue> {
+    let worker = Worker::new(WORKER_SCRIPT)?;
+    let canvas = get_canvas().transfer_control_to_offscreen()?;
+
+    let start = message("start")?;
+    Reflect::set(&start, &"canvas".into(), &canvas)?;
+    worker.post_message_with_transfer(&start, &Array::of1(&canvas))?;
+
+    EventListener::new(&worker, "message", move |e| {
+        let data = e.dyn_ref::<MessageEvent>().unwrap().data();
+        if let Some((pressed, time)) = read_keys(&data) {
+            latency::observe(pressed, time - get_time_origin());
+            return;
+        }
+        let sound_timer = match read_sound_timer(&data) {
+            Some(sound_timer) => sound_timer,
+            None => return,
+        };
+        if let Some(buzzer) = buzzer.borrow_mut().as_mut() {
+            buzzer.update(sound_timer);
+        }
+        haptics.borrow_mut().update(sound_timer.value);
+        render_sound_indicator(sound_timer.value > 0);
+        api::update_sound(sound_timer.value > 0);
+    })
+    .forget();
+
+    // Panel listeners update the panels before the event bubbles up here, so
+    // the settings sent are the new ones.
+    let document = window().unwrap().document().unwrap();
+    for event in ["input", "change"] {
+        EventListener::new(&document, event, |_| send_settings()).forget();
+    }
+
+    WORKER.with(|current| *current.borrow_mut() = Some(worker));
+    Ok(())
+}
+
+/// Returns whether the emulator runs in the worker rather than on the page.
+pub fn is_running() -> bool {
+    WORKER.with(|worker| worker.borrow().is_some())
+}
+
+/// Has the emulator worker load `rom`, if it's running.
+pub fn send_rom(rom: &[u8]) {
+    post_to_worker(|| {
+        let message = message("rom")?;
+        Reflect::set(&message, &"data".into(), &Uint8Array::from(rom))?;
+        Reflect::set(&message, &"settings".into(), &settings_from_panels())?;
+        Ok(message)
+    });
+}
+
+/// Sends the settings in the panels to the emulator worker, if it's running,
+/// for changes made other than from the panels.
+pub fn send_settings() {
+    post_to_worker(|| {
+        let message = message("settings")?;
+        Reflect::set(&message, &"settings".into(), &settings_from_panels())?;
+        Ok(message)
+    });
+}
+
+/// Passes a key press or release on to the emulator worker, if it's running.
+pub fn send_key(key: u8, pressed: bool, second: bool) {
+    post_to_worker(|| {
+        let message = message("key")?;
+        Reflect::set(&message, &"key".into(), &key.into())?;
+        Reflect::set(&message, &"pressed".into(), &pressed.into())?;
+        Reflect::set(&message, &"second".into(), &second.into())?;
+        Ok(message)
+    });
+}
+
+fn post_to_worker(build: impl FnOnce() -> Result<Object, JsValue>) {
+    WORKER.with(|worker| {
+        if let Some(worker) = worker.borrow().as_ref() {
+            if let Err(e) = build().and_then(|message| worker.post_message(&message)) {
+                console::error_2(&"Can't send message to the emulator worker".into(), &e);
+            }
+        }
+    });
+}
+
+fn message(kind: &str) -> Result<Object, JsValue> {
+    let message = Object::new();
+    Reflect::set(&message, &"type".into(), &kind.into())?;
+    Ok(message)
+}
+
+fn settings_from_panels() -> JsValue {
+    let profile = get_profile_from_panel();
+    let Quirks {
+        shift_uses_vy,
+        load_store_increments_i,
+        jump_uses_vx,
+        logic_resets_vf,
+        clip_sprites,
+    } = get_quirks_from_panel();
+    let quirks = [
+        shift_uses_vy,
+        load_store_increments_i,
+        jump_uses_vx,
+        logic_resets_vf,
+        clip_sprites,
+    ]
+    .iter()
+    .map(|&quirk| JsValue::from(quirk))
+    .collect::<Array>();
+
+    let clock_speed: f64 = get_clock_speed_input().value().parse().unwrap();
+    let available_keys = profile.available_keys & AVAILABLE_KEYS.with(Cell::get);
+    let filters = display_filter::filters();
+
+    let settings = Object::new();
+    let fields: [(&str, JsValue); 10] = [
+        ("clockSpeed", clock_speed.into()),
+        ("cpuModel", get_cpu_model_select().value().into()),
+        ("quirks", quirks.into()),
+        ("strictMode", get_checkbox("strict-mode").checked().into()),
+        ("profile", get_profile_select().value().into()),
+        ("availableKeys", available_keys.into()),
+        ("palette", current_palette().to_string().into()),
+        ("crtFilter", filters.crt.into()),
+        ("pixelShape", filters.pixel_shape.to_string().into()),
+        ("afterglow", phosphor::persistence().into()),
+    ];
+    for (name, value) in fields.iter() {
+        Reflect::set(&settings, &(*name).into(), value).unwrap();
+    }
+    settings.into()
+}
+
+fn read_sound_timer(data: &JsValue) -> Option<TimerState> {
+    if get_field(data, "type").as_string()? != "sound" {
+        return None;
+    }
+    Some(TimerState {
+        value: get_field(data, "value").as_f64()? as u8,
+        phase: get_field(data, "phase").as_f64()?,
+    })
+}
+
+/// Reads the pressed keys and the tick's time out of a `keys` message.
+fn read_keys(data: &JsValue) -> Option<(u16, f64)> {
+    if get_field(data, "type").as_string().as_deref() != Some("keys") {
+        return None;
+    }
+    let pressed = get_field(data, "pressed").as_f64()? as u16;
+    let time = get_field(data, "time").as_f64()?;
+    Some((pressed, time))
+}
+
+fn get_field(object: &JsValue, name: &str) -> JsValue {
+    Reflect::get(object, &name.into()).unwrap_or(JsValue::UNDEFINED)
+}
+
+/// Entry point of the emulator worker, called by `worker.js`. Waits for the
+/// page to hand over the display, then runs whatever ROM it sends.
+#[wasm_bindgen(js_name = workerMain)]
+pub fn worker_main() {
+    #[cfg(debug_assertions)]
+    console_error_panic_hook::set_once();
+
+    let scope = global().unchecked_into::<DedicatedWorkerGlobalScope>();
+    let chip8 = Rc::new(RefCell::new(Chip8Emulator::new(get_current_time())));
+    let context = Rc::new(RefCell::new(None));
+
+    {
+        let chip8 = Rc::clone(&chip8);
+        let context = Rc::clone(&context);
+        EventListener::new(&scope, "message", move |e| {
+            let data = e.dyn_ref::<MessageEvent>().unwrap().data();
+            if let Err(e) = handle_message(&chip8, &context, &data) {
+                console::error_2(&"Can't handle message from the page".into(), &e);
+            }
+        })
+        .forget();
+    }
+
+    let mut last_frame_time = get_current_time();
+    let mut last_sound_value = 0;
+    let mut last_pressed = 0;
+    request_animation_frames(move |current_time| {
+        let mut chip8 = chip8.borrow_mut();
+        skip_frame_gap(&mut chip8, last_frame_time, current_time);
+        last_frame_time = current_time;
+        chip8.tick(current_time);
+
+        let pressed = chip8.get_pressed_keys();
+        if pressed != last_pressed {
+            last_pressed = pressed;
+            // The worker's clock starts at a different time than the page's.
+            let time = get_time_origin() + current_time;
+            if let Err(e) = post_keys(&scope, pressed, time) {
+                console::error_2(&"Can't send the pressed keys to the page".into(), &e);
+            }
+        }
+
+        let sound_timer = chip8.get_sound_timer_state();
+        if sound_timer.value != last_sound_value {
+            last_sound_value = sound_timer.value;
+            if let Err(e) = post_sound_timer(&scope, sound_timer) {
+                console::error_2(&"Can't send the sound timer to the page".into(), &e);
+            }
+        }
+
+        if let Some(context) = context.borrow().as_ref() {
+            render_offscreen(&mut chip8, context);
+        }
+    });
+}
+
+fn handle_message(
+    chip8: &Rc<RefCell<Chip8Emulator>>,
+    context: &Rc<RefCell<Option<OffscreenCanvasRenderingContext2d>>>,
+    data: &JsValue,
+) -> Result<(), JsValue> {
+    let mut chip8 = chip8.borrow_mut();
+    match get_field(data, "type").as_string().as_deref() {
+        Some("start") => {
+            let canvas = get_field(data, "canvas").unchecked_into::<OffscreenCanvas>();
+            let new_context = canvas
+                .get_context("2d")?
+                .ok_or("Can't get a 2d context for the OffscreenCanvas")?;
+            *context.borrow_mut() = Some(new_context.unchecked_into());
+        }
+        Some("rom") => {
+            let rom = get_field(data, "data")
+                .unchecked_into::<Uint8Array>()
+                .to_vec();
+            chip8.reset(get_current_time());
+            apply_settings(&mut chip8, &get_field(data, "settings"));
+            chip8.load_rom(&rom)?;
+        }
+        Some("settings") => apply_settings(&mut chip8, &get_field(data, "settings")),
+        Some("key") => {
+            let key = get_field(data, "key").as_f64().ok_or("Missing key")? as u8;
+            let pressed = get_field(data, "pressed").is_truthy();
+            if get_field(data, "second").is_truthy() {
+                chip8.queue_second_key(key, pressed);
+            } else {
+                chip8.queue_key(key, pressed);
+            }
+        }
+        _ => return Err("Unknown message".into()),
+    }
+    Ok(())
+}
+
+/// Applies settings sent by the page the way the page applies its panels when
+/// it loads a ROM.
+fn apply_settings(chip8: &mut Chip8Emulator, settings: &JsValue) {
+    let cpu_model = get_cpu_model(
+        &get_field(settings, "cpuModel")
+            .as_string()
+            .unwrap_or_default(),
+    );
+    chip8.set_cost_model((cpu_model.cost_model)());
+    if let Some(clock_speed) = get_field(settings, "clockSpeed").as_f64() {
+        chip8.set_clock_speed(clock_speed);
+    }
+
+    let quirks = Array::from(&get_field(settings, "quirks"));
+    let quirk = |index| quirks.get(index).is_truthy();
+    chip8.set_quirks(Quirks {
+        shift_uses_vy: quirk(0),
+        load_store_increments_i: quirk(1),
+        jump_uses_vx: quirk(2),
+        logic_resets_vf: quirk(3),
+        clip_sprites: quirk(4),
+    });
+    chip8.set_strict_mode(get_field(settings, "strictMode").is_truthy());
+
+    let profile = get_field(settings, "profile")
+        .as_string()
+        .and_then(|profile| Profile::parse(&profile))
+        .unwrap_or_else(Profile::chip8);
+    apply_profile(chip8, &profile);
+    if let Some(keys) = get_field(settings, "availableKeys").as_f64() {
+        chip8.set_available_keys(keys as u16);
+    }
+
+    let palette = get_field(settings, "palette").as_string();
+    if let Some(palette) = palette.as_deref().and_then(Palette::parse) {
+        if palette != current_palette() {
+            set_palette(palette);
+            chip8.mark_gfx_changed();
+        }
+    }
+
+    let filters = DisplayFilters {
+        crt: get_field(settings, "crtFilter").is_truthy(),
+        pixel_shape: get_field(settings, "pixelShape")
+            .as_string()
+            .and_then(|shape| PixelShape::parse(&shape))
+            .unwrap_or(PixelShape::Square),
+    };
+    if filters != display_filter::filters() {
+        display_filter::set_filters(filters);
+        chip8.mark_gfx_changed();
+    }
+
+    let persistence = get_field(settings, "afterglow").as_f64().unwrap_or(0.0) as f32;
+    if persistence != phosphor::persistence() {
+        phosphor::set_persistence(persistence);
+        chip8.mark_gfx_changed();
+    }
+}
+
+fn post_sound_timer(
+    scope: &DedicatedWorkerGlobalScope,
+    sound_timer: TimerState,
+) -> Result<(), JsValue> {
+    let message = message("sound")?;
+    Reflect::set(&message, &"value".into(), &sound_timer.value.into())?;
+    Reflect::set(&message, &"phase".into(), &sound_timer.phase.into())?;
+    scope.post_message(&message)
+}
+
+fn post_keys(scope: &DedicatedWorkerGlobalScope, pressed: u16, time: f64) -> Result<(), JsValue> {
+    let message = message("keys")?;
+    Reflect::set(&message, &"pressed".into(), &pressed.into())?;
+    Reflect::set(&message, &"time".into(), &time.into())?;
+    scope.post_message(&message)
+}
+
+fn render_offscreen(chip8: &mut Chip8Emulator, context: &OffscreenCanvasRenderingContext2d) {
+    let dirty_rows = chip8.take_gfx_dirty_rows();
+    if dirty_rows.is_empty() && !phosphor::is_fading() {
+        return;
+    }
+
+    let canvas = context.canvas();
+    let (width, height) =
+        display_filter::canvas_size(chip8.get_gfx_width(), chip8.get_gfx_height());
+    if (canvas.width(), canvas.height()) != (width, height) {
+        canvas.set_width(width);
+        canvas.set_height(height);
+    }
+    draw_display(chip8, &dirty_rows, |image, y| {
+        context.put_image_data(image, 0.0, y)
+    });
+}
@@ -0,0 +1,194 @@
+//! Expressions in the Debugger panel, such as `V[3] + I` or
+//! `mem[I+1] & 0x0F`: watched ones are listed with their values, updated
+//! every frame, and break conditions pause the emulator before any
+//! instruction while they aren't 0.
+
+use super::{debug_panel, get_element_by_id};
+use crate::chip8emulator::{Chip8Emulator, Expression};
+use gloo::events::EventListener;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlInputElement, KeyboardEvent};
+
+thread_local! {
+    /// The expressions whose values are shown.
+    static WATCHED: RefCell<Vec<Expression>> = const { RefCell::new(Vec::new()) };
+
+    /// The values as last shown, so only the ones that changed are written.
+    static SHOWN_VALUES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+
+    static SHOWN_STATUS: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Watches expressions typed in the panel with the Watch value button or
+/// Enter, and breaks on them with the Break when button.
+pub fn register_expressions(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    EventListener::new(&get_element_by_id("expression-watch"), "click", |_| {
+        watch_expression();
+    })
+    .forget();
+
+    EventListener::new(&get_input(), "keydown", |e| {
+        if e.dyn_ref::<KeyboardEvent>().unwrap().key() == "Enter" {
+            watch_expression();
+        }
+    })
+    .forget();
+
+    let break_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("expression-break"), "click", move |_| {
+        if let Some(condition) = parse_input() {
+            break_chip8.borrow_mut().add_break_condition(condition);
+            show_conditions(&break_chip8);
+        }
+    })
+    .forget();
+}
+
+fn watch_expression() {
+    if let Some(expression) = parse_input() {
+        WATCHED.with(|watched| {
+            let mut watched = watched.borrow_mut();
+            if !watched.contains(&expression) {
+                watched.push(expression);
+            }
+        });
+        show_watched();
+    }
+}
+
+/// Returns the expression typed in the panel and clears it, or says why it
+/// can't be parsed.
+fn parse_input() -> Option<Expression> {
+    let input = get_input();
+    match Expression::parse(&input.value()) {
+        Ok(expression) => {
+            input.set_value("");
+            Some(expression)
+        }
+        Err(message) => {
+            set_status(&format!("Can't parse \"{}\": {}", input.value(), message));
+            None
+        }
+    }
+}
+
+/// Lists the watched expressions, each with a button removing it. Their
+/// values are filled in by `update`.
+fn show_watched() {
+    let remove = |expression: Expression| {
+        WATCHED.with(|watched| watched.borrow_mut().retain(|e| *e != expression));
+        show_watched();
+    };
+    let watched = WATCHED.with(|watched| watched.borrow().clone());
+    debug_panel::show_list("expression-list", &watched, Rc::new(remove));
+    SHOWN_VALUES.with(|shown| shown.borrow_mut().clear());
+}
+
+/// Lists the break conditions, each with a button removing it.
+fn show_conditions(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let remove_chip8 = Rc::clone(chip8);
+    let remove = move |condition: Expression| {
+        remove_chip8.borrow_mut().remove_break_condition(&condition);
+        show_conditions(&remove_chip8);
+    };
+    let conditions = chip8.borrow().get_break_conditions().to_vec();
+    debug_panel::show_list("condition-list", &conditions, Rc::new(remove));
+}
+
+/// Shows the watched expressions' values, and which condition paused the
+/// emulator, if any. Called while the Debugger panel is open.
+pub fn update(chip8: &Chip8Emulator) {
+    let values = WATCHED.with(|watched| {
+        watched
+            .borrow()
+            .iter()
+            .map(|expression| describe_value(expression, chip8.evaluate(expression)))
+            .collect::<Vec<_>>()
+    });
+    SHOWN_VALUES.with(|shown| {
+        let mut shown = shown.borrow_mut();
+        let items = get_element_by_id("expression-list").children();
+        for (index, value) in values.iter().enumerate() {
+            if shown.get(index) == Some(value) {
+                continue;
+            }
+            // The text comes before the Remove button.
+            if let Some(text) = items.item(index as u32).and_then(|item| item.first_child()) {
+                text.set_text_content(Some(value));
+            }
+        }
+        *shown = values;
+    });
+
+    let status = describe_condition_hit(chip8);
+    let changed = SHOWN_STATUS.with(|shown| {
+        let mut shown = shown.borrow_mut();
+        let changed = *shown != status;
+        *shown = status.clone();
+        changed
+    });
+    if changed {
+        set_status(&status);
+    }
+}
+
+fn describe_value(expression: &Expression, value: Result<u32, String>) -> String {
+    match value {
+        Ok(value) => format!("{} = {:X} ({})", expression, value, value),
+        Err(message) => format!("{}: {}", expression, message),
+    }
+}
+
+fn describe_condition_hit(chip8: &Chip8Emulator) -> String {
+    match chip8.get_condition_hit() {
+        Some(condition) if chip8.is_paused() => {
+            format!("Paused at {:03X}: {}", chip8.get_pc(), condition)
+        }
+        _ => String::new(),
+    }
+}
+
+fn set_status(text: &str) {
+    get_element_by_id("expression-status").set_text_content(Some(text));
+}
+
+fn get_input() -> HtmlInputElement {
+    get_element_by_id("expression-input")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #expression-input is not an input element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_value() {
+        let expression = Expression::parse("V[3] + I").unwrap();
+        assert_eq!(
+            describe_value(&expression, Ok(0x237)),
+            "V[3] + I = 237 (567)"
+        );
+        assert_eq!(
+            describe_value(&expression, Err("division by zero".to_string())),
+            "V[3] + I: division by zero"
+        );
+    }
+
+    #[test]
+    fn test_describe_condition_hit() {
+        // Counts V3 up.
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x73, 0x01, 0x12, 0x00]).unwrap();
+        chip8.add_break_condition(Expression::parse("V3 == 2").unwrap());
+        assert_eq!(describe_condition_hit(&chip8), "");
+
+        chip8.tick(100.0);
+        assert!(chip8.is_paused());
+        assert_eq!(describe_condition_hit(&chip8), "Paused at 202: V3 == 2");
+        chip8.resume();
+        assert_eq!(describe_condition_hit(&chip8), "");
+    }
+}
@@ -0,0 +1,86 @@
+//! Fullscreen mode, which scales the display by the largest whole number that
+//! fits the screen, so its pixels stay sharp, and letterboxes the rest.
+
+use super::get_element_by_id;
+use gloo::events::EventListener;
+use std::cell::Cell;
+use wasm_bindgen::JsCast;
+use web_sys::{console, window, HtmlElement};
+
+thread_local! {
+    /// Width and height of the emulated display, in its own pixels.
+    static DISPLAY_SIZE: Cell<(u32, u32)> = const { Cell::new((64, 32)) };
+}
+
+/// Makes the fullscreen button toggle fullscreen, and keeps the display
+/// scaled to the screen while in it.
+pub fn register_fullscreen_button() {
+    EventListener::new(&get_element_by_id("fullscreen"), "click", |_| {
+        get_html_element("fullscreen").blur().unwrap();
+        let document = window().unwrap().document().unwrap();
+        if document.fullscreen_element().is_some() {
+            document.exit_fullscreen();
+        } else if let Err(e) = get_element_by_id("screen").request_fullscreen() {
+            console::error_2(&"Can't enter fullscreen".into(), &e);
+        }
+    })
+    .forget();
+
+    let window = window().unwrap();
+    let document = window.document().unwrap();
+    EventListener::new(&document, "fullscreenchange", |_| fit_display()).forget();
+    EventListener::new(&window, "resize", |_| fit_display()).forget();
+}
+
+/// Scales the display again after it changed size, as Mega-Chip ROMs do.
+pub fn set_display_size(width: u32, height: u32) {
+    DISPLAY_SIZE.with(|size| size.set((width, height)));
+    fit_display();
+}
+
+/// Sizes the canvas to a whole multiple of the display's size in device
+/// pixels while in fullscreen, and leaves it to the stylesheet otherwise.
+fn fit_display() {
+    let window = window().unwrap();
+    let style = get_html_element("canvas").style();
+    if window.document().unwrap().fullscreen_element().is_none() {
+        style.remove_property("width").unwrap();
+        style.remove_property("height").unwrap();
+        return;
+    }
+
+    let pixel_ratio = window.device_pixel_ratio();
+    let screen_width = window.inner_width().unwrap().as_f64().unwrap() * pixel_ratio;
+    let screen_height = window.inner_height().unwrap().as_f64().unwrap() * pixel_ratio;
+    let (width, height) = DISPLAY_SIZE.with(Cell::get);
+    let scale = integer_scale(width, height, screen_width as u32, screen_height as u32);
+
+    let css_size = |size: u32| format!("{}px", (size * scale) as f64 / pixel_ratio);
+    style.set_property("width", &css_size(width)).unwrap();
+    style.set_property("height", &css_size(height)).unwrap();
+}
+
+/// Returns the largest whole number a display can be scaled by and still fit
+/// the screen, and at least 1.
+fn integer_scale(width: u32, height: u32, screen_width: u32, screen_height: u32) -> u32 {
+    (screen_width / width).min(screen_height / height).max(1)
+}
+
+fn get_html_element(id: &str) -> HtmlElement {
+    get_element_by_id(id)
+        .dyn_into::<HtmlElement>()
+        .unwrap_or_else(|_| panic!("Element with id #{} is not an HTML element", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integer_scale() {
+        assert_eq!(integer_scale(64, 32, 1920, 1080), 30);
+        assert_eq!(integer_scale(128, 64, 1920, 1080), 15);
+        assert_eq!(integer_scale(256, 192, 1366, 768), 4);
+        assert_eq!(integer_scale(256, 192, 200, 100), 1);
+    }
+}
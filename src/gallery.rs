@@ -0,0 +1,141 @@
+use super::{
+    get_binary_file, get_element_by_id, get_json_file, load_rom, palette_picker, ROMS_DIR,
+};
+use crate::chip8emulator::{thumbnail, Chip8Emulator};
+use gloo::events::EventListener;
+use js_sys::{Array, Reflect};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::{Clamped, JsCast};
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{
+    console, window, CanvasRenderingContext2d, Element, HtmlCanvasElement, HtmlElement, ImageData,
+};
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// How long each ROM runs before its thumbnail is captured. Three seconds is
+/// enough for most bundled games to get past their title screen setup.
+const THUMBNAIL_FRAMES: u32 = 180;
+
+struct RomEntry {
+    name: String,
+    title: String,
+}
+
+/// Replaces the emulator view with a grid of cards, one per ROM in the
+/// manifest. Each card shows a thumbnail generated by running the ROM
+/// headlessly, and loads the ROM into `chip8` when clicked.
+pub async fn show_gallery(chip8: &Rc<RefCell<Chip8Emulator>>) -> Result<(), JsValue> {
+    let manifest = get_json_file(&format!("{}/{}", ROMS_DIR, MANIFEST_FILE)).await?;
+    let entries = parse_manifest(&manifest)?;
+
+    let gallery = get_element_by_id("gallery");
+    set_gallery_visible(true);
+
+    for entry in entries {
+        let (card, ctx) = create_card(&entry)?;
+        gallery.append_child(&card)?;
+        register_card_click(chip8, &card, &entry.name);
+
+        match get_binary_file(&format!("{}/{}", ROMS_DIR, entry.name)).await {
+            Ok(rom) => {
+                let thumbnail =
+                    match thumbnail(&rom, THUMBNAIL_FRAMES, palette_picker::current_palette()) {
+                        Ok(thumbnail) => thumbnail,
+                        Err(e) => {
+                            console::error_1(&format!("Can't run {}: {}", entry.name, e).into());
+                            continue;
+                        }
+                    };
+                let canvas = ctx.canvas().unwrap();
+                canvas.set_width(thumbnail.get_width());
+                canvas.set_height(thumbnail.get_height());
+
+                let image = ImageData::new_with_u8_clamped_array_and_sh(
+                    Clamped(thumbnail.rgba()),
+                    thumbnail.get_width(),
+                    thumbnail.get_height(),
+                )?;
+                ctx.put_image_data(&image, 0.0, 0.0)?;
+            }
+            Err(e) => console::error_2(&format!("Can't load {}", entry.name).into(), &e),
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_manifest(manifest: &JsValue) -> Result<Vec<RomEntry>, JsValue> {
+    if !Array::is_array(manifest) {
+        return Err("ROM manifest is not an array".into());
+    }
+
+    Array::from(manifest)
+        .iter()
+        .map(|entry| {
+            let name = Reflect::get(&entry, &"name".into())?
+                .as_string()
+                .ok_or_else(|| JsValue::from("ROM manifest entry has no name"))?;
+            let title = Reflect::get(&entry, &"title".into())?
+                .as_string()
+                .unwrap_or_else(|| name.clone());
+            Ok(RomEntry { name, title })
+        })
+        .collect()
+}
+
+fn create_card(entry: &RomEntry) -> Result<(Element, CanvasRenderingContext2d), JsValue> {
+    let document = window().unwrap().document().unwrap();
+
+    let card = document.create_element("button")?;
+    card.set_class_name("gallery-card");
+    card.set_attribute("title", &entry.name)?;
+
+    let canvas = document
+        .create_element("canvas")?
+        .dyn_into::<HtmlCanvasElement>()?;
+    canvas.set_class_name("gallery-thumbnail");
+    card.append_child(&canvas)?;
+
+    let label = document.create_element("span")?;
+    label.set_class_name("gallery-title");
+    label.set_text_content(Some(&entry.title));
+    card.append_child(&label)?;
+
+    let ctx = canvas
+        .get_context("2d")?
+        .unwrap()
+        .dyn_into::<CanvasRenderingContext2d>()?;
+
+    Ok((card, ctx))
+}
+
+fn register_card_click(chip8: &Rc<RefCell<Chip8Emulator>>, card: &Element, rom_name: &str) {
+    let chip8 = Rc::clone(chip8);
+    let rom_name = rom_name.to_string();
+    EventListener::new(card, "click", move |_| {
+        let chip8 = Rc::clone(&chip8);
+        let rom_name = rom_name.clone();
+        spawn_local(async move {
+            match load_rom(&chip8, &rom_name).await {
+                Ok(()) => set_gallery_visible(false),
+                Err(e) => console::error_2(&format!("Can't load ROM {}", rom_name).into(), &e),
+            }
+        });
+    })
+    .forget();
+}
+
+fn set_gallery_visible(visible: bool) {
+    get_element_by_id("gallery")
+        .dyn_into::<HtmlElement>()
+        .expect("Element with id #gallery is not an HTML element")
+        .set_hidden(!visible);
+
+    get_element_by_id("emulator")
+        .dyn_into::<HtmlElement>()
+        .expect("Element with id #emulator is not an HTML element")
+        .set_hidden(visible);
+}
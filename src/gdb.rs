@@ -0,0 +1,455 @@
+//! GDB remote serial protocol stub, so GDB front-ends can debug CHIP-8
+//! programs running in the core.
+//!
+//! The stub runs the ROM headlessly in real time and waits for a single GDB
+//! connection over TCP. It supports reading registers, reading and writing
+//! memory, software breakpoints, stepping, continuing and interrupting. The
+//! register layout is described to GDB with a target description: V0-VF, then
+//! I and PC as 16-bit little-endian values, then the delay and sound timers.
+
+use crate::chip8emulator::{assemble, Chip8Emulator, State};
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+/// How long a read waits for GDB before the emulator is ticked again.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+/// Signal reported when the program stops at a breakpoint or after a step.
+const SIGTRAP: u8 = 5;
+/// Signal reported when GDB interrupts the program.
+const SIGINT: u8 = 2;
+/// Signal reported when an instruction faults, e.g. an unknown opcode.
+const SIGSEGV: u8 = 11;
+const INTERRUPT: u8 = 0x03;
+const REGISTER_COUNT: usize = 20;
+
+/// Loads the ROM at `path`, assembling it first if it's a `.asm` file, and
+/// serves one GDB connection on `port`. The program starts stopped at its
+/// first instruction.
+pub fn run(path: &str, port: u16) -> io::Result<()> {
+    let rom = fs::read(path)?;
+    let rom = if path.ends_with(".asm") {
+        assemble(&String::from_utf8_lossy(&rom))
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, e.to_string()))?
+    } else {
+        rom
+    };
+
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    eprintln!("Waiting for GDB on port {}", port);
+    let (mut stream, address) = listener.accept()?;
+    eprintln!("GDB connected from {}", address);
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+
+    let start = Instant::now();
+    let mut stub = Stub::new(0.0);
+    stub.chip8
+        .load_rom(&rom)
+        .map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+
+    let mut reader = PacketReader::default();
+    let mut buffer = [0; 1024];
+    loop {
+        match stream.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(len) => reader.push(&buffer[..len]),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(e) => return Err(e),
+        }
+
+        while let Some(incoming) = reader.next_incoming() {
+            let reply = match incoming {
+                Incoming::Packet(packet) => {
+                    stream.write_all(b"+")?;
+                    stub.handle(&packet)
+                }
+                Incoming::BadChecksum => {
+                    stream.write_all(b"-")?;
+                    None
+                }
+                Incoming::Interrupt => Some(stub.interrupt()),
+            };
+            if let Some(reply) = reply {
+                send_packet(&mut stream, &reply)?;
+            }
+            if stub.is_detached() {
+                return Ok(());
+            }
+        }
+
+        if let Some(reply) = stub.tick(start.elapsed().as_secs_f64() * 1000.0) {
+            send_packet(&mut stream, &reply)?;
+        }
+    }
+}
+
+fn send_packet(stream: &mut TcpStream, data: &str) -> io::Result<()> {
+    stream.write_all(frame_packet(data).as_bytes())
+}
+
+fn frame_packet(data: &str) -> String {
+    format!("${}#{:02x}", data, packet_checksum(data.as_bytes()))
+}
+
+fn packet_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+#[derive(Debug, PartialEq)]
+enum Incoming {
+    Packet(String),
+    BadChecksum,
+    /// GDB sends a bare 0x03 byte to stop a running program.
+    Interrupt,
+}
+
+/// Splits the bytes received from GDB into packets.
+#[derive(Default)]
+struct PacketReader {
+    buffer: Vec<u8>,
+}
+
+impl PacketReader {
+    fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Returns the next complete message, skipping acknowledgements.
+    fn next_incoming(&mut self) -> Option<Incoming> {
+        loop {
+            match *self.buffer.first()? {
+                INTERRUPT => {
+                    self.buffer.remove(0);
+                    return Some(Incoming::Interrupt);
+                }
+                b'$' => {
+                    let end = self.buffer.iter().position(|&byte| byte == b'#')?;
+                    if self.buffer.len() < end + 3 {
+                        return None;
+                    }
+                    let packet: Vec<u8> = self.buffer.drain(..end + 3).collect();
+                    let data = &packet[1..end];
+                    let expected = std::str::from_utf8(&packet[end + 1..])
+                        .ok()
+                        .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+                    return Some(if expected == Some(packet_checksum(data)) {
+                        Incoming::Packet(String::from_utf8_lossy(data).into_owned())
+                    } else {
+                        Incoming::BadChecksum
+                    });
+                }
+                _ => {
+                    self.buffer.remove(0);
+                }
+            }
+        }
+    }
+}
+
+/// The state of a GDB session, independent of the connection.
+struct Stub {
+    chip8: Chip8Emulator,
+    /// Whether GDB is waiting for the program to stop after continuing.
+    running: bool,
+    detached: bool,
+}
+
+impl Stub {
+    fn new(current_time: f64) -> Stub {
+        let mut chip8 = Chip8Emulator::new(current_time);
+        chip8.pause();
+        Stub {
+            chip8,
+            running: false,
+            detached: false,
+        }
+    }
+
+    fn is_detached(&self) -> bool {
+        self.detached
+    }
+
+    /// Runs the emulator up to `current_time` and returns a stop reply if it
+    /// hit a breakpoint, the ROM ended or an instruction faulted.
+    fn tick(&mut self, current_time: f64) -> Option<String> {
+        self.chip8.tick(current_time);
+        if !self.running {
+            return None;
+        }
+        let signal = match self.chip8.get_state() {
+            State::Paused | State::Halted => SIGTRAP,
+            State::Faulted => SIGSEGV,
+            _ => return None,
+        };
+        self.running = false;
+        Some(stop_reply(signal))
+    }
+
+    fn interrupt(&mut self) -> String {
+        self.chip8.pause();
+        self.running = false;
+        stop_reply(SIGINT)
+    }
+
+    /// Handles a packet and returns the reply, if any. An empty reply tells
+    /// GDB the packet isn't supported.
+    fn handle(&mut self, packet: &str) -> Option<String> {
+        let command = packet.get(..1).unwrap_or_default();
+        let args = packet.get(1..).unwrap_or_default();
+        let reply = match command {
+            "?" => stop_reply(SIGTRAP),
+            "g" => self
+                .registers()
+                .iter()
+                .map(|value| encode_hex(value))
+                .collect(),
+            "p" => match usize::from_str_radix(args, 16)
+                .ok()
+                .and_then(|n| self.registers().get(n).map(|value| encode_hex(value)))
+            {
+                Some(value) => value,
+                None => "E01".to_string(),
+            },
+            "m" => match parse_range(args).and_then(|(address, len)| {
+                let end = address.checked_add(len)?;
+                self.chip8.get_memory().get(address..end)
+            }) {
+                Some(data) => encode_hex(data),
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let written = args.split_once(':').and_then(|(range, data)| {
+                    let (address, len) = parse_range(range)?;
+                    address.checked_add(len)?;
+                    let data = decode_hex(data).filter(|data| data.len() == len)?;
+                    Some(self.chip8.write_memory(u16::try_from(address).ok()?, &data))
+                });
+                if written == Some(true) {
+                    "OK".to_string()
+                } else {
+                    "E01".to_string()
+                }
+            }
+            "Z" | "z" => match parse_breakpoint(args) {
+                Some(address) => {
+                    if command == "Z" {
+                        self.chip8.add_breakpoint(address);
+                    } else {
+                        self.chip8.remove_breakpoint(address);
+                    }
+                    "OK".to_string()
+                }
+                None => String::new(),
+            },
+            "s" => {
+                self.chip8.step_instruction();
+                stop_reply(SIGTRAP)
+            }
+            "c" => {
+                self.chip8.resume();
+                self.running = true;
+                return None;
+            }
+            "H" => "OK".to_string(),
+            "D" => {
+                self.detached = true;
+                "OK".to_string()
+            }
+            "k" => {
+                self.detached = true;
+                return None;
+            }
+            _ => self.handle_query(packet),
+        };
+        Some(reply)
+    }
+
+    fn handle_query(&self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            "PacketSize=1000;qXfer:features:read+".to_string()
+        } else if packet == "qAttached" {
+            "1".to_string()
+        } else if let Some(args) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            match parse_range(args) {
+                Some((offset, len)) => read_chunk(&target_description(), offset, len),
+                None => "E01".to_string(),
+            }
+        } else {
+            String::new()
+        }
+    }
+
+    /// The registers in the order given by the target description, each in
+    /// target byte order.
+    fn registers(&self) -> Vec<Vec<u8>> {
+        let mut registers: Vec<Vec<u8>> = self
+            .chip8
+            .get_registers()
+            .iter()
+            .map(|&value| vec![value])
+            .collect();
+        registers.push(self.chip8.get_I().to_le_bytes().to_vec());
+        registers.push(self.chip8.get_pc().to_le_bytes().to_vec());
+        registers.push(vec![self.chip8.get_delay_timer()]);
+        registers.push(vec![self.chip8.get_sound_timer()]);
+        debug_assert_eq!(registers.len(), REGISTER_COUNT);
+        registers
+    }
+}
+
+fn stop_reply(signal: u8) -> String {
+    format!("S{:02x}", signal)
+}
+
+fn target_description() -> String {
+    let mut registers: Vec<String> = (0..16)
+        .map(|i| format!(r#"<reg name="v{:x}" bitsize="8" type="uint8"/>"#, i))
+        .collect();
+    registers.push(r#"<reg name="i" bitsize="16" type="data_ptr"/>"#.to_string());
+    registers.push(r#"<reg name="pc" bitsize="16" type="code_ptr"/>"#.to_string());
+    registers.push(r#"<reg name="dt" bitsize="8" type="uint8"/>"#.to_string());
+    registers.push(r#"<reg name="st" bitsize="8" type="uint8"/>"#.to_string());
+
+    format!(
+        concat!(
+            r#"<?xml version="1.0"?><!DOCTYPE target SYSTEM "gdb-target.dtd">"#,
+            r#"<target version="1.0"><feature name="org.chip8.core">{}</feature></target>"#
+        ),
+        registers.concat()
+    )
+}
+
+/// Replies to a `qXfer` read: `m` followed by data if there is more to read,
+/// or `l` followed by the rest.
+fn read_chunk(document: &str, offset: usize, len: usize) -> String {
+    let rest = document.get(offset..).unwrap_or_default();
+    if rest.len() > len {
+        format!("m{}", &rest[..len])
+    } else {
+        format!("l{}", rest)
+    }
+}
+
+/// Parses `addr,length` in hex.
+fn parse_range(args: &str) -> Option<(usize, usize)> {
+    let (address, len) = args.split_once(',')?;
+    Some((
+        usize::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+/// Parses the arguments of a `Z0` or `z0` packet, `0,addr,kind`. Other
+/// breakpoint types aren't supported.
+fn parse_breakpoint(args: &str) -> Option<u16> {
+    let mut parts = args.split(',');
+    if parts.next()? != "0" {
+        return None;
+    }
+    u16::from_str_radix(parts.next()?, 16)
+        .ok()
+        .filter(|&address| address < 0x1000)
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stub() {
+        // Increments V0 forever.
+        let mut stub = Stub::new(0.0);
+        stub.chip8.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut handle = |packet: &str| stub.handle(packet);
+
+        assert_eq!(handle("?"), Some("S05".to_string()));
+        assert_eq!(handle("s"), Some("S05".to_string()));
+        let registers = handle("g").unwrap();
+        assert_eq!(registers.len(), 2 * (16 + 2 + 2 + 2));
+        assert!(registers.starts_with("01000000"));
+        assert_eq!(handle("p11"), Some("0202".to_string()));
+
+        assert_eq!(handle("m200,4"), Some("70011200".to_string()));
+        assert_eq!(handle("M300,2:a2b3"), Some("OK".to_string()));
+        assert_eq!(handle("m300,2"), Some("a2b3".to_string()));
+        assert_eq!(handle("mfff,2"), Some("E01".to_string()));
+        assert_eq!(handle("m1,ffffffffffffffff"), Some("E01".to_string()));
+        assert_eq!(handle("M1,ffffffffffffffff:00"), Some("E01".to_string()));
+
+        assert_eq!(handle("Z0,202,2"), Some("OK".to_string()));
+        assert_eq!(handle("Z2,300,1"), Some(String::new()));
+        assert_eq!(handle("c"), None);
+        assert_eq!(stub.tick(100.0), Some("S05".to_string()));
+        assert_eq!(stub.chip8.get_registers()[0], 2);
+        assert_eq!(stub.tick(200.0), None);
+
+        assert_eq!(stub.handle("z0,202,2"), Some("OK".to_string()));
+        stub.handle("c");
+        assert_eq!(stub.tick(300.0), None);
+        assert_eq!(stub.interrupt(), "S02");
+
+        let description = stub.handle("qXfer:features:read:target.xml:0,20").unwrap();
+        assert_eq!(description, "m<?xml version=\"1.0\"?><!DOCTYPE t");
+        assert_eq!(stub.handle("vMustReplyEmpty"), Some(String::new()));
+        stub.handle("D");
+        assert!(stub.is_detached());
+    }
+
+    #[test]
+    fn test_stub_fault() {
+        let mut stub = Stub::new(0.0);
+        stub.chip8.load_rom(&[0x70, 0x01, 0x00, 0xee]).unwrap();
+        assert_eq!(stub.handle("c"), None);
+        assert_eq!(stub.tick(100.0), Some("S0b".to_string()));
+        assert_eq!(stub.tick(200.0), None);
+
+        // Continuing can't get past the fault, so it stops again right away.
+        stub.handle("c");
+        assert_eq!(stub.tick(300.0), Some("S0b".to_string()));
+    }
+
+    #[test]
+    fn test_stub_halt() {
+        // Ends with a jump to itself.
+        let mut stub = Stub::new(0.0);
+        stub.chip8.load_rom(&[0x70, 0x01, 0x12, 0x02]).unwrap();
+        stub.handle("c");
+        assert_eq!(stub.tick(100.0), Some("S05".to_string()));
+        assert_eq!(stub.chip8.get_registers()[0], 1);
+        assert_eq!(stub.tick(200.0), None);
+    }
+
+    #[test]
+    fn test_packet_reader() {
+        let mut reader = PacketReader::default();
+        reader.push(b"+$g#67$m200,");
+        assert_eq!(
+            reader.next_incoming(),
+            Some(Incoming::Packet("g".to_string()))
+        );
+        assert_eq!(reader.next_incoming(), None);
+
+        reader.push(b"4#00\x03");
+        assert_eq!(reader.next_incoming(), Some(Incoming::BadChecksum));
+        assert_eq!(reader.next_incoming(), Some(Incoming::Interrupt));
+        assert_eq!(reader.next_incoming(), None);
+
+        assert_eq!(frame_packet("OK"), "$OK#9a");
+    }
+}
@@ -0,0 +1,312 @@
+use super::memory_usage::{self, BufferCap, Eviction};
+use super::screenshot::scale_image;
+use super::{display_colors, download_file, get_element_by_id};
+use crate::chip8emulator::Chip8Emulator;
+use gif::{Encoder, EncodingError, Frame, Repeat};
+use gloo::events::EventListener;
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{console, HtmlButtonElement, HtmlSelectElement};
+
+/// How often the display is sampled, in milliseconds.
+const SAMPLE_INTERVAL: f64 = 1000.0 / 60.0;
+/// Shortest time a GIF frame is shown, in hundredths of a second. Browsers
+/// show frames with shorter delays for much longer instead, so samples closer
+/// together than this are dropped.
+const MIN_FRAME_DELAY: u16 = 2;
+/// Width GIFs are scaled up to, so small displays aren't tiny when shared.
+const GIF_WIDTH: u32 = 256;
+
+/// Samples the display at 60 Hz for a while, with a color table built from
+/// the colors seen.
+pub struct GifRecorder {
+    width: u32,
+    height: u32,
+    duration: f64,
+    start_time: Option<f64>,
+    /// When the recording was last updated, which is where it ends.
+    last_time: f64,
+    last_sample_time: f64,
+    colors: Vec<[u8; 3]>,
+    color_indices: HashMap<[u8; 3], u8>,
+    /// When each sample was taken, and the color index of each of its pixels.
+    samples: Vec<(f64, Vec<u8>)>,
+    /// Keeps a long recording of a large display from using up memory.
+    cap: BufferCap,
+}
+
+impl GifRecorder {
+    /// Starts a recording of a display of the given size, `duration`
+    /// milliseconds long.
+    pub fn new(width: u32, height: u32, duration: f64, cap: BufferCap) -> GifRecorder {
+        GifRecorder {
+            width,
+            height,
+            duration,
+            start_time: None,
+            last_time: 0.0,
+            last_sample_time: f64::NEG_INFINITY,
+            colors: Vec::new(),
+            color_indices: HashMap::new(),
+            samples: Vec::new(),
+            cap,
+        }
+    }
+
+    /// Samples the display at `current_time` if it's time to. Returns whether
+    /// the recording should go on: it ends after its duration, when the
+    /// display changes size, or when it's full and the cap says to stop.
+    pub fn record(
+        &mut self,
+        current_time: f64,
+        width: u32,
+        height: u32,
+        color_at: impl Fn(u32, u32) -> [u8; 3],
+    ) -> bool {
+        let start_time = *self.start_time.get_or_insert(current_time);
+        self.last_time = current_time;
+        if current_time - start_time >= self.duration || (width, height) != self.size() {
+            return false;
+        }
+        // Frames don't arrive exactly on time, so a sample is taken on the
+        // first frame that's close enough.
+        if current_time - self.last_sample_time < SAMPLE_INTERVAL * 0.9 {
+            return true;
+        }
+        self.last_sample_time = current_time;
+
+        let sample_len = (width * height) as usize;
+        if self.byte_len() + sample_len > self.cap.max_bytes {
+            if self.cap.eviction == Eviction::Stop {
+                return false;
+            }
+            let kept = (self.cap.max_bytes / sample_len).saturating_sub(1);
+            let excess = self.samples.len().saturating_sub(kept);
+            self.samples.drain(..excess);
+        }
+
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(self.color_index(color_at(x, y)));
+            }
+        }
+        self.samples.push((current_time, pixels));
+        true
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the index of `color` in the color table, adding it if there's
+    /// room. A GIF has at most 256 colors, and the first is used for the rest.
+    fn color_index(&mut self, color: [u8; 3]) -> u8 {
+        if let Some(&index) = self.color_indices.get(&color) {
+            return index;
+        }
+        if self.colors.len() == 256 {
+            return 0;
+        }
+        let index = self.colors.len() as u8;
+        self.colors.push(color);
+        self.color_indices.insert(color, index);
+        index
+    }
+
+    pub fn byte_len(&self) -> usize {
+        self.samples.iter().map(|(_, pixels)| pixels.len()).sum()
+    }
+
+    /// Returns the recording as a looping GIF, scaled up by `scale`.
+    pub fn finish(self, scale: u32) -> Result<Vec<u8>, EncodingError> {
+        let mut palette: Vec<u8> = self.colors.concat();
+        if palette.is_empty() {
+            palette.extend_from_slice(&[0, 0, 0]);
+        }
+
+        let (width, height) = ((self.width * scale) as u16, (self.height * scale) as u16);
+        let mut gif = Vec::new();
+        let mut encoder = Encoder::new(&mut gif, width, height, &palette)?;
+        encoder.set_repeat(Repeat::Infinite)?;
+
+        for (pixels, delay) in frame_delays(&self.samples, self.last_time) {
+            let frame = Frame {
+                width,
+                height,
+                delay,
+                buffer: Cow::Owned(scale_image(pixels, self.width, 1, scale)),
+                ..Frame::default()
+            };
+            encoder.write_frame(&frame)?;
+        }
+        drop(encoder);
+        Ok(gif)
+    }
+}
+
+/// Pairs the samples to show with how long each is shown, in hundredths of a
+/// second, up to `end_time`. Samples that are the same as the one before, or
+/// too soon after it, are left out and the one before is shown for longer.
+fn frame_delays(samples: &[(f64, Vec<u8>)], end_time: f64) -> Vec<(&[u8], u16)> {
+    let centiseconds = |time: f64| (time / 10.0).round() as i64;
+    let mut frames = Vec::new();
+    let mut shown: Option<(f64, &[u8])> = None;
+    for (time, pixels) in samples {
+        if let Some((shown_time, shown_pixels)) = shown {
+            let delay = centiseconds(*time) - centiseconds(shown_time);
+            if delay < MIN_FRAME_DELAY as i64 || shown_pixels == &pixels[..] {
+                continue;
+            }
+            frames.push((shown_pixels, delay as u16));
+        }
+        shown = Some((*time, pixels));
+    }
+    if let Some((shown_time, shown_pixels)) = shown {
+        let delay = centiseconds(end_time) - centiseconds(shown_time);
+        frames.push((shown_pixels, delay.max(MIN_FRAME_DELAY as i64) as u16));
+    }
+    frames
+}
+
+thread_local! {
+    static RECORDER: RefCell<Option<GifRecorder>> = const { RefCell::new(None) };
+}
+
+/// Samples the display at `current_time` into the recording, if any, and
+/// downloads the recording once it's over.
+pub fn record_gif(chip8: &Chip8Emulator, current_time: f64) {
+    let done = RECORDER.with(|recorder| {
+        recorder.borrow_mut().as_mut().is_some_and(|recorder| {
+            let (width, height) = (chip8.get_gfx_width(), chip8.get_gfx_height());
+            !recorder.record(current_time, width, height, display_colors(chip8))
+        })
+    });
+    if done {
+        finish_recording();
+    }
+}
+
+/// How many bytes the recording in progress uses, if any.
+pub fn recording_bytes() -> usize {
+    RECORDER.with(|recorder| recorder.borrow().as_ref().map_or(0, GifRecorder::byte_len))
+}
+
+/// Wires up the button that starts recording for the chosen duration, and
+/// stops it early.
+pub fn register_gif_recording(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&get_record_button(), "click", move |_| {
+        let button = get_record_button();
+        button.blur().unwrap();
+        if RECORDER.with(|recorder| recorder.borrow().is_some()) {
+            finish_recording();
+            return;
+        }
+
+        let seconds: f64 = get_duration_select().value().parse().unwrap_or(5.0);
+        let chip8 = chip8.borrow();
+        let recorder = GifRecorder::new(
+            chip8.get_gfx_width(),
+            chip8.get_gfx_height(),
+            seconds * 1000.0,
+            memory_usage::limits().gif_recording,
+        );
+        RECORDER.with(|current| *current.borrow_mut() = Some(recorder));
+        button.set_text_content(Some("Stop GIF"));
+    })
+    .forget();
+}
+
+fn finish_recording() {
+    let recorder = match RECORDER.with(|recorder| recorder.borrow_mut().take()) {
+        Some(recorder) => recorder,
+        None => return,
+    };
+    get_record_button().set_text_content(Some("Record GIF"));
+
+    let scale = (GIF_WIDTH / recorder.width).max(1);
+    match recorder.finish(scale) {
+        Ok(gif) => download_file("chip8-gameplay.gif", &gif, "image/gif"),
+        Err(e) => console::error_1(&format!("Can't encode the GIF: {}", e).into()),
+    }
+}
+
+fn get_record_button() -> HtmlButtonElement {
+    get_element_by_id("gif-record")
+        .dyn_into::<HtmlButtonElement>()
+        .expect("Element with id #gif-record is not a button element")
+}
+
+fn get_duration_select() -> HtmlSelectElement {
+    get_element_by_id("gif-duration")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #gif-duration is not a select element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_usage::MemoryLimits;
+
+    #[test]
+    fn test_gif_recorder() {
+        let cap = MemoryLimits::default().gif_recording;
+        let mut recorder = GifRecorder::new(2, 1, 100.0, cap);
+        // The left pixel blinks every other sample.
+        let mut time = 0.0;
+        let mut blink = false;
+        while recorder.record(time, 2, 1, |x, _| {
+            if x == 0 && blink {
+                [0xff, 0xff, 0xff]
+            } else {
+                [0, 0, 0]
+            }
+        }) {
+            time += SAMPLE_INTERVAL;
+            blink = !blink;
+        }
+        assert_eq!(recorder.samples.len(), 6);
+        assert_eq!(recorder.colors, [[0, 0, 0], [0xff, 0xff, 0xff]]);
+        assert_eq!(recorder.byte_len(), 12);
+        assert!(!recorder.record(time, 4, 2, |_, _| [0, 0, 0]));
+
+        let frames = frame_delays(&recorder.samples, 100.0);
+        let delays: Vec<u16> = frames.iter().map(|&(_, delay)| delay).collect();
+        assert_eq!(delays.iter().sum::<u16>(), 10);
+        assert!(delays.iter().all(|&delay| delay >= MIN_FRAME_DELAY));
+
+        let gif = recorder.finish(2).unwrap();
+        assert_eq!(&gif[..6], b"GIF89a");
+        assert_eq!(&gif[6..10], &[4, 0, 2, 0]);
+        assert_eq!(gif.last(), Some(&0x3b));
+    }
+
+    #[test]
+    fn test_gif_recorder_cap() {
+        // Room for three samples of a 2x1 display.
+        let cap = |eviction| BufferCap {
+            max_bytes: 7,
+            eviction,
+        };
+        let record = |recorder: &mut GifRecorder, count: u32| {
+            (0..count).all(|sample| {
+                let time = sample as f64 * SAMPLE_INTERVAL;
+                recorder.record(time, 2, 1, |_, _| [0, 0, 0])
+            })
+        };
+
+        let mut recorder = GifRecorder::new(2, 1, 1000.0, cap(Eviction::Stop));
+        assert!(!record(&mut recorder, 5));
+        assert_eq!(recorder.byte_len(), 6);
+        assert_eq!(recorder.samples[0].0, 0.0);
+
+        let mut recorder = GifRecorder::new(2, 1, 1000.0, cap(Eviction::DropOldest));
+        assert!(record(&mut recorder, 5));
+        assert_eq!(recorder.byte_len(), 6);
+        assert_eq!(recorder.samples[0].0, 2.0 * SAMPLE_INTERVAL);
+    }
+}
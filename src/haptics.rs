@@ -0,0 +1,100 @@
+use super::audio::SOUND_TIMER_STEP;
+use super::get_checkbox;
+use gloo::events::EventListener;
+use std::cell::RefCell;
+use std::rc::Rc;
+use web_sys::window;
+
+/// Vibrates the device while the CHIP-8 sound timer is active, for feedback
+/// where audio is muted or inaudible.
+pub struct Haptics {
+    enabled: bool,
+    /// Sound timer value at the last update.
+    last_sound_timer: u8,
+}
+
+impl Haptics {
+    pub fn new(enabled: bool) -> Haptics {
+        Haptics {
+            enabled,
+            last_sound_timer: 0,
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        if self.enabled && !enabled && self.last_sound_timer > 0 {
+            vibrate(0);
+        }
+        self.enabled = enabled;
+    }
+
+    /// Updates the vibration with the current state of the sound timer.
+    pub fn update(&mut self, sound_timer: u8) {
+        if let Some(duration) = self.next_vibration(sound_timer) {
+            vibrate(duration);
+        }
+    }
+
+    /// Returns how long the device should vibrate from now on, in
+    /// milliseconds, if that changed since the last update. Vibrations last as
+    /// long as the sound timer will, so they don't need to be renewed every
+    /// frame, and are cancelled with a duration of 0.
+    fn next_vibration(&mut self, sound_timer: u8) -> Option<u32> {
+        let last_sound_timer = self.last_sound_timer;
+        self.last_sound_timer = sound_timer;
+        if !self.enabled {
+            return None;
+        }
+
+        if sound_timer > last_sound_timer {
+            Some((sound_timer as f64 * SOUND_TIMER_STEP * 1000.0).round() as u32)
+        } else if sound_timer == 0 && last_sound_timer > 0 {
+            Some(0)
+        } else {
+            None
+        }
+    }
+}
+
+fn vibrate(duration: u32) {
+    // Browsers without the Vibration API, like desktop Safari, simply don't
+    // vibrate.
+    if let Some(window) = window() {
+        window.navigator().vibrate_with_duration(duration);
+    }
+}
+
+/// Shows whether vibration is enabled in the sound panel and applies changes
+/// made there to `haptics`.
+pub fn register_vibrate_checkbox(haptics: &Rc<RefCell<Haptics>>) {
+    let checkbox = get_checkbox("vibrate");
+    checkbox.set_checked(haptics.borrow().enabled);
+
+    let haptics = Rc::clone(haptics);
+    EventListener::new(&checkbox, "input", move |_| {
+        let checkbox = get_checkbox("vibrate");
+        checkbox.blur().unwrap();
+        haptics.borrow_mut().set_enabled(checkbox.checked());
+    })
+    .forget();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_vibration() {
+        let mut haptics = Haptics::new(true);
+        assert_eq!(haptics.next_vibration(0), None);
+        assert_eq!(haptics.next_vibration(6), Some(100));
+        assert_eq!(haptics.next_vibration(5), None);
+        assert_eq!(haptics.next_vibration(30), Some(500));
+        assert_eq!(haptics.next_vibration(0), Some(0));
+        assert_eq!(haptics.next_vibration(0), None);
+
+        let mut haptics = Haptics::new(false);
+        assert_eq!(haptics.next_vibration(6), None);
+        assert_eq!(haptics.next_vibration(0), None);
+    }
+}
@@ -0,0 +1,194 @@
+//! Hex dump of memory in the Debugger panel, 16 bytes a row, with the
+//! instruction at PC and the byte at I highlighted. Only the rows scrolled
+//! into view are read and updated, so it's cheap to keep open. It can follow
+//! PC or I, scrolling to keep their row in view.
+
+use super::get_element_by_id;
+use crate::chip8emulator::Chip8Emulator;
+use gloo::events::EventListener;
+use std::cell::{Cell, RefCell};
+use std::ops::Range;
+use wasm_bindgen::JsCast;
+use web_sys::{window, HtmlElement, HtmlSelectElement};
+
+const ROW_BYTES: usize = 16;
+const ROWS: usize = 4096 / ROW_BYTES;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Follow {
+    Off,
+    Pc,
+    I,
+}
+
+impl Follow {
+    fn parse(value: &str) -> Option<Follow> {
+        match value {
+            "off" => Some(Follow::Off),
+            "pc" => Some(Follow::Pc),
+            "i" => Some(Follow::I),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the rows at least partly in view in a view `height` pixels high
+/// scrolled down `scroll_top` pixels.
+fn visible_rows(scroll_top: i32, height: i32, row_height: i32) -> Range<usize> {
+    if row_height <= 0 {
+        return 0..0;
+    }
+    let first = (scroll_top.max(0) / row_height) as usize;
+    let last = ((scroll_top.max(0) + height.max(0) + row_height - 1) / row_height) as usize;
+    first.min(ROWS)..last.min(ROWS)
+}
+
+/// Returns where to scroll to have `row` in the middle of the view, unless
+/// it's fully in view already.
+fn follow_scroll_top(row: usize, scroll_top: i32, height: i32, row_height: i32) -> Option<i32> {
+    let top = row as i32 * row_height;
+    if top >= scroll_top && top + row_height <= scroll_top + height {
+        return None;
+    }
+    Some((top - (height - row_height) / 2).max(0))
+}
+
+thread_local! {
+    static FOLLOW: Cell<Follow> = const { Cell::new(Follow::Off) };
+
+    /// The bytes shown in each row, or `None` for rows not shown yet.
+    static SHOWN: RefCell<Vec<Option<Vec<u8>>>> = RefCell::new(vec![None; ROWS]);
+
+    /// The addresses highlighted as PC and I.
+    static MARKED: Cell<Option<(u16, u16)>> = const { Cell::new(None) };
+}
+
+/// Fills the hex view with a row for every 16 bytes of memory.
+pub fn register_hex_view() {
+    let document = window().unwrap().document().unwrap();
+    let view = get_view();
+    for row in 0..ROWS {
+        let line = document.create_element("div").unwrap();
+        line.set_class_name("hex-row");
+        let address = document.create_element("span").unwrap();
+        address.set_class_name("hex-address");
+        address.set_text_content(Some(&format!("{:03X}", row * ROW_BYTES)));
+        line.append_child(&address).unwrap();
+        for _ in 0..ROW_BYTES {
+            let byte = document.create_element("span").unwrap();
+            byte.set_class_name("hex-byte");
+            line.append_child(&byte).unwrap();
+        }
+        view.append_child(&line).unwrap();
+    }
+
+    EventListener::new(&get_follow_select(), "change", |_| {
+        let select = get_follow_select();
+        select.blur().unwrap();
+        let follow = Follow::parse(&select.value()).unwrap_or(Follow::Off);
+        FOLLOW.with(|current| current.set(follow));
+    })
+    .forget();
+}
+
+/// Shows the rows of memory in view and the PC and I markers. Called while
+/// the Debugger panel is open.
+pub fn update(chip8: &Chip8Emulator) {
+    let view = get_view();
+    let rows = view.children();
+    let row_height = match rows
+        .item(0)
+        .and_then(|row| row.dyn_into::<HtmlElement>().ok())
+    {
+        Some(row) => row.offset_height(),
+        None => return,
+    };
+
+    let target = match FOLLOW.with(Cell::get) {
+        Follow::Off => None,
+        Follow::Pc => Some(chip8.get_pc()),
+        Follow::I => Some(chip8.get_I()),
+    };
+    if let Some(target) = target {
+        let row = (target as usize / ROW_BYTES).min(ROWS - 1);
+        let scroll_top =
+            follow_scroll_top(row, view.scroll_top(), view.client_height(), row_height);
+        if let Some(scroll_top) = scroll_top {
+            view.set_scroll_top(scroll_top);
+        }
+    }
+
+    SHOWN.with(|shown| {
+        let mut shown = shown.borrow_mut();
+        for row in visible_rows(view.scroll_top(), view.client_height(), row_height) {
+            let bytes = chip8.memory_slice((row * ROW_BYTES) as u16, ROW_BYTES);
+            if shown[row].as_deref() == Some(bytes) {
+                continue;
+            }
+            if let Some(line) = rows.item(row as u32) {
+                let cells = line.children();
+                for (index, byte) in bytes.iter().enumerate() {
+                    if let Some(cell) = cells.item(index as u32 + 1) {
+                        cell.set_text_content(Some(&format!("{:02X}", byte)));
+                    }
+                }
+            }
+            shown[row] = Some(bytes.to_vec());
+        }
+    });
+
+    let marks = (chip8.get_pc(), chip8.get_I());
+    let marked = MARKED.with(|marked| marked.replace(Some(marks)));
+    if marked != Some(marks) {
+        if let Some((pc, i)) = marked {
+            mark(pc, "hex-pc", false);
+            mark(pc.wrapping_add(1), "hex-pc", false);
+            mark(i, "hex-i", false);
+        }
+        let (pc, i) = marks;
+        mark(pc, "hex-pc", true);
+        mark(pc.wrapping_add(1), "hex-pc", true);
+        mark(i, "hex-i", true);
+    }
+}
+
+fn mark(address: u16, class: &str, marked: bool) {
+    let address = address as usize;
+    let cell = get_view()
+        .children()
+        .item((address / ROW_BYTES) as u32)
+        .and_then(|row| row.children().item((address % ROW_BYTES) as u32 + 1));
+    if let Some(cell) = cell {
+        cell.class_list().toggle_with_force(class, marked).unwrap();
+    }
+}
+
+fn get_view() -> HtmlElement {
+    get_element_by_id("hex-view")
+        .dyn_into::<HtmlElement>()
+        .expect("Element with id #hex-view is not a text element")
+}
+
+fn get_follow_select() -> HtmlSelectElement {
+    get_element_by_id("hex-follow")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #hex-follow is not a select element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrolling() {
+        assert_eq!(visible_rows(0, 100, 20), 0..5);
+        assert_eq!(visible_rows(30, 100, 20), 1..7);
+        assert_eq!(visible_rows(5000, 200, 20), 250..256);
+        assert_eq!(visible_rows(0, 100, 0), 0..0);
+
+        // Rows in view stay put, others are scrolled to the middle.
+        assert_eq!(follow_scroll_top(2, 0, 100, 20), None);
+        assert_eq!(follow_scroll_top(5, 0, 100, 20), Some(60));
+        assert_eq!(follow_scroll_top(1, 200, 100, 20), Some(0));
+    }
+}
@@ -0,0 +1,339 @@
+//! Virtual joystick over the bottom left corner of the display, and swipes
+//! on the display, for moving around in games on phones and tablets. Both
+//! press the CHIP-8 keys chosen for up, down, left and right in the Controls
+//! panel: the joystick while it's pushed that way, and a swipe until the
+//! finger is lifted. Like the touch keypad, the joystick is only shown where
+//! the main pointer is a finger.
+
+use super::{get_element_by_id, press_key, storage};
+use crate::chip8emulator::Chip8Emulator;
+use gloo::events::{EventListener, EventListenerOptions};
+use std::cell::{Cell, RefCell};
+use std::convert::TryInto;
+use std::fmt;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{console, window, Element, HtmlElement, HtmlInputElement, HtmlSelectElement};
+use web_sys::{HtmlCanvasElement, PointerEvent};
+
+/// Directions in the order `Directions` keeps their keys, as the ids of
+/// their selects in the Controls panel end.
+const DIRECTION_NAMES: [&str; 4] = ["up", "down", "left", "right"];
+/// How far the joystick has to be pushed to press a key, as a part of its
+/// radius.
+const DEAD_ZONE: f64 = 0.3;
+/// tan(22.5°): the joystick presses two keys at once when pushed within
+/// 22.5° of a diagonal.
+const DIAGONAL_SLOPE: f64 = 0.414;
+/// How far a finger has to move on the display to swipe, in CSS pixels.
+const SWIPE_DISTANCE: f64 = 30.0;
+
+/// The CHIP-8 keys pressed for each direction, in the order of
+/// `DIRECTION_NAMES`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Directions {
+    keys: [u8; 4],
+}
+
+impl Directions {
+    pub fn key(&self, direction: usize) -> u8 {
+        self.keys[direction]
+    }
+
+    /// Parses keys written as by the `Display` impl: four hex digits for up,
+    /// down, left and right, separated by commas.
+    pub fn parse(value: &str) -> Option<Directions> {
+        let keys: Vec<u8> = value
+            .split(',')
+            .map(|key| {
+                u8::from_str_radix(key.trim(), 16)
+                    .ok()
+                    .filter(|&key| key <= 0xf)
+            })
+            .collect::<Option<_>>()?;
+        Some(Directions {
+            keys: keys.try_into().ok()?,
+        })
+    }
+}
+
+impl fmt::Display for Directions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [up, down, left, right] = self.keys;
+        write!(f, "{:x},{:x},{:x},{:x}", up, down, left, right)
+    }
+}
+
+impl Default for Directions {
+    /// 2, 8, 4 and 6, which most games move with.
+    fn default() -> Directions {
+        Directions {
+            keys: [0x2, 0x8, 0x4, 0x6],
+        }
+    }
+}
+
+/// Returns which directions the joystick pushed by `x` and `y`, as parts of
+/// its radius with y going down, points in, in the order of
+/// `DIRECTION_NAMES`.
+fn stick_directions(x: f64, y: f64) -> [bool; 4] {
+    if x.hypot(y) < DEAD_ZONE {
+        return [false; 4];
+    }
+    let vertical = y.abs() >= x.abs() * DIAGONAL_SLOPE;
+    let horizontal = x.abs() >= y.abs() * DIAGONAL_SLOPE;
+    [
+        vertical && y < 0.0,
+        vertical && y > 0.0,
+        horizontal && x < 0.0,
+        horizontal && x > 0.0,
+    ]
+}
+
+/// Returns the direction of a swipe by `dx` and `dy` CSS pixels, as an index
+/// into `DIRECTION_NAMES`, once it's long enough.
+fn swipe_direction(dx: f64, dy: f64) -> Option<usize> {
+    if dx.hypot(dy) < SWIPE_DISTANCE {
+        return None;
+    }
+    Some(match (dy.abs() > dx.abs(), dy < 0.0, dx < 0.0) {
+        (true, true, _) => 0,
+        (true, false, _) => 1,
+        (false, _, true) => 2,
+        (false, _, false) => 3,
+    })
+}
+
+thread_local! {
+    static DIRECTIONS: Cell<Directions> = Cell::new(Directions::default());
+
+    static ENABLED: Cell<bool> = const { Cell::new(true) };
+}
+
+/// The keys a joystick or a swipe has pressed, for each direction. They're
+/// released even if the keys chosen change while they're held.
+type Pressed = Rc<Cell<[Option<u8>; 4]>>;
+
+/// Presses the keys of `directions` and releases the others.
+fn set_directions(chip8: &Rc<RefCell<Chip8Emulator>>, pressed: &Pressed, directions: [bool; 4]) {
+    let keys = DIRECTIONS.with(Cell::get);
+    let mut current = pressed.get();
+    for (direction, current) in current.iter_mut().enumerate() {
+        let next = Some(keys.key(direction)).filter(|_| directions[direction]);
+        if next != *current {
+            if let Some(key) = current.take() {
+                press_key(chip8, key, false);
+            }
+            if let Some(key) = next {
+                press_key(chip8, key, true);
+            }
+            *current = next;
+        }
+    }
+    pressed.set(current);
+}
+
+/// Adds the joystick to the display and swipes to the canvas, and the
+/// settings of both to the Controls panel.
+pub fn register_joystick(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    DIRECTIONS.with(|directions| directions.set(storage::load_joystick().unwrap_or_default()));
+    ENABLED.with(|enabled| enabled.set(storage::load_touch_controls().unwrap_or(true)));
+    register_settings();
+    register_stick(chip8);
+    register_swipes(chip8);
+    show_settings();
+}
+
+fn register_stick(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let stick = get_element_by_id("joystick");
+    let pressed = Pressed::default();
+    // The pointer pushing the joystick.
+    let pointer = Rc::new(Cell::new(None));
+    // Not passive, so pushing the joystick doesn't scroll the page.
+    let options = EventListenerOptions::enable_prevent_default();
+    for event in ["pointerdown", "pointermove", "pointerup", "pointercancel"] {
+        let chip8 = Rc::clone(chip8);
+        let pressed = Rc::clone(&pressed);
+        let pointer = Rc::clone(&pointer);
+        let target = stick.clone();
+        EventListener::new_with_options(&stick, event, options, move |e| {
+            let e = e.dyn_ref::<PointerEvent>().unwrap();
+            e.prevent_default();
+            if event == "pointerdown" && pointer.get().is_none() {
+                pointer.set(Some(e.pointer_id()));
+                // Keeps the joystick's pointer events coming when the finger
+                // slides off it.
+                target.set_pointer_capture(e.pointer_id()).unwrap();
+            } else if pointer.get() != Some(e.pointer_id()) {
+                return;
+            }
+
+            let (x, y) = if event == "pointerup" || event == "pointercancel" {
+                pointer.set(None);
+                (0.0, 0.0)
+            } else {
+                let rect = target.get_bounding_client_rect();
+                let radius = rect.width() / 2.0;
+                let x = (e.client_x() as f64 - rect.left() - radius) / radius;
+                let y = (e.client_y() as f64 - rect.top() - radius) / radius;
+                // The knob stays inside the joystick.
+                let length = x.hypot(y).max(1.0);
+                (x / length, y / length)
+            };
+            set_directions(&chip8, &pressed, stick_directions(x, y));
+            move_knob(x, y);
+        })
+        .forget();
+    }
+}
+
+/// Moves the knob `x` and `y` radii of the joystick from its center. The
+/// knob is a radius wide, so percents of its size are percents of a radius.
+fn move_knob(x: f64, y: f64) {
+    let knob = get_element_by_id("joystick-knob")
+        .dyn_into::<HtmlElement>()
+        .expect("Element with id #joystick-knob is not a text element");
+    let transform = format!("translate({}%, {}%)", x * 100.0, y * 100.0);
+    knob.style().set_property("transform", &transform).unwrap();
+}
+
+fn register_swipes(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let canvas = get_element_by_id("canvas")
+        .dyn_into::<HtmlCanvasElement>()
+        .expect("Element with id #canvas is not a canvas element");
+    let pressed = Pressed::default();
+    // The finger swiping and where it went down.
+    let start = Rc::new(Cell::new(None));
+    for event in ["pointerdown", "pointermove", "pointerup", "pointercancel"] {
+        let chip8 = Rc::clone(chip8);
+        let pressed = Rc::clone(&pressed);
+        let start = Rc::clone(&start);
+        EventListener::new(&canvas, event, move |e| {
+            let e = e.dyn_ref::<PointerEvent>().unwrap();
+            // A mouse drags rather than swipes.
+            if e.pointer_type() != "touch" || !ENABLED.with(Cell::get) {
+                return;
+            }
+            let (x, y) = (e.client_x() as f64, e.client_y() as f64);
+            match (event, start.get()) {
+                ("pointerdown", None) => start.set(Some((e.pointer_id(), x, y))),
+                // A swipe keeps its direction until the finger is lifted.
+                ("pointermove", Some((id, start_x, start_y)))
+                    if id == e.pointer_id() && pressed.get() == [None; 4] =>
+                {
+                    if let Some(direction) = swipe_direction(x - start_x, y - start_y) {
+                        let mut directions = [false; 4];
+                        directions[direction] = true;
+                        set_directions(&chip8, &pressed, directions);
+                    }
+                }
+                ("pointerup" | "pointercancel", Some((id, _, _))) if id == e.pointer_id() => {
+                    start.set(None);
+                    set_directions(&chip8, &pressed, [false; 4]);
+                }
+                _ => {}
+            }
+        })
+        .forget();
+    }
+}
+
+/// Fills the direction selects in the Controls panel with the keypad's keys,
+/// and changes the settings from there.
+fn register_settings() {
+    let document = window().unwrap().document().unwrap();
+    for (direction, name) in DIRECTION_NAMES.iter().enumerate() {
+        let select = get_direction_select(name);
+        for key in 0..16 {
+            let option = document.create_element("option").unwrap();
+            option
+                .set_attribute("value", &format!("{:x}", key))
+                .unwrap();
+            option.set_text_content(Some(&format!("Key {:X}", key)));
+            select.append_child(&option).unwrap();
+        }
+
+        EventListener::new(&select, "change", move |_| {
+            let select = get_direction_select(DIRECTION_NAMES[direction]);
+            select.blur().unwrap();
+            let key = u8::from_str_radix(&select.value(), 16).unwrap_or(0);
+            let mut directions = DIRECTIONS.with(Cell::get);
+            directions.keys[direction] = key;
+            DIRECTIONS.with(|current| current.set(directions));
+            if let Err(e) = storage::store_joystick(directions) {
+                console::error_2(&"Can't store the joystick keys".into(), &e);
+            }
+        })
+        .forget();
+    }
+
+    EventListener::new(&get_checkbox(), "input", |_| {
+        let checkbox = get_checkbox();
+        checkbox.blur().unwrap();
+        ENABLED.with(|enabled| enabled.set(checkbox.checked()));
+        show_settings();
+        if let Err(e) = storage::store_touch_controls(checkbox.checked()) {
+            console::error_2(&"Can't store the touch controls setting".into(), &e);
+        }
+    })
+    .forget();
+}
+
+fn show_settings() {
+    let enabled = ENABLED.with(Cell::get);
+    let directions = DIRECTIONS.with(Cell::get);
+    get_checkbox().set_checked(enabled);
+    for (direction, name) in DIRECTION_NAMES.iter().enumerate() {
+        get_direction_select(name).set_value(&format!("{:x}", directions.key(direction)));
+    }
+    // Swiping on the display doesn't scroll the page while it's on.
+    get_screen()
+        .class_list()
+        .toggle_with_force("touch-controls", enabled)
+        .unwrap();
+}
+
+fn get_screen() -> Element {
+    get_element_by_id("screen")
+}
+
+fn get_checkbox() -> HtmlInputElement {
+    get_element_by_id("touch-controls")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #touch-controls is not an input element")
+}
+
+fn get_direction_select(name: &str) -> HtmlSelectElement {
+    let id = format!("joystick-{}", name);
+    get_element_by_id(&id)
+        .dyn_into::<HtmlSelectElement>()
+        .unwrap_or_else(|_| panic!("Element with id #{} is not a select element", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_directions() {
+        let directions = Directions::parse("2,8,4,6").unwrap();
+        assert_eq!(directions, Directions::default());
+        assert_eq!(directions.to_string(), "2,8,4,6");
+        assert_eq!(Directions::parse("5,8,a,F").unwrap().key(3), 0xf);
+        assert_eq!(Directions::parse("2,8,4"), None);
+        assert_eq!(Directions::parse("2,8,4,g"), None);
+    }
+
+    #[test]
+    fn test_stick_and_swipe_directions() {
+        assert_eq!(stick_directions(0.1, -0.1), [false; 4]);
+        assert_eq!(stick_directions(0.0, -1.0), [true, false, false, false]);
+        assert_eq!(stick_directions(1.0, 0.2), [false, false, false, true]);
+        // Down and to the left, near the diagonal.
+        assert_eq!(stick_directions(-0.6, 0.7), [false, true, true, false]);
+
+        assert_eq!(swipe_direction(10.0, 5.0), None);
+        assert_eq!(swipe_direction(-40.0, 10.0), Some(2));
+        assert_eq!(swipe_direction(5.0, 50.0), Some(1));
+    }
+}
@@ -0,0 +1,648 @@
+//! Keyboard bindings of the keypad. Each position of the 4x4 keypad is bound
+//! to a keyboard key, which the Controls panel lets users change: clicking a
+//! cell and pressing a key binds that key to the cell's position. A second
+//! key set, for the other player of two-player games, binds more keys to the
+//! same keypad; it starts out empty. Keys are told apart by where they are on
+//! the keyboard, not by what they type, so bindings work the same with any
+//! keyboard layout or Caps Lock.
+//!
+//! A ROM can have bindings of its own, used whenever it's loaded instead of
+//! the ones shared by every ROM. Some bundled games come with some: extra
+//! keys in the second key set, like the arrow keys for BRIX's paddle.
+
+use super::{get_element_by_id, keypad_view, shortcuts, storage, KEYPAD_LAYOUT};
+use gloo::events::{EventListener, EventListenerOptions};
+use std::cell::{Cell, RefCell};
+use std::convert::TryInto;
+use std::fmt;
+use std::thread::LocalKey;
+use wasm_bindgen::JsCast;
+use web_sys::{console, window, Element, HtmlInputElement, HtmlSelectElement, KeyboardEvent};
+
+/// Option of the preset select for bindings changed by hand.
+const CUSTOM_PRESET: &str = "custom";
+/// Names of the key sets, as the key set select shows them.
+const KEY_SET_NAMES: [&str; 2] = ["Player 1", "Player 2"];
+/// Physical keys standing in for the 4x4 keypad, row by row: `1234`/`QWER`/
+/// `ASDF`/`ZXCV` on a QWERTY keyboard, and the same cluster on any other.
+const KEYBOARD_CODES: [&str; 16] = [
+    "Digit1", "Digit2", "Digit3", "Digit4", "KeyQ", "KeyW", "KeyE", "KeyR", "KeyA", "KeyS", "KeyD",
+    "KeyF", "KeyZ", "KeyX", "KeyC", "KeyV",
+];
+/// Physical keys standing in for the CHIP-8X's second keypad, `7890`/`UIOP`/
+/// `JKL;`/`M,./` on a QWERTY keyboard.
+const SECOND_KEYBOARD_CODES: [&str; 16] = [
+    "Digit7",
+    "Digit8",
+    "Digit9",
+    "Digit0",
+    "KeyU",
+    "KeyI",
+    "KeyO",
+    "KeyP",
+    "KeyJ",
+    "KeyK",
+    "KeyL",
+    "Semicolon",
+    "KeyM",
+    "Comma",
+    "Period",
+    "Slash",
+];
+/// The arrow keys and Space, at the positions of 2, 4, 6 and 8, which most
+/// games move with, and of 5, which many fire or jump with.
+const ARROW_KEYBOARD_CODES: [&str; 16] = [
+    "",
+    "ArrowUp",
+    "",
+    "",
+    "ArrowLeft",
+    "Space",
+    "ArrowRight",
+    "",
+    "",
+    "ArrowDown",
+    "",
+    "",
+    "",
+    "",
+    "",
+    "",
+];
+/// Keys bundled ROMs add to the second key set, by ROM checksum, as the
+/// CHIP-8 keys pressed and the codes of the keyboard keys pressing them.
+const ROM_KEYS: [(u32, &[(u8, &str)]); 5] = [
+    // BRIX: 4 and 6 move the paddle.
+    (0x494a_44ac, &[(0x4, "ArrowLeft"), (0x6, "ArrowRight")]),
+    // INVADERS: 4 and 6 move, 5 fires.
+    (
+        0xaa01_0e34,
+        &[(0x4, "ArrowLeft"), (0x5, "Space"), (0x6, "ArrowRight")],
+    ),
+    // TANK: 2, 4, 6 and 8 move, 5 fires.
+    (
+        0xe8a5_29cc,
+        &[
+            (0x2, "ArrowUp"),
+            (0x4, "ArrowLeft"),
+            (0x5, "Space"),
+            (0x6, "ArrowRight"),
+            (0x8, "ArrowDown"),
+        ],
+    ),
+    // PONG and PONG2: C and D move the right paddle.
+    (0x30e3_34a2, &[(0xc, "ArrowUp"), (0xd, "ArrowDown")]),
+    (0x285b_ac0e, &[(0xc, "ArrowUp"), (0xd, "ArrowDown")]),
+];
+/// Labels of the codes of keys whose label isn't in their code, as on a
+/// QWERTY keyboard.
+const CODE_LABELS: [(&str, &str); 11] = [
+    ("Minus", "-"),
+    ("Equal", "="),
+    ("BracketLeft", "["),
+    ("BracketRight", "]"),
+    ("Backslash", "\\"),
+    ("Semicolon", ";"),
+    ("Quote", "'"),
+    ("Backquote", "`"),
+    ("Comma", ","),
+    ("Period", "."),
+    ("Slash", "/"),
+];
+
+/// The physical key bound to each position of the keypad, row by row, as
+/// `KeyboardEvent.code` names it, or an empty string for none. The CHIP-8 key
+/// at a position depends on the profile's keypad layout.
+#[derive(Clone, Debug, PartialEq)]
+pub struct KeyMap {
+    keys: [String; 16],
+}
+
+impl KeyMap {
+    /// Bindings to choose from, by name. Physical keys are in the same place
+    /// on every keyboard layout, so one preset serves QWERTY, AZERTY and
+    /// Dvorak alike. The left-handed one is the cluster on the right, which
+    /// the CHIP-8X's second keypad also uses. The arrows one binds only the
+    /// usual direction and action keys, and suits the second key set.
+    pub const PRESETS: [(&'static str, [&'static str; 16]); 4] = [
+        ("standard", KEYBOARD_CODES),
+        ("left-handed", SECOND_KEYBOARD_CODES),
+        ("arrows", ARROW_KEYBOARD_CODES),
+        ("none", [""; 16]),
+    ];
+
+    pub fn preset(name: &str) -> Option<KeyMap> {
+        KeyMap::PRESETS
+            .iter()
+            .find(|&&(preset, _)| preset == name)
+            .map(|&(_, keys)| KeyMap::new(keys))
+    }
+
+    /// Returns the name of the preset with the same bindings, if any.
+    pub fn preset_name(&self) -> Option<&'static str> {
+        KeyMap::PRESETS
+            .iter()
+            .find(|&&(_, keys)| self.keys.iter().zip(keys).all(|(a, b)| a == b))
+            .map(|&(name, _)| name)
+    }
+
+    pub fn new(keys: [&str; 16]) -> KeyMap {
+        KeyMap {
+            keys: keys.map(str::to_string),
+        }
+    }
+
+    /// Returns bindings with no key bound to any position.
+    pub fn empty() -> KeyMap {
+        KeyMap::new([""; 16])
+    }
+
+    /// Parses bindings written as by the `Display` impl: the codes of the keys
+    /// bound to each position, separated by commas.
+    pub fn parse(value: &str) -> Option<KeyMap> {
+        let codes: Vec<&str> = value.split(',').map(str::trim).collect();
+        let keys: [&str; 16] = codes.as_slice().try_into().ok()?;
+        // No key can be bound to two positions.
+        for (i, code) in keys.iter().enumerate() {
+            if !code.is_empty() && keys[..i].contains(code) {
+                return None;
+            }
+        }
+        Some(KeyMap::new(keys))
+    }
+
+    /// Returns the code of the key bound to `position`, empty if none is.
+    pub fn key(&self, position: usize) -> &str {
+        &self.keys[position]
+    }
+
+    /// Returns the position the key with the code `key` is bound to, if any.
+    pub fn position(&self, key: &str) -> Option<usize> {
+        self.keys.iter().position(|k| !k.is_empty() && k == key)
+    }
+
+    /// Binds `key` to `position`. A position `key` was bound to before gets
+    /// the key `position` had, so no key presses two positions.
+    pub fn bind(&mut self, position: usize, key: &str) {
+        if let Some(previous) = self.position(key) {
+            self.keys.swap(previous, position);
+        } else {
+            self.keys[position] = key.to_string();
+        }
+    }
+
+    /// Unbinds `key` from the position it's bound to, if any.
+    pub fn unbind(&mut self, key: &str) {
+        if let Some(position) = self.position(key) {
+            self.keys[position].clear();
+        }
+    }
+}
+
+impl fmt::Display for KeyMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.keys.join(","))
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> KeyMap {
+        KeyMap::new(KEYBOARD_CODES)
+    }
+}
+
+/// The bindings of both key sets, as kept for a ROM.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlProfile {
+    pub key_maps: [KeyMap; 2],
+}
+
+impl ControlProfile {
+    /// Returns these bindings with the keys the bundled ROM with the given
+    /// checksum adds to the second key set, bound to the positions of their
+    /// CHIP-8 keys in `layout`, or `None` if the ROM adds none.
+    pub fn with_rom_keys(&self, checksum: u32, layout: [u8; 16]) -> Option<ControlProfile> {
+        let &(_, keys) = ROM_KEYS.iter().find(|&&(rom, _)| rom == checksum)?;
+        let mut profile = self.clone();
+        for &(key, code) in keys {
+            let position = layout.iter().position(|&k| k == key)?;
+            profile.key_maps[0].unbind(code);
+            profile.key_maps[1].bind(position, code);
+        }
+        Some(profile)
+    }
+
+    /// Parses bindings written as by the `Display` impl: those of each key
+    /// set, as `KeyMap::parse` takes them, separated by a semicolon.
+    pub fn parse(value: &str) -> Option<ControlProfile> {
+        let (player_1, player_2) = value.split_once(';')?;
+        Some(ControlProfile {
+            key_maps: [KeyMap::parse(player_1)?, KeyMap::parse(player_2)?],
+        })
+    }
+}
+
+impl fmt::Display for ControlProfile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{};{}", self.key_maps[0], self.key_maps[1])
+    }
+}
+
+/// Returns how the key with the code `code` is shown in the Controls panel,
+/// e.g. "Q" for `KeyQ`.
+fn key_label(code: &str) -> &str {
+    if code.is_empty() {
+        return "-";
+    }
+    if let Some(&(_, label)) = CODE_LABELS.iter().find(|&&(c, _)| c == code) {
+        return label;
+    }
+    code.strip_prefix("Key")
+        .or_else(|| code.strip_prefix("Digit"))
+        .unwrap_or(code)
+}
+
+thread_local! {
+    pub static KEY_MAP: RefCell<KeyMap> = RefCell::new(KeyMap::default());
+
+    /// Bindings of the second key set, which press the same keypad.
+    pub static PLAYER_2_KEY_MAP: RefCell<KeyMap> = RefCell::new(KeyMap::empty());
+
+    /// Bindings of the CHIP-8X's second keypad, which can't be changed yet.
+    pub static SECOND_KEY_MAP: RefCell<KeyMap> = RefCell::new(KeyMap::new(SECOND_KEYBOARD_CODES));
+
+    /// The key set the Controls panel edits, as an index into `KEY_SET_NAMES`.
+    static EDITED_SET: Cell<usize> = const { Cell::new(0) };
+
+    /// The position waiting for a key to be bound to it.
+    static CAPTURING: Cell<Option<usize>> = const { Cell::new(None) };
+
+    /// Checksum of the ROM loaded, whose own bindings are changed while
+    /// "Controls for this ROM" is checked.
+    static ROM_CHECKSUM: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+/// Returns the bindings of the key set at `index` of `KEY_SET_NAMES`.
+fn key_set(index: usize) -> &'static LocalKey<RefCell<KeyMap>> {
+    match index {
+        0 => &KEY_MAP,
+        _ => &PLAYER_2_KEY_MAP,
+    }
+}
+
+/// Returns the bindings shared by every ROM, from local storage.
+fn shared_profile() -> ControlProfile {
+    ControlProfile {
+        key_maps: [
+            storage::load_key_map(0).unwrap_or_default(),
+            storage::load_key_map(1).unwrap_or_else(KeyMap::empty),
+        ],
+    }
+}
+
+fn current_profile() -> ControlProfile {
+    ControlProfile {
+        key_maps: [0, 1].map(|set| key_set(set).with(|key_map| key_map.borrow().clone())),
+    }
+}
+
+fn set_profile(profile: ControlProfile) {
+    let [player_1, player_2] = profile.key_maps;
+    KEY_MAP.with(|key_map| *key_map.borrow_mut() = player_1);
+    PLAYER_2_KEY_MAP.with(|key_map| *key_map.borrow_mut() = player_2);
+    CAPTURING.with(|capturing| capturing.set(None));
+    show_bindings();
+}
+
+/// Fills the Controls panel with a cell for each position of the keypad, and
+/// binds the next key pressed to a cell that was clicked. Bindings are
+/// restored from and stored to local storage.
+pub fn register_key_bindings() {
+    set_profile(shared_profile());
+
+    let document = window().unwrap().document().unwrap();
+    for name in KEY_SET_NAMES {
+        let option = document.create_element("option").unwrap();
+        option.set_text_content(Some(name));
+        get_set_select().append_child(&option).unwrap();
+    }
+
+    let bindings = get_bindings();
+    for position in 0..16 {
+        let cell = document.create_element("button").unwrap();
+        cell.set_class_name("key-binding");
+        cell.set_attribute("type", "button").unwrap();
+        bindings.append_child(&cell).unwrap();
+
+        EventListener::new(&cell, "click", move |_| {
+            let capturing = CAPTURING.with(Cell::get);
+            // Clicking the cell waiting for a key again gives up on it.
+            let next = if capturing == Some(position) {
+                None
+            } else {
+                Some(position)
+            };
+            CAPTURING.with(|capturing| capturing.set(next));
+            show_bindings();
+        })
+        .forget();
+    }
+    show_bindings();
+
+    EventListener::new(&get_set_select(), "change", |_| {
+        let select = get_set_select();
+        select.blur().unwrap();
+        EDITED_SET.with(|set| set.set(select.selected_index().max(0) as usize));
+        CAPTURING.with(|capturing| capturing.set(None));
+        show_bindings();
+    })
+    .forget();
+
+    EventListener::new(&get_preset_select(), "change", |_| {
+        let select = get_preset_select();
+        select.blur().unwrap();
+        // Picking "custom" keeps the current bindings to edit.
+        if let Some(key_map) = KeyMap::preset(&select.value()) {
+            set_key_map(key_map);
+        }
+    })
+    .forget();
+
+    EventListener::new(&get_element_by_id("key-reset"), "click", |_| {
+        // Forgetting the bindings rather than storing the default ones lets
+        // a later change of the default apply.
+        let forgotten = match ROM_CHECKSUM.with(Cell::get) {
+            Some(checksum) if get_rom_checkbox().checked() => {
+                let forgotten = storage::remove_rom_controls(checksum);
+                apply_rom_controls(checksum);
+                forgotten
+            }
+            _ => {
+                let forgotten =
+                    (0..KEY_SET_NAMES.len()).try_for_each(|set| storage::store_key_map(set, None));
+                set_profile(shared_profile());
+                forgotten
+            }
+        };
+        if let Err(e) = forgotten {
+            console::error_2(&"Can't reset the key bindings".into(), &e);
+        }
+    })
+    .forget();
+
+    EventListener::new(&get_rom_checkbox(), "input", |_| {
+        let checkbox = get_rom_checkbox();
+        checkbox.blur().unwrap();
+        let checksum = match ROM_CHECKSUM.with(Cell::get) {
+            Some(checksum) => checksum,
+            None => return,
+        };
+        if checkbox.checked() {
+            store_bindings();
+        } else {
+            if let Err(e) = storage::remove_rom_controls(checksum) {
+                console::error_2(&"Can't forget the ROM's controls".into(), &e);
+            }
+            set_profile(shared_profile());
+        }
+    })
+    .forget();
+
+    // Runs before the keypad's and the pause menu's listeners, so the key
+    // bound doesn't also press a CHIP-8 key or open the menu.
+    let options = EventListenerOptions::run_in_capture_phase();
+    EventListener::new_with_options(&window().unwrap(), "keydown", options, |e| {
+        let position = match CAPTURING.with(Cell::take) {
+            Some(position) => position,
+            None => return,
+        };
+        let e = e.dyn_ref::<KeyboardEvent>().unwrap();
+        e.prevent_default();
+        e.stop_propagation();
+        // Escape gives up on binding a key.
+        if e.code() != "Escape" {
+            let edited = EDITED_SET.with(Cell::get);
+            // A key presses the keypad for one player only.
+            for set in 0..KEY_SET_NAMES.len() {
+                key_set(set).with(|key_map| {
+                    let mut key_map = key_map.borrow_mut();
+                    if set == edited {
+                        key_map.bind(position, &e.code());
+                    } else {
+                        key_map.unbind(&e.code());
+                    }
+                });
+            }
+            store_bindings();
+        }
+        show_bindings();
+    })
+    .forget();
+}
+
+/// Replaces the bindings of the key set being edited with `key_map`.
+fn set_key_map(key_map: KeyMap) {
+    let edited = EDITED_SET.with(Cell::get);
+    for set in 0..KEY_SET_NAMES.len() {
+        key_set(set).with(|current| {
+            let mut current = current.borrow_mut();
+            if set == edited {
+                *current = key_map.clone();
+            } else {
+                for position in 0..16 {
+                    current.unbind(key_map.key(position));
+                }
+            }
+        });
+    }
+    CAPTURING.with(|capturing| capturing.set(None));
+    show_bindings();
+    store_bindings();
+}
+
+/// Stores the bindings as the loaded ROM's own if "Controls for this ROM" is
+/// checked, or as the ones shared by every ROM otherwise.
+fn store_bindings() {
+    let profile = current_profile();
+    let stored = match ROM_CHECKSUM.with(Cell::get) {
+        Some(checksum) if get_rom_checkbox().checked() => {
+            storage::store_rom_controls(checksum, &profile)
+        }
+        _ => (0..KEY_SET_NAMES.len())
+            .try_for_each(|set| storage::store_key_map(set, Some(&profile.key_maps[set]))),
+    };
+    if let Err(e) = stored {
+        console::error_2(&"Can't store the key bindings".into(), &e);
+    }
+}
+
+/// Switches to the bindings stored for the ROM with the given checksum, or
+/// to the ones it comes with if it's a bundled ROM that has some, or to the
+/// ones shared by every ROM.
+pub fn apply_rom_controls(checksum: u32) {
+    ROM_CHECKSUM.with(|rom| rom.set(Some(checksum)));
+    let layout = KEYPAD_LAYOUT.with(Cell::get);
+    let profile = storage::load_rom_controls(checksum)
+        .or_else(|| shared_profile().with_rom_keys(checksum, layout));
+    get_rom_checkbox().set_checked(profile.is_some());
+    set_profile(profile.unwrap_or_else(shared_profile));
+}
+
+/// Shows each position's CHIP-8 key in the current keypad layout and the
+/// keys both key sets bind to it in the Controls panel, and the preset the
+/// edited set's bindings match.
+pub fn show_bindings() {
+    let layout = KEYPAD_LAYOUT.with(Cell::get);
+    let capturing = CAPTURING.with(Cell::get);
+    let edited = EDITED_SET.with(Cell::get);
+    let key_maps: Vec<KeyMap> = (0..KEY_SET_NAMES.len())
+        .map(|set| key_set(set).with(|key_map| key_map.borrow().clone()))
+        .collect();
+    get_preset_select().set_value(key_maps[edited].preset_name().unwrap_or(CUSTOM_PRESET));
+
+    let cells = get_bindings().children();
+    for (position, chip8_key) in layout.iter().enumerate() {
+        let cell = match cells.item(position as u32) {
+            Some(cell) => cell,
+            None => continue,
+        };
+        let keys: Vec<&str> = key_maps
+            .iter()
+            .enumerate()
+            .map(|(set, key_map)| {
+                if set == edited && capturing == Some(position) {
+                    "press a key"
+                } else {
+                    key_label(key_map.key(position))
+                }
+            })
+            .collect();
+        cell.set_text_content(Some(&format!("{:X}: {}", chip8_key, keys.join(" / "))));
+        cell.class_list()
+            .toggle_with_force("capturing", capturing == Some(position))
+            .unwrap();
+    }
+    shortcuts::show_conflicts();
+    keypad_view::set_labels();
+}
+
+/// Returns the labels of the keyboard keys either key set binds to
+/// `position`, e.g. "Q / Left".
+pub fn bound_keys(position: usize) -> String {
+    let labels: Vec<String> = (0..KEY_SET_NAMES.len())
+        .map(|set| key_set(set).with(|key_map| key_map.borrow().key(position).to_string()))
+        .filter(|code| !code.is_empty())
+        .map(|code| key_label(&code).to_string())
+        .collect();
+    labels.join(" / ")
+}
+
+fn get_bindings() -> Element {
+    get_element_by_id("key-bindings")
+}
+
+fn get_set_select() -> HtmlSelectElement {
+    get_element_by_id("key-set")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #key-set is not a select element")
+}
+
+fn get_rom_checkbox() -> HtmlInputElement {
+    get_element_by_id("key-rom-controls")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #key-rom-controls is not an input element")
+}
+
+fn get_preset_select() -> HtmlSelectElement {
+    get_element_by_id("key-preset")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #key-preset is not a select element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8emulator::Profile;
+
+    #[test]
+    fn test_key_map() {
+        let mut key_map = KeyMap::default();
+        assert_eq!(key_map.position("KeyQ"), Some(4));
+        assert_eq!(key_map.position("q"), None);
+
+        key_map.bind(4, "ArrowUp");
+        assert_eq!(key_map.key(4), "ArrowUp");
+        assert_eq!(key_map.position("KeyQ"), None);
+
+        // Binding a key that's in use swaps it with the position's key.
+        key_map.bind(0, "KeyW");
+        assert_eq!(key_map.key(0), "KeyW");
+        assert_eq!(key_map.key(5), "Digit1");
+    }
+
+    #[test]
+    fn test_key_map_presets() {
+        assert_eq!(KeyMap::default().preset_name(), Some("standard"));
+        let mut key_map = KeyMap::preset("left-handed").unwrap();
+        assert_eq!(key_map.key(11), "Semicolon");
+        assert_eq!(key_map.preset_name(), Some("left-handed"));
+        key_map.bind(0, "Digit1");
+        assert_eq!(key_map.preset_name(), None);
+        assert_eq!(KeyMap::preset("colemak"), None);
+
+        // The arrows are at the positions of 2, 4, 6 and 8 on the COSMAC VIP.
+        let arrows = KeyMap::preset("arrows").unwrap();
+        let layout = Profile::chip8().keypad_layout;
+        let key = |code| arrows.position(code).map(|position| layout[position]);
+        assert_eq!(key("ArrowUp"), Some(0x2));
+        assert_eq!(key("ArrowDown"), Some(0x8));
+        assert_eq!(key("Space"), Some(0x5));
+        assert_eq!(key("KeyQ"), None);
+
+        assert_eq!(key_label("KeyQ"), "Q");
+        assert_eq!(key_label("Digit1"), "1");
+        assert_eq!(key_label("Semicolon"), ";");
+        assert_eq!(key_label("ArrowUp"), "ArrowUp");
+    }
+
+    #[test]
+    fn test_parse_key_map() {
+        let mut key_map = KeyMap::default();
+        key_map.bind(7, "Space");
+        assert_eq!(KeyMap::parse(&key_map.to_string()), Some(key_map));
+        assert_eq!(
+            KeyMap::parse(&KeyMap::default().to_string().replace("KeyW", "KeyQ")),
+            None
+        );
+        assert_eq!(KeyMap::parse("Digit1,Digit2"), None);
+
+        let mut player_2 = KeyMap::empty();
+        player_2.bind(12, "ArrowLeft");
+        player_2.bind(13, "ArrowRight");
+        assert_eq!(player_2.position(""), None);
+        assert_eq!(KeyMap::parse(&player_2.to_string()), Some(player_2.clone()));
+        player_2.unbind("ArrowLeft");
+        assert_eq!(player_2.key(12), "");
+        assert_eq!(player_2.preset_name(), None);
+    }
+
+    #[test]
+    fn test_control_profile() {
+        let shared = ControlProfile {
+            key_maps: [KeyMap::default(), KeyMap::empty()],
+        };
+        assert_eq!(
+            ControlProfile::parse(&shared.to_string()),
+            Some(shared.clone())
+        );
+        assert_eq!(ControlProfile::parse(&KeyMap::default().to_string()), None);
+
+        // BRIX on the COSMAC VIP keypad, where 4 and 6 are Q and E.
+        let layout = Profile::chip8().keypad_layout;
+        let brix = shared.with_rom_keys(0x494a_44ac, layout).unwrap();
+        assert_eq!(brix.key_maps[0], KeyMap::default());
+        assert_eq!(brix.key_maps[1].position("ArrowLeft"), Some(4));
+        assert_eq!(brix.key_maps[1].position("ArrowRight"), Some(6));
+        assert_eq!(shared.with_rom_keys(0, layout), None);
+    }
+}
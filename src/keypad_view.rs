@@ -0,0 +1,106 @@
+//! Live view of the keypad under the display, lighting up the keys the
+//! running ROM sees pressed, each labeled with the keyboard keys bound to it.
+//! Handy for finding out which keys a game uses.
+
+use super::{get_element_by_id, keymap, storage, KEYPAD_LAYOUT};
+use gloo::events::EventListener;
+use std::cell::Cell;
+use wasm_bindgen::JsCast;
+use web_sys::{console, window, Element, HtmlElement, HtmlInputElement};
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+
+    /// The keys shown pressed, or `None` if the view needs updating.
+    static SHOWN_KEYS: Cell<Option<u16>> = const { Cell::new(None) };
+}
+
+/// Fills the view with a cell for each key, and shows or hides it with the
+/// checkbox in the display panel.
+pub fn register_keypad_view() {
+    let document = window().unwrap().document().unwrap();
+    let view = get_view();
+    for _ in 0..16 {
+        let cell = document.create_element("div").unwrap();
+        cell.set_class_name("keypad-view-key");
+        for class in ["keypad-view-chip8", "keypad-view-binding"] {
+            let label = document.create_element("span").unwrap();
+            label.set_class_name(class);
+            cell.append_child(&label).unwrap();
+        }
+        view.append_child(&cell).unwrap();
+    }
+    set_labels();
+
+    ENABLED.with(|enabled| enabled.set(storage::load_keypad_view().unwrap_or(false)));
+    show_view();
+
+    EventListener::new(&get_checkbox(), "input", |_| {
+        let checkbox = get_checkbox();
+        checkbox.blur().unwrap();
+        ENABLED.with(|enabled| enabled.set(checkbox.checked()));
+        show_view();
+        if let Err(e) = storage::store_keypad_view(checkbox.checked()) {
+            console::error_2(&"Can't store the keypad view setting".into(), &e);
+        }
+    })
+    .forget();
+}
+
+fn show_view() {
+    let enabled = ENABLED.with(Cell::get);
+    get_checkbox().set_checked(enabled);
+    get_view()
+        .dyn_into::<HtmlElement>()
+        .expect("Element with id #keypad-view is not a text element")
+        .set_hidden(!enabled);
+    SHOWN_KEYS.with(|shown| shown.set(None));
+}
+
+/// Lights up the keys in `pressed`, one bit per key, if the view is shown.
+pub fn update(pressed: u16) {
+    if !ENABLED.with(Cell::get) || SHOWN_KEYS.with(Cell::get) == Some(pressed) {
+        return;
+    }
+    SHOWN_KEYS.with(|shown| shown.set(Some(pressed)));
+
+    let layout = KEYPAD_LAYOUT.with(Cell::get);
+    let cells = get_view().children();
+    for (position, key) in layout.iter().enumerate() {
+        if let Some(cell) = cells.item(position as u32) {
+            cell.class_list()
+                .toggle_with_force("pressed", pressed & 1 << key != 0)
+                .unwrap();
+        }
+    }
+}
+
+/// Labels the cells with their keys in the current keypad layout and the
+/// keyboard keys bound to them.
+pub fn set_labels() {
+    let layout = KEYPAD_LAYOUT.with(Cell::get);
+    let cells = get_view().children();
+    for (position, key) in layout.iter().enumerate() {
+        let cell = match cells.item(position as u32) {
+            Some(cell) => cell,
+            None => continue,
+        };
+        let labels = cell.children();
+        if let (Some(chip8), Some(binding)) = (labels.item(0), labels.item(1)) {
+            chip8.set_text_content(Some(&format!("{:X}", key)));
+            binding.set_text_content(Some(&keymap::bound_keys(position)));
+        }
+    }
+    // The pressed keys may be at other positions now.
+    SHOWN_KEYS.with(|shown| shown.set(None));
+}
+
+fn get_view() -> Element {
+    get_element_by_id("keypad-view")
+}
+
+fn get_checkbox() -> HtmlInputElement {
+    get_element_by_id("keypad-view-enabled")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #keypad-view-enabled is not an input element")
+}
@@ -0,0 +1,146 @@
+//! Input latency measurement, a diagnostic mode for comparing how quickly the
+//! emulator sees keys when it runs on the page and in the emulator worker.
+//! Each key press is timestamped when the browser received it, and measured
+//! up to the tick whose keypad has the key down, the point from which the ROM
+//! can read it. The display panel reports the average and the worst.
+
+use super::{emulator_worker, get_element_by_id};
+use gloo::events::EventListener;
+use std::cell::{Cell, RefCell};
+use wasm_bindgen::JsCast;
+use web_sys::HtmlInputElement;
+
+/// How long a press is waited for before it's given up on, in milliseconds,
+/// e.g. for keys the emulated machine doesn't have.
+const MAX_LATENCY: f64 = 1000.0;
+
+/// Pairs key presses with the tick that saw them and keeps count.
+#[derive(Default)]
+struct LatencyMeter {
+    /// Keys pressed but not seen yet, with when they were pressed.
+    pending: Vec<(u8, f64)>,
+    count: u32,
+    total: f64,
+    worst: f64,
+}
+
+impl LatencyMeter {
+    fn key_down(&mut self, key: u8, time: f64) {
+        self.pending.retain(|&(pending, _)| pending != key);
+        self.pending.push((key, time));
+    }
+
+    /// Counts the pending presses of the keys in `pressed`, one bit per key,
+    /// as seen by the tick at `time`. Returns whether any was counted.
+    fn observe(&mut self, pressed: u16, time: f64) -> bool {
+        let mut latencies = Vec::new();
+        self.pending.retain(|&(key, pressed_time)| {
+            if pressed & 1 << key == 0 {
+                return time - pressed_time < MAX_LATENCY;
+            }
+            latencies.push((time - pressed_time).max(0.0));
+            false
+        });
+        for &latency in &latencies {
+            self.count += 1;
+            self.total += latency;
+            self.worst = self.worst.max(latency);
+        }
+        !latencies.is_empty()
+    }
+
+    fn average(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.total / self.count as f64)
+    }
+}
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+
+    static METER: RefCell<LatencyMeter> = RefCell::new(LatencyMeter::default());
+}
+
+/// Turns measuring on and off with the checkbox in the display panel. It
+/// starts over every time it's turned on.
+pub fn register_latency_checkbox() {
+    EventListener::new(&get_checkbox(), "input", |_| {
+        let checkbox = get_checkbox();
+        checkbox.blur().unwrap();
+        ENABLED.with(|enabled| enabled.set(checkbox.checked()));
+        METER.with(|meter| *meter.borrow_mut() = LatencyMeter::default());
+        show_report();
+    })
+    .forget();
+}
+
+/// Starts timing a press of `key` received at `time`, if measuring.
+pub fn key_down(key: u8, time: f64) {
+    if ENABLED.with(Cell::get) {
+        METER.with(|meter| meter.borrow_mut().key_down(key, time));
+    }
+}
+
+/// Stops timing the presses of the keys a tick at `time` saw pressed, one
+/// bit per key in `pressed`, and shows the new numbers.
+pub fn observe(pressed: u16, time: f64) {
+    if ENABLED.with(Cell::get) && METER.with(|meter| meter.borrow_mut().observe(pressed, time)) {
+        show_report();
+    }
+}
+
+fn show_report() {
+    let text = if ENABLED.with(Cell::get) {
+        METER.with(|meter| {
+            let meter = meter.borrow();
+            match meter.average() {
+                Some(average) => format!(
+                    "{:.1} ms average, {:.1} ms worst over {} presses, {}",
+                    average,
+                    meter.worst,
+                    meter.count,
+                    if emulator_worker::is_running() {
+                        "in the worker"
+                    } else {
+                        "on the page"
+                    }
+                ),
+                None => "Press some keys".to_string(),
+            }
+        })
+    } else {
+        String::new()
+    };
+    get_element_by_id("latency-report").set_text_content(Some(&text));
+}
+
+fn get_checkbox() -> HtmlInputElement {
+    get_element_by_id("latency-enabled")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #latency-enabled is not an input element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_meter() {
+        let mut meter = LatencyMeter::default();
+        assert_eq!(meter.average(), None);
+
+        meter.key_down(5, 100.0);
+        meter.key_down(2, 104.0);
+        // Other keys being down doesn't count.
+        assert!(!meter.observe(1 << 3, 110.0));
+        assert!(meter.observe(1 << 5, 116.0));
+        assert!(meter.observe(1 << 5 | 1 << 2, 134.0));
+        assert_eq!(meter.count, 2);
+        assert_eq!(meter.worst, 30.0);
+        assert_eq!(meter.average(), Some(23.0));
+
+        // A press never seen is given up on.
+        meter.key_down(0xf, 200.0);
+        assert!(!meter.observe(0, 1300.0));
+        assert!(meter.pending.is_empty());
+    }
+}
@@ -1,16 +1,83 @@
+mod api;
+mod audio;
+mod audio_recording;
+mod audio_worklet;
+mod call_graph;
+mod capabilities;
+mod cheat_search;
+mod cheats;
 mod chip8emulator;
+mod config;
+mod crash_report;
+#[cfg(feature = "dap")]
+pub mod dap;
+mod debug_panel;
+mod disassembly_view;
+mod display_filter;
+mod emulator_worker;
+mod expressions;
+mod fullscreen;
+mod gallery;
+#[cfg(feature = "gdb")]
+pub mod gdb;
+mod gif_recording;
+mod haptics;
+mod hex_view;
+mod joystick;
+mod keymap;
+mod keypad_view;
+mod latency;
+mod macros;
+mod memory_usage;
+mod midi;
+mod opcode_breaks;
+mod opcode_reference;
+mod osd;
+mod palette_picker;
+mod patching;
+mod pause_menu;
+mod perf_overlay;
+mod phosphor;
+mod profiler;
+mod rpc;
+mod screenshot;
+mod shortcuts;
+#[cfg(feature = "soak")]
+pub mod soak;
+mod sprite_editor;
+mod sprite_viewer;
+mod storage;
+mod symbol_file;
+mod themes;
+mod touch_keypad;
+mod trace_log;
+mod turbo;
+mod video_recording;
+mod watchpoints;
 
-use chip8emulator::Chip8Emulator;
-use gloo::{events::EventListener, timers::callback::Interval};
-use js_sys::Uint8Array;
-use std::cell::RefCell;
+pub use api::debug_api;
+use audio::{AudioBackend, AudioConfig, Buzzer};
+use capabilities::Capability;
+use chip8emulator::{assemble, lint, Chip8Emulator, CostModel, Profile, Quirks, SaveRegion, State};
+use config::{Config, InitialRom, Mode};
+use gloo::{
+    events::{EventListener, EventListenerOptions},
+    timers::callback::Interval,
+};
+use haptics::Haptics;
+use js_sys::{global, Array, Reflect, Uint8Array};
+use keymap::{KeyMap, KEY_MAP, PLAYER_2_KEY_MAP, SECOND_KEY_MAP};
+use std::cell::{Cell, RefCell};
+use std::ops::Range;
 use std::rc::Rc;
+use std::thread::LocalKey;
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsCast;
+use wasm_bindgen::{Clamped, JsCast};
 use wasm_bindgen_futures::{spawn_local, JsFuture};
 use web_sys::{
-    window, CanvasRenderingContext2d, Element, HtmlCanvasElement, HtmlElement, HtmlInputElement,
-    HtmlSelectElement, KeyboardEvent, Performance, Response,
+    console, window, Blob, BlobPropertyBag, CanvasRenderingContext2d, DedicatedWorkerGlobalScope,
+    Element, File, HtmlAnchorElement, HtmlCanvasElement, HtmlElement, HtmlInputElement,
+    HtmlSelectElement, ImageData, KeyboardEvent, Performance, ResizeObserver, Response, Url,
 };
 
 // When the `wee_alloc` feature is enabled, this uses `wee_alloc` as the global
@@ -22,94 +89,657 @@ use web_sys::{
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 const ROMS_DIR: &str = "roms";
-const PIXEL_OFF_COLOR: &str = "#000000";
-const PIXEL_ON_COLOR: &str = "#00a86b";
+/// Splash screen shown until a ROM is selected, assembled from `src/boot.asm`
+/// by the build script.
+const BOOT_ROM: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/boot.ch8"));
+/// How often save RAM written by the ROM is flushed to local storage, in
+/// milliseconds.
+const SAVE_RAM_PERSIST_INTERVAL: u32 = 1000;
+/// Longest gap between animation frames, in milliseconds, that the emulator
+/// catches up on. Browsers stop sending frames to background tabs, and running
+/// the whole time away when the tab comes back would play the game unseen.
+const MAX_FRAME_GAP: f64 = 250.0;
+const QUIRK_CHECKBOX_IDS: [&str; 5] = [
+    "quirk-shift-uses-vy",
+    "quirk-load-store-increments-i",
+    "quirk-jump-uses-vx",
+    "quirk-logic-resets-vf",
+    "quirk-clip-sprites",
+];
 
 // This is like the `main` function, except for JavaScript.
 #[wasm_bindgen(start)]
 pub async fn main_js() {
+    // The module is also started in the emulator worker, which has no page
+    // to set up and waits for `workerMain` instead.
+    if window().is_none() {
+        return;
+    }
+
     // This provides better error messages in debug mode.
     // It's disabled in release mode so it doesn't bloat up the file size.
     #[cfg(debug_assertions)]
     console_error_panic_hook::set_once();
 
+    let config = Config::from_page();
+    SAVE_REGIONS.with(|regions| *regions.borrow_mut() = config.save_regions.clone());
+    AVAILABLE_KEYS.with(|keys| keys.set(config.available_keys));
+    memory_usage::set_limits(config.memory_limits);
+
     let chip8 = Rc::new(RefCell::new(Chip8Emulator::new(get_current_time())));
+    api::set_emulator(&chip8);
+    api::expose_on_window();
+
+    if let Some(profile) = &config.profile {
+        get_profile_select().set_value(profile);
+        set_quirks_panel(get_profile_from_panel().quirks);
+    }
+    opcode_reference::show_opcode_table(&get_profile_from_panel());
+
+    register_inputs(&chip8);
+    touch_keypad::register_touch_keypad(&chip8);
+    joystick::register_joystick(&chip8);
+    midi::register_midi(&chip8);
+    keymap::register_key_bindings();
+    keypad_view::register_keypad_view();
+    turbo::register_turbo_panel();
+
+    register_rom_select(&chip8);
+
+    register_rom_file(&chip8);
+
+    register_clock_speed_input(&chip8);
+
+    register_cpu_model_select(&chip8);
+
+    register_profile_select(&chip8);
+
+    register_quirks_panel(&chip8);
+
+    register_strict_mode(&chip8);
+
+    palette_picker::register_palette_panel(&chip8, config.scheme_palettes);
+    display_filter::register_display_filter_panel(&chip8);
+    phosphor::register_afterglow_input(&chip8);
+    perf_overlay::register_perf_overlay_checkbox();
+    latency::register_latency_checkbox();
+    debug_panel::register_debug_panel(&chip8);
+    hex_view::register_hex_view();
+    watchpoints::register_watchpoints(&chip8);
+    expressions::register_expressions(&chip8);
+    opcode_breaks::register_opcode_breaks(&chip8);
+    trace_log::register_trace(&chip8);
+    profiler::register_profiler(&chip8);
+    call_graph::register_call_graph(&chip8);
+    symbol_file::register_symbol_file(&chip8);
+    patching::register_patching(&chip8);
+    cheat_search::register_cheat_search(&chip8);
+    cheats::register_cheats(&chip8);
+    sprite_viewer::register_sprite_viewer(&chip8);
+    sprite_editor::register_sprite_editor(&chip8);
+    crash_report::register_crash_report(&chip8);
+    if let Some(manifest) = config.themes.clone() {
+        let chip8 = Rc::clone(&chip8);
+        spawn_local(async move { themes::load_themes(&chip8, &manifest).await });
+    }
+
+    fullscreen::register_fullscreen_button();
+
+    screenshot::register_screenshot_buttons(&chip8);
+    gif_recording::register_gif_recording(&chip8);
+
+    macros::register_macro_panel();
+
+    memory_usage::register_memory_panel(&chip8);
+
+    if config.debug_protocol {
+        rpc::register_post_message(&chip8, config.debug_origins.clone());
+    }
+    if let Some(url) = &config.debug_socket {
+        if let Err(e) = rpc::connect_websocket(&chip8, url) {
+            console::error_2(&format!("Can't connect to debugger at {}", url).into(), &e);
+        }
+    }
+
+    register_save_ram_persistence(&chip8);
+
+    let buzzer = Rc::new(RefCell::new(None));
+    audio::register_audio_panel(&buzzer, config.audio, &chip8);
+    audio_recording::register_audio_recording();
+    video_recording::register_video_recording(&buzzer);
+    capabilities::apply_capabilities();
+    match capabilities::capabilities().audio_backend(config.audio_backend) {
+        Some(backend) => create_buzzer(&buzzer, backend, config.audio),
+        None => console::warn_1(&"Web Audio isn't supported, so the buzzer is off".into()),
+    }
+
+    let haptics = Rc::new(RefCell::new(Haptics::new(config.vibrate)));
+    haptics::register_vibrate_checkbox(&haptics);
+
+    if !(config.worker && start_worker(Rc::clone(&buzzer), Rc::clone(&haptics))) {
+        start(&chip8, buzzer, haptics);
+    }
+
+    if config.mode == Mode::Emulator && !config.skip_boot {
+        load_rom_data(&chip8, BOOT_ROM).expect("The boot ROM doesn't fit in memory");
+    }
 
+    match (config.mode, config.initial_rom) {
+        (Mode::Gallery, _) => {
+            if let Err(e) = gallery::show_gallery(&chip8).await {
+                console::error_2(&"Can't show the ROM gallery".into(), &e);
+                show_rom_prompt();
+            }
+        }
+        (Mode::Emulator, InitialRom::Named(rom_name)) => {
+            if let Err(e) = load_rom(&chip8, &rom_name).await {
+                console::error_2(&format!("Can't load initial ROM {}", rom_name).into(), &e);
+                osd::show_error(&format!("Can't load {}", rom_name));
+                show_rom_prompt();
+            }
+        }
+        (Mode::Emulator, InitialRom::None) => show_rom_prompt(),
+    }
+}
+
+/// Moves the emulator to a worker, if the browser can draw from one. Returns
+/// whether it did; otherwise the page runs the emulator itself.
+fn start_worker(buzzer: Rc<RefCell<Option<Buzzer>>>, haptics: Rc<RefCell<Haptics>>) -> bool {
+    if !capabilities::capabilities().supports(Capability::OffscreenCanvas) {
+        console::warn_1(
+            &"OffscreenCanvas isn't supported, so the emulator runs on the page".into(),
+        );
+        return false;
+    }
+    match emulator_worker::start_worker(buzzer, haptics) {
+        Ok(()) => true,
+        Err(e) => {
+            console::error_2(&"Can't start the emulator worker".into(), &e);
+            false
+        }
+    }
+}
+
+fn start(
+    chip8: &Rc<RefCell<Chip8Emulator>>,
+    buzzer: Rc<RefCell<Option<Buzzer>>>,
+    haptics: Rc<RefCell<Haptics>>,
+) {
     set_canvas_size(
         chip8.borrow().get_gfx_width(),
         chip8.borrow().get_gfx_height(),
     );
+    if let Err(e) = observe_canvas_size() {
+        console::error_2(&"Can't watch the canvas size".into(), &e);
+    }
+    pause_menu::register_pause_menu(chip8);
 
-    load_rom(&chip8, "INVADERS").await;
+    let chip8 = Rc::clone(chip8);
+    let mut last_frame_time = get_current_time();
+    request_animation_frames(move |current_time| {
+        let mut chip8 = chip8.borrow_mut();
+        let frame_gap = current_time - last_frame_time;
+        skip_frame_gap(&mut chip8, last_frame_time, current_time);
+        last_frame_time = current_time;
 
-    register_inputs(&chip8);
+        macros::play_due(&mut chip8, current_time);
+        turbo::update(&mut chip8, current_time);
+        shortcuts::fast_forward(&mut chip8, frame_gap);
+        chip8.tick(current_time);
+        latency::observe(chip8.get_pressed_keys(), current_time);
+        let perf_changed =
+            perf_overlay::record_frame(current_time, chip8.get_instruction_count(), frame_gap);
 
-    register_rom_select(&chip8);
+        if let Some(buzzer) = buzzer.borrow_mut().as_mut() {
+            buzzer.update(chip8.get_sound_timer_state());
+        }
+        haptics.borrow_mut().update(chip8.get_sound_timer());
+        audio_recording::record_audio(&chip8, current_time);
+        gif_recording::record_gif(&chip8, current_time);
 
-    register_tps_select(&chip8);
+        let dirty_rows = chip8.take_gfx_dirty_rows();
+        if !dirty_rows.is_empty() || phosphor::is_fading() {
+            render(&chip8, &dirty_rows);
+        } else if osd::is_visible() || perf_changed {
+            present_frame();
+        }
+        let playing = chip8.is_sound_playing();
+        let pressed = chip8.get_pressed_keys();
+        render_sound_indicator(playing);
+        keypad_view::update(pressed);
+        crash_report::update(&chip8);
+        notify_halt(&chip8);
+        debug_panel::update(&chip8);
 
-    start(&chip8);
+        // The callbacks may call back into the debug API.
+        drop(chip8);
+        api::update_sound(playing);
+        api::update_keys(pressed);
+    });
 }
 
-fn start(chip8: &Rc<RefCell<Chip8Emulator>>) {
-    let chip8 = Rc::clone(&chip8);
-    Interval::new(1, move || {
-        let mut chip8 = chip8.borrow_mut();
+/// Skips the time between frames beyond `MAX_FRAME_GAP`.
+fn skip_frame_gap(chip8: &mut Chip8Emulator, last_frame_time: f64, current_time: f64) {
+    if current_time - last_frame_time > MAX_FRAME_GAP {
+        chip8.skip_time(current_time - MAX_FRAME_GAP);
+    }
+}
 
-        chip8.tick(get_current_time());
+/// Calls `f` before every repaint for as long as the page is open. The CPU
+/// catches up on the time since the last frame, so the emulator runs at its
+/// clock speed while only waking up once per frame.
+fn request_animation_frames(mut f: impl FnMut(f64) + 'static) {
+    let callback = Rc::new(RefCell::new(None::<Closure<dyn FnMut(f64)>>));
+    let next_callback = Rc::clone(&callback);
+    *callback.borrow_mut() = Some(Closure::new(move |_timestamp: f64| {
+        // The frame timestamp can be slightly behind `now()`, and timers and
+        // input use `now()`, so stick to it to keep time from going backwards.
+        f(get_current_time());
+        request_animation_frame(next_callback.borrow().as_ref().unwrap());
+    }));
+    request_animation_frame(callback.borrow().as_ref().unwrap());
+}
 
-        if chip8.gfx_needs_rerender() {
-            render(&chip8);
+fn request_animation_frame(callback: &Closure<dyn FnMut(f64)>) {
+    let callback = callback.as_ref().unchecked_ref();
+    match window() {
+        Some(window) => window.request_animation_frame(callback),
+        // The emulator worker gets frames too, since it draws to an
+        // OffscreenCanvas.
+        None => global()
+            .unchecked_into::<DedicatedWorkerGlobalScope>()
+            .request_animation_frame(callback),
+    }
+    .unwrap();
+}
+
+/// Creates the buzzer in the background, since loading an AudioWorklet is
+/// asynchronous, and stores it in `buzzer` once it's ready.
+fn create_buzzer(buzzer: &Rc<RefCell<Option<Buzzer>>>, backend: AudioBackend, config: AudioConfig) {
+    let buzzer = Rc::clone(buzzer);
+    spawn_local(async move {
+        match Buzzer::new(backend, config).await {
+            Ok(new_buzzer) => *buzzer.borrow_mut() = Some(new_buzzer),
+            Err(e) => console::error_2(&"Can't create the buzzer".into(), &e),
         }
-    })
-    .forget();
+    });
 }
 
-async fn load_rom(chip8: &Rc<RefCell<Chip8Emulator>>, rom_name: &str) {
+async fn load_rom(chip8: &Rc<RefCell<Chip8Emulator>>, rom_name: &str) -> Result<(), JsValue> {
     let path = format!("{}/{}", ROMS_DIR, rom_name);
+    let buffer = get_binary_file(&path).await?;
+
+    get_element_by_id("rom-name")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #rom-name is not a select element")
+        .set_value(rom_name);
 
-    let buffer = get_binary_file(&path)
-        .await
-        .expect(&format!("Can't load {}", path));
+    load_rom_data(chip8, &buffer)?;
+    osd::show(&format!("{} loaded", rom_name));
+    show_rom_info(&buffer, &chip8.borrow())?;
+    Ok(())
+}
 
+/// Starts `buffer` with the settings in the panels. If it can't be loaded,
+/// says why in the ROM prompt and leaves the emulator without a ROM.
+fn load_rom_data(chip8: &Rc<RefCell<Chip8Emulator>>, buffer: &[u8]) -> Result<(), String> {
+    persist_save_ram(&mut chip8.borrow_mut());
     chip8.borrow_mut().reset(get_current_time());
 
-    let tps_select = get_element_by_id("ticks-per-second")
-        .dyn_into::<HtmlInputElement>()
-        .expect("Element with id #ticks-per-second is not an input element");
+    let cpu_model = get_cpu_model(&get_cpu_model_select().value());
+    chip8.borrow_mut().set_cost_model((cpu_model.cost_model)());
+    chip8
+        .borrow_mut()
+        .set_clock_speed(get_clock_speed_input().value().parse().unwrap());
+    chip8.borrow_mut().set_quirks(get_quirks_from_panel());
     chip8
         .borrow_mut()
-        .set_ticks_per_second(tps_select.value().parse().unwrap());
+        .set_strict_mode(get_checkbox("strict-mode").checked());
+    debug_panel::apply_history_limit(&mut chip8.borrow_mut());
+    trace_log::apply_trace_limit(&mut chip8.borrow_mut());
+    profiler::apply_profiling(&mut chip8.borrow_mut());
+    apply_profile(&mut chip8.borrow_mut(), &get_profile_from_panel());
+    show_keypad_labels();
 
-    chip8.borrow_mut().load_rom(&buffer);
+    let rom_error = get_element_by_id("rom-error");
+    if let Err(e) = chip8.borrow_mut().load_rom(buffer) {
+        rom_error.set_text_content(Some(&e));
+        show_rom_prompt();
+        return Err(e);
+    }
+    rom_error.set_text_content(None);
+    ROM.with(|rom| *rom.borrow_mut() = buffer.to_vec());
+    symbol_file::apply_symbols(&mut chip8.borrow_mut());
+    restore_save_ram(&mut chip8.borrow_mut());
+    cheats::apply_rom_cheats(chip8);
+    emulator_worker::send_rom(buffer);
+    audio::apply_rom_tone(chip8.borrow().get_rom_checksum());
+    keymap::apply_rom_controls(chip8.borrow().get_rom_checksum());
+
+    get_element_by_id("rom-prompt")
+        .dyn_into::<HtmlElement>()
+        .expect("Element with id #rom-prompt is not a text element")
+        .set_hidden(true);
+    Ok(())
+}
+
+/// Starts the ROM loaded last over, with the settings in the panels.
+fn reload_rom(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    if let Err(e) = load_rom_data(chip8, &get_rom()) {
+        osd::show_error(&e);
+    }
+}
+
+/// Overwrites the ROM `chip8` loaded last, as kept to start over with, from
+/// where `address` is in memory, so edits last through resets and can be
+/// exported. Returns false without writing anything if `data` isn't all in
+/// the ROM.
+fn patch_rom(chip8: &Chip8Emulator, address: u16, data: &[u8]) -> bool {
+    let range = chip8.get_rom_range();
+    if address < range.start || address as usize + data.len() > range.end as usize {
+        return false;
+    }
+    let start = (address - range.start) as usize;
+    ROM.with(|rom| rom.borrow_mut()[start..start + data.len()].copy_from_slice(data));
+    true
+}
+
+/// Returns the ROM loaded last, with any patches.
+fn get_rom() -> Vec<u8> {
+    ROM.with(|rom| rom.borrow().clone())
+}
+
+/// Shows the "no ROM loaded" prompt and clears the ROM selection so the page
+/// can sit idle until the user picks a ROM or opens a file.
+fn show_rom_prompt() {
+    get_element_by_id("rom-name")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #rom-name is not a select element")
+        .set_value("");
+
+    get_element_by_id("rom-prompt")
+        .dyn_into::<HtmlElement>()
+        .expect("Element with id #rom-prompt is not a text element")
+        .set_hidden(false);
+}
+
+/// Restores the loaded ROM's save RAM from local storage, if the page enabled
+/// save regions.
+fn restore_save_ram(chip8: &mut Chip8Emulator) {
+    let regions = SAVE_REGIONS.with(|regions| regions.borrow().clone());
+    if regions.is_empty() {
+        return;
+    }
+
+    chip8.set_save_regions(regions);
+    if let Some(data) = storage::load_save_ram(chip8.get_rom_checksum()) {
+        chip8.load_save_ram(&data);
+    }
+}
+
+/// Writes the loaded ROM's save RAM to local storage if the ROM changed it.
+fn persist_save_ram(chip8: &mut Chip8Emulator) {
+    if chip8.save_ram_needs_persisting() {
+        if let Err(e) = storage::store_save_ram(chip8.get_rom_checksum(), &chip8.get_save_ram()) {
+            console::error_2(&"Can't persist save RAM".into(), &e);
+        }
+    }
+}
+
+/// Persists save RAM once a second, and when the page is closed.
+fn register_save_ram_persistence(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let interval_chip8 = Rc::clone(chip8);
+    Interval::new(SAVE_RAM_PERSIST_INTERVAL, move || {
+        persist_save_ram(&mut interval_chip8.borrow_mut())
+    })
+    .forget();
+
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&window().unwrap(), "pagehide", move |_| {
+        persist_save_ram(&mut chip8.borrow_mut())
+    })
+    .forget();
+}
+
+/// Lists the portability warnings for `rom` and the code `chip8` can't reach
+/// in it in the ROM info panel.
+fn show_rom_info(rom: &[u8], chip8: &Chip8Emulator) -> Result<(), JsValue> {
+    let document = window().unwrap().document().unwrap();
+    let list = get_element_by_id("rom-warnings");
+    list.set_text_content(None);
+
+    let warnings = lint(rom);
+    if warnings.is_empty() {
+        let item = document.create_element("li")?;
+        item.set_text_content(Some("No portability warnings"));
+        list.append_child(&item)?;
+    }
+
+    for warning in warnings {
+        let item = document.create_element("li")?;
+        item.set_text_content(Some(&warning.to_string()));
+        item.set_attribute("title", warning.quirk.description())?;
+        list.append_child(&item)?;
+    }
+
+    let list = get_element_by_id("rom-unreachable");
+    list.set_text_content(None);
+    for range in chip8.get_unreachable_code() {
+        let item = document.create_element("li")?;
+        item.set_text_content(Some(&format!(
+            "{:#05X}-{:#05X}: unreachable code or unused data",
+            range.start,
+            range.end - 1
+        )));
+        list.append_child(&item)?;
+    }
+
+    get_element_by_id("rom-info")
+        .dyn_into::<HtmlElement>()
+        .expect("Element with id #rom-info is not an HTML element")
+        .set_hidden(false);
+
+    Ok(())
 }
 
+/// Resizes the frame the display is drawn on, and the page's canvas to match.
 fn set_canvas_size(width: u32, height: u32) {
-    let canvas = get_context().canvas().unwrap();
-    canvas.set_width(width);
-    canvas.set_height(height);
+    FRAME_CANVAS.with(|frame| {
+        frame.set_width(width);
+        frame.set_height(height);
+    });
+    fit_canvas();
+}
+
+/// Sizes the page's canvas to a whole multiple of the frame that has at least
+/// as many pixels as the device pixels it's shown on, so the browser doesn't
+/// blur it when scaling it up on HiDPI screens, and redraws the frame on it.
+fn fit_canvas() {
+    let canvas = get_canvas();
+    let (frame_width, frame_height) = FRAME_CANVAS.with(|frame| (frame.width(), frame.height()));
+    let device_width = canvas.client_width() as f64 * window().unwrap().device_pixel_ratio();
+    let scale = canvas_scale(frame_width, device_width);
+
+    if (canvas.width(), canvas.height()) != (frame_width * scale, frame_height * scale) {
+        canvas.set_width(frame_width * scale);
+        canvas.set_height(frame_height * scale);
+    }
+    present_frame();
+}
+
+/// Fits the canvas again whenever it's shown at a different size, e.g. when
+/// the window is resized, the page goes fullscreen or the gallery is left.
+fn observe_canvas_size() -> Result<(), JsValue> {
+    let callback = Closure::<dyn FnMut()>::new(fit_canvas);
+    ResizeObserver::new(callback.as_ref().unchecked_ref())?.observe(&get_canvas());
+    callback.forget();
+    Ok(())
+}
+
+/// Returns the whole number a frame `frame_width` pixels wide is scaled up by
+/// to cover `device_width` device pixels, and at least 1.
+fn canvas_scale(frame_width: u32, device_width: f64) -> u32 {
+    (device_width / frame_width as f64).ceil().max(1.0) as u32
 }
 
-fn render(chip8: &Chip8Emulator) {
+/// Draws the frame scaled up onto the page's canvas, keeping pixels sharp,
+/// with the pause menu, the performance overlay and the on-screen display's
+/// messages over it.
+fn present_frame() {
+    let ctx = get_context();
+    let canvas = ctx.canvas().unwrap();
+    ctx.set_image_smoothing_enabled(false);
+    let result = FRAME_CANVAS.with(|frame| {
+        ctx.draw_image_with_html_canvas_element_and_dw_and_dh(
+            frame,
+            0.0,
+            0.0,
+            canvas.width() as f64,
+            canvas.height() as f64,
+        )
+    });
+    if let Err(e) = result {
+        console::error_2(&"Can't render the display".into(), &e);
+    }
+    pause_menu::draw(&ctx);
+    perf_overlay::draw(&ctx);
+    osd::draw(&ctx, get_current_time());
+}
+
+/// Highlights the display border and shows a speaker icon while the sound
+/// timer is active, for anyone who can't hear the buzzer.
+fn render_sound_indicator(playing: bool) {
+    if SOUND_INDICATOR_SHOWN.with(|shown| shown.replace(playing)) == playing {
+        return;
+    }
+
+    for id in ["canvas", "sound-indicator"] {
+        get_element_by_id(id)
+            .class_list()
+            .toggle_with_force("sound-active", playing)
+            .unwrap();
+    }
+}
+
+/// Says so on the display when the ROM ends.
+fn notify_halt(chip8: &Chip8Emulator) {
+    let halted = chip8.get_state() == State::Halted;
+    if HALTED.with(|shown| shown.replace(halted)) != halted && halted {
+        osd::show(&format!("Program ended at {:03X}", chip8.get_pc()));
+    }
+}
+
+/// Repaints the rows of the display in `dirty_rows` on the frame, and shows it
+/// on the page. The emulator reports every row as changed whenever the display
+/// changes size.
+fn render(chip8: &Chip8Emulator, dirty_rows: &[Range<u32>]) {
+    // Mega-Chip ROMs switch the display size while running.
+    let (width, height) =
+        display_filter::canvas_size(chip8.get_gfx_width(), chip8.get_gfx_height());
+    if FRAME_CANVAS.with(|frame| (frame.width(), frame.height())) != (width, height) {
+        set_canvas_size(width, height);
+        fullscreen::set_display_size(chip8.get_gfx_width(), chip8.get_gfx_height());
+    }
+
+    FRAME_CONTEXT.with(|ctx| {
+        draw_display(chip8, dirty_rows, |image, y| {
+            ctx.put_image_data(image, 0.0, y)
+        })
+    });
+    present_frame();
+}
+
+/// Draws the rows of the display in `dirty_rows` with `put_image`, which puts
+/// an image of some rows on a canvas at the given y position.
+fn draw_display<P>(chip8: &Chip8Emulator, dirty_rows: &[Range<u32>], put_image: P)
+where
+    P: Fn(&ImageData, f64) -> Result<(), JsValue>,
+{
     let width = chip8.get_gfx_width();
     let height = chip8.get_gfx_height();
+    render_image(width, height, dirty_rows, put_image, display_colors(chip8));
+}
 
-    let ctx = get_context();
-    ctx.begin_path();
+/// Returns the color of each pixel of the display, as it's drawn on the page.
+fn display_colors(chip8: &Chip8Emulator) -> impl Fn(u32, u32) -> [u8; 3] + '_ {
+    let indexed = chip8.is_gfx_indexed();
+    let color_zones = chip8.has_color_zones();
+    let background = chip8.get_gfx_background();
+    let palette = palette_picker::current_palette();
+    move |x, y| {
+        if indexed {
+            chip8.get_gfx_indexed_color(x, y)
+        } else if color_zones && chip8.get_gfx_pixel(x, y) {
+            chip8.get_gfx_foreground(x, y)
+        } else if color_zones {
+            background
+        } else if chip8.get_gfx_pixel(x, y) {
+            palette.pixel_on
+        } else {
+            palette.pixel_off
+        }
+    }
+}
 
-    ctx.set_fill_style(&PIXEL_OFF_COLOR.into());
-    ctx.fill_rect(0.0, 0.0, width as f64, height as f64);
+/// Writes the colors of the pixels in `rows` into an RGBA copy of the display
+/// and puts each run of rows on the canvas in one call, instead of filling a
+/// rect per pixel. The copy is kept between frames, so unchanged rows cost
+/// nothing. With the afterglow or a display filter on, the whole display is
+/// processed and put on the canvas at once instead.
+fn render_image<P, F>(width: u32, height: u32, rows: &[Range<u32>], put_image: P, color_at: F)
+where
+    P: Fn(&ImageData, f64) -> Result<(), JsValue>,
+    F: Fn(u32, u32) -> [u8; 3],
+{
+    FRAME.with(|frame| {
+        let mut frame = frame.borrow_mut();
+        frame.resize((width * height * 4) as usize, 0);
 
-    ctx.set_fill_style(&PIXEL_ON_COLOR.into());
-    for x in 0..width {
-        for y in 0..height {
-            if chip8.get_gfx_pixel(x, y) {
-                ctx.fill_rect(x as f64, y as f64, 1.0, 1.0);
+        for run in rows {
+            for y in run.clone() {
+                for x in 0..width {
+                    let [r, g, b] = color_at(x, y);
+                    let index = ((y * width + x) * 4) as usize;
+                    frame[index..index + 4].copy_from_slice(&[r, g, b, 0xff]);
+                }
             }
         }
-    }
 
-    ctx.stroke();
+        if phosphor::is_enabled() || display_filter::is_enabled() {
+            phosphor::with_afterglow(&frame, |frame| {
+                if display_filter::is_enabled() {
+                    display_filter::filter_frame(
+                        frame,
+                        width,
+                        height,
+                        |filtered, filtered_width| {
+                            put_rgba(filtered, filtered_width, 0, &put_image)
+                        },
+                    );
+                } else {
+                    put_rgba(frame, width, 0, &put_image);
+                }
+            });
+            return;
+        }
+        for run in rows {
+            let bytes = &frame[(run.start * width * 4) as usize..(run.end * width * 4) as usize];
+            put_rgba(bytes, width, run.start, &put_image);
+        }
+    });
+}
+
+/// Puts RGBA pixels `width` wide on the canvas at the given y position.
+fn put_rgba<P>(bytes: &[u8], width: u32, y: u32, put_image: &P)
+where
+    P: Fn(&ImageData, f64) -> Result<(), JsValue>,
+{
+    let height = bytes.len() as u32 / (width * 4);
+    let result = ImageData::new_with_u8_clamped_array_and_sh(Clamped(bytes), width, height)
+        .and_then(|image| put_image(&image, y as f64));
+    if let Err(e) = result {
+        console::error_2(&"Can't render the display".into(), &e);
+    }
 }
 
 async fn get_binary_file(path: &str) -> Result<Vec<u8>, JsValue> {
@@ -125,122 +755,461 @@ async fn get_binary_file(path: &str) -> Result<Vec<u8>, JsValue> {
     Ok(Uint8Array::new(&buffer).to_vec())
 }
 
+async fn get_json_file(path: &str) -> Result<JsValue, JsValue> {
+    let window = web_sys::window().unwrap();
+    let resp_value = JsFuture::from(window.fetch_with_str(path)).await?;
+    let resp: Response = resp_value.dyn_into().unwrap();
+
+    if !resp.ok() {
+        return Err(JsValue::NULL);
+    }
+
+    JsFuture::from(resp.json()?).await
+}
+
+/// Makes the browser save `data` as a file named `name`.
+fn download_file(name: &str, data: &[u8], mime_type: &str) {
+    let parts = Array::of1(&Uint8Array::from(data));
+    let options = BlobPropertyBag::new();
+    options.set_type(mime_type);
+    match Blob::new_with_u8_array_sequence_and_options(&parts, &options) {
+        Ok(blob) => download_blob(name, &blob),
+        Err(e) => console::error_2(&format!("Can't download {}", name).into(), &e),
+    }
+}
+
+fn download_blob(name: &str, blob: &Blob) {
+    let result = (|| -> Result<(), JsValue> {
+        let url = Url::create_object_url_with_blob(blob)?;
+
+        let link = web_sys::window()
+            .unwrap()
+            .document()
+            .unwrap()
+            .create_element("a")?
+            .dyn_into::<HtmlAnchorElement>()
+            .unwrap();
+        link.set_href(&url);
+        link.set_download(name);
+        link.click();
+
+        Url::revoke_object_url(&url)
+    })();
+
+    if let Err(e) = result {
+        console::error_2(&format!("Can't download {}", name).into(), &e);
+    }
+}
+
 fn register_rom_select(chip8: &Rc<RefCell<Chip8Emulator>>) {
     let rom_name_select = get_element_by_id("rom-name")
         .dyn_into::<HtmlSelectElement>()
         .expect("Element with id #rom-name is not a select element");
 
-    let chip8 = Rc::clone(&chip8);
+    let chip8 = Rc::clone(chip8);
     EventListener::new(&rom_name_select, "change", move |e| {
         let e = e.clone();
         let chip8 = Rc::clone(&chip8);
         spawn_local(async move {
             let e = e.target().unwrap();
             e.dyn_ref::<HtmlElement>().unwrap().blur().unwrap();
-            load_rom(&chip8, &e.dyn_into::<HtmlSelectElement>().unwrap().value()).await;
+            let rom_name = e.dyn_into::<HtmlSelectElement>().unwrap().value();
+            if let Err(e) = load_rom(&chip8, &rom_name).await {
+                console::error_2(&format!("Can't load ROM {}", rom_name).into(), &e);
+                osd::show_error(&format!("Can't load {}", rom_name));
+            }
         });
     })
     .forget();
 }
 
-fn register_tps_select(chip8: &Rc<RefCell<Chip8Emulator>>) {
-    let tps_select = get_element_by_id("ticks-per-second")
+fn register_rom_file(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let rom_file_input = get_element_by_id("rom-file")
         .dyn_into::<HtmlInputElement>()
-        .expect("Element with id #ticks-per-second is not an input element");
+        .expect("Element with id #rom-file is not an input element");
+
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&rom_file_input, "change", move |e| {
+        let input = e.target().unwrap().dyn_into::<HtmlInputElement>().unwrap();
+        input.blur().unwrap();
+
+        let file = match input.files().and_then(|files| files.get(0)) {
+            Some(file) => file,
+            None => return,
+        };
+
+        let chip8 = Rc::clone(&chip8);
+        spawn_local(async move {
+            match read_rom_file(&file).await {
+                Ok(buffer) => {
+                    show_rom_prompt();
+                    if let Err(e) = load_rom_data(&chip8, &buffer) {
+                        console::error_1(&format!("Can't load {}: {}", file.name(), e).into());
+                        osd::show_error(&format!("Can't load {}", file.name()));
+                        return;
+                    }
+                    osd::show(&format!("{} loaded", file.name()));
+                    if let Err(e) = show_rom_info(&buffer, &chip8.borrow()) {
+                        console::error_2(&"Can't show ROM info".into(), &e);
+                    }
+                }
+                Err(e) => {
+                    console::error_2(&format!("Can't load {}", file.name()).into(), &e);
+                    osd::show_error(&format!("Can't load {}", file.name()));
+                }
+            }
+        });
+    })
+    .forget();
+}
+
+/// Reads a ROM image from a local file. Files ending in `.asm` are treated as
+/// assembly source and assembled first.
+async fn read_rom_file(file: &File) -> Result<Vec<u8>, JsValue> {
+    if file.name().to_ascii_lowercase().ends_with(".asm") {
+        let source = JsFuture::from(file.text()).await?.as_string().unwrap();
+        assemble(&source).map_err(|e| e.to_string().into())
+    } else {
+        let buffer = JsFuture::from(file.array_buffer()).await?;
+        Ok(Uint8Array::new(&buffer).to_vec())
+    }
+}
+
+fn register_clock_speed_input(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let clock_speed_input = get_clock_speed_input();
 
-    let chip8 = Rc::clone(&chip8);
-    EventListener::new(&tps_select, "input", move |e| {
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&clock_speed_input, "input", move |e| {
         let e = e.target().unwrap();
         e.dyn_ref::<HtmlElement>().unwrap().blur().unwrap();
-        let new_tps = e
+        let new_clock_speed = e
             .dyn_into::<HtmlInputElement>()
             .unwrap()
             .value()
             .parse()
             .unwrap();
 
-        chip8.borrow_mut().set_ticks_per_second(new_tps);
+        chip8.borrow_mut().set_clock_speed(new_clock_speed);
+        set_clock_speed_text(new_clock_speed);
+    })
+    .forget();
+}
+
+/// A choice in the CPU model select: how instructions are costed, and the
+/// range and default of the clock speed slider in Hz for that model.
+struct CpuModel {
+    cost_model: fn() -> CostModel,
+    min_clock_speed: f64,
+    max_clock_speed: f64,
+    default_clock_speed: f64,
+}
+
+fn get_cpu_model(name: &str) -> CpuModel {
+    match name {
+        "cosmac-vip" => CpuModel {
+            cost_model: CostModel::cosmac_vip,
+            min_clock_speed: 100_000.0,
+            max_clock_speed: 4_000_000.0,
+            default_clock_speed: 1_760_900.0,
+        },
+        _ => CpuModel {
+            cost_model: CostModel::uniform,
+            min_clock_speed: 1.0,
+            max_clock_speed: 3000.0,
+            default_clock_speed: 800.0,
+        },
+    }
+}
+
+fn register_cpu_model_select(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let cpu_model_select = get_cpu_model_select();
+
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&cpu_model_select, "change", move |e| {
+        let select = e.target().unwrap().dyn_into::<HtmlSelectElement>().unwrap();
+        select.blur().unwrap();
+        let cpu_model = get_cpu_model(&select.value());
+
+        let clock_speed_input = get_clock_speed_input();
+        clock_speed_input.set_min(&cpu_model.min_clock_speed.to_string());
+        clock_speed_input.set_max(&cpu_model.max_clock_speed.to_string());
+        clock_speed_input.set_value(&cpu_model.default_clock_speed.to_string());
+
+        let mut chip8 = chip8.borrow_mut();
+        chip8.set_cost_model((cpu_model.cost_model)());
+        chip8.set_clock_speed(cpu_model.default_clock_speed);
+        set_clock_speed_text(cpu_model.default_clock_speed);
+    })
+    .forget();
+}
+
+fn get_clock_speed_input() -> HtmlInputElement {
+    get_element_by_id("clock-speed")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #clock-speed is not an input element")
+}
+
+fn get_cpu_model_select() -> HtmlSelectElement {
+    get_element_by_id("cpu-model")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #cpu-model is not a select element")
+}
 
-        get_element_by_id("ticks-per-second-text")
-            .dyn_into::<HtmlElement>()
-            .expect("Element with id #ticks-per-second is not a text element")
-            .set_inner_text(&new_tps.to_string());
+fn set_clock_speed_text(clock_speed: f64) {
+    get_element_by_id("clock-speed-text")
+        .dyn_into::<HtmlElement>()
+        .expect("Element with id #clock-speed-text is not a text element")
+        .set_inner_text(&clock_speed.to_string());
+}
+
+fn register_profile_select(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&get_profile_select(), "change", move |_| {
+        get_profile_select().blur().unwrap();
+        let profile = get_profile_from_panel();
+        set_quirks_panel(profile.quirks);
+        opcode_reference::show_opcode_table(&profile);
+
+        let mut chip8 = chip8.borrow_mut();
+        chip8.set_quirks(profile.quirks);
+        apply_profile(&mut chip8, &profile);
+        show_keypad_labels();
     })
     .forget();
 }
 
+/// Applies the parts of `profile` that aren't in the quirks panel.
+fn apply_profile(chip8: &mut Chip8Emulator, profile: &Profile) {
+    chip8.set_font(&profile.font);
+    chip8.set_extension(profile.extension);
+    chip8.set_force_hires(profile.hires);
+    chip8.set_available_keys(profile.available_keys & AVAILABLE_KEYS.with(Cell::get));
+    KEYPAD_LAYOUT.with(|layout| layout.set(profile.keypad_layout));
+}
+
+/// Shows the CHIP-8 keys of the current keypad layout on the touch keypad and
+/// in the Controls panel.
+fn show_keypad_labels() {
+    touch_keypad::set_labels();
+    keymap::show_bindings();
+}
+
+fn get_profile_from_panel() -> Profile {
+    Profile::parse(&get_profile_select().value()).unwrap_or_else(Profile::chip8)
+}
+
+fn get_profile_select() -> HtmlSelectElement {
+    get_element_by_id("profile")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #profile is not a select element")
+}
+
+fn register_quirks_panel(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    for id in QUIRK_CHECKBOX_IDS.iter() {
+        let checkbox = get_checkbox(id);
+
+        let chip8 = Rc::clone(chip8);
+        EventListener::new(&checkbox, "change", move |e| {
+            let e = e.target().unwrap();
+            e.dyn_ref::<HtmlElement>().unwrap().blur().unwrap();
+            chip8.borrow_mut().set_quirks(get_quirks_from_panel());
+        })
+        .forget();
+    }
+}
+
+fn register_strict_mode(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let checkbox = get_checkbox("strict-mode");
+
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&checkbox, "change", move |e| {
+        let checkbox = e.target().unwrap().dyn_into::<HtmlInputElement>().unwrap();
+        checkbox.blur().unwrap();
+        chip8.borrow_mut().set_strict_mode(checkbox.checked());
+    })
+    .forget();
+}
+
+fn set_quirks_panel(quirks: Quirks) {
+    let checked = [
+        quirks.shift_uses_vy,
+        quirks.load_store_increments_i,
+        quirks.jump_uses_vx,
+        quirks.logic_resets_vf,
+        quirks.clip_sprites,
+    ];
+    for (id, checked) in QUIRK_CHECKBOX_IDS.iter().zip(checked) {
+        get_checkbox(id).set_checked(checked);
+    }
+}
+
+fn get_quirks_from_panel() -> Quirks {
+    let [shift_uses_vy, load_store_increments_i, jump_uses_vx, logic_resets_vf, clip_sprites] =
+        QUIRK_CHECKBOX_IDS.map(|id| get_checkbox(id).checked());
+
+    Quirks {
+        shift_uses_vy,
+        load_store_increments_i,
+        jump_uses_vx,
+        logic_resets_vf,
+        clip_sprites,
+    }
+}
+
+fn get_checkbox(id: &str) -> HtmlInputElement {
+    get_element_by_id(id)
+        .dyn_into::<HtmlInputElement>()
+        .unwrap_or_else(|_| panic!("Element with id #{} is not an input element", id))
+}
+
 fn register_inputs(chip8: &Rc<RefCell<Chip8Emulator>>) {
-    add_input_event(chip8, "keydown", |chip8, key| {
-        chip8.borrow_mut().keydown(key);
+    shortcuts::register_shortcuts(chip8);
+
+    // Both players' key sets press the same keypad.
+    for key_map in [&KEY_MAP, &PLAYER_2_KEY_MAP] {
+        add_input_event(chip8, "keydown", key_map, |chip8, key, e| {
+            if !e.repeat() {
+                latency::key_down(key, e.time_stamp());
+            }
+            press_key(chip8, key, true)
+        });
+
+        add_input_event(chip8, "keyup", key_map, |chip8, key, _| {
+            press_key(chip8, key, false)
+        });
+    }
+
+    add_input_event(chip8, "keydown", &SECOND_KEY_MAP, |chip8, key, _| {
+        chip8.borrow_mut().queue_second_key(key, true);
+        emulator_worker::send_key(key, true, true);
     });
 
-    add_input_event(chip8, "keyup", |chip8, key| {
-        chip8.borrow_mut().keyup(key);
+    add_input_event(chip8, "keyup", &SECOND_KEY_MAP, |chip8, key, _| {
+        chip8.borrow_mut().queue_second_key(key, false);
+        emulator_worker::send_key(key, false, true);
     });
 }
 
-fn add_input_event<F>(chip8: &Rc<RefCell<Chip8Emulator>>, event: &'static str, f: F)
-where
-    F: Fn(&Rc<RefCell<Chip8Emulator>>, u8) + 'static,
+/// Presses or releases `key` of the keypad, as the keyboard or the touch
+/// keypad does.
+fn press_key(chip8: &Rc<RefCell<Chip8Emulator>>, key: u8, pressed: bool) {
+    macros::record_key(key, pressed);
+    turbo::hold(key, pressed, get_current_time());
+    chip8.borrow_mut().queue_key(key, pressed);
+    emulator_worker::send_key(key, pressed, false);
+}
+
+fn add_input_event<F>(
+    chip8: &Rc<RefCell<Chip8Emulator>>,
+    event: &'static str,
+    key_map: &'static LocalKey<RefCell<KeyMap>>,
+    f: F,
+) where
+    F: Fn(&Rc<RefCell<Chip8Emulator>>, u8, &KeyboardEvent) + 'static,
 {
-    let chip8 = Rc::clone(&chip8);
+    let chip8 = Rc::clone(chip8);
 
-    EventListener::new(&web_sys::window().unwrap(), event, move |e| {
+    // Not passive, so bound keys don't scroll the page or open quick find.
+    let options = EventListenerOptions::enable_prevent_default();
+    EventListener::new_with_options(&web_sys::window().unwrap(), event, options, move |e| {
         let e: KeyboardEvent = e.clone().dyn_into().unwrap();
-        if let Some(key) = jskey_to_chip8key(&e.key()) {
-            f(&chip8, key);
+        if is_typing(&e) {
+            return;
+        }
+        if let Some(key) = key_map.with(|key_map| jscode_to_chip8key(&key_map.borrow(), &e.code()))
+        {
+            // With a modifier, keys do what they usually do too, e.g. Ctrl+R
+            // reloading.
+            if !(e.ctrl_key() || e.meta_key() || e.alt_key()) {
+                e.prevent_default();
+            }
+            f(&chip8, key, &e);
         }
     })
     .forget();
 }
 
+/// Returns whether `e` is typing into a text field, and so isn't meant for
+/// the keypad or shortcuts.
+fn is_typing(e: &KeyboardEvent) -> bool {
+    e.target()
+        .and_then(|target| target.dyn_into::<HtmlInputElement>().ok())
+        .is_some_and(|input| ["text", "number", "search"].contains(&input.type_().as_str()))
+}
+
 fn get_element_by_id(id: &str) -> Element {
     window()
         .unwrap()
         .document()
         .unwrap()
         .get_element_by_id(id)
-        .expect(&format!("No element with id {}", id))
-}
-
-fn jskey_to_chip8key(key: &str) -> Option<u8> {
-    match key {
-        "1" => Some(1),
-        "2" => Some(2),
-        "3" => Some(3),
-        "4" => Some(0xC),
-        "q" => Some(4),
-        "w" => Some(5),
-        "e" => Some(6),
-        "r" => Some(0xD),
-        "a" => Some(7),
-        "s" => Some(8),
-        "d" => Some(9),
-        "f" => Some(0xE),
-        "z" => Some(0xA),
-        "x" => Some(0),
-        "c" => Some(0xB),
-        "v" => Some(0xF),
-        _ => None,
-    }
+        .unwrap_or_else(|| panic!("No element with id {}", id))
+}
+
+/// Maps the physical key on the keyboard with the `KeyboardEvent.code` `code`
+/// to the CHIP-8 key at the same position of the keypad, with the keys bound
+/// in `key_map` standing in for the keypad. Codes don't depend on the
+/// keyboard layout or Caps Lock.
+fn jscode_to_chip8key(key_map: &KeyMap, code: &str) -> Option<u8> {
+    let position = key_map.position(code)?;
+    Some(KEYPAD_LAYOUT.with(Cell::get)[position])
 }
 
 thread_local! {
+    static SAVE_REGIONS: RefCell<Vec<SaveRegion>> = const { RefCell::new(Vec::new()) };
+
+    /// Keys the emulated machine has, one bit per key.
+    static AVAILABLE_KEYS: Cell<u16> = const { Cell::new(u16::MAX) };
+
+    /// The CHIP-8 key at each position of the keypad.
+    static KEYPAD_LAYOUT: Cell<[u8; 16]> = Cell::new(Profile::chip8().keypad_layout);
+
+    static SOUND_INDICATOR_SHOWN: Cell<bool> = const { Cell::new(false) };
+
+    /// Whether the ROM had ended as of the last frame.
+    static HALTED: Cell<bool> = const { Cell::new(false) };
+
+    /// The ROM loaded last, to start over with.
+    static ROM: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+
+    /// RGBA pixels of the last rendered frame.
+    static FRAME: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+
+    /// The page's or the emulator worker's clock.
     static PERFORMANCE: Performance =
-        window().unwrap().performance().unwrap();
+        Reflect::get(&global(), &"performance".into()).unwrap().unchecked_into();
 
     static CONTEXT: CanvasRenderingContext2d =
-        get_element_by_id("canvas")
-            .dyn_into::<HtmlCanvasElement>()
-            .expect("Element with id #canvas is not a canvas")
-            .get_context("2d").unwrap().unwrap()
+        get_canvas().get_context("2d").unwrap().unwrap()
+            .dyn_into::<CanvasRenderingContext2d>().unwrap();
+
+    /// Canvas the display is drawn on at its own size, or at the size of the
+    /// display filters' output, before it's scaled up onto the page's canvas.
+    static FRAME_CANVAS: HtmlCanvasElement =
+        window().unwrap().document().unwrap().create_element("canvas").unwrap()
+            .dyn_into::<HtmlCanvasElement>().unwrap();
+
+    static FRAME_CONTEXT: CanvasRenderingContext2d =
+        FRAME_CANVAS.with(|frame| frame.get_context("2d")).unwrap().unwrap()
             .dyn_into::<CanvasRenderingContext2d>().unwrap();
 }
 
+fn get_canvas() -> HtmlCanvasElement {
+    get_element_by_id("canvas")
+        .dyn_into::<HtmlCanvasElement>()
+        .expect("Element with id #canvas is not a canvas")
+}
+
 fn get_current_time() -> f64 {
     PERFORMANCE.with(|p| p.now())
 }
 
+/// Returns when the clock of `get_current_time` started, in milliseconds
+/// since the epoch.
+fn get_time_origin() -> f64 {
+    PERFORMANCE.with(|p| p.time_origin())
+}
+
 fn get_context() -> CanvasRenderingContext2d {
     CONTEXT.with(|c| c.clone())
 }
@@ -0,0 +1,451 @@
+use super::{get_current_time, get_element_by_id, is_typing, storage};
+use crate::chip8emulator::Chip8Emulator;
+use gloo::events::{EventListener, EventListenerOptions};
+use std::cell::RefCell;
+use std::fmt;
+use std::mem::size_of;
+use wasm_bindgen::JsCast;
+use web_sys::{console, window, HtmlButtonElement, HtmlSelectElement, KeyboardEvent};
+
+/// Keys that replay the macro of the slot with the same index, until other
+/// keys are bound to them, as `KeyboardEvent.code` names them.
+const MACRO_HOTKEYS: [&str; 4] = ["F1", "F2", "F3", "F4"];
+
+/// A CHIP-8 key being pressed or released, `time` milliseconds after the
+/// start of the macro.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyEvent {
+    pub time: f64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// Records key events as they happen, relative to the first one.
+#[derive(Default)]
+pub struct Recorder {
+    start: Option<f64>,
+    events: Vec<KeyEvent>,
+    /// Keys currently held, one bit per key.
+    held: u16,
+}
+
+impl Recorder {
+    pub fn record(&mut self, time: f64, key: u8, pressed: bool) {
+        let mask = 1 << key;
+        // Ignore key repeat and releases of keys pressed before recording.
+        if pressed == (self.held & mask != 0) {
+            return;
+        }
+        self.held ^= mask;
+
+        let start = *self.start.get_or_insert(time);
+        self.events.push(KeyEvent {
+            time: time - start,
+            key,
+            pressed,
+        });
+    }
+
+    /// Ends the recording at `time`. Keys still held are released then, so
+    /// replaying the macro never leaves a key stuck down.
+    pub fn finish(mut self, time: f64) -> Vec<KeyEvent> {
+        for key in 0..16 {
+            if self.held & 1 << key != 0 {
+                self.record(time, key, false);
+            }
+        }
+        self.events
+    }
+}
+
+/// Replays recorded events starting at a given time.
+pub struct Player {
+    start: f64,
+    events: Vec<KeyEvent>,
+    next: usize,
+}
+
+impl Player {
+    pub fn new(start: f64, events: Vec<KeyEvent>) -> Player {
+        Player {
+            start,
+            events,
+            next: 0,
+        }
+    }
+
+    /// Returns the events due by `time` that haven't been returned yet.
+    pub fn poll(&mut self, time: f64) -> &[KeyEvent] {
+        let first = self.next;
+        while self
+            .events
+            .get(self.next)
+            .is_some_and(|event| self.start + event.time <= time)
+        {
+            self.next += 1;
+        }
+        &self.events[first..self.next]
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next == self.events.len()
+    }
+}
+
+/// A recorded macro and the key that replays it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MacroSlot {
+    pub hotkey: String,
+    pub events: Option<Vec<KeyEvent>>,
+}
+
+/// The macro slots, as kept in local storage.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MacroSlots(pub [MacroSlot; MACRO_HOTKEYS.len()]);
+
+impl MacroSlots {
+    /// Parses slots written as by the `Display` impl: a line per slot with the
+    /// hotkey, `=`, and the events as `time:key:pressed` separated by spaces,
+    /// e.g. `F1=0:5:1 50:5:0`.
+    pub fn parse(value: &str) -> Option<MacroSlots> {
+        let mut slots = MacroSlots::default();
+        let mut lines = value.lines();
+        for slot in slots.0.iter_mut() {
+            let (hotkey, events) = lines.next()?.split_once('=')?;
+            slot.hotkey = hotkey.to_string();
+            if !events.is_empty() {
+                slot.events = Some(
+                    events
+                        .split(' ')
+                        .map(parse_key_event)
+                        .collect::<Option<_>>()?,
+                );
+            }
+        }
+        Some(slots)
+    }
+}
+
+fn parse_key_event(value: &str) -> Option<KeyEvent> {
+    let mut fields = value.split(':');
+    let event = KeyEvent {
+        time: fields.next()?.parse().ok()?,
+        key: u8::from_str_radix(fields.next()?, 16)
+            .ok()
+            .filter(|&key| key <= 0xf)?,
+        pressed: fields.next()? == "1",
+    };
+    Some(event).filter(|_| fields.next().is_none())
+}
+
+impl fmt::Display for MacroSlots {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for slot in &self.0 {
+            let events: Vec<String> = slot
+                .events
+                .iter()
+                .flatten()
+                .map(|event| format!("{}:{:x}:{}", event.time, event.key, event.pressed as u8))
+                .collect();
+            writeln!(f, "{}={}", slot.hotkey, events.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for MacroSlots {
+    fn default() -> MacroSlots {
+        MacroSlots(MACRO_HOTKEYS.map(|hotkey| MacroSlot {
+            hotkey: hotkey.to_string(),
+            events: None,
+        }))
+    }
+}
+
+#[derive(Default)]
+struct Macros {
+    /// The slot being recorded into, and its recorder.
+    recording: Option<(usize, Recorder)>,
+    /// The slot waiting for a key to be bound to it.
+    binding: Option<usize>,
+    slots: MacroSlots,
+    playing: Vec<Player>,
+}
+
+thread_local! {
+    static MACROS: RefCell<Macros> = RefCell::new(Macros::default());
+}
+
+/// Adds a key event to the macro being recorded, if any.
+pub fn record_key(key: u8, pressed: bool) {
+    MACROS.with(|macros| {
+        if let Some((_, recorder)) = &mut macros.borrow_mut().recording {
+            recorder.record(get_current_time(), key, pressed);
+        }
+    });
+}
+
+/// How many bytes the recorded macros and the one being recorded use.
+pub fn macro_bytes() -> usize {
+    MACROS.with(|macros| {
+        let macros = macros.borrow();
+        let recording = macros
+            .recording
+            .as_ref()
+            .map_or(0, |(_, recorder)| recorder.events.len());
+        let recorded: usize = macros
+            .slots
+            .0
+            .iter()
+            .flat_map(|slot| &slot.events)
+            .map(Vec::len)
+            .sum();
+        (recording + recorded) * size_of::<KeyEvent>()
+    })
+}
+
+/// Sends the key events of macros being replayed that are due by
+/// `current_time` to the emulator, queued like presses of real keys.
+pub fn play_due(chip8: &mut Chip8Emulator, current_time: f64) {
+    MACROS.with(|macros| {
+        let playing = &mut macros.borrow_mut().playing;
+        for player in playing.iter_mut() {
+            for event in player.poll(current_time) {
+                chip8.queue_key(event.key, event.pressed);
+            }
+        }
+        playing.retain(|player| !player.is_done());
+    });
+}
+
+/// Wires up the macro panel's buttons and the hotkeys that replay recorded
+/// macros, restoring the macros and hotkeys stored in local storage.
+pub fn register_macro_panel() {
+    if let Some(slots) = storage::load_macros() {
+        MACROS.with(|macros| macros.borrow_mut().slots = slots);
+    }
+    show_slots();
+
+    let button = get_record_button();
+    EventListener::new(&button, "click", move |_| {
+        let button = get_record_button();
+        button.blur().unwrap();
+        toggle_recording(&button);
+    })
+    .forget();
+
+    EventListener::new(&get_element_by_id("macro-play"), "click", |_| {
+        play_slot(selected_slot());
+    })
+    .forget();
+
+    EventListener::new(&get_element_by_id("macro-bind"), "click", |_| {
+        let slot = selected_slot();
+        MACROS.with(|macros| macros.borrow_mut().binding = Some(slot));
+        set_status(&format!("Press a key to replay macro {} with", slot + 1));
+    })
+    .forget();
+
+    // Runs before the keypad's listeners, so the key bound doesn't also
+    // press a CHIP-8 key.
+    let options = EventListenerOptions::run_in_capture_phase();
+    EventListener::new_with_options(&window().unwrap(), "keydown", options, |e| {
+        let slot = match MACROS.with(|macros| macros.borrow_mut().binding.take()) {
+            Some(slot) => slot,
+            None => return,
+        };
+        let e: KeyboardEvent = e.clone().dyn_into().unwrap();
+        e.prevent_default();
+        e.stop_propagation();
+        // Escape gives up on binding a key.
+        if e.code() == "Escape" {
+            return set_status("Record a key sequence, then press its hotkey to replay it");
+        }
+        MACROS.with(|macros| macros.borrow_mut().slots.0[slot].hotkey = e.code());
+        set_status(&format!("{} replays macro {}", e.code(), slot + 1));
+        show_slots();
+        store_macros();
+    })
+    .forget();
+
+    // Hotkeys like F1 have browser defaults, so the listener can't be passive.
+    let options = EventListenerOptions::enable_prevent_default();
+    EventListener::new_with_options(&window().unwrap(), "keydown", options, |e| {
+        let e: KeyboardEvent = e.clone().dyn_into().unwrap();
+        if is_typing(&e) {
+            return;
+        }
+        let slot = MACROS.with(|macros| {
+            let macros = macros.borrow();
+            macros
+                .slots
+                .0
+                .iter()
+                .position(|slot| slot.hotkey == e.code())
+        });
+        if let Some(slot) = slot {
+            e.prevent_default();
+            if !e.repeat() {
+                play_slot(slot);
+            }
+        }
+    })
+    .forget();
+}
+
+fn toggle_recording(button: &HtmlButtonElement) {
+    MACROS.with(|macros| {
+        let mut macros = macros.borrow_mut();
+        match macros.recording.take() {
+            Some((slot, recorder)) => {
+                let events = recorder.finish(get_current_time());
+                let slot = &mut macros.slots.0[slot];
+                set_status(&format!(
+                    "{} replays {} key events",
+                    slot.hotkey,
+                    events.len()
+                ));
+                slot.events = Some(events);
+                button.set_text_content(Some("Record"));
+            }
+            None => {
+                let slot = selected_slot();
+                macros.recording = Some((slot, Recorder::default()));
+                set_status(&format!(
+                    "Recording macro for {}",
+                    macros.slots.0[slot].hotkey
+                ));
+                button.set_text_content(Some("Stop"));
+            }
+        }
+    });
+    store_macros();
+}
+
+fn play_slot(slot: usize) {
+    MACROS.with(|macros| {
+        let mut macros = macros.borrow_mut();
+        if let Some(events) = macros.slots.0[slot].events.clone() {
+            macros.playing.push(Player::new(get_current_time(), events));
+        }
+    });
+}
+
+/// Shows each slot's hotkey in the slot select.
+fn show_slots() {
+    let options = get_slot_select().children();
+    MACROS.with(|macros| {
+        for (index, slot) in macros.borrow().slots.0.iter().enumerate() {
+            if let Some(option) = options.item(index as u32) {
+                option.set_text_content(Some(&format!("{} ({})", index + 1, slot.hotkey)));
+            }
+        }
+    });
+}
+
+fn store_macros() {
+    let stored = MACROS.with(|macros| storage::store_macros(&macros.borrow().slots));
+    if let Err(e) = stored {
+        console::error_2(&"Can't store the macros".into(), &e);
+    }
+}
+
+fn selected_slot() -> usize {
+    get_slot_select().selected_index().max(0) as usize
+}
+
+fn set_status(status: &str) {
+    get_element_by_id("macro-status").set_text_content(Some(status));
+}
+
+fn get_record_button() -> HtmlButtonElement {
+    get_element_by_id("macro-record")
+        .dyn_into::<HtmlButtonElement>()
+        .expect("Element with id #macro-record is not a button")
+}
+
+fn get_slot_select() -> HtmlSelectElement {
+    get_element_by_id("macro-slot")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #macro-slot is not a select element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recorder() {
+        let mut recorder = Recorder::default();
+        recorder.record(100.0, 5, false);
+        recorder.record(100.0, 5, true);
+        recorder.record(130.0, 5, true);
+        recorder.record(150.0, 5, false);
+        recorder.record(200.0, 6, true);
+
+        assert_eq!(
+            recorder.finish(250.0),
+            vec![
+                KeyEvent {
+                    time: 0.0,
+                    key: 5,
+                    pressed: true
+                },
+                KeyEvent {
+                    time: 50.0,
+                    key: 5,
+                    pressed: false
+                },
+                KeyEvent {
+                    time: 100.0,
+                    key: 6,
+                    pressed: true
+                },
+                KeyEvent {
+                    time: 150.0,
+                    key: 6,
+                    pressed: false
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_player() {
+        let event = |time, pressed| KeyEvent {
+            time,
+            key: 1,
+            pressed,
+        };
+        let mut player = Player::new(1000.0, vec![event(0.0, true), event(50.0, false)]);
+
+        assert!(player.poll(999.0).is_empty());
+        assert_eq!(player.poll(1000.0), &[event(0.0, true)]);
+        assert!(player.poll(1049.0).is_empty());
+        assert!(!player.is_done());
+        assert_eq!(player.poll(1100.0), &[event(50.0, false)]);
+        assert!(player.is_done());
+    }
+
+    #[test]
+    fn test_macro_slots() {
+        let mut slots = MacroSlots::default();
+        slots.0[1].hotkey = "KeyM".to_string();
+        slots.0[1].events = Some(vec![
+            KeyEvent {
+                time: 0.0,
+                key: 0xa,
+                pressed: true,
+            },
+            KeyEvent {
+                time: 12.5,
+                key: 0xa,
+                pressed: false,
+            },
+        ]);
+        assert_eq!(slots.to_string(), "F1=\nKeyM=0:a:1 12.5:a:0\nF3=\nF4=\n");
+        assert_eq!(MacroSlots::parse(&slots.to_string()), Some(slots));
+        assert_eq!(MacroSlots::parse("F1=\nF2=0:g:1\nF3=\nF4=\n"), None);
+        assert_eq!(MacroSlots::parse("F1=\n"), None);
+    }
+}
@@ -0,0 +1,189 @@
+use super::{
+    audio_recording, get_element_by_id, gif_recording, macros, pause_menu, video_recording,
+};
+use crate::chip8emulator::Chip8Emulator;
+use gloo::timers::callback::Interval;
+use js_sys::{ArrayBuffer, WebAssembly};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+
+/// How often the Memory panel is refreshed, in milliseconds.
+const PANEL_UPDATE_INTERVAL: u32 = 1000;
+
+/// What happens to a buffer once it reaches its cap.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Eviction {
+    /// The oldest data is dropped to make room, keeping the most recent part.
+    DropOldest,
+    /// The buffer stops growing, keeping the start.
+    Stop,
+}
+
+impl Eviction {
+    pub fn parse(value: &str) -> Option<Eviction> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "drop-oldest" => Some(Eviction::DropOldest),
+            "stop" => Some(Eviction::Stop),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Eviction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Eviction::DropOldest => "drop-oldest",
+            Eviction::Stop => "stop",
+        })
+    }
+}
+
+/// How large a buffer may grow, and what happens once it's full.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BufferCap {
+    pub max_bytes: usize,
+    pub eviction: Eviction,
+}
+
+/// Caps for the buffers that grow while the page is used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MemoryLimits {
+    /// The samples of a WAV recording, 10 minutes by default.
+    pub audio_recording: BufferCap,
+    /// The data of a video recording. A WebM file can't lose its start, so
+    /// video recordings always stop when full.
+    pub video_recording: BufferCap,
+    /// The samples of a GIF recording. With `Eviction::Stop` the recording
+    /// ends early when full.
+    pub gif_recording: BufferCap,
+}
+
+impl Default for MemoryLimits {
+    fn default() -> MemoryLimits {
+        MemoryLimits {
+            audio_recording: BufferCap {
+                max_bytes: 600 * 44100 * 2,
+                eviction: Eviction::Stop,
+            },
+            video_recording: BufferCap {
+                max_bytes: 256 * 1024 * 1024,
+                eviction: Eviction::Stop,
+            },
+            gif_recording: BufferCap {
+                max_bytes: 64 * 1024 * 1024,
+                eviction: Eviction::Stop,
+            },
+        }
+    }
+}
+
+/// How many bytes the page's buffers currently use.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MemoryUsage {
+    /// The whole WebAssembly heap, which never shrinks.
+    pub wasm_heap: usize,
+    pub audio_recording: usize,
+    /// Held by the browser outside of the WebAssembly heap.
+    pub video_recording: usize,
+    pub gif_recording: usize,
+    pub macros: usize,
+    /// The states kept for stepping back in the debugger.
+    pub rewind_history: usize,
+    pub trace: usize,
+    /// The state saved from the pause menu.
+    pub saved_state: usize,
+}
+
+thread_local! {
+    static LIMITS: Cell<MemoryLimits> = Cell::new(MemoryLimits::default());
+}
+
+pub fn set_limits(limits: MemoryLimits) {
+    LIMITS.with(|current| current.set(limits));
+}
+
+pub fn limits() -> MemoryLimits {
+    LIMITS.with(Cell::get)
+}
+
+pub fn memory_usage(chip8: &Chip8Emulator) -> MemoryUsage {
+    MemoryUsage {
+        wasm_heap: wasm_heap_bytes(),
+        audio_recording: audio_recording::recording_bytes(),
+        video_recording: video_recording::recording_bytes(),
+        gif_recording: gif_recording::recording_bytes(),
+        macros: macros::macro_bytes(),
+        rewind_history: chip8.get_history_bytes(),
+        trace: chip8.get_trace_bytes(),
+        saved_state: pause_menu::saved_state_bytes(),
+    }
+}
+
+fn wasm_heap_bytes() -> usize {
+    wasm_bindgen::memory()
+        .unchecked_into::<WebAssembly::Memory>()
+        .buffer()
+        .unchecked_into::<ArrayBuffer>()
+        .byte_length() as usize
+}
+
+/// Keeps the Memory panel showing the current usage.
+pub fn register_memory_panel(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let chip8 = Rc::clone(chip8);
+    Interval::new(PANEL_UPDATE_INTERVAL, move || {
+        let usage = memory_usage(&chip8.borrow());
+        let limits = limits();
+        let text = format!(
+            "WebAssembly heap {}, WAV recording {} of {}, video recording {} of {}, GIF recording {} of {}, macros {}, rewind history {}, trace {}, saved state {}",
+            format_bytes(usage.wasm_heap),
+            format_bytes(usage.audio_recording),
+            format_bytes(limits.audio_recording.max_bytes),
+            format_bytes(usage.video_recording),
+            format_bytes(limits.video_recording.max_bytes),
+            format_bytes(usage.gif_recording),
+            format_bytes(limits.gif_recording.max_bytes),
+            format_bytes(usage.macros),
+            format_bytes(usage.rewind_history),
+            format_bytes(usage.trace),
+            format_bytes(usage.saved_state),
+        );
+        get_element_by_id("memory-usage").set_text_content(Some(&text));
+    })
+    .forget();
+}
+
+fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(256 * 1024 * 1024), "256.0 MiB");
+        assert_eq!(format_bytes(3 << 30), "3.0 GiB");
+
+        assert_eq!(Eviction::parse("Drop-Oldest"), Some(Eviction::DropOldest));
+        assert_eq!(Eviction::parse("stop"), Some(Eviction::Stop));
+        assert_eq!(Eviction::parse("lru"), None);
+        assert_eq!(Eviction::DropOldest.to_string(), "drop-oldest");
+    }
+}
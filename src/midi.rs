@@ -0,0 +1,198 @@
+//! MIDI input through the Web MIDI API, for 4x4 pad controllers standing in
+//! for the keypad. Sixteen notes from a first note are mapped to the keypad's
+//! positions, laid out as the pads usually are: the first note on the bottom
+//! left pad, going left to right and then up. The first note can be typed in
+//! or learned by pressing the bottom left pad.
+
+use super::{get_element_by_id, press_key, storage, KEYPAD_LAYOUT};
+use crate::chip8emulator::Chip8Emulator;
+use gloo::events::EventListener;
+use js_sys::Function;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{console, window, HtmlInputElement, MidiAccess, MidiInput, MidiMessageEvent};
+
+/// Note of the bottom left pad on most pad controllers, C1.
+const DEFAULT_FIRST_NOTE: u8 = 36;
+/// Highest first note that leaves room for all 16 pads below note 128.
+const MAX_FIRST_NOTE: u8 = 112;
+
+/// Parses a MIDI message turning a note on or off, on any channel, as the
+/// note and whether it was pressed. A note on with no velocity is a note off.
+fn parse_note(data: &[u8]) -> Option<(u8, bool)> {
+    match *data {
+        [status, note, velocity] if status & 0xf0 == 0x90 => Some((note, velocity > 0)),
+        [status, note, _] if status & 0xf0 == 0x80 => Some((note, false)),
+        _ => None,
+    }
+}
+
+/// Returns the position on the keypad of the pad playing `note`, with
+/// `first_note` on the bottom left pad.
+fn pad_position(first_note: u8, note: u8) -> Option<usize> {
+    let pad = note.checked_sub(first_note)? as usize;
+    if pad >= 16 {
+        return None;
+    }
+    Some((3 - pad / 4) * 4 + pad % 4)
+}
+
+thread_local! {
+    static FIRST_NOTE: Cell<u8> = const { Cell::new(DEFAULT_FIRST_NOTE) };
+
+    /// Whether the next note pressed becomes the first note.
+    static LEARNING: Cell<bool> = const { Cell::new(false) };
+
+    /// The key each pad pressed, released even if the mapping changes while
+    /// it's held.
+    static PRESSED: Cell<[Option<u8>; 16]> = const { Cell::new([None; 16]) };
+
+    /// Handles the messages of every MIDI input, once MIDI is connected.
+    static ON_MESSAGE: RefCell<Option<Function>> = const { RefCell::new(None) };
+}
+
+/// Sets up the MIDI controls of the Controls panel. MIDI is only connected
+/// when asked, since browsers ask for permission first.
+pub fn register_midi(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let first_note = storage::load_midi_first_note().unwrap_or(DEFAULT_FIRST_NOTE);
+    FIRST_NOTE.with(|note| note.set(first_note));
+    get_first_note_input().set_value_as_number(first_note as f64);
+
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("midi-connect"), "click", move |_| {
+        let chip8 = Rc::clone(&chip8);
+        spawn_local(async move {
+            if let Err(e) = connect(chip8).await {
+                set_status("Can't use MIDI");
+                console::error_2(&"Can't connect MIDI".into(), &e);
+            }
+        });
+    })
+    .forget();
+
+    EventListener::new(&get_element_by_id("midi-learn"), "click", |_| {
+        LEARNING.with(|learning| learning.set(true));
+        set_status("Press the bottom left pad");
+    })
+    .forget();
+
+    EventListener::new(&get_first_note_input(), "input", |_| {
+        let value = get_first_note_input().value_as_number();
+        if value.is_finite() {
+            set_first_note(value.clamp(0.0, MAX_FIRST_NOTE as f64) as u8);
+        }
+    })
+    .forget();
+}
+
+async fn connect(chip8: Rc<RefCell<Chip8Emulator>>) -> Result<(), JsValue> {
+    let access = window().unwrap().navigator().request_midi_access()?;
+    let access: MidiAccess = JsFuture::from(access).await?.dyn_into()?;
+
+    let on_message = Closure::<dyn FnMut(MidiMessageEvent)>::new(move |e: MidiMessageEvent| {
+        if let Some((note, pressed)) = e.data().ok().and_then(|data| parse_note(&data)) {
+            play_note(&chip8, note, pressed);
+        }
+    });
+    // Handed over to JavaScript for as long as the page is open.
+    let on_message = on_message.into_js_value().unchecked_into();
+    ON_MESSAGE.with(|current| *current.borrow_mut() = Some(on_message));
+    listen_to_inputs(&access)?;
+
+    // Pads plugged in later are listened to as well.
+    let target = access.clone();
+    EventListener::new(&access, "statechange", move |_| {
+        if let Err(e) = listen_to_inputs(&target) {
+            console::error_2(&"Can't listen to MIDI inputs".into(), &e);
+        }
+    })
+    .forget();
+    Ok(())
+}
+
+/// Handles the messages of every input of `access`. Inputs already handled
+/// are set the same handler again, so none gets two.
+fn listen_to_inputs(access: &MidiAccess) -> Result<(), JsValue> {
+    let mut count = 0;
+    for input in access.inputs().values() {
+        let input: MidiInput = input?.dyn_into()?;
+        ON_MESSAGE.with(|on_message| input.set_onmidimessage(on_message.borrow().as_ref()));
+        count += 1;
+    }
+    set_status(&match count {
+        0 => "No MIDI inputs".to_string(),
+        1 => "1 MIDI input".to_string(),
+        count => format!("{} MIDI inputs", count),
+    });
+    Ok(())
+}
+
+fn play_note(chip8: &Rc<RefCell<Chip8Emulator>>, note: u8, pressed: bool) {
+    if pressed && LEARNING.with(|learning| learning.replace(false)) {
+        let first_note = note.min(MAX_FIRST_NOTE);
+        set_first_note(first_note);
+        get_first_note_input().set_value_as_number(first_note as f64);
+        set_status(&format!("Pads start at note {}", first_note));
+        return;
+    }
+
+    let pad = match pad_position(FIRST_NOTE.with(Cell::get), note) {
+        Some(pad) => pad,
+        None => return,
+    };
+    let mut keys = PRESSED.with(Cell::get);
+    let key = if pressed {
+        let key = KEYPAD_LAYOUT.with(Cell::get)[pad];
+        keys[pad] = Some(key);
+        key
+    } else {
+        match keys[pad].take() {
+            Some(key) => key,
+            None => return,
+        }
+    };
+    PRESSED.with(|pressed| pressed.set(keys));
+    press_key(chip8, key, pressed);
+}
+
+fn set_first_note(first_note: u8) {
+    FIRST_NOTE.with(|note| note.set(first_note));
+    if let Err(e) = storage::store_midi_first_note(first_note) {
+        console::error_2(&"Can't store the first MIDI note".into(), &e);
+    }
+}
+
+fn set_status(text: &str) {
+    get_element_by_id("midi-status").set_text_content(Some(text));
+}
+
+fn get_first_note_input() -> HtmlInputElement {
+    get_element_by_id("midi-first-note")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #midi-first-note is not an input element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_midi_notes() {
+        assert_eq!(parse_note(&[0x90, 36, 100]), Some((36, true)));
+        assert_eq!(parse_note(&[0x95, 40, 0]), Some((40, false)));
+        assert_eq!(parse_note(&[0x80, 36, 64]), Some((36, false)));
+        // Control change and short messages aren't notes.
+        assert_eq!(parse_note(&[0xb0, 1, 64]), None);
+        assert_eq!(parse_note(&[0x90, 36]), None);
+
+        // The bottom left pad is the first position of the bottom row.
+        assert_eq!(pad_position(36, 36), Some(12));
+        assert_eq!(pad_position(36, 39), Some(15));
+        assert_eq!(pad_position(36, 51), Some(3));
+        assert_eq!(pad_position(36, 35), None);
+        assert_eq!(pad_position(36, 52), None);
+    }
+}
@@ -0,0 +1,126 @@
+//! Opcode breaks in the Debugger panel: opcode patterns such as `DXYN` that
+//! pause the emulator before any matching instruction, for finding where a
+//! ROM draws or reads keys without knowing its addresses.
+
+use super::{debug_panel, get_element_by_id};
+use crate::chip8emulator::{Chip8Emulator, OpcodePattern};
+use gloo::events::EventListener;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlInputElement, KeyboardEvent};
+
+thread_local! {
+    static SHOWN_STATUS: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Adds opcode breaks typed in the panel, with the Break on button or Enter.
+pub fn register_opcode_breaks(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let button_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("opcode-break-add"), "click", move |_| {
+        add_opcode_break(&button_chip8);
+    })
+    .forget();
+
+    let input_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_input(), "keydown", move |e| {
+        if e.dyn_ref::<KeyboardEvent>().unwrap().key() == "Enter" {
+            add_opcode_break(&input_chip8);
+        }
+    })
+    .forget();
+}
+
+fn add_opcode_break(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let input = get_input();
+    match OpcodePattern::parse(&input.value()) {
+        Some(pattern) => {
+            chip8.borrow_mut().add_opcode_break(pattern);
+            input.set_value("");
+            show_opcode_breaks(chip8);
+        }
+        None => set_status(&format!(
+            "Can't break on \"{}\", expected four hex digits or X, Y, N and K, like DXYN",
+            input.value()
+        )),
+    }
+}
+
+/// Lists the opcode breaks, each with a button removing it.
+fn show_opcode_breaks(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let remove_chip8 = Rc::clone(chip8);
+    let remove = move |pattern| {
+        remove_chip8.borrow_mut().remove_opcode_break(pattern);
+        show_opcode_breaks(&remove_chip8);
+    };
+    let patterns = chip8.borrow().get_opcode_breaks().to_vec();
+    debug_panel::show_list("opcode-break-list", &patterns, Rc::new(remove));
+}
+
+/// Says which pattern the instruction the emulator is paused at matches, if
+/// any. Called while the Debugger panel is open.
+pub fn update(chip8: &Chip8Emulator) {
+    let status = if chip8.is_paused() {
+        describe_break(chip8)
+    } else {
+        String::new()
+    };
+    let changed = SHOWN_STATUS.with(|shown| {
+        let mut shown = shown.borrow_mut();
+        let changed = *shown != status;
+        *shown = status.clone();
+        changed
+    });
+    if changed {
+        set_status(&status);
+    }
+}
+
+fn describe_break(chip8: &Chip8Emulator) -> String {
+    let pc = chip8.get_pc();
+    let opcode = match *chip8.memory_slice(pc, 2) {
+        [high, low] => u16::from_be_bytes([high, low]),
+        _ => return String::new(),
+    };
+    chip8
+        .get_opcode_breaks()
+        .iter()
+        .find(|pattern| pattern.matches(opcode))
+        .map_or_else(String::new, |pattern| {
+            format!(
+                "Paused at {:03X} on {:04X}, matching {}",
+                pc, opcode, pattern
+            )
+        })
+}
+
+fn set_status(text: &str) {
+    get_element_by_id("opcode-break-status").set_text_content(Some(text));
+}
+
+fn get_input() -> HtmlInputElement {
+    get_element_by_id("opcode-break-input")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #opcode-break-input is not an input element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_break() {
+        // Clears the screen, then draws.
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x00, 0xe0, 0xd0, 0x15]).unwrap();
+        chip8.add_opcode_break(OpcodePattern::parse("DXYN").unwrap());
+        assert_eq!(describe_break(&chip8), "");
+
+        chip8.tick(100.0);
+        assert!(chip8.is_paused());
+        assert_eq!(
+            describe_break(&chip8),
+            "Paused at 202 on D015, matching DXYN"
+        );
+    }
+}
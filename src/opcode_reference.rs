@@ -0,0 +1,40 @@
+use super::get_element_by_id;
+use crate::chip8emulator::{opcode_table, Profile};
+use wasm_bindgen::JsValue;
+use web_sys::{window, Element};
+
+/// Fills the opcode reference panel with the instructions `profile` runs.
+pub fn show_opcode_table(profile: &Profile) {
+    if let Err(e) = fill_opcode_table(profile) {
+        web_sys::console::error_2(&"Can't show the opcode reference".into(), &e);
+    }
+}
+
+fn fill_opcode_table(profile: &Profile) -> Result<(), JsValue> {
+    let document = window().unwrap().document().unwrap();
+    let body = get_element_by_id("opcode-table");
+    body.set_text_content(None);
+
+    for entry in opcode_table(profile) {
+        let row = document.create_element("tr")?;
+        let extension = entry.extension.map(|extension| extension.to_string());
+        let cells = [
+            entry.pattern.as_str(),
+            entry.example.as_str(),
+            extension.as_deref().unwrap_or(""),
+            &entry.quirks.join(", "),
+        ];
+        for text in cells.iter() {
+            append_cell(&row, text)?;
+        }
+        body.append_child(&row)?;
+    }
+    Ok(())
+}
+
+fn append_cell(row: &Element, text: &str) -> Result<(), JsValue> {
+    let cell = window().unwrap().document().unwrap().create_element("td")?;
+    cell.set_text_content(Some(text));
+    row.append_child(&cell)?;
+    Ok(())
+}
@@ -0,0 +1,125 @@
+//! On-screen display: short messages like "ROM loaded" or errors, drawn over
+//! the display on the page's canvas for a couple of seconds, so feedback
+//! doesn't need elements of its own on the page.
+
+use super::get_current_time;
+use std::cell::RefCell;
+use web_sys::CanvasRenderingContext2d;
+
+/// How long a message is shown, in milliseconds, including fading out.
+const MESSAGE_DURATION: f64 = 2000.0;
+const FADE_DURATION: f64 = 300.0;
+/// Most messages shown at once. Older ones make way for new ones.
+const MAX_MESSAGES: usize = 3;
+/// Text height as a part of the canvas height, and the fewest pixels it's
+/// drawn with.
+const TEXT_SIZE: f64 = 1.0 / 14.0;
+const MIN_TEXT_SIZE: f64 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Severity {
+    Info,
+    Error,
+}
+
+struct Message {
+    text: String,
+    severity: Severity,
+    shown_at: f64,
+}
+
+thread_local! {
+    static MESSAGES: RefCell<Vec<Message>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Shows `text` over the display for a moment.
+pub fn show(text: &str) {
+    push(text, Severity::Info);
+}
+
+/// Shows `text` over the display for a moment, in a color for errors.
+pub fn show_error(text: &str) {
+    push(text, Severity::Error);
+}
+
+fn push(text: &str, severity: Severity) {
+    MESSAGES.with(|messages| {
+        let mut messages = messages.borrow_mut();
+        if messages.len() == MAX_MESSAGES {
+            messages.remove(0);
+        }
+        messages.push(Message {
+            text: text.to_string(),
+            severity,
+            shown_at: get_current_time(),
+        });
+    });
+}
+
+/// Returns whether messages are shown, so the canvas needs to be drawn again
+/// every frame to fade them out, and once more to clear them.
+pub fn is_visible() -> bool {
+    MESSAGES.with(|messages| !messages.borrow().is_empty())
+}
+
+/// Draws the messages shown at `current_time` in the bottom left corner of
+/// the canvas `ctx` draws on, newest at the bottom, and forgets the ones that
+/// are over.
+pub fn draw(ctx: &CanvasRenderingContext2d, current_time: f64) {
+    MESSAGES.with(|messages| {
+        let mut messages = messages.borrow_mut();
+        messages.retain(|message| current_time - message.shown_at < MESSAGE_DURATION);
+        if messages.is_empty() {
+            return;
+        }
+
+        let height = ctx.canvas().map_or(0, |canvas| canvas.height()) as f64;
+        let size = (height * TEXT_SIZE).max(MIN_TEXT_SIZE).round();
+        let padding = (size / 3.0).round();
+        ctx.save();
+        ctx.set_font(&format!("{}px monospace", size));
+        ctx.set_text_baseline("bottom");
+
+        let mut bottom = height - padding;
+        for message in messages.iter().rev() {
+            let width = ctx
+                .measure_text(&message.text)
+                .map_or(0.0, |metrics| metrics.width());
+            ctx.set_global_alpha(opacity(current_time - message.shown_at));
+            ctx.set_fill_style_str("rgba(0, 0, 0, 0.7)");
+            ctx.fill_rect(
+                padding,
+                bottom - size - padding,
+                width + 2.0 * padding,
+                size + padding,
+            );
+            ctx.set_fill_style_str(match message.severity {
+                Severity::Info => "#ffffff",
+                Severity::Error => "#ff6b6b",
+            });
+            ctx.fill_text(&message.text, 2.0 * padding, bottom - padding / 2.0)
+                .unwrap();
+            bottom -= size + 2.0 * padding;
+        }
+        ctx.restore();
+    });
+}
+
+/// Returns how opaque a message `age` milliseconds old is: fully until it
+/// starts fading out at the end of its time.
+fn opacity(age: f64) -> f64 {
+    ((MESSAGE_DURATION - age) / FADE_DURATION).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opacity() {
+        assert_eq!(opacity(0.0), 1.0);
+        assert_eq!(opacity(MESSAGE_DURATION - FADE_DURATION), 1.0);
+        assert_eq!(opacity(MESSAGE_DURATION - FADE_DURATION / 2.0), 0.5);
+        assert_eq!(opacity(MESSAGE_DURATION), 0.0);
+    }
+}
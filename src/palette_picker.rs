@@ -0,0 +1,176 @@
+use super::{emulator_worker, get_element_by_id, storage};
+use crate::chip8emulator::{hex_color, parse_hex_color, Chip8Emulator, Palette};
+use gloo::events::EventListener;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{console, window, HtmlInputElement, HtmlSelectElement, MediaQueryList};
+
+/// Option of the palette select for colors picked by hand.
+const CUSTOM_PALETTE: &str = "custom";
+/// Option of the palette select that follows the system's color scheme.
+const SYSTEM_PALETTE: &str = "system";
+const COLOR_INPUT_IDS: [&str; 2] = ["palette-on", "palette-off"];
+const DARK_SCHEME_QUERY: &str = "(prefers-color-scheme: dark)";
+
+/// The palettes the display is drawn in when following the system's color
+/// scheme, one for each scheme.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SchemePalettes {
+    pub dark: Palette,
+    pub light: Palette,
+}
+
+impl Default for SchemePalettes {
+    fn default() -> SchemePalettes {
+        SchemePalettes {
+            dark: Palette::default(),
+            light: Palette {
+                pixel_on: [0x0b, 0x5d, 0x3b],
+                pixel_off: [0xf4, 0xf1, 0xe8],
+            },
+        }
+    }
+}
+
+thread_local! {
+    static PALETTE: Cell<Palette> = const { Cell::new(Palette::PRESETS[0].1) };
+
+    static SCHEME_PALETTES: Cell<SchemePalettes> = Cell::new(SchemePalettes::default());
+
+    /// Whether the palette follows the system's color scheme.
+    static FOLLOWS_SYSTEM: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Returns the palette the display is drawn in.
+pub fn current_palette() -> Palette {
+    PALETTE.with(Cell::get)
+}
+
+pub fn set_palette(palette: Palette) {
+    PALETTE.with(|current| current.set(palette));
+}
+
+/// Shows the stored palette in the display panel, and applies palettes chosen
+/// there, either a preset, colors picked by hand, or the one of
+/// `scheme_palettes` for the system's color scheme.
+pub fn register_palette_panel(chip8: &Rc<RefCell<Chip8Emulator>>, scheme_palettes: SchemePalettes) {
+    SCHEME_PALETTES.with(|palettes| palettes.set(scheme_palettes));
+    if storage::load_system_palette().unwrap_or(false) {
+        FOLLOWS_SYSTEM.with(|follows| follows.set(true));
+        set_palette(system_palette());
+    } else {
+        set_palette(storage::load_palette().unwrap_or_default());
+    }
+    set_palette_panel(current_palette());
+
+    {
+        let chip8 = Rc::clone(chip8);
+        EventListener::new(&get_palette_select(), "change", move |_| {
+            let select = get_palette_select();
+            select.blur().unwrap();
+            if select.value() == SYSTEM_PALETTE {
+                FOLLOWS_SYSTEM.with(|follows| follows.set(true));
+                set_palette_panel(system_palette());
+                apply_palette(&chip8, system_palette());
+            } else if let Some(palette) = Palette::preset(&select.value()) {
+                choose_palette(&chip8, palette);
+            }
+            // Picking "custom" keeps the current colors to edit.
+        })
+        .forget();
+    }
+
+    for id in COLOR_INPUT_IDS {
+        let chip8 = Rc::clone(chip8);
+        EventListener::new(&get_element_by_id(id), "input", move |_| {
+            let palette = Palette {
+                pixel_on: read_color("palette-on"),
+                pixel_off: read_color("palette-off"),
+            };
+            FOLLOWS_SYSTEM.with(|follows| follows.set(false));
+            get_palette_select().set_value(preset_name(palette));
+            apply_palette(&chip8, palette);
+        })
+        .forget();
+    }
+
+    if let Some(query) = get_dark_scheme_query() {
+        let chip8 = Rc::clone(chip8);
+        EventListener::new(&query, "change", move |_| {
+            if FOLLOWS_SYSTEM.with(Cell::get) {
+                set_palette_panel(system_palette());
+                apply_palette(&chip8, system_palette());
+                // The worker only hears of changes made in the panels.
+                emulator_worker::send_settings();
+            }
+        })
+        .forget();
+    }
+}
+
+/// Switches to `palette` as if it was picked in the display panel.
+pub fn choose_palette(chip8: &Rc<RefCell<Chip8Emulator>>, palette: Palette) {
+    FOLLOWS_SYSTEM.with(|follows| follows.set(false));
+    set_palette_panel(palette);
+    apply_palette(chip8, palette);
+}
+
+fn apply_palette(chip8: &Rc<RefCell<Chip8Emulator>>, palette: Palette) {
+    set_palette(palette);
+    chip8.borrow_mut().mark_gfx_changed();
+    let stored = storage::store_palette(palette)
+        .and_then(|()| storage::store_system_palette(FOLLOWS_SYSTEM.with(Cell::get)));
+    if let Err(e) = stored {
+        console::error_2(&"Can't store the palette".into(), &e);
+    }
+}
+
+/// Returns the palette for the system's color scheme, the dark one if the
+/// browser can't tell.
+fn system_palette() -> Palette {
+    let palettes = SCHEME_PALETTES.with(Cell::get);
+    match get_dark_scheme_query() {
+        Some(query) if !query.matches() => palettes.light,
+        _ => palettes.dark,
+    }
+}
+
+fn set_palette_panel(palette: Palette) {
+    let name = if FOLLOWS_SYSTEM.with(Cell::get) {
+        SYSTEM_PALETTE
+    } else {
+        preset_name(palette)
+    };
+    get_palette_select().set_value(name);
+    get_color_input("palette-on").set_value(&hex_color(palette.pixel_on));
+    get_color_input("palette-off").set_value(&hex_color(palette.pixel_off));
+}
+
+fn preset_name(palette: Palette) -> &'static str {
+    Palette::PRESETS
+        .iter()
+        .find(|&&(_, preset)| preset == palette)
+        .map_or(CUSTOM_PALETTE, |&(name, _)| name)
+}
+
+fn read_color(id: &str) -> [u8; 3] {
+    // Color inputs always hold a valid lowercase hex color.
+    parse_hex_color(&get_color_input(id).value()).unwrap_or_default()
+}
+
+fn get_dark_scheme_query() -> Option<MediaQueryList> {
+    window()?.match_media(DARK_SCHEME_QUERY).ok()?
+}
+
+fn get_palette_select() -> HtmlSelectElement {
+    get_element_by_id("palette")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #palette is not a select element")
+}
+
+fn get_color_input(id: &str) -> HtmlInputElement {
+    get_element_by_id(id)
+        .dyn_into::<HtmlInputElement>()
+        .unwrap_or_else(|_| panic!("Element with id #{} is not an input element", id))
+}
@@ -0,0 +1,111 @@
+//! Live patching in the Debugger panel: while paused, the instruction at an
+//! address, PC by default, can be overwritten with an opcode in hex or an
+//! instruction in assembly, then run by resuming, to try out a change to a
+//! ROM without rebuilding it.
+
+use super::get_element_by_id;
+use crate::chip8emulator::{assemble, Chip8Emulator};
+use gloo::events::EventListener;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlInputElement, KeyboardEvent};
+
+/// Patches the instruction typed in the panel, with the Patch button or
+/// Enter.
+pub fn register_patching(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let button_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("patch-apply"), "click", move |_| {
+        patch(&mut button_chip8.borrow_mut());
+    })
+    .forget();
+
+    let input_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_input("patch-instruction"), "keydown", move |e| {
+        if e.dyn_ref::<KeyboardEvent>().unwrap().key() == "Enter" {
+            patch(&mut input_chip8.borrow_mut());
+        }
+    })
+    .forget();
+}
+
+fn patch(chip8: &mut Chip8Emulator) {
+    if !chip8.is_paused() {
+        return;
+    }
+    let status = match parse_patch(
+        &get_input("patch-address").value(),
+        &get_input("patch-instruction").value(),
+        chip8.get_pc(),
+    ) {
+        Ok((address, bytes)) if chip8.write_memory(address, &bytes) => {
+            let text = chip8
+                .disassemble(address, 1)
+                .pop()
+                .map_or_else(String::new, |instruction| instruction.text);
+            format!("Patched {:03X}: {}", address, text)
+        }
+        Ok((address, _)) => format!("Can't patch {:03X}, it's past the end of memory", address),
+        Err(message) => format!("Can't patch: {}", message),
+    };
+    get_element_by_id("patch-status").set_text_content(Some(&status));
+}
+
+/// Returns the address to patch, `pc` if `address` is empty, and the bytes
+/// to write there, from an opcode in hex like `A234` or an instruction like
+/// `LD I, 0x234`.
+fn parse_patch(address: &str, instruction: &str, pc: u16) -> Result<(u16, Vec<u8>), String> {
+    let address = match address.trim() {
+        "" => pc,
+        address => parse_hex(address)
+            .ok_or_else(|| format!("\"{}\" isn't an address like 2A4", address))?,
+    };
+    let instruction = instruction.trim();
+    let bytes = match parse_hex(instruction) {
+        Some(opcode) if instruction.trim_start_matches("0x").len() == 4 => {
+            opcode.to_be_bytes().to_vec()
+        }
+        _ => assemble(instruction).map_err(|e| e.message)?,
+    };
+    if bytes.is_empty() {
+        return Err("no instruction given".to_string());
+    }
+    Ok((address, bytes))
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    u16::from_str_radix(text.trim_start_matches("0x"), 16).ok()
+}
+
+fn get_input(id: &str) -> HtmlInputElement {
+    get_element_by_id(id)
+        .dyn_into::<HtmlInputElement>()
+        .unwrap_or_else(|_| panic!("Element with id #{} is not an input element", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_patch() {
+        assert_eq!(
+            parse_patch("", "A234", 0x202),
+            Ok((0x202, vec![0xa2, 0x34]))
+        );
+        assert_eq!(
+            parse_patch("2a4", "ld i, 0x234", 0x202),
+            Ok((0x2a4, vec![0xa2, 0x34]))
+        );
+        assert_eq!(
+            parse_patch("", "0x00E0", 0x202),
+            Ok((0x202, vec![0x00, 0xe0]))
+        );
+        assert_eq!(
+            parse_patch("", "ADD", 0x202),
+            Err("invalid operands for ADD".to_string())
+        );
+        assert!(parse_patch("PC", "CLS", 0x202).is_err());
+        assert!(parse_patch("", "", 0x202).is_err());
+    }
+}
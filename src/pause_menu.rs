@@ -0,0 +1,189 @@
+//! Menu shown over the dimmed display while the game is paused with Escape,
+//! to resume, reset, or save and load a state, picked with the arrow keys and
+//! Enter.
+
+use super::{get_current_time, osd, palette_picker, present_frame, reload_rom};
+use crate::chip8emulator::{hex_color, Chip8Emulator, SaveState};
+use gloo::events::{EventListener, EventListenerOptions};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{window, CanvasRenderingContext2d, KeyboardEvent};
+
+/// Text height of the menu items as a part of the canvas height.
+const TEXT_SIZE: f64 = 1.0 / 10.0;
+const DIM_COLOR: &str = "rgba(0, 0, 0, 0.65)";
+const ITEM_COLOR: &str = "#bbbbbb";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MenuItem {
+    Resume,
+    Reset,
+    SaveState,
+    LoadState,
+}
+
+impl MenuItem {
+    const ALL: [MenuItem; 4] = [
+        MenuItem::Resume,
+        MenuItem::Reset,
+        MenuItem::SaveState,
+        MenuItem::LoadState,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            MenuItem::Resume => "Resume",
+            MenuItem::Reset => "Reset",
+            MenuItem::SaveState => "Save state",
+            MenuItem::LoadState => "Load state",
+        }
+    }
+}
+
+/// Returns the index of the item `delta` items away from `selected`, going
+/// around at either end.
+fn move_selection(selected: usize, delta: isize) -> usize {
+    let count = MenuItem::ALL.len() as isize;
+    (selected as isize + delta).rem_euclid(count) as usize
+}
+
+thread_local! {
+    /// The selected item while the menu is open.
+    static SELECTED: Cell<Option<usize>> = const { Cell::new(None) };
+
+    /// The state saved from the menu, kept until the page is closed.
+    static SAVED_STATE: RefCell<Option<SaveState>> = const { RefCell::new(None) };
+}
+
+/// Pauses and shows the menu when Escape is pressed, and handles the menu's
+/// keys while it's open.
+pub fn register_pause_menu(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let chip8 = Rc::clone(chip8);
+    // Not passive, so the keys the menu uses don't also scroll the page.
+    let options = EventListenerOptions::enable_prevent_default();
+    EventListener::new_with_options(&window().unwrap(), "keydown", options, move |e| {
+        let e = e.dyn_ref::<KeyboardEvent>().unwrap();
+        let selected = match SELECTED.with(Cell::get) {
+            Some(selected) => selected,
+            None if e.key() == "Escape" && !e.repeat() => return open(&chip8),
+            None => return,
+        };
+
+        match e.key().as_str() {
+            "Escape" => close(&chip8),
+            "ArrowUp" => select(move_selection(selected, -1)),
+            "ArrowDown" => select(move_selection(selected, 1)),
+            "Enter" | " " => choose(&chip8, MenuItem::ALL[selected]),
+            _ => return,
+        }
+        e.prevent_default();
+    })
+    .forget();
+}
+
+fn open(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    chip8.borrow_mut().pause();
+    select(0);
+}
+
+fn close(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    SELECTED.with(|selected| selected.set(None));
+    chip8.borrow_mut().resume();
+    present_frame();
+}
+
+fn select(item: usize) {
+    SELECTED.with(|selected| selected.set(Some(item)));
+    present_frame();
+}
+
+fn choose(chip8: &Rc<RefCell<Chip8Emulator>>, item: MenuItem) {
+    match item {
+        MenuItem::Resume => {}
+        MenuItem::Reset => {
+            reload_rom(chip8);
+            osd::show("Reset");
+        }
+        MenuItem::SaveState => save_state(chip8),
+        MenuItem::LoadState => load_state(chip8),
+    }
+    close(chip8);
+}
+
+/// Saves a state to load later, as the Save state item does.
+pub fn save_state(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let state = chip8.borrow().save_state();
+    SAVED_STATE.with(|saved| *saved.borrow_mut() = Some(state));
+    osd::show("State saved");
+}
+
+/// How many bytes the saved state uses, if any.
+pub fn saved_state_bytes() -> usize {
+    SAVED_STATE.with(|saved| saved.borrow().as_ref().map_or(0, SaveState::byte_len))
+}
+
+/// Loads the state saved last, as the Load state item does.
+pub fn load_state(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let loaded = SAVED_STATE.with(|saved| match saved.borrow().as_ref() {
+        Some(state) => {
+            chip8.borrow_mut().load_state(state, get_current_time());
+            true
+        }
+        None => false,
+    });
+    if loaded {
+        osd::show("State loaded");
+    } else {
+        osd::show_error("No state saved yet");
+    }
+}
+
+pub fn is_open() -> bool {
+    SELECTED.with(Cell::get).is_some()
+}
+
+/// Dims the canvas `ctx` draws on and draws the menu over it, if it's open.
+pub fn draw(ctx: &CanvasRenderingContext2d) {
+    let selected = match SELECTED.with(Cell::get) {
+        Some(selected) => selected,
+        None => return,
+    };
+    let canvas = ctx.canvas().unwrap();
+    let (width, height) = (canvas.width() as f64, canvas.height() as f64);
+    let size = (height * TEXT_SIZE).round();
+
+    ctx.save();
+    ctx.set_fill_style_str(DIM_COLOR);
+    ctx.fill_rect(0.0, 0.0, width, height);
+    ctx.set_font(&format!("{}px monospace", size));
+    ctx.set_text_align("center");
+    ctx.set_text_baseline("middle");
+
+    let highlight = hex_color(palette_picker::current_palette().pixel_on);
+    let top = (height - size * 1.5 * MenuItem::ALL.len() as f64) / 2.0 + size * 0.75;
+    for (index, item) in MenuItem::ALL.iter().enumerate() {
+        let (text, color) = if index == selected {
+            (format!("> {} <", item.label()), highlight.as_str())
+        } else {
+            (item.label().to_string(), ITEM_COLOR)
+        };
+        ctx.set_fill_style_str(color);
+        ctx.fill_text(&text, width / 2.0, top + size * 1.5 * index as f64)
+            .unwrap();
+    }
+    ctx.restore();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_move_selection() {
+        assert_eq!(move_selection(0, 1), 1);
+        assert_eq!(move_selection(0, -1), MenuItem::ALL.len() - 1);
+        assert_eq!(move_selection(MenuItem::ALL.len() - 1, 1), 0);
+        assert_eq!(MenuItem::ALL[2].label(), "Save state");
+    }
+}
@@ -0,0 +1,175 @@
+//! Optional overlay in the top right corner of the display showing frames per
+//! second, instructions executed per second, and the tick backlog: the most
+//! emulated time a single frame had to catch up on. A slow device shows a low
+//! frame rate and a backlog well above a frame's 17 ms.
+
+use super::{get_element_by_id, storage};
+use gloo::events::EventListener;
+use std::cell::{Cell, RefCell};
+use wasm_bindgen::JsCast;
+use web_sys::{console, CanvasRenderingContext2d, HtmlInputElement};
+
+/// How often the numbers are updated, in milliseconds.
+const SAMPLE_WINDOW: f64 = 500.0;
+/// Text height as a part of the canvas height, and the fewest pixels it's
+/// drawn with.
+const TEXT_SIZE: f64 = 1.0 / 16.0;
+const MIN_TEXT_SIZE: f64 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PerfStats {
+    fps: f64,
+    ips: f64,
+    /// Longest time between two frames in the window, in milliseconds.
+    backlog: f64,
+}
+
+/// Counts frames and instructions over a window of time.
+#[derive(Default)]
+struct PerfCounter {
+    /// When the window started and the instruction count then.
+    start: Option<(f64, u64)>,
+    frames: u32,
+    backlog: f64,
+    stats: Option<PerfStats>,
+}
+
+impl PerfCounter {
+    /// Counts a frame at `current_time` that caught up on `backlog`
+    /// milliseconds, with `instruction_count` instructions executed so far.
+    /// Returns whether the stats were updated.
+    fn record_frame(&mut self, current_time: f64, instruction_count: u64, backlog: f64) -> bool {
+        let (start_time, start_count) = match self.start {
+            Some(start) => start,
+            None => {
+                self.start = Some((current_time, instruction_count));
+                return false;
+            }
+        };
+        self.frames += 1;
+        self.backlog = self.backlog.max(backlog);
+
+        let elapsed = current_time - start_time;
+        if elapsed < SAMPLE_WINDOW {
+            return false;
+        }
+        let seconds = elapsed / 1000.0;
+        self.stats = Some(PerfStats {
+            fps: self.frames as f64 / seconds,
+            // The count starts over when a ROM is loaded.
+            ips: instruction_count.saturating_sub(start_count) as f64 / seconds,
+            backlog: self.backlog,
+        });
+        *self = PerfCounter {
+            start: Some((current_time, instruction_count)),
+            stats: self.stats,
+            ..PerfCounter::default()
+        };
+        true
+    }
+}
+
+thread_local! {
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+
+    static COUNTER: RefCell<PerfCounter> = RefCell::new(PerfCounter::default());
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.with(Cell::get)
+}
+
+/// Counts a frame for the overlay, if it's on. Returns whether the numbers
+/// changed, so the overlay needs to be drawn again.
+pub fn record_frame(current_time: f64, instruction_count: u64, backlog: f64) -> bool {
+    is_enabled()
+        && COUNTER.with(|counter| {
+            counter
+                .borrow_mut()
+                .record_frame(current_time, instruction_count, backlog)
+        })
+}
+
+/// Draws the numbers in the top right corner of the canvas `ctx` draws on,
+/// if the overlay is on.
+pub fn draw(ctx: &CanvasRenderingContext2d) {
+    let stats = match COUNTER.with(|counter| counter.borrow().stats) {
+        Some(stats) if is_enabled() => stats,
+        _ => return,
+    };
+    let text = format!(
+        "{:.0} FPS  {:.0} IPS  {:.0} ms",
+        stats.fps, stats.ips, stats.backlog
+    );
+
+    let width = ctx.canvas().map_or(0, |canvas| canvas.width()) as f64;
+    let height = ctx.canvas().map_or(0, |canvas| canvas.height()) as f64;
+    let size = (height * TEXT_SIZE).max(MIN_TEXT_SIZE).round();
+    let padding = (size / 3.0).round();
+    ctx.save();
+    ctx.set_font(&format!("{}px monospace", size));
+    ctx.set_text_baseline("top");
+    let text_width = ctx
+        .measure_text(&text)
+        .map_or(0.0, |metrics| metrics.width());
+    let left = width - text_width - 3.0 * padding;
+    ctx.set_fill_style_str("rgba(0, 0, 0, 0.7)");
+    ctx.fill_rect(left, padding, text_width + 2.0 * padding, size + padding);
+    ctx.set_fill_style_str("#ffffff");
+    ctx.fill_text(&text, left + padding, padding * 1.5).unwrap();
+    ctx.restore();
+}
+
+/// Shows whether the overlay is on in the display panel, and turns it on
+/// and off from there.
+pub fn register_perf_overlay_checkbox() {
+    ENABLED.with(|enabled| enabled.set(storage::load_perf_overlay().unwrap_or(false)));
+    get_checkbox().set_checked(is_enabled());
+
+    EventListener::new(&get_checkbox(), "input", |_| {
+        let checkbox = get_checkbox();
+        checkbox.blur().unwrap();
+        ENABLED.with(|enabled| enabled.set(checkbox.checked()));
+        // Numbers from before it was turned off would be stale.
+        COUNTER.with(|counter| *counter.borrow_mut() = PerfCounter::default());
+        super::present_frame();
+        if let Err(e) = storage::store_perf_overlay(checkbox.checked()) {
+            console::error_2(&"Can't store the performance overlay setting".into(), &e);
+        }
+    })
+    .forget();
+}
+
+fn get_checkbox() -> HtmlInputElement {
+    get_element_by_id("perf-overlay")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #perf-overlay is not an input element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perf_counter() {
+        let mut counter = PerfCounter::default();
+        assert!(!counter.record_frame(0.0, 100, 0.0));
+        for frame in 1..20 {
+            let time = frame as f64 * 25.0;
+            assert!(!counter.record_frame(time, 100 + frame * 10, 25.0));
+        }
+        assert!(counter.stats.is_none());
+
+        assert!(counter.record_frame(500.0, 300, 40.0));
+        assert_eq!(
+            counter.stats,
+            Some(PerfStats {
+                fps: 40.0,
+                ips: 400.0,
+                backlog: 40.0,
+            })
+        );
+        assert_eq!(counter.frames, 0);
+        assert_eq!(counter.start, Some((500.0, 300)));
+    }
+}
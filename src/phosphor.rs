@@ -0,0 +1,122 @@
+//! Optional afterglow that makes pixels fade out over a few frames instead of
+//! switching off at once, like the phosphor of a CRT. Most CHIP-8 games erase
+//! and redraw their sprites every frame, which flickers badly without it.
+
+use super::{get_element_by_id, storage};
+use crate::chip8emulator::Chip8Emulator;
+use gloo::events::EventListener;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{console, HtmlInputElement};
+
+thread_local! {
+    /// Part of a pixel's brightness kept each frame after it's switched off,
+    /// from 0 (off) to below 1.
+    static PERSISTENCE: Cell<f32> = const { Cell::new(0.0) };
+
+    /// RGBA pixels of the last frame shown, with the afterglow.
+    static GLOW: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+
+    /// Whether the last frame shown had pixels still fading out.
+    static FADING: Cell<bool> = const { Cell::new(false) };
+}
+
+pub fn persistence() -> f32 {
+    PERSISTENCE.with(Cell::get)
+}
+
+pub fn set_persistence(persistence: f32) {
+    PERSISTENCE.with(|current| current.set(persistence.clamp(0.0, 0.99)));
+    if persistence <= 0.0 {
+        GLOW.with(|glow| glow.borrow_mut().clear());
+        FADING.with(|fading| fading.set(false));
+    }
+}
+
+pub fn is_enabled() -> bool {
+    persistence() > 0.0
+}
+
+/// Returns whether pixels are still fading out, so the display needs to be
+/// drawn again even if nothing changed.
+pub fn is_fading() -> bool {
+    FADING.with(Cell::get)
+}
+
+/// Passes `frame`, an RGBA image of the display, to `show` with the afterglow
+/// of the frames shown before it.
+pub fn with_afterglow(frame: &[u8], show: impl FnOnce(&[u8])) {
+    if !is_enabled() {
+        show(frame);
+        return;
+    }
+
+    GLOW.with(|glow| {
+        let mut glow = glow.borrow_mut();
+        if glow.len() != frame.len() {
+            glow.clear();
+            glow.extend_from_slice(frame);
+        }
+        let fading = fade(&mut glow, frame, persistence());
+        FADING.with(|current| current.set(fading));
+        show(&glow);
+    });
+}
+
+/// Fades `glow`, the last frame shown, towards `frame`. Each color channel
+/// lights up at once but only dims by `persistence` per frame. Returns
+/// whether any channel is still brighter than in `frame`.
+fn fade(glow: &mut [u8], frame: &[u8], persistence: f32) -> bool {
+    let mut fading = false;
+    for (shown, &target) in glow.iter_mut().zip(frame) {
+        let dimmed = (*shown as f32 * persistence) as u8;
+        *shown = dimmed.max(target);
+        fading |= *shown > target;
+    }
+    fading
+}
+
+/// Shows the afterglow in the display panel, and changes it from there.
+pub fn register_afterglow_input(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    set_persistence(storage::load_afterglow().unwrap_or(0.0));
+    get_afterglow_input().set_value_as_number((persistence() * 100.0).round() as f64);
+
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&get_afterglow_input(), "input", move |_| {
+        let input = get_afterglow_input();
+        input.blur().unwrap();
+        set_persistence(input.value_as_number() as f32 / 100.0);
+        chip8.borrow_mut().mark_gfx_changed();
+        if let Err(e) = storage::store_afterglow(persistence()) {
+            console::error_2(&"Can't store the afterglow setting".into(), &e);
+        }
+    })
+    .forget();
+}
+
+fn get_afterglow_input() -> HtmlInputElement {
+    get_element_by_id("afterglow")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #afterglow is not an input element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fade() {
+        let mut glow = [200, 0, 10, 0xff];
+        assert!(fade(&mut glow, &[0, 100, 10, 0xff], 0.5));
+        assert_eq!(glow, [100, 100, 10, 0xff]);
+        assert!(fade(&mut glow, &[0, 0, 10, 0xff], 0.5));
+        assert_eq!(glow, [50, 50, 10, 0xff]);
+
+        for _ in 0..10 {
+            fade(&mut glow, &[0, 0, 10, 0xff], 0.5);
+        }
+        assert!(!fade(&mut glow, &[0, 0, 10, 0xff], 0.5));
+        assert_eq!(glow, [0, 0, 10, 0xff]);
+    }
+}
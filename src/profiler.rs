@@ -0,0 +1,143 @@
+//! Execution profiler in the Debugger panel: when turned on, the emulator
+//! counts how many times the instruction at each address, and each kind of
+//! instruction, runs. The hottest addresses and a histogram of opcodes are
+//! listed on demand with their share of the time, for ROM authors to see
+//! where their programs spend it.
+
+use super::get_element_by_id;
+use crate::chip8emulator::Chip8Emulator;
+use gloo::events::EventListener;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{window, HtmlInputElement};
+
+/// How many addresses the report lists.
+const HOT_SPOT_COUNT: usize = 20;
+
+/// Turns profiling on and off with the checkbox, and shows and resets the
+/// counts with the buttons.
+pub fn register_profiler(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let checkbox_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_checkbox(), "input", move |_| {
+        get_checkbox().blur().unwrap();
+        apply_profiling(&mut checkbox_chip8.borrow_mut());
+        show_report(&[]);
+    })
+    .forget();
+
+    let show_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("profile-show"), "click", move |_| {
+        show_report(&describe_hot_spots(&show_chip8.borrow()));
+    })
+    .forget();
+
+    let opcodes_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("profile-opcodes"), "click", move |_| {
+        show_report(&describe_opcode_stats(&opcodes_chip8.borrow()));
+    })
+    .forget();
+
+    let reset_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("profile-reset"), "click", move |_| {
+        reset_chip8.borrow_mut().reset_profile();
+        show_report(&[]);
+    })
+    .forget();
+}
+
+/// Has `chip8` count executions if the checkbox is checked.
+pub fn apply_profiling(chip8: &mut Chip8Emulator) {
+    chip8.set_profiling(get_checkbox().checked());
+}
+
+/// Returns a line for each of the hottest addresses, with its share of the
+/// instructions executed, how many times it ran and its instruction.
+fn describe_hot_spots(chip8: &Chip8Emulator) -> Vec<String> {
+    let total = chip8.get_profiled_count().max(1) as f64;
+    chip8
+        .get_hot_spots(HOT_SPOT_COUNT)
+        .into_iter()
+        .map(|(address, hits)| {
+            let text = chip8
+                .disassemble(address, 1)
+                .pop()
+                .map_or_else(String::new, |instruction| instruction.text);
+            format!(
+                "{:03X}  {:5.1}%  {:>8}  {}",
+                address,
+                hits as f64 * 100.0 / total,
+                hits,
+                text
+            )
+        })
+        .collect()
+}
+
+/// Returns a line for each kind of instruction executed, with its share of
+/// the instructions, how many times it ran and a bar as long as its share.
+fn describe_opcode_stats(chip8: &Chip8Emulator) -> Vec<String> {
+    let total = chip8.get_profiled_count().max(1) as f64;
+    chip8
+        .get_opcode_stats()
+        .into_iter()
+        .map(|(pattern, count)| {
+            let share = count as f64 * 100.0 / total;
+            let bar = "#".repeat((share / 2.5).round() as usize);
+            format!("{}  {:5.1}%  {:>8}  {}", pattern, share, count, bar)
+        })
+        .collect()
+}
+
+fn show_report(lines: &[String]) {
+    let document = window().unwrap().document().unwrap();
+    let report = get_element_by_id("profile-report");
+    report.set_text_content(None);
+    for line in lines {
+        let item = document.create_element("li").unwrap();
+        item.set_text_content(Some(line));
+        report.append_child(&item).unwrap();
+    }
+}
+
+fn get_checkbox() -> HtmlInputElement {
+    get_element_by_id("profile-enabled")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #profile-enabled is not an input element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports() {
+        // Loops over two instructions after the first.
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8
+            .load_rom(&[0x60, 0x05, 0x70, 0x01, 0x12, 0x02])
+            .unwrap();
+        chip8.set_profiling(true);
+        assert!(describe_hot_spots(&chip8).is_empty());
+
+        for _ in 0..7 {
+            chip8.step_instruction();
+        }
+        assert_eq!(
+            describe_hot_spots(&chip8),
+            [
+                "202   42.9%         3  ADD V0, 0x01",
+                "204   42.9%         3  JP 0x202",
+                "200   14.3%         1  LD V0, 0x05",
+            ]
+        );
+        assert_eq!(
+            describe_opcode_stats(&chip8),
+            [
+                "1NNN   42.9%         3  #################",
+                "7XNN   42.9%         3  #################",
+                "6XNN   14.3%         1  ######",
+            ]
+        );
+    }
+}
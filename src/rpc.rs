@@ -0,0 +1,389 @@
+//! JSON-RPC 2.0 debug protocol, so external tools can control the emulator.
+//!
+//! Requests are accepted as `postMessage` messages from other windows, such as
+//! a parent page embedding the emulator in an iframe, and as text frames on a
+//! WebSocket the page connects to. Both are off unless enabled in the page
+//! config. Methods:
+//!
+//! * `getState` returns `{ paused, state, breakpoints }`, `state` being
+//!   what the emulator is doing, like `running` or `waitingForKey`.
+//! * `pause`, `resume`, and `step` and `stepFrame` with an optional
+//!   `{ count }` of instructions or frames to run.
+//! * `stepOver`, which runs a subroutine call until it returns, and
+//!   `stepOut`, which runs until the current subroutine returns.
+//! * `setBreakpoint` and `clearBreakpoint` with `{ address }`.
+//! * `readMemory` with `{ address, length }` returns an array of bytes.
+//! * `writeMemory` with `{ address, data }` writes an array of bytes.
+//! * `readRegisters` returns `{ v, i, pc, delayTimer, soundTimer, stack }`,
+//!   plus `delayTimerPhase` and `soundTimerPhase`, how far each timer is
+//!   toward its next decrement from 0 to 1.
+//! * `disassemble` with `{ address, count }` returns an array of
+//!   `{ address, opcode, text }`.
+
+use crate::chip8emulator::Chip8Emulator;
+use gloo::events::EventListener;
+use js_sys::{Array, Object, Reflect, JSON};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{console, window, MessageEvent, WebSocket, Window};
+
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+/// Most instructions a single `step` request may execute.
+const MAX_STEP_COUNT: u32 = 100_000;
+
+/// Most frames a single `stepFrame` request may run, a minute's worth.
+const MAX_STEP_FRAME_COUNT: u32 = 3600;
+
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i32, message: &str) -> RpcError {
+        RpcError {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// Answers requests posted to this window by other windows from the page's
+/// own origin or one of `allowed_origins`.
+pub fn register_post_message(chip8: &Rc<RefCell<Chip8Emulator>>, allowed_origins: Vec<String>) {
+    let chip8 = Rc::clone(chip8);
+    let own_origin = window().unwrap().location().origin().ok();
+    EventListener::new(&window().unwrap(), "message", move |e| {
+        let e = e.dyn_ref::<MessageEvent>().unwrap();
+        if !is_allowed_origin(&e.origin(), own_origin.as_deref(), &allowed_origins) {
+            return;
+        }
+        let source = match e
+            .source()
+            .and_then(|source| source.dyn_into::<Window>().ok())
+        {
+            Some(source) => source,
+            None => return,
+        };
+
+        // Other scripts on the page may use postMessage for their own purposes,
+        // so anything that doesn't look like JSON-RPC is ignored.
+        let data = e.data();
+        if Reflect::get(&data, &"jsonrpc".into())
+            .ok()
+            .and_then(|v| v.as_string())
+            != Some("2.0".to_string())
+        {
+            return;
+        }
+
+        if let Some(response) = handle_request(&chip8, &data) {
+            if let Err(e) = source.post_message(&response, &e.origin()) {
+                console::error_2(&"Can't send debug protocol response".into(), &e);
+            }
+        }
+    })
+    .forget();
+}
+
+fn is_allowed_origin(origin: &str, own_origin: Option<&str>, allowed_origins: &[String]) -> bool {
+    own_origin == Some(origin) || allowed_origins.iter().any(|allowed| allowed == origin)
+}
+
+/// Connects to a debugger listening on `url` and answers its requests.
+pub fn connect_websocket(chip8: &Rc<RefCell<Chip8Emulator>>, url: &str) -> Result<(), JsValue> {
+    let socket = WebSocket::new(url)?;
+
+    let chip8 = Rc::clone(chip8);
+    let reply_socket = socket.clone();
+    EventListener::new(&socket, "message", move |e| {
+        let text = match e.dyn_ref::<MessageEvent>().unwrap().data().as_string() {
+            Some(text) => text,
+            None => return,
+        };
+
+        let response = match JSON::parse(&text) {
+            Ok(request) => handle_request(&chip8, &request),
+            Err(_) => Some(error_response(
+                JsValue::NULL,
+                RpcError::new(PARSE_ERROR, "Parse error"),
+            )),
+        };
+
+        if let Some(response) = response {
+            let sent = JSON::stringify(&response)
+                .map(String::from)
+                .and_then(|response| reply_socket.send_with_str(&response));
+            if let Err(e) = sent {
+                console::error_2(&"Can't send debug protocol response".into(), &e);
+            }
+        }
+    })
+    .forget();
+
+    Ok(())
+}
+
+/// Handles a request object and returns the response, or `None` for
+/// notifications, which have no `id`.
+fn handle_request(chip8: &Rc<RefCell<Chip8Emulator>>, request: &JsValue) -> Option<JsValue> {
+    let id = Reflect::get(request, &"id".into()).unwrap_or(JsValue::UNDEFINED);
+    let is_notification = id.is_undefined();
+
+    let result = match Reflect::get(request, &"method".into())
+        .ok()
+        .and_then(|method| method.as_string())
+    {
+        Some(method) => {
+            let params = Reflect::get(request, &"params".into()).unwrap_or(JsValue::UNDEFINED);
+            dispatch(&mut chip8.borrow_mut(), &method, &params)
+        }
+        None => Err(RpcError::new(INVALID_REQUEST, "Invalid request")),
+    };
+
+    if is_notification {
+        return None;
+    }
+
+    Some(match result {
+        Ok(result) => {
+            let response = new_response(id);
+            set(&response, "result", &result);
+            response.into()
+        }
+        Err(error) => error_response(id, error),
+    })
+}
+
+fn dispatch(
+    chip8: &mut Chip8Emulator,
+    method: &str,
+    params: &JsValue,
+) -> Result<JsValue, RpcError> {
+    match method {
+        "getState" => {
+            let state = Object::new();
+            set(&state, "paused", &chip8.is_paused().into());
+            set(&state, "state", &chip8.get_state().name().into());
+            set(&state, "breakpoints", &to_array(chip8.get_breakpoints()));
+            Ok(state.into())
+        }
+        "pause" => {
+            chip8.pause();
+            Ok(JsValue::NULL)
+        }
+        "resume" => {
+            chip8.resume();
+            Ok(JsValue::NULL)
+        }
+        "step" => {
+            let count = optional_param(params, "count")?.unwrap_or(1);
+            if count > MAX_STEP_COUNT {
+                return Err(RpcError::new(INVALID_PARAMS, "count is too large"));
+            }
+            chip8.pause();
+            for _ in 0..count {
+                chip8.step_instruction();
+            }
+            Ok(JsValue::NULL)
+        }
+        "stepFrame" => {
+            let count = optional_param(params, "count")?.unwrap_or(1);
+            if count > MAX_STEP_FRAME_COUNT {
+                return Err(RpcError::new(INVALID_PARAMS, "count is too large"));
+            }
+            chip8.pause();
+            for _ in 0..count {
+                chip8.step_frame();
+            }
+            Ok(JsValue::NULL)
+        }
+        "stepOver" => {
+            chip8.pause();
+            chip8.step_over();
+            Ok(JsValue::NULL)
+        }
+        "stepOut" => {
+            chip8.pause();
+            chip8.step_out();
+            Ok(JsValue::NULL)
+        }
+        "setBreakpoint" => {
+            chip8.add_breakpoint(address_param(chip8, params)?);
+            Ok(JsValue::NULL)
+        }
+        "clearBreakpoint" => {
+            let address = address_param(chip8, params)?;
+            Ok(chip8.remove_breakpoint(address).into())
+        }
+        "readMemory" => {
+            let address = address_param(chip8, params)? as usize;
+            let length = param(params, "length")? as usize;
+            match address
+                .checked_add(length)
+                .and_then(|end| chip8.get_memory().get(address..end))
+            {
+                Some(data) => Ok(to_array(data.iter().copied())),
+                None => Err(RpcError::new(INVALID_PARAMS, "Range is out of memory")),
+            }
+        }
+        "writeMemory" => {
+            let address = address_param(chip8, params)?;
+            let data = bytes_param(params, "data")?;
+            if !chip8.write_memory(address, &data) {
+                return Err(RpcError::new(INVALID_PARAMS, "Range is out of memory"));
+            }
+            Ok(JsValue::NULL)
+        }
+        "readRegisters" => {
+            let registers = Object::new();
+            set(
+                &registers,
+                "v",
+                &to_array(chip8.get_registers().iter().copied()),
+            );
+            set(&registers, "i", &chip8.get_I().into());
+            set(&registers, "pc", &chip8.get_pc().into());
+            set(&registers, "delayTimer", &chip8.get_delay_timer().into());
+            set(&registers, "soundTimer", &chip8.get_sound_timer().into());
+            set(
+                &registers,
+                "delayTimerPhase",
+                &chip8.get_delay_timer_state().phase.into(),
+            );
+            set(
+                &registers,
+                "soundTimerPhase",
+                &chip8.get_sound_timer_state().phase.into(),
+            );
+            set(
+                &registers,
+                "stack",
+                &to_array(chip8.get_stack().iter().copied()),
+            );
+            Ok(registers.into())
+        }
+        "disassemble" => {
+            let address = address_param(chip8, params)?;
+            let count = param(params, "count")? as usize;
+            let lines = chip8
+                .disassemble(address, count)
+                .into_iter()
+                .map(|line| {
+                    let object = Object::new();
+                    set(&object, "address", &line.address.into());
+                    set(&object, "opcode", &line.opcode.into());
+                    set(&object, "text", &line.text.into());
+                    object
+                })
+                .collect::<Array>();
+            Ok(lines.into())
+        }
+        _ => Err(RpcError::new(METHOD_NOT_FOUND, "Method not found")),
+    }
+}
+
+/// Reads the `address` param and checks that it is inside CHIP-8 memory.
+fn address_param(chip8: &Chip8Emulator, params: &JsValue) -> Result<u16, RpcError> {
+    let address = param(params, "address")?;
+    if address as usize >= chip8.get_memory().len() {
+        return Err(RpcError::new(INVALID_PARAMS, "address is out of memory"));
+    }
+    Ok(address as u16)
+}
+
+/// Reads an array of bytes param.
+fn bytes_param(params: &JsValue, name: &str) -> Result<Vec<u8>, RpcError> {
+    let invalid = || {
+        RpcError::new(
+            INVALID_PARAMS,
+            &format!("{} must be an array of bytes", name),
+        )
+    };
+    let value = if params.is_object() {
+        Reflect::get(params, &name.into()).unwrap_or(JsValue::UNDEFINED)
+    } else {
+        JsValue::UNDEFINED
+    };
+    if !Array::is_array(&value) {
+        return Err(invalid());
+    }
+
+    Array::from(&value)
+        .iter()
+        .map(|byte| match byte.as_f64() {
+            Some(n) if (0.0..=255.0).contains(&n) && n.fract() == 0.0 => Ok(n as u8),
+            _ => Err(invalid()),
+        })
+        .collect()
+}
+
+fn param(params: &JsValue, name: &str) -> Result<u32, RpcError> {
+    optional_param(params, name)?
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, &format!("Missing param {}", name)))
+}
+
+/// Reads a non-negative integer param, or returns `None` if it's absent.
+fn optional_param(params: &JsValue, name: &str) -> Result<Option<u32>, RpcError> {
+    if !params.is_object() {
+        return Ok(None);
+    }
+
+    let value = Reflect::get(params, &name.into()).unwrap_or(JsValue::UNDEFINED);
+    if value.is_undefined() {
+        return Ok(None);
+    }
+
+    match value.as_f64() {
+        Some(n) if n >= 0.0 && n.fract() == 0.0 && n <= u32::MAX as f64 => Ok(Some(n as u32)),
+        _ => Err(RpcError::new(
+            INVALID_PARAMS,
+            &format!("{} must be a non-negative integer", name),
+        )),
+    }
+}
+
+fn new_response(id: JsValue) -> Object {
+    let response = Object::new();
+    set(&response, "jsonrpc", &"2.0".into());
+    set(&response, "id", &id);
+    response
+}
+
+fn error_response(id: JsValue, error: RpcError) -> JsValue {
+    let response = new_response(if id.is_undefined() { JsValue::NULL } else { id });
+    let error_object = Object::new();
+    set(&error_object, "code", &error.code.into());
+    set(&error_object, "message", &error.message.into());
+    set(&response, "error", &error_object);
+    response.into()
+}
+
+fn set(object: &Object, key: &str, value: &JsValue) {
+    Reflect::set(object, &key.into(), value).unwrap();
+}
+
+fn to_array<T: Into<JsValue>>(values: impl IntoIterator<Item = T>) -> JsValue {
+    values.into_iter().map(Into::into).collect::<Array>().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_allowed_origin() {
+        let allowed = ["https://example.com".to_string()];
+        let own = Some("https://chip8.example");
+        assert!(is_allowed_origin("https://chip8.example", own, &allowed));
+        assert!(is_allowed_origin("https://example.com", own, &allowed));
+        assert!(!is_allowed_origin("https://evil.example", own, &allowed));
+        assert!(!is_allowed_origin("null", None, &[]));
+    }
+}
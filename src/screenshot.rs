@@ -0,0 +1,302 @@
+use super::{display_colors, download_blob, download_file, get_element_by_id};
+use crate::chip8emulator::{hex_color, Chip8Emulator};
+use gloo::events::EventListener;
+use gloo::timers::callback::Timeout;
+use js_sys::{global, Array, Function, Object, Promise, Reflect};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::{Clamped, JsCast};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{
+    console, window, Blob, CanvasRenderingContext2d, HtmlButtonElement, HtmlCanvasElement,
+    HtmlSelectElement, ImageData,
+};
+
+const SCREENSHOT_NAME: &str = "chip8-screenshot.png";
+const SVG_NAME: &str = "chip8-screenshot.svg";
+/// How long the copy button says the screenshot was copied, in milliseconds.
+const COPIED_DURATION: u32 = 1500;
+
+/// Makes the screenshot buttons download the display as a PNG or an SVG, or
+/// copy it to the clipboard, scaled up by the factor chosen next to them.
+pub fn register_screenshot_buttons(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    {
+        let chip8 = Rc::clone(chip8);
+        EventListener::new(&get_element_by_id("screenshot"), "click", move |_| {
+            get_button("screenshot").blur().unwrap();
+            let result = draw_screenshot(&chip8.borrow(), get_scale()).and_then(|canvas| {
+                let download = Closure::once_into_js(|blob: Option<Blob>| match blob {
+                    Some(blob) => download_blob(SCREENSHOT_NAME, &blob),
+                    None => console::error_1(&"Can't encode the screenshot as PNG".into()),
+                });
+                canvas.to_blob_with_type(download.unchecked_ref(), "image/png")
+            });
+            if let Err(e) = result {
+                console::error_2(&"Can't take a screenshot".into(), &e);
+            }
+        })
+        .forget();
+    }
+
+    {
+        let chip8 = Rc::clone(chip8);
+        EventListener::new(&get_element_by_id("screenshot-svg"), "click", move |_| {
+            get_button("screenshot-svg").blur().unwrap();
+            let chip8 = chip8.borrow();
+            let svg = display_svg(
+                chip8.get_gfx_width(),
+                chip8.get_gfx_height(),
+                get_scale(),
+                display_colors(&chip8),
+            );
+            download_file(SVG_NAME, svg.as_bytes(), "image/svg+xml");
+        })
+        .forget();
+    }
+
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("screenshot-copy"), "click", move |_| {
+        get_button("screenshot-copy").blur().unwrap();
+        // Browsers only allow writing to the clipboard right after a click,
+        // so the write starts now and is given the PNG once it's encoded.
+        let written = draw_screenshot(&chip8.borrow(), get_scale()).and_then(copy_to_clipboard);
+        spawn_local(async move {
+            let result = match written {
+                Ok(written) => JsFuture::from(written).await.map(|_| ()),
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => show_copied(),
+                Err(e) => console::error_2(&"Can't copy the screenshot".into(), &e),
+            }
+        });
+    })
+    .forget();
+}
+
+/// Draws the display on a canvas of its own, so the screenshot has exactly
+/// the display's colors whatever the page's canvas is scaled or filtered
+/// with.
+fn draw_screenshot(chip8: &Chip8Emulator, scale: u32) -> Result<HtmlCanvasElement, JsValue> {
+    let (width, height) = (chip8.get_gfx_width(), chip8.get_gfx_height());
+    let colors = display_colors(chip8);
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let [r, g, b] = colors(x, y);
+            pixels.extend_from_slice(&[r, g, b, 0xff]);
+        }
+    }
+
+    let image = scale_image(&pixels, width, 4, scale);
+    let canvas = window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .create_element("canvas")?
+        .dyn_into::<HtmlCanvasElement>()?;
+    canvas.set_width(width * scale);
+    canvas.set_height(height * scale);
+    canvas
+        .get_context("2d")?
+        .ok_or("Can't get a 2d context for the screenshot")?
+        .dyn_into::<CanvasRenderingContext2d>()?
+        .put_image_data(
+            &ImageData::new_with_u8_clamped_array_and_sh(
+                Clamped(&image),
+                width * scale,
+                height * scale,
+            )?,
+            0.0,
+            0.0,
+        )?;
+    Ok(canvas)
+}
+
+/// Draws the display as an SVG image, `scale` times its size by default. The
+/// most common color fills the background, and each run of pixels of another
+/// color along a row is one rectangle, grouped by color.
+pub fn display_svg(
+    width: u32,
+    height: u32,
+    scale: u32,
+    color_at: impl Fn(u32, u32) -> [u8; 3],
+) -> String {
+    let mut counts = HashMap::new();
+    for y in 0..height {
+        for x in 0..width {
+            *counts.entry(color_at(x, y)).or_insert(0) += 1;
+        }
+    }
+    let background = counts
+        .into_iter()
+        .max_by_key(|&(color, count)| (count, color))
+        .map_or([0, 0, 0], |(color, _)| color);
+
+    // Rectangles of each color, in the order the colors first appear.
+    let mut groups: Vec<([u8; 3], String)> = Vec::new();
+    for y in 0..height {
+        let mut x = 0;
+        while x < width {
+            let color = color_at(x, y);
+            let start = x;
+            while x < width && color_at(x, y) == color {
+                x += 1;
+            }
+            if color == background {
+                continue;
+            }
+            let rects = match groups.iter().position(|&(group, _)| group == color) {
+                Some(index) => &mut groups[index].1,
+                None => {
+                    groups.push((color, String::new()));
+                    &mut groups.last_mut().unwrap().1
+                }
+            };
+            write!(
+                rects,
+                r#"<rect x="{}" y="{}" width="{}" height="1"/>"#,
+                start,
+                y,
+                x - start
+            )
+            .unwrap();
+        }
+    }
+
+    let mut svg = format!(
+        concat!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {w} {h}" "#,
+            r#"width="{sw}" height="{sh}" shape-rendering="crispEdges">"#,
+            r#"<rect width="{w}" height="{h}" fill="{fill}"/>"#
+        ),
+        w = width,
+        h = height,
+        sw = width * scale,
+        sh = height * scale,
+        fill = hex_color(background)
+    );
+    for (color, rects) in groups {
+        write!(svg, r#"<g fill="{}">{}</g>"#, hex_color(color), rects).unwrap();
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Writes the canvas to the clipboard as a PNG with the async Clipboard API,
+/// returning the promise of the write.
+fn copy_to_clipboard(canvas: HtmlCanvasElement) -> Result<Promise, JsValue> {
+    let png = Promise::new(&mut |resolve, reject| {
+        if let Err(e) = canvas.to_blob_with_type(&resolve, "image/png") {
+            reject.call1(&JsValue::UNDEFINED, &e).unwrap();
+        }
+    });
+    let data = Object::new();
+    Reflect::set(&data, &"image/png".into(), &png)?;
+    let item = Reflect::construct(
+        Reflect::get(&global(), &"ClipboardItem".into())?.unchecked_ref::<Function>(),
+        &Array::of1(&data),
+    )?;
+
+    let clipboard = Reflect::get(&window().unwrap().navigator(), &"clipboard".into())?;
+    let write = Reflect::get(&clipboard, &"write".into())?.dyn_into::<Function>()?;
+    Ok(write
+        .call1(&clipboard, &Array::of1(&item))?
+        .unchecked_into())
+}
+
+/// Says on the copy button that the screenshot was copied, for a moment.
+fn show_copied() {
+    let button = get_button("screenshot-copy");
+    button.set_inner_text("Copied");
+    Timeout::new(COPIED_DURATION, move || button.set_inner_text("Copy")).forget();
+}
+
+fn get_scale() -> u32 {
+    get_element_by_id("screenshot-scale")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #screenshot-scale is not a select element")
+        .value()
+        .parse()
+        .unwrap_or(1)
+}
+
+fn get_button(id: &str) -> HtmlButtonElement {
+    get_element_by_id(id)
+        .dyn_into::<HtmlButtonElement>()
+        .unwrap_or_else(|_| panic!("Element with id #{} is not a button element", id))
+}
+
+/// Scales up an image `width` pixels wide, of `pixel_size` bytes per pixel, by
+/// repeating each pixel `scale` times across and down.
+pub fn scale_image(pixels: &[u8], width: u32, pixel_size: usize, scale: u32) -> Vec<u8> {
+    let (width, scale) = (width as usize, scale as usize);
+    let mut scaled = Vec::with_capacity(pixels.len() * scale * scale);
+    for row in pixels.chunks_exact(width * pixel_size) {
+        let start = scaled.len();
+        for pixel in row.chunks_exact(pixel_size) {
+            for _ in 0..scale {
+                scaled.extend_from_slice(pixel);
+            }
+        }
+        for _ in 1..scale {
+            scaled.extend_from_within(start..start + width * scale * pixel_size);
+        }
+    }
+    scaled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scale_image() {
+        let red = [0xff, 0, 0, 0xff];
+        let blue = [0, 0, 0xff, 0xff];
+        let pixels = [red, blue, blue, red].concat();
+
+        assert_eq!(scale_image(&pixels, 2, 4, 1), pixels);
+        assert_eq!(scale_image(&[1, 2], 2, 1, 2), [1, 1, 2, 2, 1, 1, 2, 2]);
+        let scaled = scale_image(&pixels, 2, 4, 2);
+        assert_eq!(
+            scaled,
+            [
+                red, red, blue, blue, //
+                red, red, blue, blue, //
+                blue, blue, red, red, //
+                blue, blue, red, red,
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_display_svg() {
+        // A row with a run of two lit pixels, and a lone red pixel below.
+        let lit = [(1, 0), (2, 0)];
+        let svg = display_svg(4, 2, 10, |x, y| {
+            if lit.contains(&(x, y)) {
+                [0xff, 0xff, 0xff]
+            } else if (x, y) == (3, 1) {
+                [0xff, 0, 0]
+            } else {
+                [0, 0, 0]
+            }
+        });
+        assert_eq!(
+            svg,
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 4 2" "#,
+                r#"width="40" height="20" shape-rendering="crispEdges">"#,
+                r##"<rect width="4" height="2" fill="#000000"/>"##,
+                r##"<g fill="#ffffff"><rect x="1" y="0" width="2" height="1"/></g>"##,
+                r##"<g fill="#ff0000"><rect x="3" y="1" width="1" height="1"/></g>"##,
+                "</svg>\n"
+            )
+        );
+    }
+}
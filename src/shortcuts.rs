@@ -0,0 +1,199 @@
+//! Keyboard shortcuts for controlling the emulator: P pauses and resumes,
+//! Backspace starts the ROM over, F5 and F7 save and load a state, and Tab
+//! fast-forwards while held. A shortcut whose key is bound to the keypad is
+//! left to the game, and the Controls panel says so.
+
+use super::{get_element_by_id, is_typing, osd, pause_menu, reload_rom, MAX_FRAME_GAP};
+use crate::chip8emulator::Chip8Emulator;
+use crate::keymap::{KEY_MAP, PLAYER_2_KEY_MAP, SECOND_KEY_MAP};
+use gloo::events::{EventListener, EventListenerOptions};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{window, KeyboardEvent};
+
+/// How many times faster than real time the emulator runs while
+/// fast-forwarding.
+const FAST_FORWARD_SPEED: f64 = 4.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Shortcut {
+    Pause,
+    Reset,
+    SaveState,
+    LoadState,
+    FastForward,
+}
+
+impl Shortcut {
+    const ALL: [Shortcut; 5] = [
+        Shortcut::Pause,
+        Shortcut::Reset,
+        Shortcut::SaveState,
+        Shortcut::LoadState,
+        Shortcut::FastForward,
+    ];
+
+    /// Returns the `KeyboardEvent.code` of the shortcut's key. R would be
+    /// the obvious key for resetting, but it's bound to the keypad by default.
+    fn code(self) -> &'static str {
+        match self {
+            Shortcut::Pause => "KeyP",
+            Shortcut::Reset => "Backspace",
+            Shortcut::SaveState => "F5",
+            Shortcut::LoadState => "F7",
+            Shortcut::FastForward => "Tab",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Shortcut::Pause => "Pause",
+            Shortcut::Reset => "Reset",
+            Shortcut::SaveState => "Save state",
+            Shortcut::LoadState => "Load state",
+            Shortcut::FastForward => "Fast-forward",
+        }
+    }
+
+    /// Returns the shortcut with the key `code`, unless `is_bound` says the
+    /// key is bound to the keypad.
+    fn for_code(code: &str, is_bound: impl Fn(&str) -> bool) -> Option<Shortcut> {
+        Shortcut::ALL
+            .iter()
+            .copied()
+            .find(|shortcut| shortcut.code() == code && !is_bound(code))
+    }
+}
+
+/// Returns the shortcuts whose keys `is_bound` says are bound to the keypad.
+fn conflicts(is_bound: impl Fn(&str) -> bool) -> Vec<Shortcut> {
+    Shortcut::ALL
+        .iter()
+        .copied()
+        .filter(|shortcut| is_bound(shortcut.code()))
+        .collect()
+}
+
+/// Returns whether the key `code` is bound to either player's keypad keys.
+fn is_bound_to_keypad(code: &str) -> bool {
+    [&KEY_MAP, &PLAYER_2_KEY_MAP]
+        .iter()
+        .any(|key_map| key_map.with(|key_map| key_map.borrow().position(code).is_some()))
+}
+
+thread_local! {
+    static FAST_FORWARDING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Handles the shortcuts' keys, except while the pause menu is open.
+pub fn register_shortcuts(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let keydown_chip8 = Rc::clone(chip8);
+    // Not passive, so Tab doesn't move the focus and F5 doesn't reload.
+    let options = EventListenerOptions::enable_prevent_default();
+    EventListener::new_with_options(&window().unwrap(), "keydown", options, move |e| {
+        let e = e.dyn_ref::<KeyboardEvent>().unwrap();
+        if is_typing(e) {
+            return;
+        }
+        let chip8 = &keydown_chip8;
+        let second_keypad = chip8.borrow().has_second_keypad();
+        let shortcut = match Shortcut::for_code(&e.code(), |code| {
+            is_bound_to_keypad(code)
+                || second_keypad
+                    && SECOND_KEY_MAP.with(|key_map| key_map.borrow().position(code).is_some())
+        }) {
+            Some(shortcut) if !pause_menu::is_open() => shortcut,
+            _ => return,
+        };
+        e.prevent_default();
+        if e.repeat() {
+            return;
+        }
+
+        match shortcut {
+            Shortcut::Pause => {
+                let paused = chip8.borrow().is_paused();
+                if paused {
+                    chip8.borrow_mut().resume();
+                    osd::show("Resumed");
+                } else {
+                    chip8.borrow_mut().pause();
+                    osd::show("Paused");
+                }
+            }
+            Shortcut::Reset => {
+                reload_rom(chip8);
+                osd::show("Reset");
+            }
+            Shortcut::SaveState => pause_menu::save_state(chip8),
+            Shortcut::LoadState => pause_menu::load_state(chip8),
+            Shortcut::FastForward => {
+                FAST_FORWARDING.with(|fast_forwarding| fast_forwarding.set(true));
+                osd::show("Fast-forward");
+            }
+        }
+    })
+    .forget();
+
+    EventListener::new(&window().unwrap(), "keyup", |e| {
+        let e = e.dyn_ref::<KeyboardEvent>().unwrap();
+        if e.code() == Shortcut::FastForward.code() {
+            FAST_FORWARDING.with(|fast_forwarding| fast_forwarding.set(false));
+        }
+    })
+    .forget();
+
+    // The key up is missed if the page loses the focus while Tab is held.
+    EventListener::new(&window().unwrap(), "blur", |_| {
+        FAST_FORWARDING.with(|fast_forwarding| fast_forwarding.set(false));
+    })
+    .forget();
+}
+
+/// Runs the extra emulated time of a frame `frame_gap` milliseconds after
+/// the previous one, while fast-forwarding.
+pub fn fast_forward(chip8: &mut Chip8Emulator, frame_gap: f64) {
+    if FAST_FORWARDING.with(Cell::get) {
+        chip8.fast_forward(frame_gap.min(MAX_FRAME_GAP) * (FAST_FORWARD_SPEED - 1.0));
+    }
+}
+
+/// Lists the shortcuts left to the game in the Controls panel, since their
+/// keys are bound to the keypad.
+pub fn show_conflicts() {
+    let conflicts = conflicts(is_bound_to_keypad);
+    let text = conflicts
+        .iter()
+        .map(|shortcut| format!("{} ({})", shortcut.label(), shortcut.code()))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let element = get_element_by_id("shortcut-conflicts");
+    if conflicts.is_empty() {
+        element.set_text_content(None);
+    } else {
+        element.set_text_content(Some(&format!(
+            "Bound to the keypad, so not shortcuts: {}",
+            text
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shortcut_conflicts() {
+        let unbound = |_: &str| false;
+        assert_eq!(Shortcut::for_code("KeyP", unbound), Some(Shortcut::Pause));
+        assert_eq!(Shortcut::for_code("F5", unbound), Some(Shortcut::SaveState));
+        assert_eq!(Shortcut::for_code("KeyQ", unbound), None);
+        assert_eq!(conflicts(unbound), []);
+
+        // The left-handed bindings put P on the keypad.
+        let left_handed = |code: &str| code == "KeyP";
+        assert_eq!(Shortcut::for_code("KeyP", left_handed), None);
+        assert_eq!(conflicts(left_handed), [Shortcut::Pause]);
+    }
+}
@@ -0,0 +1,273 @@
+//! Soak test that plays random keys into ROMs for as long as it's given, to
+//! catch what short unit tests miss: panics deep into a game, buffers that
+//! grow without bound and state that stops being reproducible.
+//!
+//! Each session runs a ROM twice from the same seed with the same random key
+//! presses, the second time saving and restoring the machine at every
+//! checkpoint. Both runs must hash to the same state at every checkpoint, no
+//! instruction may fault, the rewind history and trace must stay within their
+//! limits, and the heap may not grow by more than `MEMORY_BUDGET` while they
+//! run. A ROM that ends, like IBM's logo, ends its session early, which is
+//! reported but isn't a failure. The heap
+//! is measured by `CountingAllocator`, which the `chip8-soak` binary installs
+//! as its global allocator unless the `wee_alloc` feature is on.
+
+use crate::chip8emulator::{Chip8Emulator, State};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+const FRAME_INTERVAL: f64 = 1000.0 / 60.0;
+/// Five minutes of simulated time per session.
+const SESSION_FRAMES: u32 = 5 * 60 * 60;
+const CHECKPOINT_FRAMES: u32 = 600;
+/// Chance each frame that a random key changes state.
+const KEY_CHANGE_CHANCE: f64 = 0.1;
+/// How much more heap a session may use than before it started.
+const MEMORY_BUDGET: usize = 8 * 1024 * 1024;
+/// How many instructions back the rewind history and the trace keep, small
+/// enough not to slow the sessions down much.
+const HISTORY_LIMIT: usize = 64;
+const TRACE_LIMIT: usize = 256;
+
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, keeping count of how many bytes are allocated.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            LIVE_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            LIVE_BYTES.fetch_sub(layout.size(), Ordering::Relaxed);
+            LIVE_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        }
+        new_ptr
+    }
+}
+
+/// Runs sessions of every ROM in `roms_dir` until `duration` has passed, and
+/// at least one round of them. Round `n` plays every ROM with seed
+/// `seed + n`, so a failure can be replayed by starting from that seed.
+/// Returns the number of failed sessions.
+pub fn run(roms_dir: &str, duration: Duration, seed: u64) -> io::Result<u32> {
+    let roms = read_roms(Path::new(roms_dir))?;
+    if roms.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "No ROMs found"));
+    }
+
+    let start = Instant::now();
+    let mut failures = 0;
+    let mut round_seed = seed;
+    loop {
+        for (name, rom) in &roms {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| session(rom, round_seed)));
+            match result {
+                Ok(Ok(None)) => eprintln!("{} with seed {}: ok", name, round_seed),
+                Ok(Ok(Some(end))) => {
+                    eprintln!(
+                        "{} with seed {}: ok, ended at {:03X}",
+                        name, round_seed, end
+                    )
+                }
+                Ok(Err(e)) => {
+                    eprintln!("{} with seed {}: {}", name, round_seed, e);
+                    failures += 1;
+                }
+                Err(_) => {
+                    eprintln!("{} with seed {}: panicked", name, round_seed);
+                    failures += 1;
+                }
+            }
+        }
+
+        round_seed += 1;
+        if start.elapsed() >= duration {
+            return Ok(failures);
+        }
+    }
+}
+
+/// Reads every file in `dir` other than the manifest, sorted by name.
+fn read_roms(dir: &Path) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let mut roms = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_none() {
+            let name = path.file_name().unwrap().to_string_lossy().into_owned();
+            roms.push((name, fs::read(&path)?));
+        }
+    }
+    roms.sort();
+    Ok(roms)
+}
+
+/// Plays a session of `rom`, and returns where it ended, if it did.
+fn session(rom: &[u8], seed: u64) -> Result<Option<u16>, String> {
+    let baseline = LIVE_BYTES.load(Ordering::Relaxed);
+    let (expected, end) = play(rom, seed, false, baseline)?;
+    let (restored, restored_end) = play(rom, seed, true, baseline)?;
+
+    match expected.iter().zip(&restored).position(|(a, b)| a != b) {
+        Some(checkpoint) => Err(format!(
+            "state differs after restoring at frame {}",
+            (checkpoint as u32 + 1) * CHECKPOINT_FRAMES
+        )),
+        None if end != restored_end => Err("ended differently after restoring".to_string()),
+        None => Ok(end),
+    }
+}
+
+/// Plays `rom` for a session with random keys from `seed`, and returns the
+/// state hash at each checkpoint and where the ROM ended, if it did. With
+/// `restore`, the machine is saved and restored at each checkpoint before
+/// it's hashed.
+fn play(
+    rom: &[u8],
+    seed: u64,
+    restore: bool,
+    baseline: usize,
+) -> Result<(Vec<u64>, Option<u16>), String> {
+    let mut chip8 = Chip8Emulator::new(0.0);
+    chip8.seed_rng(seed);
+    chip8.set_history_limit(HISTORY_LIMIT);
+    chip8.set_trace_limit(TRACE_LIMIT);
+    chip8.load_rom(rom)?;
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut pressed = [false; 16];
+    let mut hashes = Vec::new();
+    for frame in 1..=SESSION_FRAMES {
+        if rng.gen_bool(KEY_CHANGE_CHANCE) {
+            let key = rng.gen_range(0, 16);
+            pressed[key as usize] ^= true;
+            chip8.queue_key(key, pressed[key as usize]);
+        }
+
+        let current_time = frame as f64 * FRAME_INTERVAL;
+        chip8.tick(current_time);
+        chip8.take_gfx_dirty_rows();
+        match chip8.get_state() {
+            State::Faulted => {
+                let report = chip8.get_crash_report().unwrap();
+                return Err(format!(
+                    "{} at {:03X} ({:04X}) in frame {}",
+                    report.reason, report.address, report.opcode, frame
+                ));
+            }
+            State::Halted => return Ok((hashes, Some(chip8.get_pc()))),
+            _ => {}
+        }
+
+        if frame % CHECKPOINT_FRAMES == 0 {
+            if restore {
+                let state = chip8.save_state();
+                chip8.load_state(&state, current_time);
+            }
+            hashes.push(state_hash(&chip8));
+
+            let history = chip8.get_history_length();
+            let trace = chip8.get_trace().count();
+            if history > HISTORY_LIMIT || trace > TRACE_LIMIT {
+                return Err(format!(
+                    "rewind history of {} and trace of {} by frame {}",
+                    history, trace, frame
+                ));
+            }
+
+            let used = LIVE_BYTES.load(Ordering::Relaxed).saturating_sub(baseline);
+            if used > MEMORY_BUDGET {
+                return Err(format!("heap grew by {} bytes by frame {}", used, frame));
+            }
+        }
+    }
+    Ok((hashes, None))
+}
+
+/// Hashes everything a program can observe or that's shown on screen.
+fn state_hash(chip8: &Chip8Emulator) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    chip8.get_memory().hash(&mut hasher);
+    chip8.get_registers().hash(&mut hasher);
+    chip8.get_I().hash(&mut hasher);
+    chip8.get_pc().hash(&mut hasher);
+    chip8.get_stack().hash(&mut hasher);
+    chip8.get_delay_timer().hash(&mut hasher);
+    chip8.get_sound_timer().hash(&mut hasher);
+
+    let width = chip8.get_gfx_width();
+    let height = chip8.get_gfx_height();
+    (width, height).hash(&mut hasher);
+    for y in 0..height {
+        for x in 0..width {
+            chip8.get_gfx_pixel(x, y).hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session() {
+        // Draws a random byte at a random position, and waits for a key.
+        let rom = [
+            0xc0, 0xff, // V0 = random
+            0xc1, 0x1f, // V1 = random & 0x1f
+            0xa3, 0x00, // I = 0x300
+            0xf0, 0x55, // Store V0 at I
+            0xd1, 0x11, // Draw 1 row at (V1, V1)
+            0xf2, 0x0a, // V2 = next key
+            0x12, 0x00, // Jump to start
+        ];
+        assert_eq!(session(&rom, 1), Ok(None));
+
+        let (first, _) = play(&rom, 1, false, 0).unwrap();
+        assert_eq!(first.len(), (SESSION_FRAMES / CHECKPOINT_FRAMES) as usize);
+        assert_ne!(first, play(&rom, 2, false, 0).unwrap().0);
+    }
+
+    #[test]
+    fn test_session_end() {
+        let rom = [
+            0xc0, 0xff, // V0 = random
+            0x12, 0x02, // Jump to self
+        ];
+        assert_eq!(session(&rom, 1), Ok(Some(0x202)));
+    }
+
+    #[test]
+    fn test_session_fault() {
+        let rom = [
+            0xc0, 0xff, // V0 = random
+            0x00, 0xee, // Return with nothing on the stack
+        ];
+        assert_eq!(
+            session(&rom, 1),
+            Err("Return with an empty stack at 202 (00EE) in frame 1".to_string())
+        );
+    }
+}
@@ -0,0 +1,138 @@
+//! Sprite editor in the Sprites panel: a grid of the pixels of the sprite
+//! picked in the gallery, each switched on or off by clicking it. Edits are
+//! written to memory right away, and to the ROM too if Also change the ROM
+//! is checked, so they last through resets and Export ROM saves them.
+
+use super::{download_file, get_element_by_id, get_rom, patch_rom, sprite_viewer};
+use crate::chip8emulator::{sprite_pixel, toggle_sprite_pixel, Chip8Emulator, SPRITE_WIDTH};
+use gloo::events::EventListener;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{console, window, Element, HtmlCanvasElement, HtmlElement, HtmlInputElement};
+
+const EXPORT_NAME: &str = "chip8-edited.ch8";
+
+/// The sprite being edited.
+struct Editing {
+    address: u16,
+    height: u16,
+    /// Its tile in the gallery, redrawn after each edit.
+    tile: HtmlCanvasElement,
+}
+
+thread_local! {
+    static EDITING: RefCell<Option<Editing>> = const { RefCell::new(None) };
+}
+
+/// Switches pixels clicked in the grid, and exports the ROM with the Export
+/// ROM button.
+pub fn register_sprite_editor(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(
+        &get_element_by_id("sprite-editor-grid"),
+        "click",
+        move |e| {
+            let pixel = match e
+                .target()
+                .and_then(|target| target.dyn_into::<Element>().ok())
+            {
+                Some(pixel) => pixel,
+                None => return,
+            };
+            let position = |name| pixel.get_attribute(name)?.parse::<u16>().ok();
+            if let (Some(x), Some(y)) = (position("data-x"), position("data-y")) {
+                toggle_pixel(&mut chip8.borrow_mut(), x.into(), y);
+            }
+        },
+    )
+    .forget();
+
+    EventListener::new(&get_element_by_id("rom-export"), "click", |_| {
+        download_file(EXPORT_NAME, &get_rom(), "application/octet-stream");
+    })
+    .forget();
+}
+
+/// Opens the `height`-row sprite at `address`, shown in the gallery on
+/// `tile`, in the editor.
+pub fn edit_sprite(chip8: &Chip8Emulator, address: u16, height: u16, tile: HtmlCanvasElement) {
+    EDITING.with(|editing| {
+        *editing.borrow_mut() = Some(Editing {
+            address,
+            height,
+            tile,
+        })
+    });
+    get_element_by_id("sprite-editor")
+        .dyn_into::<HtmlElement>()
+        .expect("Element with id #sprite-editor is not an HTML element")
+        .set_hidden(false);
+    set_status(&format!("Editing the sprite at {:03X}", address));
+    show_grid(chip8, address, height);
+}
+
+fn toggle_pixel(chip8: &mut Chip8Emulator, x: u32, y: u16) {
+    EDITING.with(|editing| {
+        let editing = editing.borrow();
+        let Editing {
+            address,
+            height,
+            tile,
+        } = match &*editing {
+            Some(editing) => editing,
+            None => return,
+        };
+        let row_address = address + y;
+        let row = toggle_sprite_pixel(chip8.memory_slice(row_address, 1)[0], x);
+        chip8.write_memory(row_address, &[row]);
+        if get_checkbox().checked() && !patch_rom(chip8, row_address, &[row]) {
+            set_status(&format!(
+                "{:03X} is outside the ROM, so only memory was changed",
+                row_address
+            ));
+        }
+
+        show_grid(chip8, *address, *height);
+        if let Err(e) =
+            sprite_viewer::draw_sprite(tile, chip8.memory_slice(*address, *height as usize))
+        {
+            console::error_2(&"Can't redraw the sprite".into(), &e);
+        }
+    });
+}
+
+/// Fills the grid with a cell for each pixel of the sprite, marked when
+/// it's on.
+fn show_grid(chip8: &Chip8Emulator, address: u16, height: u16) {
+    let document = window().unwrap().document().unwrap();
+    let grid = get_element_by_id("sprite-editor-grid");
+    grid.set_text_content(None);
+    for (y, &row) in chip8
+        .memory_slice(address, height as usize)
+        .iter()
+        .enumerate()
+    {
+        for x in 0..SPRITE_WIDTH {
+            let pixel = document.create_element("div").unwrap();
+            pixel.set_class_name(if sprite_pixel(row, x) {
+                "sprite-pixel on"
+            } else {
+                "sprite-pixel"
+            });
+            pixel.set_attribute("data-x", &x.to_string()).unwrap();
+            pixel.set_attribute("data-y", &y.to_string()).unwrap();
+            grid.append_child(&pixel).unwrap();
+        }
+    }
+}
+
+fn set_status(text: &str) {
+    get_element_by_id("sprite-editor-status").set_text_content(Some(text));
+}
+
+fn get_checkbox() -> HtmlInputElement {
+    get_element_by_id("sprite-edit-rom")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #sprite-edit-rom is not an input element")
+}
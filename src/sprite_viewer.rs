@@ -0,0 +1,158 @@
+//! Sprites panel: shows the memory of a range, the loaded ROM by default, as
+//! a gallery of sprites drawn the way DXYN draws them, a byte a row, to help
+//! find the graphics in a ROM. By default only the ones with a pixel on that
+//! the ROM reads as data are shown. Clicking one opens it in the sprite
+//! editor.
+
+use super::{get_element_by_id, palette_picker, sprite_editor};
+use crate::chip8emulator::{sprite_rgba, Chip8Emulator, SPRITE_WIDTH};
+use gloo::events::EventListener;
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+use wasm_bindgen::{Clamped, JsCast, JsValue};
+use web_sys::{
+    console, window, CanvasRenderingContext2d, HtmlCanvasElement, HtmlInputElement, ImageData,
+};
+
+/// How many sprites the gallery shows at most.
+const SPRITE_LIMIT: usize = 256;
+/// The most rows DXYN draws.
+const MAX_SPRITE_HEIGHT: u16 = 15;
+
+/// Shows the sprites in the range typed in the panel with the Show sprites
+/// button.
+pub fn register_sprite_viewer(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("sprite-show"), "click", move |_| {
+        if let Err(e) = show_sprites(&chip8) {
+            console::error_2(&"Can't show the sprites".into(), &e);
+        }
+    })
+    .forget();
+}
+
+fn show_sprites(chip8: &Rc<RefCell<Chip8Emulator>>) -> Result<(), JsValue> {
+    let range = match parse_range(
+        &get_input("sprite-start").value(),
+        &get_input("sprite-end").value(),
+        chip8.borrow().get_rom_range(),
+    ) {
+        Some(range) => range,
+        None => {
+            set_status("Expected addresses in hex like 200, the first one before the second");
+            return Ok(());
+        }
+    };
+    let height = get_input("sprite-height")
+        .value()
+        .parse()
+        .unwrap_or(8)
+        .clamp(1, MAX_SPRITE_HEIGHT);
+    let data_only = get_input("sprite-data-only").checked();
+    let addresses = chip8
+        .borrow()
+        .find_sprites(range.clone(), height, data_only);
+    set_status(&format!(
+        "{} sprites {} rows high in {:03X}-{:03X}{}",
+        addresses.len(),
+        height,
+        range.start,
+        range.end.saturating_sub(1),
+        if addresses.len() > SPRITE_LIMIT {
+            format!(", showing the first {}", SPRITE_LIMIT)
+        } else {
+            String::new()
+        }
+    ));
+
+    let document = window().unwrap().document().unwrap();
+    let gallery = get_element_by_id("sprite-gallery");
+    gallery.set_text_content(None);
+    for address in addresses.into_iter().take(SPRITE_LIMIT) {
+        let height = height.min(range.end - address);
+        let tile = document.create_element("figure")?;
+        tile.set_class_name("sprite-tile");
+        tile.set_attribute("title", "Edit this sprite")?;
+        let canvas = document
+            .create_element("canvas")?
+            .dyn_into::<HtmlCanvasElement>()?;
+        canvas.set_class_name("sprite-canvas");
+        draw_sprite(
+            &canvas,
+            chip8.borrow().memory_slice(address, height as usize),
+        )?;
+        tile.append_child(&canvas)?;
+
+        let label = document.create_element("figcaption")?;
+        label.set_text_content(Some(&format!("{:03X}", address)));
+        tile.append_child(&label)?;
+        gallery.append_child(&tile)?;
+
+        let chip8 = Rc::clone(chip8);
+        EventListener::new(&tile, "click", move |_| {
+            sprite_editor::edit_sprite(&chip8.borrow(), address, height, canvas.clone());
+        })
+        .forget();
+    }
+    Ok(())
+}
+
+/// Draws the sprite with the rows `rows` on `canvas`, a canvas pixel a
+/// sprite pixel, in the display's colors.
+pub fn draw_sprite(canvas: &HtmlCanvasElement, rows: &[u8]) -> Result<(), JsValue> {
+    canvas.set_width(SPRITE_WIDTH);
+    canvas.set_height(rows.len() as u32);
+    let image = ImageData::new_with_u8_clamped_array_and_sh(
+        Clamped(&sprite_rgba(rows, palette_picker::current_palette())),
+        SPRITE_WIDTH,
+        rows.len() as u32,
+    )?;
+    canvas
+        .get_context("2d")?
+        .unwrap()
+        .dyn_into::<CanvasRenderingContext2d>()?
+        .put_image_data(&image, 0.0, 0.0)
+}
+
+/// Returns the addresses from `start` up to `end`, both in hex, or the
+/// bounds of `rom` for the ones left empty.
+fn parse_range(start: &str, end: &str, rom: Range<u16>) -> Option<Range<u16>> {
+    let parse = |text: &str, default: u16| {
+        let text = text.trim();
+        if text.is_empty() {
+            return Some(default);
+        }
+        let digits = text
+            .strip_prefix("0x")
+            .or_else(|| text.strip_prefix("0X"))
+            .unwrap_or(text);
+        u16::from_str_radix(digits, 16).ok()
+    };
+    let range = parse(start, rom.start)?..parse(end, rom.end)?;
+    (range.start < range.end && range.end <= 0x1000).then_some(range)
+}
+
+fn set_status(text: &str) {
+    get_element_by_id("sprite-status").set_text_content(Some(text));
+}
+
+fn get_input(id: &str) -> HtmlInputElement {
+    get_element_by_id(id)
+        .dyn_into::<HtmlInputElement>()
+        .unwrap_or_else(|_| panic!("Element with id #{} is not an input element", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("", "", 0x200..0x2f6), Some(0x200..0x2f6));
+        assert_eq!(parse_range("0x50", "a0", 0x200..0x2f6), Some(0x50..0xa0));
+        assert_eq!(parse_range("300", "", 0x200..0x2f6), None);
+        assert_eq!(parse_range("F00", "1001", 0x200..0x2f6), None);
+        assert_eq!(parse_range("G00", "", 0x200..0x2f6), None);
+    }
+}
@@ -0,0 +1,359 @@
+use crate::audio::Tone;
+use crate::chip8emulator::{Cheat, Palette};
+use crate::display_filter::PixelShape;
+use crate::joystick::Directions;
+use crate::keymap::{ControlProfile, KeyMap};
+use crate::macros::MacroSlots;
+use crate::turbo::TurboSettings;
+use wasm_bindgen::JsValue;
+use web_sys::{window, Storage};
+
+/// Prefix of the local storage keys save RAM is kept under. The rest of the
+/// key is the checksum of the ROM it belongs to.
+const SAVE_RAM_KEY_PREFIX: &str = "chip8-save-ram-";
+/// Prefix of the local storage keys buzzer tones chosen for a ROM are kept
+/// under, followed by the ROM's checksum.
+const TONE_KEY_PREFIX: &str = "chip8-tone-";
+/// Prefix of the local storage keys a ROM's own key bindings are kept under,
+/// followed by the ROM's checksum.
+const ROM_CONTROLS_KEY_PREFIX: &str = "chip8-rom-controls-";
+/// Prefix of the local storage keys a ROM's cheats are kept under, followed
+/// by the ROM's checksum. They're separated by commas, each written as by
+/// `Cheat`'s `Display` impl.
+const CHEATS_KEY_PREFIX: &str = "chip8-cheats-";
+/// Local storage key of the display palette, written as by `Palette`'s
+/// `Display` impl.
+const PALETTE_KEY: &str = "chip8-palette";
+/// Local storage key of whether the palette follows the system's color
+/// scheme, as "1" or "0".
+const SYSTEM_PALETTE_KEY: &str = "chip8-palette-system";
+/// Local storage key of whether the CRT filter is on, as "1" or "0".
+const CRT_FILTER_KEY: &str = "chip8-crt-filter";
+const PIXEL_SHAPE_KEY: &str = "chip8-pixel-shape";
+/// Local storage key of the part of a pixel's brightness kept each frame after
+/// it's switched off.
+const AFTERGLOW_KEY: &str = "chip8-afterglow";
+/// Local storage key of whether the performance overlay is shown, as "1" or
+/// "0".
+const PERF_OVERLAY_KEY: &str = "chip8-perf-overlay";
+/// Local storage key of whether the keypad view is shown, as "1" or "0".
+const KEYPAD_VIEW_KEY: &str = "chip8-keypad-view";
+/// Local storage keys of the keyboard bindings of the keypad, one for each
+/// key set, written as by `KeyMap`'s `Display` impl, absent for the default
+/// ones.
+const KEY_MAP_KEYS: [&str; 2] = ["chip8-key-map", "chip8-key-map-2"];
+/// Local storage key of the recorded macros and their hotkeys, written as by
+/// `MacroSlots`' `Display` impl.
+const MACROS_KEY: &str = "chip8-macros";
+/// Local storage key of the turbo keys, written as by `TurboSettings`'
+/// `Display` impl.
+const TURBO_KEY: &str = "chip8-turbo";
+/// Local storage key of the keys the virtual joystick and swipes press,
+/// written as by `Directions`' `Display` impl.
+const JOYSTICK_KEY: &str = "chip8-joystick";
+/// Local storage key of whether the virtual joystick and swipes are on, as "1"
+/// or "0".
+const TOUCH_CONTROLS_KEY: &str = "chip8-touch-controls";
+/// Local storage key of the MIDI note of the bottom left pad.
+const MIDI_FIRST_NOTE_KEY: &str = "chip8-midi-first-note";
+/// Local storage key of the name of the theme chosen, absent for none.
+const THEME_KEY: &str = "chip8-theme";
+
+/// Returns the save RAM stored for the ROM with the given checksum, if any.
+pub fn load_save_ram(checksum: u32) -> Option<Vec<u8>> {
+    let data = get_local_storage()?
+        .get_item(&save_ram_key(checksum))
+        .ok()??;
+    decode_hex(&data)
+}
+
+pub fn store_save_ram(checksum: u32, data: &[u8]) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .set_item(&save_ram_key(checksum), &encode_hex(data))
+}
+
+fn save_ram_key(checksum: u32) -> String {
+    format!("{}{:08x}", SAVE_RAM_KEY_PREFIX, checksum)
+}
+
+/// Returns the buzzer tone chosen for the ROM with the given checksum, if any.
+pub fn load_rom_tone(checksum: u32) -> Option<Tone> {
+    let tone = get_local_storage()?.get_item(&tone_key(checksum)).ok()??;
+    Tone::parse(&tone)
+}
+
+pub fn store_rom_tone(checksum: u32, tone: Tone) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .set_item(&tone_key(checksum), &tone.to_string())
+}
+
+pub fn remove_rom_tone(checksum: u32) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .remove_item(&tone_key(checksum))
+}
+
+fn tone_key(checksum: u32) -> String {
+    format!("{}{:08x}", TONE_KEY_PREFIX, checksum)
+}
+
+/// Returns the key bindings kept for the ROM with the given checksum, if any.
+pub fn load_rom_controls(checksum: u32) -> Option<ControlProfile> {
+    let profile = get_local_storage()?
+        .get_item(&rom_controls_key(checksum))
+        .ok()??;
+    ControlProfile::parse(&profile)
+}
+
+pub fn store_rom_controls(checksum: u32, profile: &ControlProfile) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .set_item(&rom_controls_key(checksum), &profile.to_string())
+}
+
+pub fn remove_rom_controls(checksum: u32) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .remove_item(&rom_controls_key(checksum))
+}
+
+fn rom_controls_key(checksum: u32) -> String {
+    format!("{}{:08x}", ROM_CONTROLS_KEY_PREFIX, checksum)
+}
+
+/// Returns the cheats kept for the ROM with the given checksum, skipping any
+/// that can't be read.
+pub fn load_rom_cheats(checksum: u32) -> Vec<Cheat> {
+    let cheats = get_local_storage()
+        .and_then(|storage| storage.get_item(&cheats_key(checksum)).ok().flatten());
+    cheats
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(Cheat::parse)
+        .collect()
+}
+
+/// Keeps `cheats` for the ROM with the given checksum, or forgets its
+/// cheats if there are none.
+pub fn store_rom_cheats(checksum: u32, cheats: &[Cheat]) -> Result<(), JsValue> {
+    let storage =
+        get_local_storage().ok_or_else(|| JsValue::from("Local storage is not available"))?;
+    if cheats.is_empty() {
+        return storage.remove_item(&cheats_key(checksum));
+    }
+    let cheats = cheats
+        .iter()
+        .map(Cheat::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    storage.set_item(&cheats_key(checksum), &cheats)
+}
+
+fn cheats_key(checksum: u32) -> String {
+    format!("{}{:08x}", CHEATS_KEY_PREFIX, checksum)
+}
+
+pub fn load_palette() -> Option<Palette> {
+    let palette = get_local_storage()?.get_item(PALETTE_KEY).ok()??;
+    Palette::parse(&palette)
+}
+
+pub fn store_palette(palette: Palette) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .set_item(PALETTE_KEY, &palette.to_string())
+}
+
+pub fn load_system_palette() -> Option<bool> {
+    let enabled = get_local_storage()?.get_item(SYSTEM_PALETTE_KEY).ok()??;
+    Some(enabled == "1")
+}
+
+pub fn store_system_palette(enabled: bool) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .set_item(SYSTEM_PALETTE_KEY, if enabled { "1" } else { "0" })
+}
+
+pub fn load_crt_filter() -> Option<bool> {
+    let enabled = get_local_storage()?.get_item(CRT_FILTER_KEY).ok()??;
+    Some(enabled == "1")
+}
+
+pub fn store_crt_filter(enabled: bool) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .set_item(CRT_FILTER_KEY, if enabled { "1" } else { "0" })
+}
+
+pub fn load_pixel_shape() -> Option<PixelShape> {
+    let shape = get_local_storage()?.get_item(PIXEL_SHAPE_KEY).ok()??;
+    PixelShape::parse(&shape)
+}
+
+pub fn store_pixel_shape(shape: PixelShape) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .set_item(PIXEL_SHAPE_KEY, &shape.to_string())
+}
+
+pub fn load_afterglow() -> Option<f32> {
+    get_local_storage()?
+        .get_item(AFTERGLOW_KEY)
+        .ok()??
+        .parse()
+        .ok()
+}
+
+pub fn store_afterglow(persistence: f32) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .set_item(AFTERGLOW_KEY, &persistence.to_string())
+}
+
+pub fn load_theme() -> Option<String> {
+    get_local_storage()?.get_item(THEME_KEY).ok()?
+}
+
+/// Stores the name of the theme chosen, or forgets it for `None`.
+pub fn store_theme(name: Option<&str>) -> Result<(), JsValue> {
+    let storage =
+        get_local_storage().ok_or_else(|| JsValue::from("Local storage is not available"))?;
+    match name {
+        Some(name) => storage.set_item(THEME_KEY, name),
+        None => storage.remove_item(THEME_KEY),
+    }
+}
+
+pub fn load_perf_overlay() -> Option<bool> {
+    let enabled = get_local_storage()?.get_item(PERF_OVERLAY_KEY).ok()??;
+    Some(enabled == "1")
+}
+
+pub fn store_perf_overlay(enabled: bool) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .set_item(PERF_OVERLAY_KEY, if enabled { "1" } else { "0" })
+}
+
+pub fn load_keypad_view() -> Option<bool> {
+    let enabled = get_local_storage()?.get_item(KEYPAD_VIEW_KEY).ok()??;
+    Some(enabled == "1")
+}
+
+pub fn store_keypad_view(enabled: bool) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .set_item(KEYPAD_VIEW_KEY, if enabled { "1" } else { "0" })
+}
+
+/// Returns the bindings stored for the key set at `set`, 0 for the first
+/// player's and 1 for the second's.
+pub fn load_key_map(set: usize) -> Option<KeyMap> {
+    let key_map = get_local_storage()?.get_item(KEY_MAP_KEYS[set]).ok()??;
+    KeyMap::parse(&key_map)
+}
+
+/// Stores the bindings of the key set at `set`, or forgets them for `None`.
+pub fn store_key_map(set: usize, key_map: Option<&KeyMap>) -> Result<(), JsValue> {
+    let storage =
+        get_local_storage().ok_or_else(|| JsValue::from("Local storage is not available"))?;
+    match key_map {
+        Some(key_map) => storage.set_item(KEY_MAP_KEYS[set], &key_map.to_string()),
+        None => storage.remove_item(KEY_MAP_KEYS[set]),
+    }
+}
+
+pub fn load_joystick() -> Option<Directions> {
+    let directions = get_local_storage()?.get_item(JOYSTICK_KEY).ok()??;
+    Directions::parse(&directions)
+}
+
+pub fn store_joystick(directions: Directions) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .set_item(JOYSTICK_KEY, &directions.to_string())
+}
+
+pub fn load_touch_controls() -> Option<bool> {
+    let enabled = get_local_storage()?.get_item(TOUCH_CONTROLS_KEY).ok()??;
+    Some(enabled == "1")
+}
+
+pub fn store_touch_controls(enabled: bool) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .set_item(TOUCH_CONTROLS_KEY, if enabled { "1" } else { "0" })
+}
+
+pub fn load_midi_first_note() -> Option<u8> {
+    let note = get_local_storage()?.get_item(MIDI_FIRST_NOTE_KEY).ok()??;
+    note.parse().ok()
+}
+
+pub fn store_midi_first_note(note: u8) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .set_item(MIDI_FIRST_NOTE_KEY, &note.to_string())
+}
+
+pub fn load_macros() -> Option<MacroSlots> {
+    let slots = get_local_storage()?.get_item(MACROS_KEY).ok()??;
+    MacroSlots::parse(&slots)
+}
+
+pub fn store_macros(slots: &MacroSlots) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .set_item(MACROS_KEY, &slots.to_string())
+}
+
+pub fn load_turbo() -> Option<TurboSettings> {
+    let settings = get_local_storage()?.get_item(TURBO_KEY).ok()??;
+    TurboSettings::parse(&settings)
+}
+
+pub fn store_turbo(settings: TurboSettings) -> Result<(), JsValue> {
+    get_local_storage()
+        .ok_or_else(|| JsValue::from("Local storage is not available"))?
+        .set_item(TURBO_KEY, &settings.to_string())
+}
+
+/// Local storage can be missing or disabled, e.g. in some private browsing
+/// modes, so callers treat it as optional.
+fn get_local_storage() -> Option<Storage> {
+    window()?.local_storage().ok()?
+}
+
+fn encode_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(data: &str) -> Option<Vec<u8>> {
+    if !data.len().is_multiple_of(2) || !data.is_ascii() {
+        return None;
+    }
+
+    (0..data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&data[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_round_trip() {
+        let data = [0x00, 0x7f, 0x80, 0xff];
+        assert_eq!(encode_hex(&data), "007f80ff");
+        assert_eq!(decode_hex("007f80ff"), Some(data.to_vec()));
+        assert_eq!(decode_hex("007"), None);
+        assert_eq!(decode_hex("zz"), None);
+        assert_eq!(save_ram_key(0xc8), "chip8-save-ram-000000c8");
+        assert_eq!(tone_key(0xc8), "chip8-tone-000000c8");
+    }
+}
@@ -0,0 +1,65 @@
+//! Octo symbol files in the Debugger panel. Loaded alongside a ROM, their
+//! labels name addresses in the disassembly and call tree in place of the
+//! names made up from jumps and calls.
+
+use super::{get_element_by_id, osd};
+use crate::chip8emulator::{parse_symbols, Chip8Emulator};
+use gloo::events::EventListener;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{console, HtmlInputElement};
+
+thread_local! {
+    /// The symbols loaded last, with the checksum of the ROM they were
+    /// loaded for.
+    static SYMBOLS: RefCell<Option<(u32, BTreeMap<u16, String>)>> = const { RefCell::new(None) };
+}
+
+/// Loads the symbol file picked for the ROM running.
+pub fn register_symbol_file(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&get_input(), "change", move |_| {
+        let input = get_input();
+        input.blur().unwrap();
+        let file = match input.files().and_then(|files| files.get(0)) {
+            Some(file) => file,
+            None => return,
+        };
+
+        let chip8 = Rc::clone(&chip8);
+        spawn_local(async move {
+            match JsFuture::from(file.text()).await {
+                Ok(text) => {
+                    let symbols = parse_symbols(&text.as_string().unwrap());
+                    osd::show(&format!("{} symbols loaded", symbols.len()));
+                    let checksum = chip8.borrow().get_rom_checksum();
+                    SYMBOLS.with(|loaded| *loaded.borrow_mut() = Some((checksum, symbols)));
+                    apply_symbols(&mut chip8.borrow_mut());
+                }
+                Err(e) => {
+                    console::error_2(&format!("Can't load {}", file.name()).into(), &e);
+                    osd::show_error(&format!("Can't load {}", file.name()));
+                }
+            }
+        });
+    })
+    .forget();
+}
+
+/// Gives `chip8` the symbols loaded for the ROM it has loaded, if any.
+pub fn apply_symbols(chip8: &mut Chip8Emulator) {
+    let symbols = SYMBOLS.with(|loaded| match &*loaded.borrow() {
+        Some((checksum, symbols)) if *checksum == chip8.get_rom_checksum() => symbols.clone(),
+        _ => BTreeMap::new(),
+    });
+    chip8.set_symbols(symbols);
+}
+
+fn get_input() -> HtmlInputElement {
+    get_element_by_id("symbol-file")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #symbol-file is not an input element")
+}
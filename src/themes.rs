@@ -0,0 +1,252 @@
+//! Theme packs: JSON files listed in a manifest and fetched at startup, each
+//! naming a look for the page. A theme file looks like
+//!
+//! ```json
+//! {
+//!   "name": "Game Boy",
+//!   "palette": "#9bbc0f,#0f380f",
+//!   "background": "#0f380f",
+//!   "pixelShape": "square",
+//!   "crt": false,
+//!   "css": { "--terminal-color": "#9bbc0f" }
+//! }
+//! ```
+//!
+//! where every field is optional. The manifest is an array of theme file
+//! names, relative to the manifest.
+
+use super::display_filter::{self, DisplayFilters, PixelShape};
+use super::{get_element_by_id, get_json_file, palette_picker, storage};
+use crate::chip8emulator::{Chip8Emulator, Palette};
+use gloo::events::EventListener;
+use js_sys::{Array, Object, Reflect};
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{console, window, CssStyleDeclaration, HtmlSelectElement};
+
+struct Theme {
+    name: String,
+    palette: Option<Palette>,
+    /// CSS color of the page behind the display.
+    background: Option<String>,
+    pixel_shape: Option<PixelShape>,
+    crt: Option<bool>,
+    /// CSS custom properties set on the page, e.g. `--terminal-color`.
+    css_variables: Vec<(String, String)>,
+}
+
+impl Theme {
+    /// Reads a theme from the parsed JSON of the theme file at `path`. Themes
+    /// without a name are named after their file.
+    fn from_json(path: &str, json: &JsValue) -> Result<Theme, JsValue> {
+        let string = |name: &str| Reflect::get(json, &name.into()).map(|value| value.as_string());
+        let invalid =
+            |field: &str| JsValue::from(format!("Theme {} has an invalid {}", path, field));
+
+        let palette = match string("palette")? {
+            Some(palette) => Some(Palette::parse(&palette).ok_or_else(|| invalid("palette"))?),
+            None => None,
+        };
+        let pixel_shape = match string("pixelShape")? {
+            Some(shape) => Some(PixelShape::parse(&shape).ok_or_else(|| invalid("pixelShape"))?),
+            None => None,
+        };
+
+        let mut css_variables = Vec::new();
+        let css = Reflect::get(json, &"css".into())?;
+        if css.is_object() {
+            for entry in Object::entries(css.unchecked_ref()).iter() {
+                let entry = Array::from(&entry);
+                match (entry.get(0).as_string(), entry.get(1).as_string()) {
+                    (Some(name), Some(value)) if name.starts_with("--") => {
+                        css_variables.push((name, value))
+                    }
+                    _ => return Err(invalid("css variable")),
+                }
+            }
+        }
+
+        Ok(Theme {
+            name: string("name")?.unwrap_or_else(|| file_stem(path).to_string()),
+            palette,
+            background: string("background")?,
+            pixel_shape,
+            crt: Reflect::get(json, &"crt".into())?.as_bool(),
+            css_variables,
+        })
+    }
+}
+
+/// Returns the path of a file listed in the manifest at `manifest`, whose
+/// names are relative to it.
+fn theme_path(manifest: &str, file: &str) -> String {
+    match manifest.rfind('/') {
+        Some(end) => format!("{}/{}", &manifest[..end], file),
+        None => file.to_string(),
+    }
+}
+
+/// Returns the name of the file at `path`, without its directory and
+/// extension.
+fn file_stem(path: &str) -> &str {
+    let name = path.rsplit('/').next().unwrap_or(path);
+    name.rfind('.').map_or(name, |end| &name[..end])
+}
+
+thread_local! {
+    static THEMES: RefCell<Vec<Theme>> = const { RefCell::new(Vec::new()) };
+
+    /// CSS custom properties the current theme set, to remove when switching.
+    static CSS_VARIABLES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Fetches the themes listed in the manifest at `manifest` and adds them to
+/// the theme select, restoring the page's look to the stored theme's.
+pub async fn load_themes(chip8: &Rc<RefCell<Chip8Emulator>>, manifest: &str) {
+    let files = match get_json_file(manifest).await {
+        Ok(files) if Array::is_array(&files) => Array::from(&files),
+        Ok(_) => return console::error_1(&"Theme manifest is not an array".into()),
+        Err(e) => return console::error_2(&"Can't load the theme manifest".into(), &e),
+    };
+
+    for file in files.iter() {
+        let path = theme_path(manifest, &file.as_string().unwrap_or_default());
+        let theme = match get_json_file(&path).await {
+            Ok(json) => Theme::from_json(&path, &json),
+            Err(e) => Err(e),
+        };
+        match theme {
+            Ok(theme) => add_theme(theme),
+            Err(e) => console::error_2(&format!("Can't load theme {}", path).into(), &e),
+        }
+    }
+
+    // Colors and filters changed since the stored theme was chosen are
+    // stored on their own, so only the page's style is restored.
+    if let Some(name) = storage::load_theme() {
+        let index = THEMES.with(|themes| themes.borrow().iter().position(|t| t.name == name));
+        if let Some(index) = index {
+            get_theme_select().set_value(&index.to_string());
+            THEMES.with(|themes| set_page_style(Some(&themes.borrow()[index])));
+        }
+    }
+
+    let chip8 = Rc::clone(chip8);
+    EventListener::new(&get_theme_select(), "change", move |_| {
+        let select = get_theme_select();
+        select.blur().unwrap();
+        // The page's own look has an empty value.
+        let index = select.value().parse::<usize>().ok();
+        THEMES.with(|themes| {
+            let themes = themes.borrow();
+            let theme = index.and_then(|index| themes.get(index));
+            apply_theme(&chip8, theme);
+            if let Err(e) = storage::store_theme(theme.map(|theme| theme.name.as_str())) {
+                console::error_2(&"Can't store the theme".into(), &e);
+            }
+        });
+    })
+    .forget();
+}
+
+fn add_theme(theme: Theme) {
+    let option = window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .create_element("option")
+        .unwrap();
+    option.set_text_content(Some(&theme.name));
+    THEMES.with(|themes| {
+        let mut themes = themes.borrow_mut();
+        option
+            .set_attribute("value", &themes.len().to_string())
+            .unwrap();
+        themes.push(theme);
+    });
+    get_theme_select().append_child(&option).unwrap();
+}
+
+/// Switches the page to `theme`, or back to its own look for `None`. The
+/// palette and filters a theme sets are applied as if picked in the display
+/// panel, so they can be adjusted from there.
+fn apply_theme(chip8: &Rc<RefCell<Chip8Emulator>>, theme: Option<&Theme>) {
+    set_page_style(theme);
+    let theme = match theme {
+        Some(theme) => theme,
+        None => return,
+    };
+
+    if let Some(palette) = theme.palette {
+        palette_picker::choose_palette(chip8, palette);
+    }
+    if theme.pixel_shape.is_some() || theme.crt.is_some() {
+        let filters = display_filter::filters();
+        display_filter::choose_filters(
+            chip8,
+            DisplayFilters {
+                crt: theme.crt.unwrap_or(filters.crt),
+                pixel_shape: theme.pixel_shape.unwrap_or(filters.pixel_shape),
+            },
+        );
+    }
+}
+
+/// Sets the page background and CSS variables of `theme`, replacing those of
+/// the theme before.
+fn set_page_style(theme: Option<&Theme>) {
+    let style = get_body_style();
+    CSS_VARIABLES.with(|variables| {
+        let mut variables = variables.borrow_mut();
+        for name in variables.drain(..) {
+            style.remove_property(&name).unwrap();
+        }
+        style.remove_property("background-color").unwrap();
+
+        let theme = match theme {
+            Some(theme) => theme,
+            None => return,
+        };
+        if let Some(background) = &theme.background {
+            style.set_property("background-color", background).unwrap();
+        }
+        for (name, value) in &theme.css_variables {
+            style.set_property(name, value).unwrap();
+            variables.push(name.clone());
+        }
+    });
+}
+
+fn get_body_style() -> CssStyleDeclaration {
+    window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .body()
+        .expect("Page has no body")
+        .style()
+}
+
+fn get_theme_select() -> HtmlSelectElement {
+    get_element_by_id("theme")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #theme is not a select element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_theme_paths() {
+        assert_eq!(
+            theme_path("themes/manifest.json", "game-boy.json"),
+            "themes/game-boy.json"
+        );
+        assert_eq!(theme_path("manifest.json", "paper.json"), "paper.json");
+        assert_eq!(file_stem("themes/game-boy.json"), "game-boy");
+        assert_eq!(file_stem("paper"), "paper");
+    }
+}
@@ -0,0 +1,76 @@
+//! The 4x4 keypad as buttons under the display, for playing on phones and
+//! tablets. It's laid out like the keyboard keys standing in for the keypad
+//! and shown only where the main pointer is a finger, by the page's style.
+//! Each button follows its own pointer, so several can be held at once.
+
+use super::{get_element_by_id, press_key, KEYPAD_LAYOUT};
+use crate::chip8emulator::Chip8Emulator;
+use gloo::events::{EventListener, EventListenerOptions};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use web_sys::{window, Element};
+
+/// Pointer events on a button, and whether they press or release it.
+const POINTER_EVENTS: [(&str, bool); 4] = [
+    ("pointerdown", true),
+    ("pointerup", false),
+    ("pointercancel", false),
+    ("pointerleave", false),
+];
+
+/// Fills the keypad on the page with a button for each key.
+pub fn register_touch_keypad(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let document = window().unwrap().document().unwrap();
+    let keypad = get_keypad();
+    for position in 0..16 {
+        let button = document.create_element("button").unwrap();
+        button.set_class_name("touch-key");
+        button.set_attribute("type", "button").unwrap();
+        keypad.append_child(&button).unwrap();
+        register_key_button(chip8, &button, position);
+    }
+    set_labels();
+}
+
+/// Presses the key at `position` of the keypad layout while `button` is held.
+fn register_key_button(chip8: &Rc<RefCell<Chip8Emulator>>, button: &Element, position: usize) {
+    // The key pressed, released even if the layout changes while it's held.
+    let pressed = Rc::new(Cell::new(None));
+    for (event, press) in POINTER_EVENTS {
+        let chip8 = Rc::clone(chip8);
+        let pressed = Rc::clone(&pressed);
+        let target = button.clone();
+        // Not passive, so holding a button doesn't select text or open a menu.
+        let options = EventListenerOptions::enable_prevent_default();
+        EventListener::new_with_options(button, event, options, move |e| {
+            e.prevent_default();
+            if press && pressed.get().is_none() {
+                let key = KEYPAD_LAYOUT.with(Cell::get)[position];
+                pressed.set(Some(key));
+                press_key(&chip8, key, true);
+            } else if let (false, Some(key)) = (press, pressed.take()) {
+                press_key(&chip8, key, false);
+            }
+            target
+                .class_list()
+                .toggle_with_force("pressed", pressed.get().is_some())
+                .unwrap();
+        })
+        .forget();
+    }
+}
+
+/// Labels the buttons with the keys they press in the current keypad layout.
+pub fn set_labels() {
+    let layout = KEYPAD_LAYOUT.with(Cell::get);
+    let buttons = get_keypad().children();
+    for (position, key) in layout.iter().enumerate() {
+        if let Some(button) = buttons.item(position as u32) {
+            button.set_text_content(Some(&format!("{:X}", key)));
+        }
+    }
+}
+
+fn get_keypad() -> Element {
+    get_element_by_id("touch-keypad")
+}
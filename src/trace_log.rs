@@ -0,0 +1,143 @@
+//! Instruction trace in the Debugger panel: when turned on, the emulator
+//! keeps the last instructions it executed with the registers each changed,
+//! dumped to the console or exported as a CSV file on demand. Nothing is
+//! logged per instruction, so it can stay on while the ROM runs at full
+//! speed.
+
+use super::{download_file, get_element_by_id};
+use crate::chip8emulator::Chip8Emulator;
+use gloo::events::EventListener;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{console, HtmlInputElement};
+
+/// How many instructions the trace keeps.
+const TRACE_LIMIT: usize = 1000;
+
+/// Turns tracing on and off with the checkbox, and dumps and exports the
+/// trace with the buttons.
+pub fn register_trace(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let checkbox_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_checkbox(), "input", move |_| {
+        get_checkbox().blur().unwrap();
+        apply_trace_limit(&mut checkbox_chip8.borrow_mut());
+    })
+    .forget();
+
+    let dump_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("trace-dump"), "click", move |_| {
+        console::log_1(&dump(&dump_chip8.borrow()).into());
+    })
+    .forget();
+
+    let export_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("trace-export"), "click", move |_| {
+        let registers = get_input("trace-registers").checked();
+        let csv = export_csv(&export_chip8.borrow(), registers);
+        download_file("chip8-trace.csv", csv.as_bytes(), "text/csv");
+    })
+    .forget();
+}
+
+/// Has `chip8` keep a trace if the checkbox is checked.
+pub fn apply_trace_limit(chip8: &mut Chip8Emulator) {
+    let enabled = get_checkbox().checked();
+    chip8.set_trace_limit(if enabled { TRACE_LIMIT } else { 0 });
+}
+
+/// Returns the trace as text, one instruction a line, oldest first.
+fn dump(chip8: &Chip8Emulator) -> String {
+    let lines = chip8
+        .get_trace()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    if lines.is_empty() {
+        return "The trace is empty".to_string();
+    }
+    format!("Last {} instructions:\n{}", lines.len(), lines.join("\n"))
+}
+
+/// Returns the trace as CSV, with a header and a row per instruction,
+/// oldest first. With `registers`, each row ends with V0-VF and I as they
+/// were after the instruction.
+fn export_csv(chip8: &Chip8Emulator, registers: bool) -> String {
+    let mut csv = String::from("address,opcode,instruction,changes");
+    if registers {
+        (0..16).for_each(|x| csv.push_str(&format!(",V{:X}", x)));
+        csv.push_str(",I");
+    }
+    csv.push('\n');
+    for entry in chip8.get_trace() {
+        let changes = entry
+            .changes
+            .iter()
+            .map(|(x, old, new)| format!("V{:X} {:02X}->{:02X}", x, old, new))
+            .chain(
+                entry
+                    .i_change
+                    .map(|(old, new)| format!("I {:04X}->{:04X}", old, new)),
+            )
+            .collect::<Vec<_>>();
+        csv.push_str(&format!(
+            "{:03X},{:04X},\"{}\",{}",
+            entry.address,
+            entry.opcode,
+            entry.text,
+            changes.join(" ")
+        ));
+        if registers {
+            for v in &entry.registers {
+                csv.push_str(&format!(",{:02X}", v));
+            }
+            csv.push_str(&format!(",{:04X}", entry.i));
+        }
+        csv.push('\n');
+    }
+    csv
+}
+
+fn get_checkbox() -> HtmlInputElement {
+    get_input("trace-enabled")
+}
+
+fn get_input(id: &str) -> HtmlInputElement {
+    get_element_by_id(id)
+        .dyn_into::<HtmlInputElement>()
+        .unwrap_or_else(|_| panic!("Element with id #{} is not an input element", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump() {
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8.load_rom(&[0x60, 0x05, 0x12, 0x02]).unwrap();
+        assert_eq!(dump(&chip8), "The trace is empty");
+
+        chip8.set_trace_limit(TRACE_LIMIT);
+        chip8.step_instruction();
+        chip8.step_instruction();
+        assert_eq!(
+            dump(&chip8),
+            "Last 2 instructions:\n\
+             200  6005  LD V0, 0x05           V0 00->05\n\
+             202  1202  JP 0x202"
+        );
+        assert_eq!(
+            export_csv(&chip8, false),
+            "address,opcode,instruction,changes\n\
+             200,6005,\"LD V0, 0x05\",V0 00->05\n\
+             202,1202,\"JP 0x202\",\n"
+        );
+        let with_registers = export_csv(&chip8, true);
+        let mut lines = with_registers.lines();
+        assert!(lines.next().unwrap().ends_with(",VE,VF,I"));
+        assert!(lines
+            .next()
+            .unwrap()
+            .ends_with("V0 00->05,05,00,00,00,00,00,00,00,00,00,00,00,00,00,00,00,0000"));
+    }
+}
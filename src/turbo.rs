@@ -0,0 +1,221 @@
+//! Turbo keys: while a key with turbo on is held, it's pressed and released
+//! over and over at its own rate, so games that fire on every press, like
+//! INVADERS, don't need the key hammered. The rest of the input doesn't see
+//! the repeats; macros record the key as held.
+
+use super::{get_element_by_id, storage};
+use crate::chip8emulator::Chip8Emulator;
+use gloo::events::EventListener;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use wasm_bindgen::JsCast;
+use web_sys::{console, HtmlInputElement, HtmlSelectElement};
+
+/// Presses per second of keys whose rate wasn't changed.
+const DEFAULT_RATE: f64 = 10.0;
+const MIN_RATE: f64 = 1.0;
+/// The keypad takes one change of a key per frame, so faster rates would
+/// skip presses at 60 frames per second.
+const MAX_RATE: f64 = 30.0;
+
+/// Which keys of the keypad have turbo on, and how many times a second each
+/// is pressed while held.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TurboSettings {
+    /// Keys with turbo on, one bit per key.
+    enabled: u16,
+    rates: [f64; 16],
+}
+
+impl TurboSettings {
+    /// Returns the rate of `key`, if turbo is on for it.
+    pub fn rate(&self, key: u8) -> Option<f64> {
+        if self.enabled & 1 << key != 0 {
+            Some(self.rates[key as usize])
+        } else {
+            None
+        }
+    }
+
+    pub fn set(&mut self, key: u8, enabled: bool, rate: f64) {
+        self.enabled = self.enabled & !(1 << key) | (enabled as u16) << key;
+        self.rates[key as usize] = rate.clamp(MIN_RATE, MAX_RATE);
+    }
+
+    /// Parses settings written as by the `Display` impl, e.g. `5:10,a:15` for
+    /// turbo on keys 5 and A at 10 and 15 presses per second.
+    pub fn parse(value: &str) -> Option<TurboSettings> {
+        let mut settings = TurboSettings::default();
+        for entry in value.split(',').filter(|entry| !entry.is_empty()) {
+            let (key, rate) = entry.split_once(':')?;
+            let key = u8::from_str_radix(key.trim(), 16)
+                .ok()
+                .filter(|&key| key <= 0xf)?;
+            settings.set(key, true, rate.trim().parse().ok()?);
+        }
+        Some(settings)
+    }
+}
+
+impl fmt::Display for TurboSettings {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let entries: Vec<String> = (0..16)
+            .filter_map(|key| Some(format!("{:x}:{}", key, self.rate(key)?)))
+            .collect();
+        write!(f, "{}", entries.join(","))
+    }
+}
+
+impl Default for TurboSettings {
+    fn default() -> TurboSettings {
+        TurboSettings {
+            enabled: 0,
+            rates: [DEFAULT_RATE; 16],
+        }
+    }
+}
+
+/// Returns whether a turbo key held for `held_for` milliseconds is pressed at
+/// `rate` presses per second: in the first half of each press.
+fn autofire_pressed(held_for: f64, rate: f64) -> bool {
+    (held_for * rate / 1000.0).fract() < 0.5
+}
+
+/// A key held down, and whether turbo has it pressed right now.
+#[derive(Clone, Copy)]
+struct Held {
+    since: f64,
+    pressed: bool,
+}
+
+thread_local! {
+    static SETTINGS: Cell<TurboSettings> = Cell::new(TurboSettings::default());
+
+    static HELD: RefCell<[Option<Held>; 16]> = const { RefCell::new([None; 16]) };
+}
+
+/// Notes that `key` was pressed or released at `time`, to repeat it while
+/// it's held if it has turbo on. The press itself is passed on as usual.
+pub fn hold(key: u8, pressed: bool, time: f64) {
+    HELD.with(|held| {
+        let held = &mut held.borrow_mut()[key as usize];
+        if !pressed {
+            *held = None;
+        } else if held.is_none() {
+            // Key repeats don't start the presses over.
+            *held = Some(Held {
+                since: time,
+                pressed: true,
+            });
+        }
+    });
+}
+
+/// Presses and releases the held turbo keys as due at `current_time`.
+pub fn update(chip8: &mut Chip8Emulator, current_time: f64) {
+    let settings = SETTINGS.with(Cell::get);
+    HELD.with(|held| {
+        for (key, held) in held.borrow_mut().iter_mut().enumerate() {
+            let held = match held {
+                Some(held) => held,
+                None => continue,
+            };
+            // Turning turbo off while the key is held leaves it pressed.
+            let pressed = settings
+                .rate(key as u8)
+                .is_none_or(|rate| autofire_pressed(current_time - held.since, rate));
+            if pressed != held.pressed {
+                held.pressed = pressed;
+                chip8.queue_key(key as u8, pressed);
+            }
+        }
+    });
+}
+
+/// Shows the turbo settings of the key picked in the Controls panel, and
+/// changes them from there.
+pub fn register_turbo_panel() {
+    SETTINGS.with(|settings| settings.set(storage::load_turbo().unwrap_or_default()));
+    show_settings();
+
+    EventListener::new(&get_key_select(), "change", |_| {
+        get_key_select().blur().unwrap();
+        show_settings();
+    })
+    .forget();
+
+    for id in ["turbo-enabled", "turbo-rate"] {
+        EventListener::new(&get_input(id), "input", |_| {
+            let key = selected_key();
+            let enabled = get_input("turbo-enabled").checked();
+            let rate = get_input("turbo-rate").value_as_number();
+            SETTINGS.with(|settings| {
+                let mut current = settings.get();
+                current.set(key, enabled, rate);
+                settings.set(current);
+            });
+            show_settings();
+            if let Err(e) = storage::store_turbo(SETTINGS.with(Cell::get)) {
+                console::error_2(&"Can't store the turbo settings".into(), &e);
+            }
+        })
+        .forget();
+    }
+}
+
+fn show_settings() {
+    let settings = SETTINGS.with(Cell::get);
+    let key = selected_key();
+    let rate = settings.rates[key as usize];
+    get_input("turbo-enabled").set_checked(settings.rate(key).is_some());
+    get_input("turbo-rate").set_value_as_number(rate);
+    get_element_by_id("turbo-rate-text").set_text_content(Some(&rate.to_string()));
+}
+
+fn selected_key() -> u8 {
+    u8::from_str_radix(&get_key_select().value(), 16).unwrap_or(0)
+}
+
+fn get_key_select() -> HtmlSelectElement {
+    get_element_by_id("turbo-key")
+        .dyn_into::<HtmlSelectElement>()
+        .expect("Element with id #turbo-key is not a select element")
+}
+
+fn get_input(id: &str) -> HtmlInputElement {
+    get_element_by_id(id)
+        .dyn_into::<HtmlInputElement>()
+        .unwrap_or_else(|_| panic!("Element with id #{} is not an input element", id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_turbo_settings() {
+        let mut settings = TurboSettings::default();
+        settings.set(0x5, true, 10.0);
+        settings.set(0xa, true, 100.0);
+        assert_eq!(settings.rate(0x5), Some(10.0));
+        assert_eq!(settings.rate(0xa), Some(MAX_RATE));
+        assert_eq!(settings.rate(0x6), None);
+        assert_eq!(settings.to_string(), "5:10,a:30");
+        assert_eq!(TurboSettings::parse(&settings.to_string()), Some(settings));
+        assert_eq!(TurboSettings::parse(""), Some(TurboSettings::default()));
+        assert_eq!(TurboSettings::parse("g:10"), None);
+
+        settings.set(0x5, false, 10.0);
+        assert_eq!(settings.rate(0x5), None);
+    }
+
+    #[test]
+    fn test_autofire_pressed() {
+        // 10 presses per second: pressed for 50 ms, released for 50 ms.
+        assert!(autofire_pressed(0.0, 10.0));
+        assert!(autofire_pressed(49.0, 10.0));
+        assert!(!autofire_pressed(50.0, 10.0));
+        assert!(!autofire_pressed(99.0, 10.0));
+        assert!(autofire_pressed(100.0, 10.0));
+    }
+}
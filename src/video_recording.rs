@@ -0,0 +1,185 @@
+use super::audio::Buzzer;
+use super::memory_usage;
+use super::{download_blob, get_canvas, get_element_by_id};
+use gloo::events::EventListener;
+use js_sys::Array;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::{JsCast, JsValue};
+use web_sys::{
+    console, Blob, BlobEvent, BlobPropertyBag, HtmlButtonElement, MediaRecorder,
+    MediaRecorderOptions, MediaStream, MediaStreamAudioDestinationNode, MediaStreamTrack,
+};
+
+const MIME_TYPE: &str = "video/webm";
+const FRAME_RATE: f64 = 60.0;
+/// How often the recorder hands over data, in milliseconds, so that the size
+/// of the recording is known while it runs.
+const TIME_SLICE: i32 = 1000;
+
+/// A gameplay recording in progress. The canvas and the buzzer are recorded
+/// together by one MediaRecorder, so they stay in sync.
+struct VideoRecording {
+    recorder: MediaRecorder,
+    /// The size of the data recorded so far.
+    bytes: Rc<Cell<usize>>,
+}
+
+impl VideoRecording {
+    fn start(buzzer: &Rc<RefCell<Option<Buzzer>>>) -> Result<VideoRecording, JsValue> {
+        let stream = get_canvas().capture_stream_with_frame_request_rate(FRAME_RATE)?;
+
+        // The buzzer is created in the background, so it may not exist yet,
+        // in which case the recording is silent.
+        let audio = match buzzer.borrow().as_ref() {
+            Some(buzzer) => Some(buzzer.add_stream_destination()?),
+            None => None,
+        };
+        if let Some(audio) = &audio {
+            for track in audio.stream().get_audio_tracks().iter() {
+                stream.add_track(&track.unchecked_into());
+            }
+        }
+
+        let options = MediaRecorderOptions::new();
+        if MediaRecorder::is_type_supported(MIME_TYPE) {
+            options.set_mime_type(MIME_TYPE);
+        }
+        let recorder =
+            MediaRecorder::new_with_media_stream_and_media_recorder_options(&stream, &options)?;
+
+        // A WebM file can't lose its start, so the recording stops once it
+        // reaches its cap, whatever eviction policy is configured.
+        let max_bytes = memory_usage::limits().video_recording.max_bytes;
+        let chunks = Rc::new(RefCell::new(Vec::new()));
+        let bytes = Rc::new(Cell::new(0));
+        let data_listener = {
+            let chunks = Rc::clone(&chunks);
+            let bytes = Rc::clone(&bytes);
+            EventListener::new(&recorder, "dataavailable", move |e| {
+                if let Some(data) = e.dyn_ref::<BlobEvent>().and_then(BlobEvent::data) {
+                    bytes.set(bytes.get() + data.size() as usize);
+                    chunks.borrow_mut().push(data);
+                }
+                // Data still arrives after a recording is stopped.
+                let recorder = e.target().unwrap().unchecked_into::<MediaRecorder>();
+                if bytes.get() >= max_bytes && is_active(&recorder) {
+                    if let Err(e) = stop_recording() {
+                        console::error_2(&"Can't stop recording gameplay".into(), &e);
+                    }
+                }
+            })
+        };
+
+        // The last data arrives after `stop` is called, so the recording is
+        // put together once the recorder reports that it has stopped.
+        let buzzer = Rc::clone(buzzer);
+        EventListener::once(&recorder, "stop", move |e| {
+            drop(data_listener);
+            let recorder = e.target().unwrap().unchecked_into::<MediaRecorder>();
+            finish(&recorder, &chunks.borrow(), &buzzer, audio.as_ref());
+        })
+        .forget();
+
+        recorder.start_with_time_slice(TIME_SLICE)?;
+        Ok(VideoRecording { recorder, bytes })
+    }
+
+    fn stop(self) -> Result<(), JsValue> {
+        self.recorder.stop()
+    }
+}
+
+/// Downloads the recorded `chunks` as one file and releases the streams the
+/// recording used.
+fn finish(
+    recorder: &MediaRecorder,
+    chunks: &[Blob],
+    buzzer: &Rc<RefCell<Option<Buzzer>>>,
+    audio: Option<&MediaStreamAudioDestinationNode>,
+) {
+    stop_tracks(&recorder.stream());
+    if let (Some(buzzer), Some(audio)) = (buzzer.borrow().as_ref(), audio) {
+        if let Err(e) = buzzer.remove_stream_destination(audio) {
+            console::error_2(&"Can't stop recording the buzzer".into(), &e);
+        }
+    }
+
+    let options = BlobPropertyBag::new();
+    options.set_type(&recorder.mime_type());
+    let parts = chunks.iter().collect::<Array>();
+    match Blob::new_with_blob_sequence_and_options(&parts, &options) {
+        Ok(video) => download_blob("chip8-gameplay.webm", &video),
+        Err(e) => console::error_2(&"Can't put the recording together".into(), &e),
+    }
+}
+
+fn stop_tracks(stream: &MediaStream) {
+    for track in stream.get_tracks().iter() {
+        track.unchecked_into::<MediaStreamTrack>().stop();
+    }
+}
+
+thread_local! {
+    static RECORDING: RefCell<Option<VideoRecording>> = const { RefCell::new(None) };
+}
+
+/// How many bytes the recording in progress uses, if any.
+pub fn recording_bytes() -> usize {
+    RECORDING.with(|recording| {
+        recording
+            .borrow()
+            .as_ref()
+            .map_or(0, |recording| recording.bytes.get())
+    })
+}
+
+fn is_active(recorder: &MediaRecorder) -> bool {
+    RECORDING.with(|recording| {
+        recording
+            .borrow()
+            .as_ref()
+            .is_some_and(|active| &active.recorder == recorder)
+    })
+}
+
+/// Stops the recording in progress, if any, which downloads it once the
+/// recorder has stopped.
+fn stop_recording() -> Result<(), JsValue> {
+    match RECORDING.with(|recording| recording.borrow_mut().take()) {
+        Some(active) => {
+            get_record_button().set_text_content(Some("Record video"));
+            active.stop()
+        }
+        None => Ok(()),
+    }
+}
+
+/// Wires up the button that starts recording gameplay, and downloads the
+/// recording as a WebM video when stopped.
+pub fn register_video_recording(buzzer: &Rc<RefCell<Option<Buzzer>>>) {
+    let buzzer = Rc::clone(buzzer);
+    EventListener::new(&get_record_button(), "click", move |_| {
+        let button = get_record_button();
+        button.blur().unwrap();
+
+        let result = if RECORDING.with(|recording| recording.borrow().is_some()) {
+            stop_recording()
+        } else {
+            VideoRecording::start(&buzzer).map(|started| {
+                RECORDING.with(|recording| *recording.borrow_mut() = Some(started));
+                button.set_text_content(Some("Stop video"));
+            })
+        };
+        if let Err(e) = result {
+            console::error_2(&"Can't record gameplay".into(), &e);
+        }
+    })
+    .forget();
+}
+
+fn get_record_button() -> HtmlButtonElement {
+    get_element_by_id("video-record")
+        .dyn_into::<HtmlButtonElement>()
+        .expect("Element with id #video-record is not a button element")
+}
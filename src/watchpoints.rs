@@ -0,0 +1,135 @@
+//! Watchpoints in the Debugger panel: registers and memory ranges that pause
+//! the emulator when an instruction changes them, listed with a button to
+//! remove each. What paused it is shown with the old and new values.
+
+use super::{debug_panel, get_element_by_id};
+use crate::chip8emulator::{Chip8Emulator, WatchHit, Watchpoint};
+use gloo::events::EventListener;
+use std::cell::RefCell;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{HtmlInputElement, KeyboardEvent};
+
+thread_local! {
+    /// The changes shown as having paused the emulator.
+    static SHOWN_HITS: RefCell<Vec<WatchHit>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Adds watchpoints typed in the panel, with the Watch button or Enter.
+pub fn register_watchpoints(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let button_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_element_by_id("watch-add"), "click", move |_| {
+        add_watchpoint(&button_chip8);
+    })
+    .forget();
+
+    let input_chip8 = Rc::clone(chip8);
+    EventListener::new(&get_input(), "keydown", move |e| {
+        if e.dyn_ref::<KeyboardEvent>().unwrap().key() == "Enter" {
+            add_watchpoint(&input_chip8);
+        }
+    })
+    .forget();
+}
+
+fn add_watchpoint(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let input = get_input();
+    match Watchpoint::parse(&input.value()) {
+        Some(watchpoint) => {
+            chip8.borrow_mut().add_watchpoint(watchpoint);
+            input.set_value("");
+            show_watchpoints(chip8);
+        }
+        None => set_status(&format!(
+            "Can't watch \"{}\", expected a register like V3, an address like E00 or a range \
+             like E00-E0F",
+            input.value()
+        )),
+    }
+}
+
+/// Lists the watchpoints, each with a button removing it.
+fn show_watchpoints(chip8: &Rc<RefCell<Chip8Emulator>>) {
+    let remove_chip8 = Rc::clone(chip8);
+    let remove = move |watchpoint| {
+        remove_chip8.borrow_mut().remove_watchpoint(watchpoint);
+        show_watchpoints(&remove_chip8);
+    };
+    debug_panel::show_list(
+        "watch-list",
+        chip8.borrow().get_watchpoints(),
+        Rc::new(remove),
+    );
+}
+
+/// Shows the changes that paused the emulator, if any. Called while the
+/// Debugger panel is open.
+pub fn update(chip8: &Chip8Emulator) {
+    let hits = chip8.get_watch_hits();
+    let changed = SHOWN_HITS.with(|shown| {
+        let mut shown = shown.borrow_mut();
+        let changed = shown.as_slice() != hits;
+        if changed {
+            *shown = hits.to_vec();
+        }
+        changed
+    });
+    if changed {
+        set_status(&describe_hits(hits));
+    }
+}
+
+fn describe_hits(hits: &[WatchHit]) -> String {
+    let changes = hits
+        .iter()
+        .map(|hit| {
+            format!(
+                "{} changed from {:02X} to {:02X}",
+                hit.location, hit.old, hit.new
+            )
+        })
+        .collect::<Vec<_>>();
+    match hits.first() {
+        Some(hit) => format!("Paused at {:03X}: {}", hit.pc, changes.join(", ")),
+        None => String::new(),
+    }
+}
+
+fn set_status(text: &str) {
+    get_element_by_id("watch-status").set_text_content(Some(text));
+}
+
+fn get_input() -> HtmlInputElement {
+    get_element_by_id("watch-input")
+        .dyn_into::<HtmlInputElement>()
+        .expect("Element with id #watch-input is not an input element")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_hits() {
+        assert_eq!(describe_hits(&[]), "");
+
+        // Stores V0 and V1 at 0xE00.
+        let mut chip8 = Chip8Emulator::new(0.0);
+        chip8
+            .load_rom(&[0x60, 0x1f, 0x61, 0x02, 0xae, 0x00, 0xf1, 0x55])
+            .unwrap();
+        chip8.add_watchpoint(Watchpoint::parse("E00-E01").unwrap());
+        chip8.add_watchpoint(Watchpoint::parse("V1").unwrap());
+        chip8.tick(100.0);
+        assert_eq!(
+            describe_hits(chip8.get_watch_hits()),
+            "Paused at 202: V1 changed from 00 to 02"
+        );
+        chip8.resume();
+        chip8.tick(200.0);
+        assert_eq!(
+            describe_hits(chip8.get_watch_hits()),
+            "Paused at 206: E00 changed from 00 to 1F, E01 changed from 00 to 02"
+        );
+    }
+}
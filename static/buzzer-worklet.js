@@ -0,0 +1,45 @@
+// Plays the CHIP-8 buzzer. The emulator sends `{ samples, volume }` with one
+// loop of the waveform to play, and `{ duration }` in seconds whenever the
+// sound timer is set. The countdown runs here, on the audio thread.
+class BuzzerProcessor extends AudioWorkletProcessor {
+    constructor() {
+        super();
+        this.samples = new Float32Array(1);
+        this.position = 0;
+        this.volume = 0;
+        this.remaining = 0;
+
+        this.port.onmessage = (e) => {
+            const message = e.data;
+            if (message.samples !== undefined) {
+                this.samples = message.samples;
+                this.position = 0;
+            }
+            if (message.volume !== undefined) {
+                this.volume = message.volume;
+            }
+            if (message.duration !== undefined) {
+                this.remaining = Math.round(message.duration * sampleRate);
+            }
+        };
+    }
+
+    process(inputs, outputs) {
+        const [first, ...rest] = outputs[0];
+        for (let i = 0; i < first.length; i++) {
+            if (this.remaining > 0) {
+                first[i] = this.samples[this.position] * this.volume;
+                this.position = (this.position + 1) % this.samples.length;
+                this.remaining--;
+            } else {
+                first[i] = 0;
+            }
+        }
+        for (const channel of rest) {
+            channel.set(first);
+        }
+        return true;
+    }
+}
+
+registerProcessor("buzzer", BuzzerProcessor);
@@ -0,0 +1,135 @@
+//! End-to-end tests for the page glue in `lib.rs`, run in a browser with
+//! `wasm-pack test --headless --chrome`.
+//!
+//! They load `static/index.html` into the test page, stub out `fetch` so the
+//! initial ROM comes from the test, start the module the way the page does and
+//! then drive it through DOM events only, checking the results through the
+//! debug API. The whole page is set up once, since the module's listeners and
+//! main loop live for as long as the page does.
+
+#![cfg(target_arch = "wasm32")]
+
+use chip8_emulator::{debug_api, main_js};
+use js_sys::{Promise, Reflect, JSON};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use wasm_bindgen_test::*;
+use web_sys::{
+    window, Element, Event, HtmlCanvasElement, HtmlInputElement, KeyboardEvent, KeyboardEventInit,
+    Response,
+};
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+/// Waits for a key, stores it at 0xE00, in save RAM, then counts up in V1
+/// forever.
+const TEST_ROM: [u8; 10] = [
+    0xF0, 0x0A, // LD V0, K
+    0xAE, 0x00, // LD I, 0xE00
+    0xF0, 0x55, // LD [I], V0
+    0x71, 0x01, // ADD V1, 0x01
+    0x12, 0x06, // JP 0x206
+];
+
+/// Makes every `fetch` answer with `TEST_ROM`.
+fn mock_fetch() {
+    let fetch = Closure::<dyn FnMut(JsValue) -> Promise>::new(|_path| {
+        let mut body = TEST_ROM.to_vec();
+        let response = Response::new_with_opt_u8_array(Some(&mut body)).unwrap();
+        Promise::resolve(&JsValue::from(response))
+    });
+    Reflect::set(&window().unwrap(), &"fetch".into(), fetch.as_ref()).unwrap();
+    fetch.forget();
+}
+
+async fn sleep(ms: i32) {
+    let promise = Promise::new(&mut |resolve, _| {
+        window()
+            .unwrap()
+            .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, ms)
+            .unwrap();
+    });
+    JsFuture::from(promise).await.unwrap();
+}
+
+fn dispatch_key(event: &str, code: &str) {
+    let init = KeyboardEventInit::new();
+    init.set_code(code);
+    let event = KeyboardEvent::new_with_keyboard_event_init_dict(event, &init).unwrap();
+    window().unwrap().dispatch_event(&event).unwrap();
+}
+
+fn get_element<T: JsCast>(id: &str) -> T {
+    window()
+        .unwrap()
+        .document()
+        .unwrap()
+        .get_element_by_id(id)
+        .unwrap()
+        .dyn_into::<T>()
+        .unwrap()
+}
+
+fn register(index: usize) -> u8 {
+    debug_api().unwrap().registers_copy()[index]
+}
+
+#[wasm_bindgen_test]
+async fn test_page() {
+    let window = window().unwrap();
+    let document = window.document().unwrap();
+    document
+        .body()
+        .unwrap()
+        .set_inner_html(include_str!("../static/index.html"));
+    window.local_storage().unwrap().unwrap().clear().unwrap();
+    let config = JSON::parse(
+        r#"{ "initialRom": "TEST", "skipBoot": true, "saveRam": "E00-E0F",
+             "audio": { "muted": true } }"#,
+    )
+    .unwrap();
+    Reflect::set(&window, &"chip8Config".into(), &config).unwrap();
+    mock_fetch();
+
+    main_js().await;
+    sleep(100).await;
+
+    // The fetched ROM is running, and waiting for a key.
+    assert_eq!(debug_api().unwrap().memory_copy()[0x200..0x20a], TEST_ROM);
+    assert_eq!(debug_api().unwrap().pc(), 0x200);
+
+    let canvas = get_element::<HtmlCanvasElement>("canvas");
+    assert_eq!((canvas.width(), canvas.height()), (64, 32));
+
+    // W is key 5 on the keypad.
+    dispatch_key("keydown", "KeyW");
+    sleep(100).await;
+    assert_eq!(debug_api().unwrap().pressed_mask(), 1 << 5);
+    dispatch_key("keyup", "KeyW");
+    assert_eq!(register(0), 5);
+    assert_eq!(debug_api().unwrap().memory_copy()[0xe00], 5);
+
+    let clock_speed = get_element::<HtmlInputElement>("clock-speed");
+    clock_speed.set_value("1");
+    clock_speed
+        .dispatch_event(&Event::new("input").unwrap())
+        .unwrap();
+    assert_eq!(
+        get_element::<Element>("clock-speed-text").text_content(),
+        Some("1".to_string())
+    );
+    let count = register(1);
+    sleep(500).await;
+    assert!(register(1).wrapping_sub(count) <= 1);
+
+    // Leaving the page saves the ROM's save RAM.
+    window
+        .dispatch_event(&Event::new("pagehide").unwrap())
+        .unwrap();
+    let storage = window.local_storage().unwrap().unwrap();
+    let saved = (0..storage.length().unwrap())
+        .filter_map(|i| storage.key(i).unwrap())
+        .any(|key| key.starts_with("chip8-save-ram-"));
+    assert!(saved);
+}
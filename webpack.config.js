@@ -5,7 +5,8 @@ const MiniCssExtractPlugin = require('mini-css-extract-plugin');
 
 const dist = path.resolve(__dirname, "dist");
 
-module.exports = {
+module.exports = [{
+    name: "page",
     mode: "production",
     entry: {
         index: "./js/index.js"
@@ -41,4 +42,19 @@ module.exports = {
 
         new MiniCssExtractPlugin(),
     ]
-};
+}, {
+    // The emulator worker loads the module built for the page, so it's
+    // bundled once the page is.
+    name: "worker",
+    dependencies: ["page"],
+    mode: "production",
+    target: "webworker",
+    entry: {
+        worker: "./js/worker.js"
+    },
+    output: {
+        path: dist,
+        filename: "[name].js",
+        chunkFilename: "worker.[id].js"
+    },
+}];